@@ -1,6 +1,5 @@
-from fast_dse import crystal, dse_optimized
+from fast_dse import crystal, dse_optimized_with_q
 import matplotlib.pyplot as plt
-import numpy as np
 
 if __name__ == "__main__":
     lattice_param = 3.89  # nanometers
@@ -10,9 +9,8 @@ if __name__ == "__main__":
     q_0 = 1
     q_f = 15
     q_step = 0.1
-    q_array = np.arange(q_0, q_f, q_step)
-    intensity = dse_optimized(q_0, q_f, q_step, sphere_crystal)
-    intensity_cube = dse_optimized(q_0, q_f, q_step, cube_crystal)
+    q_array, intensity = dse_optimized_with_q(q_0, q_f, q_step, sphere_crystal)
+    _, intensity_cube = dse_optimized_with_q(q_0, q_f, q_step, cube_crystal)
 
     plt.plot(q_array, intensity, label="sphere")
     plt.plot(q_array, intensity_cube, label="cube")
@@ -0,0 +1,208 @@
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use super::{dse_at_q, q_grid, q_linspace, q_logspace, DistanceMatrix, Error, Result};
+
+/// Half-width of the fine windows of [`auto_adaptive_q_grid`] around each
+/// Bragg peak, in units of `2 pi / lattice_param`: a full width of
+/// `4 pi / lattice_param * 0.1`.
+pub const ADAPTIVE_WINDOW_HALF_WIDTH: f64 = 0.1;
+
+/// Ratio of the coarse to the fine q step of [`auto_adaptive_q_grid`].
+pub const ADAPTIVE_REFINEMENT: f64 = 10.0;
+
+/// [`dse_core`](super::dse_core) on several q segments, each evaluated on
+/// the [`q_grid`] of its `(q_start, q_end, q_step)`.
+///
+/// The grids are concatenated in the order given, so a point shared by two
+/// abutting segments appears twice. The distance matrix is built once for all
+/// segments and the q values are evaluated in parallel. Returns the q values
+/// and the intensities. Fails unless every segment has finite bounds with
+/// `q_start <= q_end` and a positive step.
+pub fn dse_adaptive(
+    positions: &[[f64; 3]],
+    q_ranges: &[(f64, f64, f64)],
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut q_values = Vec::new();
+    for (i, &(q_start, q_end, q_step)) in q_ranges.iter().enumerate() {
+        if !(q_start.is_finite() && q_end.is_finite() && q_start <= q_end) {
+            return Err(Error::InvalidArgument(format!(
+                "q range {} must have finite bounds with q_start <= q_end, got ({}, {})",
+                i, q_start, q_end
+            )));
+        }
+        if q_step.is_nan() || q_step <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "q range {} must have a positive q_step, got {}",
+                i, q_step
+            )));
+        }
+        q_values.extend(q_grid(q_start, q_end, q_step));
+    }
+    Ok(dse_at_q_values(positions, q_values))
+}
+
+/// Whether `n = h^2 + k^2 + l^2` for some integers, i.e. `n` is not of the
+/// form `4^a (8 b + 7)` (Legendre's three-square theorem).
+fn is_sum_of_three_squares(mut n: u64) -> bool {
+    while n > 0 && n.is_multiple_of(4) {
+        n /= 4;
+    }
+    n % 8 != 7
+}
+
+/// q grid from `min_q` to `max_q` of about `target_n_points` points that is
+/// [`ADAPTIVE_REFINEMENT`] times finer around the Bragg peaks of a cubic
+/// lattice of `lattice_param` than elsewhere.
+///
+/// The peaks are the simple cubic positions `2 pi sqrt(h^2 + k^2 + l^2) /
+/// lattice_param`, which include those of FCC and BCC. Each gets a fine
+/// window of half-width [`ADAPTIVE_WINDOW_HALF_WIDTH`] `* 2 pi /
+/// lattice_param`; overlapping windows are merged. The steps are chosen so
+/// that the coarse and fine segments together hold `target_n_points`, up to
+/// rounding of each segment to a whole number of even steps. The grid is
+/// strictly increasing and ends at `max_q`. Fails unless
+/// `0 <= min_q < max_q`, `lattice_param > 0` and `target_n_points >= 2`.
+pub fn auto_adaptive_q_grid(
+    min_q: f64,
+    max_q: f64,
+    target_n_points: usize,
+    lattice_param: f64,
+) -> Result<Vec<f64>> {
+    if !(min_q.is_finite() && max_q.is_finite() && 0.0 <= min_q && min_q < max_q) {
+        return Err(Error::InvalidArgument(format!(
+            "min_q and max_q must be finite with 0 <= min_q < max_q, got {} and {}",
+            min_q, max_q
+        )));
+    }
+    if lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param must be positive, got {}",
+            lattice_param
+        )));
+    }
+    if target_n_points < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "target_n_points must be at least 2, got {}",
+            target_n_points
+        )));
+    }
+
+    // Fine windows around the peaks, merged and clipped to [min_q, max_q].
+    let q_unit = 2.0 * PI / lattice_param;
+    let half_width = ADAPTIVE_WINDOW_HALF_WIDTH * q_unit;
+    let mut windows: Vec<(f64, f64)> = Vec::new();
+    for n in 1.. {
+        let q_peak = q_unit * (n as f64).sqrt();
+        if q_peak - half_width > max_q {
+            break;
+        }
+        if !is_sum_of_three_squares(n) {
+            continue;
+        }
+        let (start, end) = (
+            (q_peak - half_width).max(min_q),
+            (q_peak + half_width).min(max_q),
+        );
+        if start >= end {
+            continue;
+        }
+        match windows.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => windows.push((start, end)),
+        }
+    }
+
+    // Alternating coarse and fine segments covering [min_q, max_q].
+    let mut segments = Vec::with_capacity(2 * windows.len() + 1);
+    let mut position = min_q;
+    for &(start, end) in &windows {
+        if start > position {
+            segments.push((position, start, false));
+        }
+        segments.push((start, end, true));
+        position = end;
+    }
+    if position < max_q {
+        segments.push((position, max_q, false));
+    }
+
+    let fine_length: f64 = windows.iter().map(|(start, end)| end - start).sum();
+    let coarse_length = max_q - min_q - fine_length;
+    let coarse_step =
+        (coarse_length + ADAPTIVE_REFINEMENT * fine_length) / (target_n_points - 1) as f64;
+    let fine_step = coarse_step / ADAPTIVE_REFINEMENT;
+
+    let mut q_values = Vec::with_capacity(target_n_points + segments.len());
+    for (start, end, fine) in segments {
+        let step = if fine { fine_step } else { coarse_step };
+        let n_steps = ((end - start) / step).round().max(1.0) as usize;
+        let even_step = (end - start) / n_steps as f64;
+        q_values.extend((0..n_steps).map(|i| start + i as f64 * even_step));
+    }
+    q_values.push(max_q);
+    Ok(q_values)
+}
+
+/// [`dse_core`](super::dse_core) on the [`auto_adaptive_q_grid`] of
+/// `min_q`, `max_q`, `target_n_points` and `lattice_param`, returned with
+/// its q values.
+pub fn dse_auto_adaptive(
+    positions: &[[f64; 3]],
+    min_q: f64,
+    max_q: f64,
+    target_n_points: usize,
+    lattice_param: f64,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let q_values = auto_adaptive_q_grid(min_q, max_q, target_n_points, lattice_param)?;
+    Ok(dse_at_q_values(positions, q_values))
+}
+
+/// [`dse_core`](super::dse_core) on the [`q_linspace`] of `min_q`, `max_q`
+/// and `n_points`, returned with its q values.
+pub fn dse_linspace(
+    min_q: f64,
+    max_q: f64,
+    n_points: usize,
+    positions: &[[f64; 3]],
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let q_values = q_linspace(min_q, max_q, n_points)?;
+    Ok(dse_at_q_values(positions, q_values))
+}
+
+/// [`dse_core`](super::dse_core) on the [`q_logspace`] of `min_q`, `max_q`
+/// and `n_points`, returned with its q values.
+pub fn dse_logspace_q(
+    min_q: f64,
+    max_q: f64,
+    n_points: usize,
+    positions: &[[f64; 3]],
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let q_values = q_logspace(min_q, max_q, n_points)?;
+    Ok(dse_at_q_values(positions, q_values))
+}
+
+/// [`dse_core`](super::dse_core) at arbitrary `q_values`, in any order, e.g.
+/// the non-uniform grid of a measured curve.
+///
+/// The distance matrix is built once and the q values are evaluated in
+/// parallel. Fails unless every q is finite and non-negative.
+pub fn dse_custom_q(q_values: &[f64], positions: &[[f64; 3]]) -> Result<Vec<f64>> {
+    if let Some(q) = q_values.iter().find(|q| !q.is_finite() || **q < 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "q values must be finite and non-negative, got {}",
+            q
+        )));
+    }
+    Ok(dse_at_q_values(positions, q_values.to_vec()).1)
+}
+
+fn dse_at_q_values(positions: &[[f64; 3]], q_values: Vec<f64>) -> (Vec<f64>, Vec<f64>) {
+    let distances = DistanceMatrix::from_atoms(positions);
+    let intensities = q_values
+        .par_iter()
+        .map(|&q| dse_at_q(q, &distances))
+        .collect();
+    (q_values, intensities)
+}
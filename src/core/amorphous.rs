@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use super::{Atoms, Error, Result};
+
+/// Packing fraction of random close packing of hard spheres, the densest
+/// disordered packing.
+pub const RANDOM_CLOSE_PACKING: f64 = 0.64;
+
+/// Consecutive rejected trial positions after which [`crystal_amorphous`]
+/// gives up.
+const MAX_ATTEMPTS_PER_ATOM: usize = 100_000;
+
+/// Largest number of grid cells per axis of the neighbour lookup grid.
+const MAX_CELLS_PER_AXIS: usize = 256;
+
+/// `n_atoms` random positions in the cube `[0, box_size)^3` with no two atoms
+/// closer than `min_distance`.
+///
+/// Atoms are placed by random sequential addition: uniform trial positions
+/// from a [`SmallRng`] seeded from `seed` are accepted unless they overlap an
+/// atom already placed, which is checked on a grid of cells at least
+/// `min_distance` wide so each trial looks at 27 cells only. The result has
+/// liquid-like short-range order and no long-range order.
+///
+/// Requests with a hard-sphere packing fraction
+/// `N (4 pi / 3) (min_distance / 2)^3 / box_size^3` above
+/// [`RANDOM_CLOSE_PACKING`] are rejected. Random sequential addition jams well
+/// before that, near 0.38, so dense requests can also fail after
+/// [`MAX_ATTEMPTS_PER_ATOM`] consecutive rejections.
+pub fn crystal_amorphous(
+    n_atoms: usize,
+    box_size: f64,
+    min_distance: f64,
+    seed: u64,
+) -> Result<Atoms> {
+    if box_size.is_nan() || box_size <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "box_size must be positive, got {}",
+            box_size
+        )));
+    }
+    if min_distance.is_nan() || min_distance < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "min_distance must be non-negative, got {}",
+            min_distance
+        )));
+    }
+    let packing_fraction =
+        n_atoms as f64 * 4.0 / 3.0 * PI * (min_distance / 2.0).powi(3) / box_size.powi(3);
+    if packing_fraction > RANDOM_CLOSE_PACKING {
+        return Err(Error::InvalidArgument(format!(
+            "packing fraction {:.3} exceeds the random close packing limit {}",
+            packing_fraction, RANDOM_CLOSE_PACKING
+        )));
+    }
+
+    let cells_per_axis = if min_distance > 0.0 {
+        ((box_size / min_distance).floor() as usize).clamp(1, MAX_CELLS_PER_AXIS)
+    } else {
+        1
+    };
+    let cell_size = box_size / cells_per_axis as f64;
+    let cell_of = |x: f64| ((x / cell_size) as usize).min(cells_per_axis - 1);
+    let mut cells: Vec<Vec<usize>> = vec![Vec::new(); cells_per_axis.pow(3)];
+    let cell_index = |c: [usize; 3]| (c[0] * cells_per_axis + c[1]) * cells_per_axis + c[2];
+    let min_distance_sq = min_distance * min_distance;
+
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut atoms = Atoms::with_capacity(n_atoms);
+    while atoms.len() < n_atoms {
+        let mut placed = false;
+        for _ in 0..MAX_ATTEMPTS_PER_ATOM {
+            let trial: [f64; 3] = [0, 1, 2].map(|_| rng.random::<f64>() * box_size);
+            let cell = trial.map(cell_of);
+            let neighbours = |axis: usize| {
+                cell[axis].saturating_sub(1)..=(cell[axis] + 1).min(cells_per_axis - 1)
+            };
+            let overlaps = neighbours(0).any(|i| {
+                neighbours(1).any(|j| {
+                    neighbours(2).any(|k| {
+                        cells[cell_index([i, j, k])].iter().any(|&other| {
+                            let p = atoms.get(other);
+                            let dist_sq = (0..3).map(|d| (p[d] - trial[d]).powi(2)).sum::<f64>();
+                            dist_sq < min_distance_sq
+                        })
+                    })
+                })
+            });
+            if !overlaps {
+                cells[cell_index(cell)].push(atoms.len());
+                atoms.push(trial);
+                placed = true;
+                break;
+            }
+        }
+        if !placed {
+            return Err(Error::InvalidArgument(format!(
+                "placed only {} of {} atoms: no free position found in {} attempts \
+                 (packing fraction {:.3}; random sequential addition jams near 0.38)",
+                atoms.len(),
+                n_atoms,
+                MAX_ATTEMPTS_PER_ATOM,
+                packing_fraction
+            )));
+        }
+    }
+    Ok(atoms)
+}
@@ -0,0 +1,509 @@
+use super::correlation::trapezoid;
+use super::{Error, Result};
+
+/// Divide intensities by `n_atoms^2`, so that the forward scattering of a
+/// crystal of identical atoms is 1.
+pub fn normalise_intensity(intensities: &[f64], n_atoms: usize) -> Vec<f64> {
+    let norm = (n_atoms as f64).powi(2);
+    intensities.iter().map(|&i| i / norm).collect()
+}
+
+/// Element-wise `signal - background`.
+pub fn subtract_background(signal: &[f64], background: &[f64]) -> Result<Vec<f64>> {
+    check_same_length("signal", signal, "background", background)?;
+    Ok(signal
+        .iter()
+        .zip(background)
+        .map(|(&s, &b)| s - b)
+        .collect())
+}
+
+/// Scale to absolute units with `I_abs = I * concentration * contrast^2`.
+pub fn scale_to_absolute(intensities: &[f64], concentration: f64, contrast: f64) -> Vec<f64> {
+    let scale = concentration * contrast * contrast;
+    intensities.iter().map(|&i| i * scale).collect()
+}
+
+/// Lorentz-polarization factor `LP = (1 + (1 - p) cos^2 2theta) / (sin^2 theta cos theta)`
+/// of powder diffraction at each q, with `theta = asin(q lambda / (4 pi))`.
+///
+/// `polarization_fraction` `p` is 0 for an unpolarised beam and 1 for a beam
+/// fully polarised perpendicular to the scattering plane, which removes the
+/// `cos^2 2theta` term. Fails unless `wavelength_nm > 0`, `p` is in [0, 1] and
+/// every q is positive with `2theta < 180` degrees, i.e. `q < 4 pi / lambda`.
+pub fn lorentz_polarization(
+    q_values: &[f64],
+    wavelength_nm: f64,
+    polarization_fraction: f64,
+) -> Result<Vec<f64>> {
+    if wavelength_nm.is_nan() || wavelength_nm <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "wavelength_nm must be positive, got {}",
+            wavelength_nm
+        )));
+    }
+    if !(0.0..=1.0).contains(&polarization_fraction) {
+        return Err(Error::InvalidArgument(format!(
+            "polarization_fraction must be in [0, 1], got {}",
+            polarization_fraction
+        )));
+    }
+    let q_limit = 4.0 * std::f64::consts::PI / wavelength_nm;
+    q_values
+        .iter()
+        .map(|&q| {
+            if q.is_nan() || q <= 0.0 || q >= q_limit {
+                return Err(Error::InvalidArgument(format!(
+                    "q = {} is outside (0, {}), where 2theta stays below 180 degrees for \
+                     wavelength {} nm",
+                    q, q_limit, wavelength_nm
+                )));
+            }
+            let theta = (q / q_limit).asin();
+            let cos_2theta = (2.0 * theta).cos();
+            Ok(
+                (1.0 + (1.0 - polarization_fraction) * cos_2theta * cos_2theta)
+                    / (theta.sin().powi(2) * theta.cos()),
+            )
+        })
+        .collect()
+}
+
+/// Element-wise `intensities / correction_factors`, e.g. to remove the
+/// [`lorentz_polarization`] factor from measured intensities.
+pub fn apply_correction(intensities: &[f64], correction_factors: &[f64]) -> Result<Vec<f64>> {
+    check_same_length(
+        "intensities",
+        intensities,
+        "correction_factors",
+        correction_factors,
+    )?;
+    Ok(intensities
+        .iter()
+        .zip(correction_factors)
+        .map(|(&i, &c)| i / c)
+        .collect())
+}
+
+/// Weighted residual sums `(sum w (I_obs - s I_calc)^2, sum w I_obs^2)` at the
+/// least-squares optimal scale `s = sum w I_obs I_calc / sum w I_calc^2`, with
+/// `w = 1 / sigma^2` or 1 without `sigma`.
+fn scaled_residuals(
+    q_values: &[f64],
+    i_obs: &[f64],
+    i_calc: &[f64],
+    sigma: Option<&[f64]>,
+) -> Result<(f64, f64)> {
+    check_same_length("q_values", q_values, "i_obs", i_obs)?;
+    check_same_length("i_obs", i_obs, "i_calc", i_calc)?;
+    let weights = least_squares_weights("i_obs", i_obs, sigma)?;
+
+    let (obs_calc, calc_sq) = weights.iter().zip(i_obs).zip(i_calc).fold(
+        (0.0, 0.0),
+        |(obs_calc, calc_sq), ((&w, &obs), &calc)| {
+            (obs_calc + w * obs * calc, calc_sq + w * calc * calc)
+        },
+    );
+    if calc_sq == 0.0 {
+        return Err(Error::InvalidArgument(
+            "i_calc is zero everywhere; no scale factor can be fitted".to_string(),
+        ));
+    }
+    let scale = obs_calc / calc_sq;
+    Ok(weights.iter().zip(i_obs).zip(i_calc).fold(
+        (0.0, 0.0),
+        |(residual, obs_sq), ((&w, &obs), &calc)| {
+            (
+                residual + w * (obs - scale * calc).powi(2),
+                obs_sq + w * obs * obs,
+            )
+        },
+    ))
+}
+
+/// Weighted R-factor `R_w = sqrt(sum w (I_obs - s I_calc)^2 / sum w I_obs^2)`.
+///
+/// `w = 1 / sigma^2`, or 1 when `sigma` is `None`, and `s` is the
+/// least-squares optimal scale factor `sum w I_obs I_calc / sum w I_calc^2`,
+/// so `i_calc` may be in arbitrary units.
+pub fn r_factor(
+    q_values: &[f64],
+    i_obs: &[f64],
+    i_calc: &[f64],
+    sigma: Option<&[f64]>,
+) -> Result<f64> {
+    let (residual, obs_sq) = scaled_residuals(q_values, i_obs, i_calc, sigma)?;
+    Ok((residual / obs_sq).sqrt())
+}
+
+/// `chi^2 = sum w (I_obs - s I_calc)^2` with the weights and optimal scale
+/// factor of [`r_factor`].
+pub fn chi_squared(
+    q_values: &[f64],
+    i_obs: &[f64],
+    i_calc: &[f64],
+    sigma: Option<&[f64]>,
+) -> Result<f64> {
+    Ok(scaled_residuals(q_values, i_obs, i_calc, sigma)?.0)
+}
+
+/// Largest number of refinement passes of the Guinier range selection.
+const GUINIER_MAX_ITERATIONS: usize = 100;
+
+/// Guinier fit `ln I(q) = ln I_0 - R_g^2 q^2 / 3`, returning `(R_g, I_0)`.
+///
+/// The fit starts from the three lowest-q points with `I > 0` and is refined
+/// by refitting on all points with `q * R_g < q_max_rg` until the selection no
+/// longer changes. `q_max_rg = 1.3` is the usual limit of validity for compact
+/// particles.
+pub fn guinier_fit(q_values: &[f64], intensities: &[f64], q_max_rg: f64) -> Result<(f64, f64)> {
+    let (q_sq, ln_i) = guinier_plot_data(q_values, intensities, q_max_rg)?;
+    let (slope, intercept) = linear_fit(&q_sq, &ln_i);
+    Ok(((-3.0 * slope).sqrt(), intercept.exp()))
+}
+
+/// The `(q^2, ln I)` points used by [`guinier_fit`], for plotting the fit.
+pub fn guinier_plot_data(
+    q_values: &[f64],
+    intensities: &[f64],
+    q_max_rg: f64,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let mut valid: Vec<(f64, f64)> = q_values
+        .iter()
+        .zip(intensities)
+        .filter(|(_, &i)| i > 0.0)
+        .map(|(&q, &i)| (q, i))
+        .collect();
+    valid.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let too_few = |n: usize| {
+        Error::InvalidArgument(format!(
+            "the Guinier fit needs at least 3 points with I > 0 and q * R_g < {}, got {}",
+            q_max_rg, n
+        ))
+    };
+    if valid.len() < 3 {
+        return Err(too_few(valid.len()));
+    }
+
+    let points = |n: usize| -> (Vec<f64>, Vec<f64>) {
+        valid[..n].iter().map(|&(q, i)| (q * q, i.ln())).unzip()
+    };
+    let mut n_points = 3;
+    for _ in 0..GUINIER_MAX_ITERATIONS {
+        let (q_sq, ln_i) = points(n_points);
+        let (slope, _) = linear_fit(&q_sq, &ln_i);
+        if slope >= 0.0 {
+            return Err(Error::InvalidArgument(
+                "ln I does not decrease with q^2; the data has no Guinier region".to_string(),
+            ));
+        }
+        let q_limit = q_max_rg / (-3.0 * slope).sqrt();
+        let next = valid.iter().take_while(|&&(q, _)| q < q_limit).count();
+        if next < 3 {
+            return Err(too_few(next));
+        }
+        if next == n_points {
+            break;
+        }
+        n_points = next;
+    }
+    Ok(points(n_points))
+}
+
+/// Largest `q_min * R_g` for which [`extrapolate_i0`] is reliable; beyond it
+/// the lowest measured q is already outside the Guinier region.
+pub const GUINIER_EXTRAPOLATION_MAX_Q_RG: f64 = 0.3;
+
+/// Forward scattering `I(0)` extrapolated with a Guinier fit over the lowest-q
+/// decade, returning `(I_0, q_min * R_g)`.
+///
+/// The fit is a straight line in `(q^2, ln I)` over the points with `q > 0`
+/// and `I > 0` in `[q_min, 10 q_min]`, where `q_min` is the lowest such q.
+/// The extrapolation is only meaningful when `q_min * R_g` is below
+/// [`GUINIER_EXTRAPOLATION_MAX_Q_RG`].
+pub fn extrapolate_i0(q_values: &[f64], intensities: &[f64]) -> Result<(f64, f64)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let mut valid: Vec<(f64, f64)> = q_values
+        .iter()
+        .zip(intensities)
+        .filter(|&(&q, &i)| q > 0.0 && i > 0.0)
+        .map(|(&q, &i)| (q, i))
+        .collect();
+    valid.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let q_min = valid.first().map_or(0.0, |&(q, _)| q);
+    let (q_sq, ln_i): (Vec<f64>, Vec<f64>) = valid
+        .iter()
+        .take_while(|&&(q, _)| q <= 10.0 * q_min)
+        .map(|&(q, i)| (q * q, i.ln()))
+        .unzip();
+    let distinct_q = q_sq.windows(2).filter(|w| w[0] != w[1]).count();
+    if distinct_q < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "the I(0) extrapolation needs at least 3 distinct q values with q > 0 and I > 0 \
+             in the lowest-q decade, got {}",
+            q_sq.len()
+        )));
+    }
+    let (slope, intercept) = linear_fit(&q_sq, &ln_i);
+    if slope >= 0.0 {
+        return Err(Error::InvalidArgument(
+            "ln I does not decrease with q^2; the data has no Guinier region".to_string(),
+        ));
+    }
+    Ok((intercept.exp(), q_min * (-3.0 * slope).sqrt()))
+}
+
+/// Scale intensities so that the [`extrapolate_i0`] forward scattering is
+/// `n_atoms^2`, the `I(0)` of a crystal of identical atoms with unit
+/// scattering factors.
+pub fn normalise_to_forward_scattering(
+    q_values: &[f64],
+    intensities: &[f64],
+    n_atoms: usize,
+) -> Result<Vec<f64>> {
+    let (i0, _) = extrapolate_i0(q_values, intensities)?;
+    let scale = (n_atoms as f64).powi(2) / i0;
+    Ok(intensities.iter().map(|&i| i * scale).collect())
+}
+
+/// Power-law fit `I(q) = K_p * q^n` over `q_min_porod <= q <= q_max_porod`,
+/// returning `(K_p, n)`.
+///
+/// The fit is a straight line in `(ln q, ln I)` over the points with `q > 0`
+/// and `I > 0`. For sharp interfaces the Porod exponent `n` is close to -4;
+/// rough or fractal surfaces give shallower decays.
+pub fn porod_fit(
+    q_values: &[f64],
+    intensities: &[f64],
+    q_min_porod: f64,
+    q_max_porod: f64,
+) -> Result<(f64, f64)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let (ln_q, ln_i): (Vec<f64>, Vec<f64>) = q_values
+        .iter()
+        .zip(intensities)
+        .filter(|&(&q, &i)| q > 0.0 && i > 0.0 && (q_min_porod..=q_max_porod).contains(&q))
+        .map(|(&q, &i)| (q.ln(), i.ln()))
+        .unzip();
+    let distinct_q = ln_q.windows(2).any(|w| w[0] != w[1]);
+    if ln_q.len() < 2 || !distinct_q {
+        return Err(Error::InvalidArgument(format!(
+            "the power-law fit needs at least 2 distinct q values with q > 0 and I > 0 in [{}, {}], got {}",
+            q_min_porod,
+            q_max_porod,
+            ln_q.len()
+        )));
+    }
+    let (slope, intercept) = linear_fit(&ln_q, &ln_i);
+    Ok((intercept.exp(), slope))
+}
+
+/// Largest `extrapolated / total` fraction of [`porod_invariant_contributions`]
+/// for either extrapolation above which the invariant is dominated by the model
+/// tails rather than the data.
+pub const POROD_EXTRAPOLATION_WARNING_FRACTION: f64 = 0.2;
+
+/// `q_max_rg` of the [`guinier_fit`] behind the low-q extrapolation of
+/// [`porod_invariant_contributions`].
+const INVARIANT_GUINIER_MAX_Q_RG: f64 = 1.3;
+
+/// Fraction of the largest q above which [`porod_invariant_contributions`]
+/// averages `q^4 I(q)` for the Porod constant of the high-q extrapolation.
+const INVARIANT_POROD_TAIL: f64 = 0.8;
+
+/// Number of points of the trapezoid rule over the Guinier extrapolation.
+const INVARIANT_GUINIER_POINTS: usize = 1001;
+
+/// Contributions `(measured, low_q, high_q)` to the Porod invariant
+/// `Q* = integral_0^inf q^2 I(q) dq`.
+///
+/// `measured` is the trapezoid rule over the given q values. With
+/// `low_q_extrapolation`, `low_q` integrates the Guinier law
+/// `I_0 exp(-R_g^2 q^2 / 3)` of [`guinier_fit`] with `q_max_rg = 1.3` from 0
+/// to the lowest q; with `high_q_extrapolation`, `high_q = K_p / q_max`
+/// integrates the Porod law `K_p / q^4` from the largest q to infinity, with
+/// `K_p` the mean of `q^4 I(q)` over the points above `0.8 q_max`, which
+/// averages out the form-factor oscillations. A disabled extrapolation
+/// contributes 0. Fails if the lengths differ or if an enabled extrapolation
+/// cannot be fitted.
+pub fn porod_invariant_contributions(
+    q_values: &[f64],
+    intensities: &[f64],
+    low_q_extrapolation: bool,
+    high_q_extrapolation: bool,
+) -> Result<(f64, f64, f64)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let integrand: Vec<f64> = q_values
+        .iter()
+        .zip(intensities)
+        .map(|(&q, &i)| q * q * i)
+        .collect();
+    let measured = trapezoid(q_values, &integrand);
+    let q_min = q_values.iter().copied().fold(f64::INFINITY, f64::min);
+    let q_max = q_values.iter().copied().fold(0.0, f64::max);
+
+    let low_q = if low_q_extrapolation && q_min > 0.0 {
+        let (rg, i0) = guinier_fit(q_values, intensities, INVARIANT_GUINIER_MAX_Q_RG)?;
+        let step = q_min / (INVARIANT_GUINIER_POINTS - 1) as f64;
+        let (q_tail, tail): (Vec<f64>, Vec<f64>) = (0..INVARIANT_GUINIER_POINTS)
+            .map(|k| {
+                let q = k as f64 * step;
+                (q, q * q * i0 * (-rg * rg * q * q / 3.0).exp())
+            })
+            .unzip();
+        trapezoid(&q_tail, &tail)
+    } else {
+        0.0
+    };
+
+    let high_q = if high_q_extrapolation {
+        let plateau: Vec<f64> = q_values
+            .iter()
+            .zip(intensities)
+            .filter(|&(&q, _)| q > 0.0 && q >= INVARIANT_POROD_TAIL * q_max)
+            .map(|(&q, &i)| q.powi(4) * i)
+            .collect();
+        if plateau.len() < 2 {
+            return Err(Error::InvalidArgument(format!(
+                "the Porod extrapolation needs at least 2 points with q >= {} q_max, got {}",
+                INVARIANT_POROD_TAIL,
+                plateau.len()
+            )));
+        }
+        let porod_constant = plateau.iter().sum::<f64>() / plateau.len() as f64;
+        porod_constant / q_max
+    } else {
+        0.0
+    };
+    Ok((measured, low_q, high_q))
+}
+
+/// Porod invariant `Q* = integral_0^inf q^2 I(q) dq`, the sum of the
+/// [`porod_invariant_contributions`].
+///
+/// Without extrapolations the integral is truncated to the measured range, so
+/// `q_values` should extend from near zero well into the Porod region.
+pub fn porod_invariant(
+    q_values: &[f64],
+    intensities: &[f64],
+    low_q_extrapolation: bool,
+    high_q_extrapolation: bool,
+) -> Result<f64> {
+    let (measured, low_q, high_q) = porod_invariant_contributions(
+        q_values,
+        intensities,
+        low_q_extrapolation,
+        high_q_extrapolation,
+    )?;
+    Ok(measured + low_q + high_q)
+}
+
+/// Kratky representation `(q, q^2 I(q))`.
+pub fn kratky_transform(q_values: &[f64], intensities: &[f64]) -> Result<(Vec<f64>, Vec<f64>)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let kratky = q_values
+        .iter()
+        .zip(intensities)
+        .map(|(&q, &i)| q * q * i)
+        .collect();
+    Ok((q_values.to_vec(), kratky))
+}
+
+/// Position and height `(q_peak, peak_value)` of the maximum of a Kratky curve.
+///
+/// The parabola through the largest sample and its two neighbours is
+/// maximised, so the peak is located between grid points; a maximum at either
+/// end of the range is returned as is. For compact particles the Guinier
+/// approximation puts the peak at `q_peak * R_g = sqrt(3)`; for a homogeneous
+/// sphere the exact value is 1.612, which a 6 nm simple cubic sphere
+/// reproduces to 1e-4.
+pub fn kratky_peak(q_values: &[f64], kratky_intensities: &[f64]) -> Result<(f64, f64)> {
+    check_same_length(
+        "q_values",
+        q_values,
+        "kratky_intensities",
+        kratky_intensities,
+    )?;
+    let Some(k) = (0..kratky_intensities.len())
+        .max_by(|&a, &b| kratky_intensities[a].total_cmp(&kratky_intensities[b]))
+    else {
+        return Err(Error::InvalidArgument(
+            "the Kratky curve has no points".to_string(),
+        ));
+    };
+    if k == 0 || k + 1 == q_values.len() {
+        return Ok((q_values[k], kratky_intensities[k]));
+    }
+    Ok(parabolic_peak(
+        &q_values[k - 1..=k + 1],
+        &kratky_intensities[k - 1..=k + 1],
+    ))
+}
+
+/// Vertex `(x, y)` of the parabola through three points whose middle one is
+/// the largest, or the middle point itself if the parabola is not concave.
+pub(crate) fn parabolic_peak(x: &[f64], y: &[f64]) -> (f64, f64) {
+    let (x0, x1, x2) = (x[0], x[1], x[2]);
+    let (y0, y1, y2) = (y[0], y[1], y[2]);
+    // Newton form y = y0 + d1 (x - x0) + d2 (x - x0)(x - x1) of the parabola.
+    let d1 = (y1 - y0) / (x1 - x0);
+    let d2 = ((y2 - y1) / (x2 - x1) - d1) / (x2 - x0);
+    if d2 >= 0.0 {
+        return (x1, y1);
+    }
+    let x_peak = (x0 + x1) / 2.0 - d1 / (2.0 * d2);
+    let y_peak = y0 + d1 * (x_peak - x0) + d2 * (x_peak - x0) * (x_peak - x1);
+    (x_peak, y_peak)
+}
+
+/// Least-squares weights `1 / sigma^2` of the points of `values`, or 1 for
+/// every point without `sigma`.
+pub(crate) fn least_squares_weights(
+    values_name: &str,
+    values: &[f64],
+    sigma: Option<&[f64]>,
+) -> Result<Vec<f64>> {
+    match sigma {
+        Some(sigma) => {
+            check_same_length(values_name, values, "sigma", sigma)?;
+            if let Some(&bad) = sigma.iter().find(|&&s| s.is_nan() || s <= 0.0) {
+                return Err(Error::InvalidArgument(format!(
+                    "sigma must be positive, got {}",
+                    bad
+                )));
+            }
+            Ok(sigma.iter().map(|s| 1.0 / (s * s)).collect())
+        }
+        None => Ok(vec![1.0; values.len()]),
+    }
+}
+
+/// Closed-form least-squares line `y = slope * x + intercept`.
+pub(crate) fn linear_fit(x: &[f64], y: &[f64]) -> (f64, f64) {
+    let n = x.len() as f64;
+    let mean_x = x.iter().sum::<f64>() / n;
+    let mean_y = y.iter().sum::<f64>() / n;
+    let (sxy, sxx) = x.iter().zip(y).fold((0.0, 0.0), |(sxy, sxx), (&x, &y)| {
+        (
+            sxy + (x - mean_x) * (y - mean_y),
+            sxx + (x - mean_x).powi(2),
+        )
+    });
+    let slope = sxy / sxx;
+    (slope, mean_y - slope * mean_x)
+}
+
+/// Fail with [`Error::InvalidArgument`] unless `a` and `b` have the same length.
+pub(crate) fn check_same_length<T, U>(a_name: &str, a: &[T], b_name: &str, b: &[U]) -> Result<()> {
+    if a.len() != b.len() {
+        return Err(Error::InvalidArgument(format!(
+            "{} has {} values but {} has {}",
+            a_name,
+            a.len(),
+            b_name,
+            b.len()
+        )));
+    }
+    Ok(())
+}
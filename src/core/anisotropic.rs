@@ -0,0 +1,72 @@
+use rayon::prelude::*;
+
+use super::q_grid;
+
+/// Single-crystal intensity `I(q) = |sum_j exp(i q . r_j)|^2` on the 3D grid
+/// spanned by the `(min_q, max_q, q_step)` ranges of q_x, q_y and q_z.
+///
+/// Unlike [`dse_core`](super::dse_core), which is the orientation average,
+/// this is the pattern of the crystal in its given orientation, e.g. for
+/// oriented single-crystal or fibre diffraction. The result is indexed
+/// `[ix][iy][iz]` on the [`q_grid`] of each range and is computed in parallel
+/// over q_x slices. The cost is O(N * N_qx * N_qy * N_qz); along q_z the phase
+/// factors are advanced by one complex multiplication per atom and point, so
+/// only each `(q_x, q_y)` column needs trigonometric functions.
+pub fn dse_3d(
+    positions: &[[f64; 3]],
+    q_x_range: (f64, f64, f64),
+    q_y_range: (f64, f64, f64),
+    q_z_range: (f64, f64, f64),
+) -> Vec<Vec<Vec<f64>>> {
+    let grid = |(min_q, max_q, q_step): (f64, f64, f64)| q_grid(min_q, max_q, q_step);
+    let (q_y, q_z) = (grid(q_y_range), grid(q_z_range));
+    let q_z_step = q_z_range.2;
+    grid(q_x_range)
+        .par_iter()
+        .map(|&qx| {
+            q_y.iter()
+                .map(|&qy| {
+                    let Some(&qz_start) = q_z.first() else {
+                        return Vec::new();
+                    };
+                    let mut amplitude = vec![(0.0, 0.0); q_z.len()];
+                    for p in positions {
+                        let (mut im, mut re) = (qx * p[0] + qy * p[1] + qz_start * p[2]).sin_cos();
+                        let (step_im, step_re) = (q_z_step * p[2]).sin_cos();
+                        for a in amplitude.iter_mut() {
+                            a.0 += re;
+                            a.1 += im;
+                            (re, im) = (re * step_re - im * step_im, re * step_im + im * step_re);
+                        }
+                    }
+                    amplitude.iter().map(|(re, im)| re * re + im * im).collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Complex scattering amplitude `A(q) = sum_j exp(i q . r_j)` of the crystal
+/// in its given orientation, returned as `(Re A, Im A)`.
+///
+/// All atoms scatter with unit amplitude, so `|A|^2` is the [`dse_3d`]
+/// intensity at `q`. The phase is relative to the origin of the coordinates.
+/// Complex numbers are kept as `(re, im)` pairs, as in [`dse_3d`].
+pub fn structure_amplitude(positions: &[[f64; 3]], q: [f64; 3]) -> (f64, f64) {
+    positions.iter().fold((0.0, 0.0), |(re, im), p| {
+        let (sin, cos) = (q[0] * p[0] + q[1] * p[1] + q[2] * p[2]).sin_cos();
+        (re + cos, im + sin)
+    })
+}
+
+/// [`structure_amplitude`] at each of `q_vectors`, computed in parallel over
+/// the q vectors.
+pub fn structure_amplitude_batch(
+    positions: &[[f64; 3]],
+    q_vectors: &[[f64; 3]],
+) -> Vec<(f64, f64)> {
+    q_vectors
+        .par_iter()
+        .map(|&q| structure_amplitude(positions, q))
+        .collect()
+}
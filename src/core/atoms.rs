@@ -0,0 +1,78 @@
+/// Atom positions stored as one contiguous `[x0, y0, z0, x1, y1, z1, ...]` buffer.
+///
+/// The O(N^2) pair loops read this buffer sequentially instead of chasing one
+/// heap allocation per atom; see `BENCHMARKS.md` for measurements.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Atoms {
+    coords: Vec<f64>,
+}
+
+impl Atoms {
+    /// Create an empty set of atoms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty set of atoms with room for `n_atoms` positions.
+    pub fn with_capacity(n_atoms: usize) -> Self {
+        Self {
+            coords: Vec::with_capacity(3 * n_atoms),
+        }
+    }
+
+    /// Append an atom at `position`.
+    pub fn push(&mut self, position: [f64; 3]) {
+        self.coords.extend_from_slice(&position);
+    }
+
+    /// Number of atoms.
+    pub fn len(&self) -> usize {
+        self.coords.len() / 3
+    }
+
+    /// Whether there are no atoms.
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    /// Position of atom `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i >= self.len()`.
+    pub fn get(&self, i: usize) -> [f64; 3] {
+        self.positions()[i]
+    }
+
+    /// Iterate over the atom positions.
+    pub fn iter(&self) -> impl Iterator<Item = [f64; 3]> + '_ {
+        self.positions().iter().copied()
+    }
+
+    /// Atom positions as `[x, y, z]` triples.
+    pub fn positions(&self) -> &[[f64; 3]] {
+        self.coords.as_chunks().0
+    }
+
+    /// Flat coordinate buffer, three values per atom.
+    pub fn as_flat(&self) -> &[f64] {
+        &self.coords
+    }
+}
+
+impl FromIterator<[f64; 3]> for Atoms {
+    fn from_iter<I: IntoIterator<Item = [f64; 3]>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut atoms = Atoms::with_capacity(iter.size_hint().0);
+        for position in iter {
+            atoms.push(position);
+        }
+        atoms
+    }
+}
+
+impl From<&[[f64; 3]]> for Atoms {
+    fn from(positions: &[[f64; 3]]) -> Self {
+        positions.iter().copied().collect()
+    }
+}
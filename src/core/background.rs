@@ -0,0 +1,166 @@
+use super::analysis::{check_same_length, least_squares_weights, linear_fit};
+use super::{dse_core, porod_fit, q_grid, Error, Result};
+
+/// Polynomial of degree `degree` in q fitted by weighted least squares to the
+/// points outside `exclude_q_ranges`, evaluated at every q value.
+///
+/// The excluded `(q_low, q_high)` ranges, inclusive, should cover the signal,
+/// e.g. the Bragg peaks, so that only the background is fitted. The weights
+/// are `1 / sigma^2`, or 1 without `sigma`. The result can be passed to
+/// [`subtract_background`](super::subtract_background).
+///
+/// The fit is done in `t = (q - q_centre) / q_half_range` over the fitted
+/// points, which keeps the normal equations well conditioned for the low
+/// degrees that backgrounds need. Fails unless more than `degree` distinct q
+/// values remain after the exclusion.
+pub fn fit_polynomial_background(
+    q_values: &[f64],
+    intensities: &[f64],
+    degree: usize,
+    exclude_q_ranges: &[(f64, f64)],
+    sigma: Option<&[f64]>,
+) -> Result<Vec<f64>> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    let weights = least_squares_weights("intensities", intensities, sigma)?;
+    let fitted: Vec<(f64, f64, f64)> = q_values
+        .iter()
+        .zip(intensities)
+        .zip(&weights)
+        .filter(|&((&q, _), _)| {
+            !exclude_q_ranges
+                .iter()
+                .any(|&(low, high)| (low..=high).contains(&q))
+        })
+        .map(|((&q, &i), &w)| (q, i, w))
+        .collect();
+    let mut distinct_q: Vec<f64> = fitted.iter().map(|&(q, _, _)| q).collect();
+    distinct_q.sort_by(f64::total_cmp);
+    distinct_q.dedup();
+    if distinct_q.len() <= degree {
+        return Err(Error::InvalidArgument(format!(
+            "a degree {} background needs at least {} distinct q values outside the excluded \
+             ranges, got {}",
+            degree,
+            degree + 1,
+            distinct_q.len()
+        )));
+    }
+
+    let q_min = distinct_q[0];
+    let q_max = distinct_q[distinct_q.len() - 1];
+    let centre = (q_min + q_max) / 2.0;
+    let half_range = if q_max > q_min {
+        (q_max - q_min) / 2.0
+    } else {
+        1.0
+    };
+    let scaled = |q: f64| (q - centre) / half_range;
+
+    // Normal equations `A c = b` with `A_jk = sum w t^(j+k)`, `b_j = sum w t^j I`.
+    let size = degree + 1;
+    let mut matrix = vec![vec![0.0; size + 1]; size];
+    for &(q, i, w) in &fitted {
+        let t = scaled(q);
+        let powers: Vec<f64> = (0..size).map(|j| t.powi(j as i32)).collect();
+        for (j, row) in matrix.iter_mut().enumerate() {
+            for (k, &power) in powers.iter().enumerate() {
+                row[k] += w * powers[j] * power;
+            }
+            row[size] += w * powers[j] * i;
+        }
+    }
+    let coefficients = solve_augmented(matrix);
+
+    Ok(q_values
+        .iter()
+        .map(|&q| {
+            let t = scaled(q);
+            coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |value, &c| value * t + c)
+        })
+        .collect())
+}
+
+/// Power-law background `I_bg = A * q^n` fitted at `q >= q_low_cutoff`,
+/// where the signal has decayed, returning `(A, n)`.
+///
+/// This is [`porod_fit`] over `[q_low_cutoff, infinity)`: a straight line in
+/// `(ln q, ln I)` through the points with `q > 0` and `I > 0`. Evaluate
+/// `A * q^n` on the q grid to pass it to
+/// [`subtract_background`](super::subtract_background).
+pub fn fit_power_law_background(
+    q_values: &[f64],
+    intensities: &[f64],
+    q_low_cutoff: f64,
+) -> Result<(f64, f64)> {
+    porod_fit(q_values, intensities, q_low_cutoff, f64::INFINITY)
+}
+
+/// [`dse_core`] plus the background `incoherent_bg + thermal_bg_coeff * q^2`
+/// of incoherent (Compton, fluorescence) and thermal diffuse scattering.
+pub fn dse_with_background(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    incoherent_bg: f64,
+    thermal_bg_coeff: f64,
+) -> Vec<f64> {
+    q_grid(min_q, max_q, q_step)
+        .iter()
+        .zip(dse_core(min_q, max_q, q_step, positions))
+        .map(|(&q, i)| i + incoherent_bg + thermal_bg_coeff * q * q)
+        .collect()
+}
+
+/// Least-squares `(incoherent_bg, thermal_bg_coeff)` of the residuals
+/// `i_obs - i_model = incoherent_bg + thermal_bg_coeff * q^2`, the background
+/// terms of [`dse_with_background`].
+///
+/// Fails unless at least 2 distinct `q^2` values are given.
+pub fn fit_background_parameters(
+    q_values: &[f64],
+    i_obs: &[f64],
+    i_model: &[f64],
+) -> Result<(f64, f64)> {
+    check_same_length("q_values", q_values, "i_obs", i_obs)?;
+    check_same_length("i_obs", i_obs, "i_model", i_model)?;
+    let q_sq: Vec<f64> = q_values.iter().map(|q| q * q).collect();
+    if !q_sq.windows(2).any(|w| w[0] != w[1]) {
+        return Err(Error::InvalidArgument(format!(
+            "the background fit needs at least 2 distinct q^2 values, got {} points",
+            q_sq.len()
+        )));
+    }
+    let residuals: Vec<f64> = i_obs.iter().zip(i_model).map(|(o, m)| o - m).collect();
+    let (thermal_bg_coeff, incoherent_bg) = linear_fit(&q_sq, &residuals);
+    Ok((incoherent_bg, thermal_bg_coeff))
+}
+
+/// Solution of the square system in the augmented matrix `[A | b]` by
+/// Gaussian elimination with partial pivoting.
+pub(crate) fn solve_augmented(mut matrix: Vec<Vec<f64>>) -> Vec<f64> {
+    let size = matrix.len();
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+            .unwrap_or(column);
+        matrix.swap(column, pivot);
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for row in lower {
+            let factor = row[column] / pivot_row[column];
+            for (x, &p) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *x -= factor * p;
+            }
+        }
+    }
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        let tail: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (matrix[row][size] - tail) / matrix[row][row];
+    }
+    solution
+}
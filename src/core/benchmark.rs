@@ -0,0 +1,86 @@
+use std::time::Instant;
+
+use super::{
+    dse_at_q, dse_core, dse_no_alloc, dse_simd, dse_symmetric, dse_ultra_optimized, q_grid,
+    DistanceMatrix, Error, Result, DEFAULT_NO_ALLOC_CHUNK_SIZE,
+};
+
+/// Largest relative deviation from [`dse_core`] accepted by
+/// [`benchmark_dse`].
+pub const BENCHMARK_RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// Time every exact Debye implementation on the same input and check that
+/// they agree.
+///
+/// Returns `(name, mean seconds per call)` over `n_repeats` calls of each,
+/// named after their Python functions, with `dse_serial` for [`dse_core`]
+/// evaluated one q after the other. Every intensity must lie within
+/// [`BENCHMARK_RELATIVE_TOLERANCE`] of [`dse_core`], relative to the larger
+/// of the value and `N`, the large-q limit, so that the rounding noise at
+/// deep minima is not flagged. Fails with [`Error::Mismatch`] naming the
+/// first disagreeing implementation and q, or if `n_repeats` is 0.
+pub fn benchmark_dse(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    n_repeats: usize,
+) -> Result<Vec<(&'static str, f64)>> {
+    if n_repeats == 0 {
+        return Err(Error::InvalidArgument(
+            "n_repeats must be at least 1".to_string(),
+        ));
+    }
+    let q_values = q_grid(min_q, max_q, q_step);
+    let serial = || {
+        let distances = DistanceMatrix::from_atoms(positions);
+        q_values.iter().map(|&q| dse_at_q(q, &distances)).collect()
+    };
+    let no_alloc = || {
+        dse_no_alloc(min_q, max_q, q_step, positions, DEFAULT_NO_ALLOC_CHUNK_SIZE)
+            .expect("the default chunk size is positive")
+    };
+    let implementations: [(&'static str, &dyn Fn() -> Vec<f64>); 6] = [
+        ("dse_serial", &serial),
+        ("dse_optimized", &|| {
+            dse_core(min_q, max_q, q_step, positions)
+        }),
+        ("dse_ultra_optimized", &|| {
+            dse_ultra_optimized(min_q, max_q, q_step, positions)
+        }),
+        ("dse_symmetric", &|| {
+            dse_symmetric(min_q, max_q, q_step, positions)
+        }),
+        ("dse_no_alloc", &no_alloc),
+        ("dse_simd", &|| dse_simd(min_q, max_q, q_step, positions)),
+    ];
+
+    let reference = dse_core(min_q, max_q, q_step, positions);
+    let floor = positions.len().max(1) as f64;
+    let mut timings = Vec::with_capacity(implementations.len());
+    for (name, run) in implementations {
+        let start = Instant::now();
+        let intensities = run();
+        for _ in 1..n_repeats {
+            run();
+        }
+        let seconds = start.elapsed().as_secs_f64() / n_repeats as f64;
+        let mismatch =
+            intensities
+                .iter()
+                .zip(&reference)
+                .zip(&q_values)
+                .find(|((value, expected), _)| {
+                    let deviation = (*value - *expected).abs() / expected.abs().max(floor);
+                    deviation.is_nan() || deviation > BENCHMARK_RELATIVE_TOLERANCE
+                });
+        if let Some(((value, expected), q)) = mismatch {
+            return Err(Error::Mismatch(format!(
+                "{} gives {} at q = {}, where dse_optimized gives {}",
+                name, value, q, expected
+            )));
+        }
+        timings.push((name, seconds));
+    }
+    Ok(timings)
+}
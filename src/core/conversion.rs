@@ -0,0 +1,87 @@
+use std::f64::consts::PI;
+
+use super::analysis::check_same_length;
+use super::{Error, Result};
+
+fn check_wavelength(wavelength_nm: f64) -> Result<()> {
+    if wavelength_nm.is_nan() || wavelength_nm <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "wavelength_nm must be positive, got {}",
+            wavelength_nm
+        )));
+    }
+    Ok(())
+}
+
+/// Scattering angle `2theta = 2 asin(q lambda / (4 pi))` in degrees of each q
+/// in 1/nm, for radiation of wavelength `wavelength_nm`.
+///
+/// Fails unless `wavelength_nm > 0` and every q is in `[0, 4 pi / lambda]`;
+/// larger q are not reachable at this wavelength.
+pub fn q_to_twotheta(q_values: &[f64], wavelength_nm: f64) -> Result<Vec<f64>> {
+    check_wavelength(wavelength_nm)?;
+    let q_limit = 4.0 * PI / wavelength_nm;
+    q_values
+        .iter()
+        .map(|&q| {
+            if q.is_nan() || q < 0.0 || q > q_limit {
+                return Err(Error::InvalidArgument(format!(
+                    "q = {} is outside [0, {}], the q reachable at wavelength {} nm",
+                    q, q_limit, wavelength_nm
+                )));
+            }
+            Ok(2.0 * (q / q_limit).asin().to_degrees())
+        })
+        .collect()
+}
+
+/// `q = 4 pi sin(theta) / lambda` in 1/nm of each scattering angle `2theta`
+/// in degrees, the inverse of [`q_to_twotheta`] on `[0, 180]` degrees.
+///
+/// Fails unless `wavelength_nm > 0`.
+pub fn twotheta_to_q(twotheta_deg: &[f64], wavelength_nm: f64) -> Result<Vec<f64>> {
+    check_wavelength(wavelength_nm)?;
+    Ok(twotheta_deg
+        .iter()
+        .map(|&twotheta| 4.0 * PI * (twotheta / 2.0).to_radians().sin() / wavelength_nm)
+        .collect())
+}
+
+/// Linearly interpolate the curve `(q_in, i_in)` at each of `q_out`, e.g. to
+/// compare a computed intensity with data measured on another q grid.
+///
+/// Fails unless `q_in` and `i_in` have the same length, `q_in` has at least
+/// two strictly increasing values and every `q_out` lies within
+/// `[q_in[0], q_in[n - 1]]`; there is no extrapolation.
+pub fn resample_iq(q_in: &[f64], i_in: &[f64], q_out: &[f64]) -> Result<Vec<f64>> {
+    check_same_length("q_in", q_in, "i_in", i_in)?;
+    if q_in.len() < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "q_in must contain at least two values, got {}",
+            q_in.len()
+        )));
+    }
+    if q_in.windows(2).any(|w| w[1] <= w[0]) {
+        return Err(Error::InvalidArgument(
+            "q_in must be strictly increasing".to_string(),
+        ));
+    }
+    let (q_first, q_last) = (q_in[0], q_in[q_in.len() - 1]);
+    q_out
+        .iter()
+        .map(|&q| {
+            if !(q_first..=q_last).contains(&q) {
+                return Err(Error::InvalidArgument(format!(
+                    "q = {} is outside the input range [{}, {}]",
+                    q, q_first, q_last
+                )));
+            }
+            // Index of the upper end of the interval holding q, clamped so
+            // that q_last falls in the last interval.
+            let upper = q_in.partition_point(|&x| x < q).clamp(1, q_in.len() - 1);
+            let (q0, q1) = (q_in[upper - 1], q_in[upper]);
+            let t = (q - q0) / (q1 - q0);
+            Ok(i_in[upper - 1] + t * (i_in[upper] - i_in[upper - 1]))
+        })
+        .collect()
+}
@@ -0,0 +1,416 @@
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use super::dse::{checked_bin_count, sinc};
+use super::{q_grid, Error, Result};
+
+/// Number density `N / V` with `V` the volume of the axis-aligned bounding box
+/// of `positions`. Zero when the box is flat or there are no atoms.
+pub fn number_density(positions: &[[f64; 3]]) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+    let mut lower = [f64::INFINITY; 3];
+    let mut upper = [f64::NEG_INFINITY; 3];
+    for p in positions {
+        for axis in 0..3 {
+            lower[axis] = lower[axis].min(p[axis]);
+            upper[axis] = upper[axis].max(p[axis]);
+        }
+    }
+    let volume: f64 = (0..3).map(|axis| upper[axis] - lower[axis]).product();
+    if volume > 0.0 {
+        positions.len() as f64 / volume
+    } else {
+        0.0
+    }
+}
+
+/// Decimal places to which pair distances are rounded before grouping.
+const PAIR_DISTANCE_DECIMALS: i32 = 6;
+
+/// Sorted unique pair distances of `positions` and their multiplicities.
+///
+/// Distances are rounded to 6 decimal places (1e-6 nm) so that pairs that are
+/// equal up to floating-point noise are grouped. Multiplicities count ordered
+/// pairs `(i, j)`, so `I(q) = sum_k counts[k] * sinc(q * distances[k])` is the
+/// Debye sum of [`dse_core`](super::dse_core). With `include_self` the `N`
+/// self pairs appear as distance 0.
+pub fn compute_pair_distances(positions: &[[f64; 3]], include_self: bool) -> (Vec<f64>, Vec<u64>) {
+    let scale = 10f64.powi(PAIR_DISTANCE_DECIMALS);
+    let mut keys = Vec::with_capacity(positions.len() * positions.len().saturating_sub(1) / 2);
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            let r = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+            keys.push((r * scale).round() as u64);
+        }
+    }
+    keys.sort_unstable();
+
+    let mut distances = Vec::new();
+    let mut counts = Vec::new();
+    if include_self && !positions.is_empty() {
+        distances.push(0.0);
+        counts.push(positions.len() as u64);
+    }
+    for key in keys {
+        if distances.last() == Some(&(key as f64 / scale)) {
+            *counts.last_mut().unwrap() += 2;
+        } else {
+            distances.push(key as f64 / scale);
+            counts.push(2);
+        }
+    }
+    (distances, counts)
+}
+
+/// Number of other atoms within `cutoff` (inclusive) of each atom.
+///
+/// The atoms are processed in parallel, each against every other atom.
+pub fn coordination_numbers(positions: &[[f64; 3]], cutoff: f64) -> Result<Vec<usize>> {
+    if cutoff.is_nan() || cutoff < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "cutoff must be non-negative, got {}",
+            cutoff
+        )));
+    }
+    let cutoff_sq = cutoff * cutoff;
+    Ok(positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            positions
+                .iter()
+                .enumerate()
+                .filter(|&(j, b)| {
+                    let dist_sq =
+                        (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2);
+                    j != i && dist_sq <= cutoff_sq
+                })
+                .count()
+        })
+        .collect())
+}
+
+/// Mean of the [`coordination_numbers`]. Fails for an empty crystal.
+pub fn average_coordination(positions: &[[f64; 3]], cutoff: f64) -> Result<f64> {
+    if positions.is_empty() {
+        return Err(Error::InvalidArgument(
+            "the average coordination of an empty crystal is undefined".to_string(),
+        ));
+    }
+    let numbers = coordination_numbers(positions, cutoff)?;
+    Ok(numbers.iter().sum::<usize>() as f64 / numbers.len() as f64)
+}
+
+/// `(coordination_number, count)` pairs of the [`coordination_numbers`],
+/// sorted by coordination number; numbers that occur nowhere are omitted.
+pub fn coordination_histogram(positions: &[[f64; 3]], cutoff: f64) -> Result<Vec<(usize, usize)>> {
+    let mut numbers = coordination_numbers(positions, cutoff)?;
+    numbers.sort_unstable();
+    let mut histogram: Vec<(usize, usize)> = Vec::new();
+    for cn in numbers {
+        match histogram.last_mut() {
+            Some((last, count)) if *last == cn => *count += 1,
+            _ => histogram.push((cn, 1)),
+        }
+    }
+    Ok(histogram)
+}
+
+/// Number of bins of width `dr` from `r_min` to `r_max`. Fails unless
+/// `dr > 0` and `0 <= r_min < r_max < inf`, which no NaN satisfies, and with
+/// more than [`MAX_DISTANCE_BINS`](super::MAX_DISTANCE_BINS) bins.
+fn check_r_bins(r_min: f64, r_max: f64, dr: f64) -> Result<usize> {
+    if dr > 0.0 && r_min >= 0.0 && r_max > r_min && r_max.is_finite() {
+        checked_bin_count(((r_max - r_min) / dr).ceil())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "expected dr > 0 and 0 <= r_min < r_max < inf, got dr={}, r_min={}, r_max={}",
+            dr, r_min, r_max
+        )))
+    }
+}
+
+/// Radial distribution function `g(r)` of `positions` on bins of width `dr`
+/// between `r_min` and `r_max`.
+///
+/// The `i != j` pair distances are histogrammed and bin `k` is normalised by
+/// `4 pi r_k^2 dr rho N`, with `r_k` the bin centre and `rho` the
+/// [`number_density`]. Returns `(r_values, g_r)`. Fails unless `dr > 0`,
+/// `0 <= r_min < r_max < inf` and there are at most
+/// [`MAX_DISTANCE_BINS`](super::MAX_DISTANCE_BINS) bins, or for a flat crystal.
+pub fn radial_distribution_function(
+    positions: &[[f64; 3]],
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let n_bins = check_r_bins(r_min, r_max, dr)?;
+    let rho = number_density(positions);
+    if rho == 0.0 {
+        return Err(Error::InvalidArgument(
+            "the crystal has no volume; g(r) needs a three-dimensional crystal".to_string(),
+        ));
+    }
+
+    let mut histogram = vec![0u64; n_bins];
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            let r = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+            if r < r_min || r >= r_max {
+                continue;
+            }
+            let bin = ((r - r_min) / dr) as usize;
+            if let Some(count) = histogram.get_mut(bin) {
+                *count += 2;
+            }
+        }
+    }
+
+    let n_atoms = positions.len() as f64;
+    let r_values: Vec<f64> = (0..n_bins).map(|k| r_min + (k as f64 + 0.5) * dr).collect();
+    let g_r = r_values
+        .iter()
+        .zip(&histogram)
+        .map(|(&r, &count)| count as f64 / (4.0 * PI * r * r * dr * rho * n_atoms))
+        .collect();
+    Ok((r_values, g_r))
+}
+
+/// Partial radial distribution function `g_AB(r)` of the atoms of species
+/// `species_a` around those of `species_b`, on the bins of
+/// [`radial_distribution_function`].
+///
+/// The ordered pairs `(i, j)`, `i != j`, with atom `i` of species A and atom
+/// `j` of species B are histogrammed and bin `k` is normalised by
+/// `4 pi r_k^2 dr rho c_B N_A`, with `c_B = N_B / N` the concentration of B,
+/// so that `g(r) = sum_AB c_A c_B g_AB(r)`. Fails unless `species` has one
+/// label per atom and both species are present.
+pub fn partial_pair_correlation(
+    positions: &[[f64; 3]],
+    species: &[u32],
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    species_a: u32,
+    species_b: u32,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    super::analysis::check_same_length("positions", positions, "species", species)?;
+    let n_bins = check_r_bins(r_min, r_max, dr)?;
+    let members = |label: u32| -> Result<Vec<usize>> {
+        let indices: Vec<usize> = (0..species.len())
+            .filter(|&i| species[i] == label)
+            .collect();
+        if indices.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "no atom has species {}",
+                label
+            )));
+        }
+        Ok(indices)
+    };
+    let (atoms_a, atoms_b) = (members(species_a)?, members(species_b)?);
+    let rho = number_density(positions);
+    if rho == 0.0 {
+        return Err(Error::InvalidArgument(
+            "the crystal has no volume; g(r) needs a three-dimensional crystal".to_string(),
+        ));
+    }
+
+    let histogram = atoms_a
+        .par_iter()
+        .fold(
+            || vec![0u64; n_bins],
+            |mut histogram, &i| {
+                let a = positions[i];
+                for &j in atoms_b.iter().filter(|&&j| j != i) {
+                    let b = positions[j];
+                    let r = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2))
+                        .sqrt();
+                    if r < r_min || r >= r_max {
+                        continue;
+                    }
+                    if let Some(count) = histogram.get_mut(((r - r_min) / dr) as usize) {
+                        *count += 1;
+                    }
+                }
+                histogram
+            },
+        )
+        .reduce(
+            || vec![0u64; n_bins],
+            |mut total, partial| {
+                for (t, p) in total.iter_mut().zip(partial) {
+                    *t += p;
+                }
+                total
+            },
+        );
+
+    let concentration_b = atoms_b.len() as f64 / positions.len() as f64;
+    let n_a = atoms_a.len() as f64;
+    let r_values: Vec<f64> = (0..n_bins).map(|k| r_min + (k as f64 + 0.5) * dr).collect();
+    let g_r = r_values
+        .iter()
+        .zip(&histogram)
+        .map(|(&r, &count)| count as f64 / (4.0 * PI * r * r * dr * rho * concentration_b * n_a))
+        .collect();
+    Ok((r_values, g_r))
+}
+
+/// Debye intensity reconstructed from a radial distribution function,
+/// `I(q) = N * (1 + integral g(r) * sin(qr)/(qr) * 4 pi r^2 rho dr)`.
+///
+/// The integral is evaluated with the trapezoid rule over `r_values`; `rho`
+/// and `n_atoms` must be those used to normalise `g_r`. Returns
+/// `(q_values, intensities)` on the [`q_grid`].
+pub fn fourier_transform_to_iq(
+    r_values: &[f64],
+    g_r: &[f64],
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    rho: f64,
+    n_atoms: usize,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    super::analysis::check_same_length("r_values", r_values, "g_r", g_r)?;
+    let q_values = q_grid(min_q, max_q, q_step);
+    let n_atoms = n_atoms as f64;
+    let intensities = q_values
+        .iter()
+        .map(|&q| {
+            let integrand: Vec<f64> = r_values
+                .iter()
+                .zip(g_r)
+                .map(|(&r, &g)| g * sinc(q * r) * 4.0 * PI * r * r * rho)
+                .collect();
+            n_atoms * (1.0 + trapezoid(r_values, &integrand))
+        })
+        .collect();
+    Ok((q_values, intensities))
+}
+
+/// Lorch modification function `M(q) = sin(pi q / q_max) / (pi q / q_max)` at
+/// each q, which falls from 1 at `q = 0` to 0 at `q_max`.
+///
+/// Multiplying `F(q)` by it before the Fourier transform to `G(r)` damps the
+/// termination ripples caused by truncating the data at `q_max`, at the cost
+/// of broadening the peaks. Fails unless `q_max > 0`.
+pub fn lorch_modification_function(q_values: &[f64], q_max: f64) -> Result<Vec<f64>> {
+    if q_max.is_nan() || q_max <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "q_max must be positive, got {}",
+            q_max
+        )));
+    }
+    Ok(q_values.iter().map(|&q| sinc(PI * q / q_max)).collect())
+}
+
+/// Product of `intensities` and `modification` at each of `q_values`, e.g.
+/// with the [`lorch_modification_function`]. Fails unless the three have the
+/// same length.
+pub fn apply_modification_function(
+    q_values: &[f64],
+    intensities: &[f64],
+    modification: &[f64],
+) -> Result<Vec<f64>> {
+    super::analysis::check_same_length("q_values", q_values, "intensities", intensities)?;
+    super::analysis::check_same_length("q_values", q_values, "modification", modification)?;
+    Ok(intensities
+        .iter()
+        .zip(modification)
+        .map(|(i, m)| i * m)
+        .collect())
+}
+
+/// Reduced pair distribution function `G(r) = (2 / pi) integral_0^q_max
+/// q (S(q) - 1) M(q) sin(q r) dq` of a structure factor, returning
+/// `(r_values, g_r)`.
+///
+/// `structure_factor` is `S(q)`, which tends to 1 at high q, e.g. from
+/// [`structure_factor`](super::structure_factor), and `M` is the
+/// [`lorch_modification_function`] of `q_max`; points above `q_max` are left
+/// out. The integral is evaluated with the trapezoid rule over `q_values`;
+/// `G(r)` is sampled on the [`q_grid`] of `r_min`, `r_max` and `dr`. Fails
+/// unless `q_max > 0` and at least two q values lie in `[0, q_max]`.
+pub fn pdf_lorch_transform(
+    q_values: &[f64],
+    structure_factor: &[f64],
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    q_max: f64,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    super::analysis::check_same_length("q_values", q_values, "structure_factor", structure_factor)?;
+    check_r_bins(r_min, r_max, dr)?;
+    let (q_kept, f_kept): (Vec<f64>, Vec<f64>) = q_values
+        .iter()
+        .zip(structure_factor)
+        .filter(|&(&q, _)| (0.0..=q_max).contains(&q))
+        .map(|(&q, &s)| (q, q * (s - 1.0)))
+        .unzip();
+    let modification = lorch_modification_function(&q_kept, q_max)?;
+    if q_kept.len() < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "the transform needs at least two q values in [0, {}], got {}",
+            q_max,
+            q_kept.len()
+        )));
+    }
+    let modified_f = apply_modification_function(&q_kept, &f_kept, &modification)?;
+    let r_values = q_grid(r_min, r_max, dr);
+    let g_r = r_values
+        .iter()
+        .map(|&r| {
+            let integrand: Vec<f64> = q_kept
+                .iter()
+                .zip(&modified_f)
+                .map(|(&q, &f)| f * (q * r).sin())
+                .collect();
+            2.0 / PI * trapezoid(&q_kept, &integrand)
+        })
+        .collect();
+    Ok((r_values, g_r))
+}
+
+/// Reduced pair distribution function of a scattering curve of `n_atoms`
+/// atoms: the [`pdf_lorch_transform`] of `S(q) = I(q) / n_atoms` with `q_max`
+/// the largest of `q_values`, returning `(r_values, g_r)`.
+///
+/// `F(q) = q (S(q) - 1)` is the total scattering function, and the Lorch
+/// modification function damps the ripples caused by truncating the
+/// integral at the largest measured q.
+pub fn pdf_from_iq(
+    q_values: &[f64],
+    intensities: &[f64],
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    n_atoms: usize,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    super::analysis::check_same_length("q_values", q_values, "intensities", intensities)?;
+    if n_atoms == 0 {
+        return Err(Error::InvalidArgument(
+            "n_atoms must be at least 1".to_string(),
+        ));
+    }
+    let q_max = q_values.iter().copied().fold(0.0, f64::max);
+    if q_max <= 0.0 {
+        return Err(Error::InvalidArgument(
+            "the scattering curve has no q values above 0".to_string(),
+        ));
+    }
+    let n_atoms = n_atoms as f64;
+    let structure_factor: Vec<f64> = intensities.iter().map(|i| i / n_atoms).collect();
+    pdf_lorch_transform(q_values, &structure_factor, r_min, r_max, dr, q_max)
+}
+
+/// Trapezoid rule integral of `y(x)` over the sample points `x`.
+pub(crate) fn trapezoid(x: &[f64], y: &[f64]) -> f64 {
+    x.windows(2)
+        .zip(y.windows(2))
+        .map(|(x, y)| 0.5 * (x[1] - x[0]) * (y[0] + y[1]))
+        .sum()
+}
@@ -0,0 +1,991 @@
+use std::iter;
+
+use super::{Atoms, Error, Matrix3, Result};
+
+/// Fractional basis of the conventional simple cubic cell.
+const SC_BASIS: [[f64; 3]; 1] = [[0.0, 0.0, 0.0]];
+
+/// Fractional basis of the conventional face-centred cubic cell.
+const FCC_BASIS: [[f64; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [0.5, 0.5, 0.0],
+    [0.5, 0.0, 0.5],
+    [0.0, 0.5, 0.5],
+];
+
+/// Fractional basis of the conventional body-centred cubic cell.
+const BCC_BASIS: [[f64; 3]; 2] = [[0.0, 0.0, 0.0], [0.5, 0.5, 0.5]];
+
+/// Ideal c/a ratio of a hexagonal close-packed lattice, sqrt(8/3).
+pub const HCP_IDEAL_C_OVER_A: f64 = 1.632_993_161_855_452;
+
+/// Shape and lattice of a crystal generated by [`crystal_core`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// Simple cubic lattice filling a cube of side `length`.
+    Cube,
+    /// Simple cubic lattice inside a sphere of diameter `length`.
+    Sphere,
+    /// Face-centred cubic lattice filling a cube of side `length`.
+    Fcc,
+    /// Body-centred cubic lattice filling a cube of side `length`.
+    Bcc,
+    /// Hexagonal close-packed lattice filling a cube of side `length`.
+    Hcp {
+        /// Ratio of the lattice height c to the in-plane parameter a.
+        c_over_a: f64,
+    },
+    /// Simple cubic lattice inside an upright cylinder of diameter `length`.
+    Cylinder {
+        /// Height of the cylinder divided by its diameter.
+        aspect_ratio: f64,
+    },
+    /// Simple cubic lattice inside a flat cylinder of diameter `length`.
+    Disk {
+        /// Height of the disk divided by its diameter, below 1.
+        aspect_ratio: f64,
+    },
+    /// Simple cubic lattice inside an ellipsoid with semi-axes `a`, `b`, `c`
+    /// along x, y, z; `length` is not used. See [`crystal_ellipsoid`].
+    Ellipsoid { a: f64, b: f64, c: f64 },
+    /// Mackay icosahedron with `num_shells` closed shells around a central
+    /// atom; `length` is not used. See [`crystal_icosahedron`].
+    Icosahedron { num_shells: usize },
+    /// Face-centred cubic truncated octahedron of edge length `length`. See
+    /// [`crystal_truncated_octahedron`].
+    TruncatedOctahedron,
+}
+
+impl Shape {
+    /// Whether the size of the crystal is set by the `length` argument of
+    /// [`crystal_core`], which is not the case for [`Shape::Ellipsoid`] and
+    /// [`Shape::Icosahedron`].
+    pub(crate) fn is_sized_by_length(self) -> bool {
+        !matches!(self, Shape::Ellipsoid { .. } | Shape::Icosahedron { .. })
+    }
+}
+
+/// Cubic Bravais lattice of a [`crystal_slab`], which also fixes the
+/// reflections allowed by [`assign_miller`](super::assign_miller).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubicLattice {
+    /// Simple cubic: every `hkl` is allowed.
+    Simple,
+    /// Face-centred cubic: `h`, `k`, `l` all even or all odd.
+    FaceCentred,
+    /// Body-centred cubic: `h + k + l` even.
+    BodyCentred,
+}
+
+impl CubicLattice {
+    /// Whether the reflection `hkl` has a non-zero structure factor.
+    pub fn allows(self, [h, k, l]: [i32; 3]) -> bool {
+        match self {
+            CubicLattice::Simple => true,
+            CubicLattice::FaceCentred => h % 2 == k % 2 && k % 2 == l % 2,
+            CubicLattice::BodyCentred => (h + k + l) % 2 == 0,
+        }
+    }
+
+    /// Fractional basis of the conventional cubic cell.
+    fn basis(self) -> &'static [[f64; 3]] {
+        match self {
+            CubicLattice::Simple => &SC_BASIS,
+            CubicLattice::FaceCentred => &FCC_BASIS,
+            CubicLattice::BodyCentred => &BCC_BASIS,
+        }
+    }
+}
+
+/// Generate the lattice points of a crystal.
+///
+/// `lattice_param` is the spacing between lattice points (the in-plane
+/// parameter a for [`Shape::Hcp`]) and `length` the size of the crystal,
+/// both in nanometers. Fails unless `lattice_param` is positive.
+pub fn crystal_core(shape: Shape, lattice_param: f64, length: f64) -> Result<Atoms> {
+    if lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param must be positive, got {}",
+            lattice_param
+        )));
+    }
+    let length_step: usize = (length / lattice_param).floor() as usize;
+    let crystal = match shape {
+        Shape::Cube => cubic_lattice(lattice_param, [length_step; 3], &SC_BASIS),
+        Shape::Sphere => {
+            let radius = length / 2.0;
+            cubic_lattice(lattice_param, [length_step; 3], &SC_BASIS)
+                .positions()
+                .iter()
+                .copied()
+                .filter(|point| {
+                    let distance = (point[0] - radius).powi(2)
+                        + (point[1] - radius).powi(2)
+                        + (point[2] - radius).powi(2);
+                    distance <= radius.powi(2)
+                })
+                .collect()
+        }
+        Shape::Fcc => cubic_lattice(lattice_param, [length_step; 3], &FCC_BASIS),
+        Shape::Bcc => cubic_lattice(lattice_param, [length_step; 3], &BCC_BASIS),
+        Shape::Hcp { c_over_a } => {
+            if c_over_a.is_nan() || c_over_a <= 0.0 {
+                return Err(Error::InvalidArgument(format!(
+                    "c_over_a must be positive, got {}",
+                    c_over_a
+                )));
+            }
+            hcp_lattice(lattice_param, c_over_a * lattice_param, length)
+        }
+        Shape::Cylinder { aspect_ratio } => {
+            if aspect_ratio.is_nan() || aspect_ratio <= 0.0 {
+                return Err(Error::InvalidArgument(format!(
+                    "aspect_ratio must be positive, got {}",
+                    aspect_ratio
+                )));
+            }
+            crystal_cylinder(lattice_param, length, aspect_ratio * length)?
+        }
+        Shape::Disk { aspect_ratio } => {
+            if aspect_ratio.is_nan() || aspect_ratio <= 0.0 || aspect_ratio >= 1.0 {
+                return Err(Error::InvalidArgument(format!(
+                    "aspect_ratio of a disk must be in (0, 1), got {}",
+                    aspect_ratio
+                )));
+            }
+            crystal_cylinder(lattice_param, length, aspect_ratio * length)?
+        }
+        Shape::Ellipsoid { a, b, c } => crystal_ellipsoid(lattice_param, a, b, c)?,
+        Shape::Icosahedron { num_shells } => crystal_icosahedron(lattice_param, num_shells),
+        Shape::TruncatedOctahedron => crystal_truncated_octahedron(lattice_param, length)?,
+    };
+    Ok(crystal)
+}
+
+/// Simple cubic lattice points inside an upright cylinder of `diameter` and
+/// `height`, both in nanometers.
+///
+/// The cylinder axis is `(diameter/2, diameter/2, z)`; a point is kept when
+/// `(x - d/2)^2 + (y - d/2)^2 <= (d/2)^2` and `0 <= z <= height`.
+pub fn crystal_cylinder(lattice_param: f64, diameter: f64, height: f64) -> Result<Atoms> {
+    if diameter.is_nan() || diameter <= 0.0 || height.is_nan() || height <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "diameter and height must be positive, got {} and {}",
+            diameter, height
+        )));
+    }
+    let radius = diameter / 2.0;
+    let radial_steps = (diameter / lattice_param).floor() as usize;
+    let height_steps = (height / lattice_param).floor() as usize;
+
+    let mut crystal = Atoms::new();
+    for i in 0..=radial_steps {
+        for j in 0..=radial_steps {
+            let x = i as f64 * lattice_param;
+            let y = j as f64 * lattice_param;
+            if (x - radius).powi(2) + (y - radius).powi(2) > radius.powi(2) {
+                continue;
+            }
+            for k in 0..=height_steps {
+                crystal.push([x, y, k as f64 * lattice_param]);
+            }
+        }
+    }
+    Ok(crystal)
+}
+
+/// Simple cubic lattice points inside a nanorod: a cylinder of `diameter`
+/// capped by two hemispheres of radius `diameter / 2`, `total_length` long
+/// overall, all in nanometers.
+///
+/// The rod axis is `(d/2, d/2, z)` with `0 <= z <= total_length`. Between the
+/// cap centres at `z = d/2` and `z = total_length - d/2` a point is kept when
+/// its distance from the axis is at most `d/2`; beyond them, when its distance
+/// from the nearer cap centre is. Fails unless `diameter > 0` and
+/// `total_length > diameter`.
+pub fn crystal_nanorod(lattice_param: f64, diameter: f64, total_length: f64) -> Result<Atoms> {
+    if diameter.is_nan() || diameter <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "diameter must be positive, got {}",
+            diameter
+        )));
+    }
+    if total_length.is_nan() || total_length <= diameter {
+        return Err(Error::InvalidArgument(format!(
+            "total_length must exceed the diameter {}, got {}",
+            diameter, total_length
+        )));
+    }
+    let radius = diameter / 2.0;
+    let (bottom_cap, top_cap) = (radius, total_length - radius);
+    let radial_steps = (diameter / lattice_param).floor() as usize;
+    let length_steps = (total_length / lattice_param).floor() as usize;
+
+    let mut crystal = Atoms::new();
+    for i in 0..=radial_steps {
+        for j in 0..=radial_steps {
+            let x = i as f64 * lattice_param;
+            let y = j as f64 * lattice_param;
+            let r_perp_sq = (x - radius).powi(2) + (y - radius).powi(2);
+            if r_perp_sq > radius.powi(2) {
+                continue;
+            }
+            for k in 0..=length_steps {
+                let z = k as f64 * lattice_param;
+                // Axial distance beyond the nearer cap centre, 0 in the body.
+                let dz = (bottom_cap - z).max(z - top_cap).max(0.0);
+                if r_perp_sq + dz * dz <= radius.powi(2) {
+                    crystal.push([x, y, z]);
+                }
+            }
+        }
+    }
+    Ok(crystal)
+}
+
+/// Slab of `width x height x thickness` nanometers along x, y and z, filled
+/// with conventional cells of `lattice`.
+///
+/// As for the cubes of [`crystal_core`], `floor(dimension / lattice_param)`
+/// cells are tiled along each axis starting at the origin. A slab much
+/// thinner in z than in x and y scatters anisotropically, e.g. in
+/// [`dse_3d`](super::dse_3d) for grazing-incidence geometries.
+pub fn crystal_slab(
+    lattice_param: f64,
+    width: f64,
+    height: f64,
+    thickness: f64,
+    lattice: CubicLattice,
+) -> Result<Atoms> {
+    if [width, height, thickness]
+        .iter()
+        .any(|d| d.is_nan() || *d <= 0.0)
+    {
+        return Err(Error::InvalidArgument(format!(
+            "width, height and thickness must be positive, got {}, {} and {}",
+            width, height, thickness
+        )));
+    }
+    let cells = [width, height, thickness].map(|d| (d / lattice_param).floor() as usize);
+    Ok(cubic_lattice(lattice_param, cells, lattice.basis()))
+}
+
+/// [`crystal_slab`] whose outermost `n_relaxed_layers` atomic layers on both
+/// z surfaces move inward, modelling surface relaxation.
+///
+/// Each spacing between two of the outermost `n_relaxed_layers + 1` layers on
+/// a surface is scaled by `1 - relaxation_fraction`, so the relaxed layers
+/// move towards the fixed layer below them; a negative fraction expands the
+/// surface instead. Layers are the distinct z planes, `lattice_param / 2`
+/// apart for FCC and BCC. Fails unless `relaxation_fraction < 1` and the slab
+/// has more than `2 * n_relaxed_layers` layers, so that the two relaxed
+/// regions do not meet.
+pub fn crystal_slab_with_surface_relaxation(
+    lattice_param: f64,
+    width: f64,
+    height: f64,
+    thickness: f64,
+    lattice: CubicLattice,
+    n_relaxed_layers: usize,
+    relaxation_fraction: f64,
+) -> Result<Atoms> {
+    if !relaxation_fraction.is_finite() || relaxation_fraction >= 1.0 {
+        return Err(Error::InvalidArgument(format!(
+            "relaxation_fraction must be finite and below 1, got {}",
+            relaxation_fraction
+        )));
+    }
+    let slab = crystal_slab(lattice_param, width, height, thickness, lattice)?;
+    let mut layers: Vec<f64> = slab.positions().iter().map(|p| p[2]).collect();
+    layers.sort_by(f64::total_cmp);
+    layers.dedup();
+    let n = n_relaxed_layers;
+    if layers.len() <= 2 * n {
+        return Err(Error::InvalidArgument(format!(
+            "a slab with {} layers cannot relax {} layers on each surface",
+            layers.len(),
+            n
+        )));
+    }
+    if n == 0 {
+        return Ok(slab);
+    }
+
+    let scale = 1.0 - relaxation_fraction;
+    let (bottom_fixed, top_fixed) = (layers[n], layers[layers.len() - 1 - n]);
+    Ok(slab
+        .positions()
+        .iter()
+        .map(|&[x, y, z]| {
+            let z = if z < bottom_fixed {
+                bottom_fixed - (bottom_fixed - z) * scale
+            } else if z > top_fixed {
+                top_fixed + (z - top_fixed) * scale
+            } else {
+                z
+            };
+            [x, y, z]
+        })
+        .collect())
+}
+
+/// Simple cubic lattice points inside an ellipsoid with semi-axes `a`, `b`
+/// and `c` (nanometers) along x, y and z.
+///
+/// The ellipsoid is centred at `(a, b, c)` and a point is kept when
+/// `(x - a)^2/a^2 + (y - b)^2/b^2 + (z - c)^2/c^2 <= 1`. The lattice is
+/// scanned over a cube of side `2 * max(a, b, c)`, so `a = b = c = radius`
+/// reproduces [`Shape::Sphere`] with `length = 2 * radius`. For very
+/// asymmetric aspect ratios the scan, and the memory needed to build it, is
+/// dominated by the longest axis.
+pub fn crystal_ellipsoid(lattice_param: f64, a: f64, b: f64, c: f64) -> Result<Atoms> {
+    if [a, b, c].iter().any(|x| x.is_nan() || *x <= 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "semi-axes must be positive, got ({}, {}, {})",
+            a, b, c
+        )));
+    }
+    let box_length = 2.0 * a.max(b).max(c);
+    let length_step = (box_length / lattice_param).floor() as usize;
+    Ok(cubic_lattice(lattice_param, [length_step; 3], &SC_BASIS)
+        .positions()
+        .iter()
+        .copied()
+        .filter(|point| {
+            ((point[0] - a) / a).powi(2)
+                + ((point[1] - b) / b).powi(2)
+                + ((point[2] - c) / c).powi(2)
+                <= 1.0
+        })
+        .collect())
+}
+
+/// Simple cubic lattice points inside a spherical shell with `inner_radius`
+/// and `outer_radius` (nanometers).
+///
+/// The shell is centred at `(R, R, R)` with `R = outer_radius`, and a point is
+/// kept when `inner_radius^2 <= |p - centre|^2 <= outer_radius^2`, so it is
+/// [`Shape::Sphere`] with `length = 2 * outer_radius` minus the inner core.
+pub fn crystal_hollow_sphere(
+    lattice_param: f64,
+    inner_radius: f64,
+    outer_radius: f64,
+) -> Result<Atoms> {
+    if inner_radius.is_nan()
+        || inner_radius <= 0.0
+        || outer_radius.is_nan()
+        || outer_radius <= inner_radius
+    {
+        return Err(Error::InvalidArgument(format!(
+            "expected 0 < inner_radius < outer_radius, got {} and {}",
+            inner_radius, outer_radius
+        )));
+    }
+    let length_step = (2.0 * outer_radius / lattice_param).floor() as usize;
+    Ok(cubic_lattice(lattice_param, [length_step; 3], &SC_BASIS)
+        .positions()
+        .iter()
+        .copied()
+        .filter(|point| {
+            let distance = (point[0] - outer_radius).powi(2)
+                + (point[1] - outer_radius).powi(2)
+                + (point[2] - outer_radius).powi(2);
+            inner_radius.powi(2) <= distance && distance <= outer_radius.powi(2)
+        })
+        .collect())
+}
+
+/// Core-shell nanoparticle of two face-centred cubic materials, returned with
+/// the species of each atom: 0 for the core and 1 for the shell.
+///
+/// Both lattices are centred on the particle centre `(R, R, R)`, with
+/// `R = core_radius + shell_thickness`, so that each has an atom there. The
+/// core lattice of `core_lattice_param` fills `|p - centre| <= core_radius`
+/// and the shell lattice of `shell_lattice_param` the shell
+/// `core_radius < |p - centre| <= R`. The two lattices are independent, so
+/// with different parameters the interface is incoherent and shell atoms can
+/// sit closer than a nearest-neighbour distance to core atoms. Fails unless
+/// both lattice parameters and `core_radius` are positive and
+/// `shell_thickness >= 0`.
+pub fn crystal_core_shell(
+    core_lattice_param: f64,
+    shell_lattice_param: f64,
+    core_radius: f64,
+    shell_thickness: f64,
+) -> Result<(Atoms, Vec<u32>)> {
+    for (name, value) in [
+        ("core_lattice_param", core_lattice_param),
+        ("shell_lattice_param", shell_lattice_param),
+        ("core_radius", core_radius),
+    ] {
+        if value.is_nan() || value <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be positive, got {}",
+                name, value
+            )));
+        }
+    }
+    if shell_thickness.is_nan() || shell_thickness < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "shell_thickness must be non-negative, got {}",
+            shell_thickness
+        )));
+    }
+    let radius = core_radius + shell_thickness;
+    let centre = [radius; 3];
+    // FCC points of `lattice_param` with an atom at the centre and a squared
+    // distance from it in `(min_sq, max_sq]`.
+    let centred_fcc = |lattice_param: f64, min_sq: f64, max_sq: f64| {
+        let half_cells = (radius / lattice_param).ceil() as usize;
+        let offset = half_cells as f64 * lattice_param;
+        cubic_lattice(lattice_param, [2 * half_cells + 1; 3], &FCC_BASIS)
+            .positions()
+            .iter()
+            .map(|p| [0, 1, 2].map(|axis| p[axis] - offset + centre[axis]))
+            .filter(|p| {
+                let distance = (0..3).map(|axis| (p[axis] - centre[axis]).powi(2)).sum();
+                min_sq < distance && distance <= max_sq
+            })
+            .collect::<Vec<[f64; 3]>>()
+    };
+    let core = centred_fcc(core_lattice_param, f64::NEG_INFINITY, core_radius.powi(2));
+    let shell = centred_fcc(shell_lattice_param, core_radius.powi(2), radius.powi(2));
+    let species = iter::repeat_n(0, core.len())
+        .chain(iter::repeat_n(1, shell.len()))
+        .collect();
+    Ok((core.into_iter().chain(shell).collect(), species))
+}
+
+/// Mackay icosahedron built shell by shell around a central atom.
+///
+/// Shell `k` places atoms on the 12 vertices of an icosahedron scaled by `k`,
+/// `k - 1` atoms along each of its 30 edges and a triangular patch of
+/// `(k - 1)(k - 2) / 2` atoms inside each of its 20 faces, i.e. `10k^2 + 2`
+/// atoms. The totals for 0, 1, 2, 3, 4 shells are 1, 13, 55, 147, 309.
+///
+/// Atoms are spaced by the FCC nearest-neighbour distance
+/// `lattice_param / sqrt(2)` within each shell; the radial spacing is about 5%
+/// shorter, as in the Mackay structure. The icosahedron is centred at the
+/// origin.
+pub fn crystal_icosahedron(lattice_param: f64, num_shells: usize) -> Atoms {
+    let vertices = icosahedron_vertices();
+    let edge_length = 2.0;
+    let is_edge = |a: usize, b: usize| {
+        let d_sq: f64 = (0..3)
+            .map(|axis| (vertices[a][axis] - vertices[b][axis]).powi(2))
+            .sum();
+        (d_sq.sqrt() - edge_length).abs() < 1e-9
+    };
+    let mut edges = Vec::with_capacity(30);
+    let mut faces = Vec::with_capacity(20);
+    for a in 0..12 {
+        for b in a + 1..12 {
+            if !is_edge(a, b) {
+                continue;
+            }
+            edges.push((a, b));
+            for c in b + 1..12 {
+                if is_edge(a, c) && is_edge(b, c) {
+                    faces.push((a, b, c));
+                }
+            }
+        }
+    }
+
+    // Atoms sit two units apart along the edges of the unit-shell icosahedron.
+    let scale = lattice_param / std::f64::consts::SQRT_2 / edge_length;
+    let point = |origin: usize, steps: &[(usize, f64)], k: f64| {
+        let mut p = [0.0; 3];
+        for (axis, p_axis) in p.iter_mut().enumerate() {
+            *p_axis = k * vertices[origin][axis];
+            for &(towards, t) in steps {
+                *p_axis += t * (vertices[towards][axis] - vertices[origin][axis]);
+            }
+            *p_axis *= scale;
+        }
+        p
+    };
+
+    let mut crystal =
+        Atoms::with_capacity(1 + (1..=num_shells).map(|k| 10 * k * k + 2).sum::<usize>());
+    crystal.push([0.0; 3]);
+    for k in 1..=num_shells {
+        let kf = k as f64;
+        for v in 0..12 {
+            crystal.push(point(v, &[], kf));
+        }
+        for &(a, b) in &edges {
+            for t in 1..k {
+                crystal.push(point(a, &[(b, t as f64)], kf));
+            }
+        }
+        for &(a, b, c) in &faces {
+            for i in 1..k {
+                for j in 1..k - i {
+                    crystal.push(point(a, &[(b, i as f64), (c, j as f64)], kf));
+                }
+            }
+        }
+    }
+    crystal
+}
+
+/// Cuboctahedron of the FCC lattice points around a central atom.
+///
+/// In units of half the lattice parameter the FCC points are the integer
+/// triples with an even sum, and shell `k` holds those with
+/// `max(|x|, |y|, |z|) <= k` and `|x| + |y| + |z| <= 2k` that are not in shell
+/// `k - 1`: the eight {111} and six {100} facets of the cuboctahedron, with
+/// `lattice_param / sqrt(2)` spacing along its edges. Shell `k` adds
+/// `10k^2 + 2` atoms, so `n_shells` shells hold
+/// `(10n^3 + 15n^2 + 11n + 3) / 3` atoms, the magic numbers 1, 13, 55, 147,
+/// 309, 561 for 0 to 5 shells. Atoms are ordered shell by shell and the
+/// particle is centred at the origin.
+pub fn crystal_cuboctahedron(lattice_param: f64, n_shells: usize) -> Atoms {
+    let n = n_shells as i64;
+    let half = lattice_param / 2.0;
+    let mut points: Vec<(i64, [i64; 3])> = Vec::new();
+    for x in -n..=n {
+        for y in -n..=n {
+            for z in -n..=n {
+                let taxicab = x.abs() + y.abs() + z.abs();
+                if taxicab % 2 != 0 || taxicab > 2 * n {
+                    continue;
+                }
+                let shell = x.abs().max(y.abs()).max(z.abs()).max(taxicab / 2);
+                points.push((shell, [x, y, z]));
+            }
+        }
+    }
+    points.sort_by_key(|&(shell, _)| shell);
+    points
+        .into_iter()
+        .map(|(_, point)| point.map(|c| c as f64 * half))
+        .collect()
+}
+
+/// Regular truncated octahedron of the FCC lattice, centred at the origin:
+/// the Wulff shape of an FCC metal with equal {100} and {111} surface
+/// energies.
+///
+/// In units of half the lattice parameter the particle holds the FCC points
+/// with `|x| + |y| + |z| <= 3s` (the eight hexagonal {111} facets) and
+/// `max(|x|, |y|, |z|) <= 2s` (the six square {100} facets), where every edge
+/// is `s` nearest-neighbour distances `lattice_param / sqrt(2)` long and
+/// `s = edge_length sqrt(2) / lattice_param` rounded to the nearest integer.
+/// For even `s` the centre is an atom, for odd `s` an octahedral hole, which
+/// keeps the facets on lattice planes. Edges of 1 to 5 spacings give the
+/// magic numbers 38, 201, 586, 1289 and 2406. Fails unless `lattice_param`
+/// is positive and `edge_length` is at least half a spacing.
+pub fn crystal_truncated_octahedron(lattice_param: f64, edge_length: f64) -> Result<Atoms> {
+    if lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param must be positive, got {}",
+            lattice_param
+        )));
+    }
+    let spacing = lattice_param / std::f64::consts::SQRT_2;
+    let spacings = (edge_length / spacing).round();
+    if spacings.is_nan() || spacings < 1.0 {
+        return Err(Error::InvalidArgument(format!(
+            "edge_length must be at least half the nearest-neighbour distance {}, got {}",
+            spacing / 2.0,
+            edge_length
+        )));
+    }
+    let s = spacings as i64;
+    let half = lattice_param / 2.0;
+    let mut crystal = Atoms::new();
+    for x in -2 * s..=2 * s {
+        for y in -2 * s..=2 * s {
+            for z in -2 * s..=2 * s {
+                let taxicab = x.abs() + y.abs() + z.abs();
+                if (taxicab + s) % 2 == 0 && taxicab <= 3 * s {
+                    crystal.push([x, y, z].map(|c| c as f64 * half));
+                }
+            }
+        }
+    }
+    Ok(crystal)
+}
+
+/// Relative tolerance for FCC points lying exactly on a Wulff facet.
+const WULFF_FACET_TOLERANCE: f64 = 1e-9;
+
+/// FCC nanoparticle with the equilibrium shape given by the Wulff
+/// construction, centred at the origin.
+///
+/// Each Miller index of `hkl_list` stands for its family of facets under the
+/// cubic point group, e.g. `[1, 0, 0]` for the six {100} facets. A facet of
+/// surface energy `gamma` lies at the distance `gamma / gamma_min * size / 2`
+/// from the centre, with `gamma_min` the smallest of the `surface_energies`,
+/// so `size` is the distance between opposite facets of the lowest-energy
+/// family and the units of the energies do not matter. The particle holds the
+/// FCC lattice points inside every facet; families whose facets lie entirely
+/// outside the others do not appear.
+///
+/// Fails unless `hkl_list` and `surface_energies` are non-empty and of the
+/// same length, every index is non-zero, and the energies and `size` are
+/// positive.
+pub fn crystal_wulff(
+    lattice_param: f64,
+    hkl_list: &[[i32; 3]],
+    surface_energies: &[f64],
+    size: f64,
+) -> Result<Atoms> {
+    super::analysis::check_same_length("hkl_list", hkl_list, "surface_energies", surface_energies)?;
+    if hkl_list.is_empty() {
+        return Err(Error::InvalidArgument(
+            "the Wulff construction needs at least one facet".to_string(),
+        ));
+    }
+    if let Some(hkl) = hkl_list.iter().find(|hkl| **hkl == [0, 0, 0]) {
+        return Err(Error::InvalidArgument(format!(
+            "Miller index {:?} does not define a facet",
+            hkl
+        )));
+    }
+    if let Some(&gamma) = surface_energies.iter().find(|&&g| g.is_nan() || g <= 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "surface energies must be positive, got {}",
+            gamma
+        )));
+    }
+    if size.is_nan() || size <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "size must be positive, got {}",
+            size
+        )));
+    }
+
+    let gamma_min = surface_energies
+        .iter()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    // Constraints n . r <= d * |n| of every symmetry-equivalent facet normal n.
+    let mut facets: Vec<([f64; 3], f64)> = Vec::new();
+    let mut half_width = f64::INFINITY;
+    for (hkl, &gamma) in hkl_list.iter().zip(surface_energies) {
+        let distance = gamma / gamma_min * size / 2.0;
+        let norm = hkl.iter().map(|&c| (c as f64).powi(2)).sum::<f64>().sqrt();
+        let largest = hkl.iter().map(|c| c.abs()).max().unwrap_or(0) as f64;
+        // Along each axis the facet with the largest index component on that
+        // axis bounds the particle to |x| <= distance * |n| / largest.
+        half_width = half_width.min(distance * norm / largest);
+        for normal in cubic_equivalents(*hkl) {
+            facets.push((normal.map(|c| c as f64), distance * norm));
+        }
+    }
+
+    let n = (2.0 * half_width / lattice_param).floor() as i64;
+    let half = lattice_param / 2.0;
+    let mut crystal = Atoms::new();
+    for x in -n..=n {
+        for y in -n..=n {
+            for z in -n..=n {
+                if (x + y + z) % 2 != 0 {
+                    continue;
+                }
+                let point = [x, y, z].map(|c| c as f64 * half);
+                let inside = facets.iter().all(|(normal, limit)| {
+                    let projection: f64 = (0..3).map(|axis| normal[axis] * point[axis]).sum();
+                    projection <= limit * (1.0 + WULFF_FACET_TOLERANCE)
+                });
+                if inside {
+                    crystal.push(point);
+                }
+            }
+        }
+    }
+    Ok(crystal)
+}
+
+/// The distinct images of a Miller index under the 48 operations of the cubic
+/// point group: all permutations and sign changes of its components.
+fn cubic_equivalents(hkl: [i32; 3]) -> Vec<[i32; 3]> {
+    const PERMUTATIONS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+    let mut images: Vec<[i32; 3]> = PERMUTATIONS
+        .iter()
+        .flat_map(|perm| {
+            (0..8).map(move |signs: i32| {
+                [0, 1, 2].map(|axis| {
+                    let sign = if signs & (1 << axis) != 0 { -1 } else { 1 };
+                    sign * hkl[perm[axis]]
+                })
+            })
+        })
+        .collect();
+    images.sort_unstable();
+    images.dedup();
+    images
+}
+
+/// Marks decahedron with a fivefold axis along z, centred at the origin.
+///
+/// Five FCC tetrahedra share the axis, each bounded by two twin planes. `p`
+/// is the number of atoms along the edges of the (100) facets perpendicular to
+/// the axis, `q` the number along the axis on the (100) facets parallel to it,
+/// and `r` the depth of the Marks re-entrance at the pentagon corners;
+/// `q = 1` gives no parallel (100) facets and `r = 0` no re-entrance. With
+/// `q = 1, r = 0` the atom counts for `p = 1, 2, 3, 4` are 1, 7, 23, 54;
+/// `q > 1, r = 0` gives the elongated Ino decahedron.
+///
+/// The axis holds `h = p + q + 2r - 1` atoms spaced by the FCC
+/// nearest-neighbour distance `lattice_param / sqrt(2)`. Ring `n` around it
+/// lies on the pentagon scaled by `n`, each wedge taking the atoms from one
+/// corner up to, but not including, the next, so the atoms on the twin planes
+/// are placed exactly once; each ring site is a column of `h - n` atoms along
+/// z. As in a real decahedron the tetrahedra are slightly strained to close
+/// the 7.35 degree gap between five ideal tetrahedra.
+///
+/// Fails unless `p >= 1` and `q >= 1`.
+pub fn crystal_decahedron(lattice_param: f64, p: usize, q: usize, r: usize) -> Result<Atoms> {
+    if p == 0 || q == 0 {
+        return Err(Error::InvalidArgument(format!(
+            "p and q must be at least 1, got p = {} and q = {}",
+            p, q
+        )));
+    }
+    let spacing = lattice_param / std::f64::consts::SQRT_2;
+    let radius = spacing * 3.0_f64.sqrt() / 2.0;
+    let corners: [[f64; 2]; 5] = std::array::from_fn(|m| {
+        let angle = std::f64::consts::FRAC_PI_2 + 2.0 * std::f64::consts::PI * m as f64 / 5.0;
+        [radius * angle.cos(), radius * angle.sin()]
+    });
+    let h = p + q + 2 * r - 1;
+    let g = p + 2 * r;
+    let column = |n: usize, xy: [f64; 2], atoms: &mut Atoms| {
+        let length = h - n;
+        for j in 0..length {
+            let z = (j as f64 - (length - 1) as f64 / 2.0) * spacing;
+            atoms.push([xy[0], xy[1], z]);
+        }
+    };
+
+    let mut atoms = Atoms::new();
+    column(0, [0.0, 0.0], &mut atoms);
+    for n in 1..h.min(g) {
+        for m in 0..5 {
+            let (v1, v2) = (corners[(m + 4) % 5], corners[m]);
+            // Sites beyond g - r from either corner are cut by the re-entrance.
+            for i in (0..n).filter(|&i| n - i < g - r && i < g - r) {
+                let (a, b) = ((n - i) as f64, i as f64);
+                column(
+                    n,
+                    [a * v1[0] + b * v2[0], a * v1[1] + b * v2[1]],
+                    &mut atoms,
+                );
+            }
+        }
+    }
+    Ok(atoms)
+}
+
+/// Supercell of `positions` tiled `nx x ny x nz` times, with repeat lengths
+/// `a`, `b` and `c` along x, y and z.
+///
+/// Every atom is translated by `(ix * a, iy * b, iz * c)` for all
+/// `ix < nx`, `iy < ny`, `iz < nz`, giving exactly `N * nx * ny * nz` atoms.
+pub fn replicate_crystal(
+    positions: &[[f64; 3]],
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    a: f64,
+    b: f64,
+    c: f64,
+) -> Atoms {
+    let mut supercell = Atoms::with_capacity(positions.len() * nx * ny * nz);
+    for ix in 0..nx {
+        for iy in 0..ny {
+            for iz in 0..nz {
+                let shift = [ix as f64 * a, iy as f64 * b, iz as f64 * c];
+                for p in positions {
+                    supercell.push([p[0] + shift[0], p[1] + shift[1], p[2] + shift[2]]);
+                }
+            }
+        }
+    }
+    supercell
+}
+
+/// `positions` shifted by `displacement`.
+///
+/// The Debye intensity depends only on pair distances, so translating a
+/// crystal leaves every DSE result unchanged.
+pub fn translate_crystal(positions: &[[f64; 3]], displacement: [f64; 3]) -> Atoms {
+    positions
+        .iter()
+        .map(|p| {
+            [
+                p[0] + displacement[0],
+                p[1] + displacement[1],
+                p[2] + displacement[2],
+            ]
+        })
+        .collect()
+}
+
+/// `positions` shifted so that their centroid lies at the origin.
+///
+/// The shapes of [`crystal_core`] start at the origin, with a sphere centred
+/// at `(radius, radius, radius)`; this re-centres them. An empty slice gives
+/// an empty crystal.
+pub fn center_crystal(positions: &[[f64; 3]]) -> Atoms {
+    let n = positions.len().max(1) as f64;
+    let mut centroid = [0.0; 3];
+    for p in positions {
+        for axis in 0..3 {
+            centroid[axis] += p[axis] / n;
+        }
+    }
+    translate_crystal(positions, centroid.map(|c| -c))
+}
+
+/// Relative determinant below which a lattice matrix is treated as singular,
+/// in units of the product of its row lengths.
+const SINGULAR_LATTICE_TOLERANCE: f64 = 1e-12;
+
+fn check_cell_lengths(a: f64, b: f64, c: f64) -> Result<()> {
+    for (name, length) in [("a", a), ("b", b), ("c", c)] {
+        if length.is_nan() || length <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be positive, got {}",
+                name, length
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fractional coordinates `(x / a, y / b, z / c)` of `positions` in an
+/// orthorhombic cell with edges `a`, `b` and `c` along x, y and z.
+///
+/// Atoms inside the cell at the origin map to `[0, 1)`. Fails unless the
+/// edges are positive.
+pub fn to_fractional_coordinates(positions: &[[f64; 3]], a: f64, b: f64, c: f64) -> Result<Atoms> {
+    check_cell_lengths(a, b, c)?;
+    Ok(positions
+        .iter()
+        .map(|p| [p[0] / a, p[1] / b, p[2] / c])
+        .collect())
+}
+
+/// Cartesian positions `(x a, y b, z c)` of the fractional coordinates
+/// `frac_positions`, the inverse of [`to_fractional_coordinates`].
+pub fn from_fractional_coordinates(
+    frac_positions: &[[f64; 3]],
+    a: f64,
+    b: f64,
+    c: f64,
+) -> Result<Atoms> {
+    check_cell_lengths(a, b, c)?;
+    Ok(frac_positions
+        .iter()
+        .map(|f| [f[0] * a, f[1] * b, f[2] * c])
+        .collect())
+}
+
+/// Cartesian positions `r = f_a a + f_b b + f_c c` of fractional coordinates
+/// in a general, possibly non-orthogonal, cell whose lattice vectors `a`, `b`
+/// and `c` are the rows of `lattice_matrix`.
+///
+/// Fails if the lattice vectors are (nearly) coplanar, i.e. the determinant
+/// is below 1e-12 times the product of their lengths.
+pub fn to_cartesian_from_fractional(
+    frac_positions: &[[f64; 3]],
+    lattice_matrix: &Matrix3,
+) -> Result<Atoms> {
+    let [a, b, c] = lattice_matrix;
+    let determinant = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+        + a[2] * (b[0] * c[1] - b[1] * c[0]);
+    let scale: f64 = lattice_matrix
+        .iter()
+        .map(|row| row.iter().map(|x| x * x).sum::<f64>().sqrt())
+        .product();
+    if determinant.is_nan() || determinant.abs() <= SINGULAR_LATTICE_TOLERANCE * scale {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_matrix must be non-singular, its determinant is {}",
+            determinant
+        )));
+    }
+    Ok(frac_positions
+        .iter()
+        .map(|f| [0, 1, 2].map(|axis| f[0] * a[axis] + f[1] * b[axis] + f[2] * c[axis]))
+        .collect())
+}
+
+/// Vertices `(0, ±1, ±φ)`, `(±1, ±φ, 0)`, `(±φ, 0, ±1)` of an icosahedron with
+/// edge length 2.
+fn icosahedron_vertices() -> [[f64; 3]; 12] {
+    let phi = (1.0 + 5.0_f64.sqrt()) / 2.0;
+    let mut vertices = [[0.0; 3]; 12];
+    let mut n = 0;
+    for s1 in [1.0, -1.0] {
+        for s2 in [1.0, -1.0] {
+            vertices[n] = [0.0, s1, s2 * phi];
+            vertices[n + 1] = [s1, s2 * phi, 0.0];
+            vertices[n + 2] = [s2 * phi, 0.0, s1];
+            n += 3;
+        }
+    }
+    vertices
+}
+
+/// Tile `cells[0] x cells[1] x cells[2]` conventional cubic cells along x, y
+/// and z, placing every fractional basis position of `basis` in each cell.
+fn cubic_lattice(lattice_param: f64, cells: [usize; 3], basis: &[[f64; 3]]) -> Atoms {
+    let mut crystal = Atoms::with_capacity(cells.iter().product::<usize>() * basis.len());
+    for i in 0..cells[0] {
+        for j in 0..cells[1] {
+            for k in 0..cells[2] {
+                for b in basis {
+                    crystal.push([
+                        (i as f64 + b[0]) * lattice_param,
+                        (j as f64 + b[1]) * lattice_param,
+                        (k as f64 + b[2]) * lattice_param,
+                    ]);
+                }
+            }
+        }
+    }
+    crystal
+}
+
+/// Hexagonal close-packed lattice with in-plane parameter `a` and height `c`,
+/// keeping the points that fall inside the cube `[0, length)^3`.
+fn hcp_lattice(a: f64, c: f64, length: f64) -> Atoms {
+    // Hexagonal cell vectors a1 = (a, 0, 0), a2 = (a/2, a*sqrt(3)/2, 0), a3 = (0, 0, c)
+    // with atoms at fractional positions (0, 0, 0) and (1/3, 1/3, 1/2).
+    let row_height = a * 3.0_f64.sqrt() / 2.0;
+    let basis = [[0.0, 0.0, 0.0], [1.0 / 3.0, 1.0 / 3.0, 0.5]];
+    let n_rows = (length / row_height).ceil() as i64 + 1;
+    let n_cols = (length / a).ceil() as i64 + 1;
+    let n_layers = (length / c).ceil() as i64 + 1;
+
+    let mut crystal = Atoms::new();
+    for k in 0..n_layers {
+        for j in 0..n_rows {
+            // Rows shift by a/2 each step along a2, so start further back in a1
+            // to keep covering the cube.
+            for i in -(j / 2 + 1)..n_cols {
+                for b in &basis {
+                    let u = i as f64 + b[0];
+                    let v = j as f64 + b[1];
+                    let point = [(u + 0.5 * v) * a, v * row_height, (k as f64 + b[2]) * c];
+                    if point.iter().all(|&x| (0.0..length).contains(&x)) {
+                        crystal.push(point);
+                    }
+                }
+            }
+        }
+    }
+    crystal
+}
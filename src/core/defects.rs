@@ -0,0 +1,224 @@
+use std::f64::consts::PI;
+
+use rand::rngs::SmallRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+
+use super::{crystal_core, Atoms, Error, Result, Shape};
+
+/// Copy of `positions` with `(vacancy_fraction * N).round()` randomly chosen
+/// atoms removed.
+///
+/// The choice is made with a [`SmallRng`] seeded from `seed`, so the same seed
+/// always removes the same sites. `SmallRng` is not guaranteed to produce the
+/// same stream across `rand` versions or platforms, so reproducibility holds
+/// for a given build. The remaining atoms keep their order.
+pub fn crystal_with_vacancies(
+    positions: &[[f64; 3]],
+    vacancy_fraction: f64,
+    seed: u64,
+) -> Result<Atoms> {
+    check_fraction("vacancy_fraction", vacancy_fraction)?;
+    let n_vacancies = (vacancy_fraction * positions.len() as f64).round() as usize;
+    let mut removed = vec![false; positions.len()];
+    let mut rng = SmallRng::seed_from_u64(seed);
+    for i in index::sample(&mut rng, positions.len(), n_vacancies) {
+        removed[i] = true;
+    }
+    Ok(positions
+        .iter()
+        .zip(&removed)
+        .filter(|(_, &removed)| !removed)
+        .map(|(&position, _)| position)
+        .collect())
+}
+
+/// Copy of `species` with `(substitution_fraction * M).round()` of the `M`
+/// atoms labelled `from_species` relabelled as `to_species`.
+///
+/// The substituted atoms are chosen with a [`SmallRng`] seeded from `seed`.
+pub fn crystal_with_substitutions(
+    species: &[u32],
+    substitution_fraction: f64,
+    from_species: u32,
+    to_species: u32,
+    seed: u64,
+) -> Result<Vec<u32>> {
+    check_fraction("substitution_fraction", substitution_fraction)?;
+    let candidates: Vec<usize> = species
+        .iter()
+        .enumerate()
+        .filter(|(_, &s)| s == from_species)
+        .map(|(i, _)| i)
+        .collect();
+    let n_substitutions = (substitution_fraction * candidates.len() as f64).round() as usize;
+
+    let mut substituted = species.to_vec();
+    let mut rng = SmallRng::seed_from_u64(seed);
+    for k in index::sample(&mut rng, candidates.len(), n_substitutions) {
+        substituted[candidates[k]] = to_species;
+    }
+    Ok(substituted)
+}
+
+/// [`crystal_core`] crystal of a random substitutional alloy, with each site
+/// labelled species 0 (A) with probability `species_a_fraction` and species 1
+/// (B) otherwise, e.g. for AuAg, CuNi or FeCr.
+///
+/// Sites are assigned independently, so the number of A atoms is binomially
+/// distributed about `species_a_fraction * N`, unlike the exact count of
+/// [`crystal_with_substitutions`]. The labels are drawn from a [`SmallRng`]
+/// seeded from `seed` and returned alongside the positions.
+pub fn crystal_random_alloy(
+    shape: Shape,
+    lattice_param: f64,
+    length: f64,
+    species_a_fraction: f64,
+    seed: u64,
+) -> Result<(Atoms, Vec<u32>)> {
+    check_fraction("species_a_fraction", species_a_fraction)?;
+    let atoms = crystal_core(shape, lattice_param, length)?;
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let species = (0..atoms.len())
+        .map(|_| u32::from(!rng.random_bool(species_a_fraction)))
+        .collect();
+    Ok((atoms, species))
+}
+
+/// Close-packed crystal of `n_layers` hexagonal layers inside an upright
+/// cylinder of `diameter`, stacked along z with random stacking faults.
+///
+/// `lattice_param` is the cubic parameter `a` of the FCC crystal the
+/// stacking builds, so the layers are its (111) planes: triangular nets of
+/// spacing `a / sqrt(2)`, `a / sqrt(3)` apart, each on one of the three
+/// lateral sites A, B or C. Each layer after the first continues the
+/// FCC sequence `A -> B -> C -> A` with probability `1 - fault_probability`
+/// and otherwise steps back (`B -> A`), one deformation fault, after which
+/// the sequence continues from the new layer. `fault_probability = 0` gives
+/// FCC stacking `ABCABC`, 1 its twin `ACBACB` and 0.5 a random
+/// close-packed stacking; faults add diffuse streaks between the FCC and HCP
+/// Bragg peaks. The cylinder axis is `(diameter/2, diameter/2, z)`, as for
+/// [`crystal_cylinder`](super::crystal_cylinder), and the first layer is at
+/// `z = 0`. The sequence is drawn from a [`SmallRng`] seeded from `seed`.
+/// Fails unless `lattice_param` and `diameter` are positive, `n_layers >= 1`
+/// and `fault_probability` is in `[0, 1]`.
+pub fn crystal_stacking_faults(
+    lattice_param: f64,
+    diameter: f64,
+    n_layers: usize,
+    fault_probability: f64,
+    seed: u64,
+) -> Result<Atoms> {
+    if lattice_param.is_nan() || lattice_param <= 0.0 || diameter.is_nan() || diameter <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param and diameter must be positive, got {} and {}",
+            lattice_param, diameter
+        )));
+    }
+    if n_layers == 0 {
+        return Err(Error::InvalidArgument(
+            "n_layers must be at least 1".to_string(),
+        ));
+    }
+    check_fraction("fault_probability", fault_probability)?;
+
+    let spacing = lattice_param / 2f64.sqrt();
+    let row_height = spacing * 3f64.sqrt() / 2.0;
+    let layer_height = lattice_param / 3f64.sqrt();
+    let radius = diameter / 2.0;
+    let reach = (radius / row_height).ceil() as i64 + 1;
+
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut crystal = Atoms::new();
+    let mut site = 0;
+    for layer in 0..n_layers {
+        if layer > 0 {
+            let step = if rng.random_bool(fault_probability) {
+                2
+            } else {
+                1
+            };
+            site = (site + step) % 3;
+        }
+        // Site s is shifted by s (a1 + a2) / 3 from A, with a1 and a2 the net vectors.
+        let shift = [site as f64 * spacing / 2.0, site as f64 * row_height / 3.0];
+        for j in -reach..=reach {
+            for i in -2 * reach..=2 * reach {
+                let x = (i as f64 + 0.5 * j as f64) * spacing + shift[0];
+                let y = j as f64 * row_height + shift[1];
+                if x * x + y * y <= radius * radius {
+                    crystal.push([radius + x, radius + y, layer as f64 * layer_height]);
+                }
+            }
+        }
+    }
+    Ok(crystal)
+}
+
+/// Copy of `positions` with every coordinate displaced by an independent
+/// Gaussian `N(0, sigma_nm^2)` offset, a frozen snapshot of isotropic thermal
+/// motion.
+///
+/// Same as [`apply_anisotropic_disorder`] with `sigma_nm` along every axis.
+pub fn apply_thermal_disorder(positions: &[[f64; 3]], sigma_nm: f64, seed: u64) -> Result<Atoms> {
+    check_sigma("sigma_nm", sigma_nm)?;
+    apply_anisotropic_disorder(positions, sigma_nm, sigma_nm, sigma_nm, seed)
+}
+
+/// Copy of `positions` with the x, y and z coordinates displaced by
+/// independent Gaussian offsets of standard deviations `sigma_x`, `sigma_y`
+/// and `sigma_z` in nanometers, e.g. for uniaxial systems.
+///
+/// The offsets are drawn by the Box-Muller transform from a [`SmallRng`]
+/// seeded from `seed`. Averaged over seeds, each distinct pair term of the
+/// Debye sum is attenuated by the Debye-Waller factor of the relative
+/// displacement, `exp(-q^2 sigma^2)` in the isotropic case, while the self
+/// terms are not, so the mean intensity tends to
+/// `N + exp(-q^2 sigma^2) (I_0(q) - N)` with `I_0` the intensity of the ideal
+/// crystal.
+pub fn apply_anisotropic_disorder(
+    positions: &[[f64; 3]],
+    sigma_x: f64,
+    sigma_y: f64,
+    sigma_z: f64,
+    seed: u64,
+) -> Result<Atoms> {
+    let sigma = [sigma_x, sigma_y, sigma_z];
+    for (name, s) in ["sigma_x", "sigma_y", "sigma_z"].into_iter().zip(sigma) {
+        check_sigma(name, s)?;
+    }
+    let mut rng = SmallRng::seed_from_u64(seed);
+    Ok(positions
+        .iter()
+        .map(|p| [0, 1, 2].map(|d| p[d] + sigma[d] * standard_normal(&mut rng)))
+        .collect())
+}
+
+/// Standard normal sample by the Box-Muller transform.
+fn standard_normal(rng: &mut SmallRng) -> f64 {
+    // 1 - u lies in (0, 1], keeping the logarithm finite.
+    let radius = (-2.0 * (1.0 - rng.random::<f64>()).ln()).sqrt();
+    radius * (2.0 * PI * rng.random::<f64>()).cos()
+}
+
+fn check_sigma(name: &str, sigma: f64) -> Result<()> {
+    if sigma.is_finite() && sigma >= 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "{} must be non-negative and finite, got {}",
+            name, sigma
+        )))
+    }
+}
+
+fn check_fraction(name: &str, fraction: f64) -> Result<()> {
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "{} must be in [0, 1], got {}",
+            name, fraction
+        )))
+    }
+}
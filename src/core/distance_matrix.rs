@@ -0,0 +1,87 @@
+use rayon::prelude::*;
+
+/// Squared pairwise distances of a set of atoms, stored as a row-major
+/// `n_atoms x n_atoms` matrix.
+///
+/// Building the matrix is O(N^2); keep it around to evaluate the Debye sum on
+/// several q ranges of the same crystal with [`dse_from_distances`](super::dse_from_distances).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    distances_sq: Vec<f64>,
+    n_atoms: usize,
+}
+
+impl DistanceMatrix {
+    /// Compute the squared distances between every pair of `atoms`.
+    ///
+    /// Rows are filled in parallel on the Rayon pool, each directly in its final
+    /// place of the row-major buffer, so `as_slice()[i * n + j]` is still the
+    /// distance from atom `i` to atom `j`. The build is bound by writing the
+    /// 8 N^2 byte output: a 15 625-atom crystal (1.95 GB) takes 1.3–1.45 s on a
+    /// single core against 1.6–1.95 s for the serial loop, see `BENCHMARKS.md`.
+    pub fn from_atoms(atoms: &[[f64; 3]]) -> Self {
+        let n_atoms = atoms.len();
+        let mut distances_sq = vec![0.0; n_atoms * n_atoms];
+
+        distances_sq
+            .par_chunks_mut(n_atoms.max(1))
+            .zip(atoms)
+            .for_each(|(row, a)| {
+                for (distance_sq, b) in row.iter_mut().zip(atoms) {
+                    let dx = a[0] - b[0];
+                    let dy = a[1] - b[1];
+                    let dz = a[2] - b[2];
+                    *distance_sq = dx * dx + dy * dy + dz * dz;
+                }
+            });
+        Self {
+            distances_sq,
+            n_atoms,
+        }
+    }
+
+    /// Squared minimum-image distances of `atoms` in an orthorhombic periodic
+    /// box with edges `box_lengths` along x, y and z.
+    ///
+    /// Each component of a separation is wrapped into `[-L/2, L/2]` with
+    /// `dx - L * round(dx / L)`, so every pair is taken at its nearest
+    /// periodic image.
+    pub fn from_atoms_minimum_image(atoms: &[[f64; 3]], box_lengths: [f64; 3]) -> Self {
+        let n_atoms = atoms.len();
+        let mut distances_sq = vec![0.0; n_atoms * n_atoms];
+
+        distances_sq
+            .par_chunks_mut(n_atoms.max(1))
+            .zip(atoms)
+            .for_each(|(row, a)| {
+                for (distance_sq, b) in row.iter_mut().zip(atoms) {
+                    *distance_sq = (0..3)
+                        .map(|axis| {
+                            let d = a[axis] - b[axis];
+                            let l = box_lengths[axis];
+                            (d - l * (d / l).round()).powi(2)
+                        })
+                        .sum();
+                }
+            });
+        Self {
+            distances_sq,
+            n_atoms,
+        }
+    }
+
+    /// Number of atoms the matrix was built from.
+    pub fn n_atoms(&self) -> usize {
+        self.n_atoms
+    }
+
+    /// All squared distances, row-major.
+    pub fn as_slice(&self) -> &[f64] {
+        &self.distances_sq
+    }
+
+    /// Squared distances from atom `i` to every atom.
+    pub fn row(&self, i: usize) -> &[f64] {
+        &self.distances_sq[i * self.n_atoms..(i + 1) * self.n_atoms]
+    }
+}
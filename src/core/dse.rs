@@ -0,0 +1,1083 @@
+use rayon::prelude::*;
+
+use super::{Atoms, DistanceMatrix, Error, ProgressFn, Result};
+
+/// Below this `|q r|`, [`sinc`] uses its Taylor series instead of `sin(x) / x`.
+///
+/// At `|x| < 1e-6` the first omitted term, `x^8 / 362880`, is below 1e-53, so
+/// the series is exact to double precision, and it agrees with the direct
+/// formula at the crossover to 15 significant figures.
+pub(crate) const SINC_TAYLOR_THRESHOLD: f64 = 1e-6;
+
+/// `sin(x) / x`, with `sinc(0) = 1` and a Taylor series near zero.
+#[inline(always)]
+pub(crate) fn sinc(x: f64) -> f64 {
+    if x.abs() < SINC_TAYLOR_THRESHOLD {
+        let x2 = x * x;
+        1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0))
+    } else {
+        x.sin() / x
+    }
+}
+
+#[inline(always)]
+fn intensity_point_optimized(q: f64, distance_sq: f64) -> f64 {
+    sinc(q * distance_sq.sqrt())
+}
+
+/// Relative tolerance, in units of `q_step`, for treating `max_q` as reachable.
+const Q_GRID_TOLERANCE: f64 = 1e-9;
+
+/// q values `min_q + i * q_step` that do not exceed `max_q`.
+///
+/// `max_q` itself is included when it lies on the grid, up to floating-point
+/// rounding of `(max_q - min_q) / q_step`. The grid is empty, rather than
+/// infinite, unless the bounds are finite and `q_step` is positive and
+/// finite; the Python wrappers reject such ranges before calling it.
+pub fn q_grid(min_q: f64, max_q: f64, q_step: f64) -> Vec<f64> {
+    if !(min_q.is_finite() && max_q.is_finite() && q_step.is_finite()) || q_step <= 0.0 {
+        return Vec::new();
+    }
+    let steps = ((max_q - min_q) / q_step + Q_GRID_TOLERANCE).floor();
+    let n_points = if steps >= 0.0 { steps as usize + 1 } else { 0 };
+    (0..n_points).map(|i| min_q + i as f64 * q_step).collect()
+}
+
+/// `n_points` evenly spaced q values from `min_q` to `max_q`, both included.
+///
+/// Unlike [`q_grid`] the number of points is fixed and the step follows from
+/// it, `(max_q - min_q) / (n_points - 1)`; the last value is exactly `max_q`.
+/// Fails unless `min_q` and `max_q` are finite with `min_q <= max_q` and
+/// `n_points >= 2`.
+pub fn q_linspace(min_q: f64, max_q: f64, n_points: usize) -> Result<Vec<f64>> {
+    if !(min_q.is_finite() && max_q.is_finite() && min_q <= max_q) {
+        return Err(Error::InvalidArgument(format!(
+            "min_q and max_q must be finite with min_q <= max_q, got {} and {}",
+            min_q, max_q
+        )));
+    }
+    if n_points < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "n_points must be at least 2, got {}",
+            n_points
+        )));
+    }
+    let q_step = (max_q - min_q) / (n_points - 1) as f64;
+    Ok((0..n_points)
+        .map(|i| {
+            if i == n_points - 1 {
+                max_q
+            } else {
+                min_q + i as f64 * q_step
+            }
+        })
+        .collect())
+}
+
+/// `n_points` logarithmically spaced q values from `min_q` to `max_q`, both
+/// included: `exp` of the [`q_linspace`] of `ln min_q` and `ln max_q`.
+///
+/// The points are denser at low q, where SAXS curves have the most structure
+/// on a log-q plot. The ends are exactly `min_q` and `max_q`. Fails unless
+/// `0 < min_q <= max_q` are finite and `n_points >= 2`.
+pub fn q_logspace(min_q: f64, max_q: f64, n_points: usize) -> Result<Vec<f64>> {
+    if !(min_q.is_finite() && max_q.is_finite() && 0.0 < min_q && min_q <= max_q) {
+        return Err(Error::InvalidArgument(format!(
+            "min_q and max_q must be finite with 0 < min_q <= max_q for a logarithmic grid, \
+             got {} and {}",
+            min_q, max_q
+        )));
+    }
+    let mut q_values: Vec<f64> = q_linspace(min_q.ln(), max_q.ln(), n_points)?
+        .into_iter()
+        .map(f64::exp)
+        .collect();
+    q_values[0] = min_q;
+    q_values[n_points - 1] = max_q;
+    Ok(q_values)
+}
+
+/// Largest `q_min * R_g` for which the Guinier region is considered resolved.
+pub const GUINIER_RESOLVED_Q_RG: f64 = 0.2;
+
+/// Lower bound on `min_q` that keeps the Guinier region of the crystal,
+/// `1 / (5 R_g)`, i.e. `q_min R_g =` [`GUINIER_RESOLVED_Q_RG`].
+///
+/// `R_g = sqrt(mean |r|^2 - |mean r|^2)` is the radius of gyration of the
+/// atom positions. Fails if there are no atoms or they all coincide.
+pub fn min_q_from_crystal_size(positions: &[[f64; 3]]) -> Result<f64> {
+    let n = positions.len() as f64;
+    let mut mean = [0.0; 3];
+    let mut mean_sq = 0.0;
+    for p in positions {
+        for axis in 0..3 {
+            mean[axis] += p[axis] / n;
+            mean_sq += p[axis] * p[axis] / n;
+        }
+    }
+    let rg_sq = mean_sq - mean.iter().map(|m| m * m).sum::<f64>();
+    if positions.is_empty() || rg_sq.is_nan() || rg_sq <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "the radius of gyration of {} atoms is zero",
+            positions.len()
+        )));
+    }
+    Ok(GUINIER_RESOLVED_Q_RG / rg_sq.sqrt())
+}
+
+/// `max_q = 2 pi oversampling / lattice_param`, the q reaching `oversampling`
+/// orders of the lattice spacing `lattice_param`.
+pub fn max_q_for_resolution(lattice_param: f64, oversampling: f64) -> Result<f64> {
+    if lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param must be positive, got {}",
+            lattice_param
+        )));
+    }
+    if oversampling.is_nan() || oversampling <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "oversampling must be positive, got {}",
+            oversampling
+        )));
+    }
+    Ok(2.0 * std::f64::consts::PI * oversampling / lattice_param)
+}
+
+/// Bytes of one entry of the `f64` [`DistanceMatrix`].
+const BYTES_PER_ATOM_PAIR: f64 = 8.0;
+
+/// Bytes held per atom besides the distance matrix: the flat `[f64; 3]`
+/// positions (24) and the `Vec<Vec<f64>>` they are converted from on the
+/// Python side (a 24-byte header and 24 bytes of coordinates).
+const BYTES_PER_ATOM: f64 = 72.0;
+
+/// Bytes per q point for the q grid and the resulting intensity.
+const BYTES_PER_Q_POINT: f64 = 16.0;
+
+/// Estimated peak memory in MB (1e6 bytes) of [`dse_core`] for `n_atoms`
+/// atoms and `n_q_points` q values.
+///
+/// Dominated by the `8 N^2` bytes of the [`DistanceMatrix`], plus the
+/// positions and the q and intensity arrays.
+pub fn estimate_memory_mb(n_atoms: usize, n_q_points: usize) -> f64 {
+    let n = n_atoms as f64;
+    (BYTES_PER_ATOM_PAIR * n * n + BYTES_PER_ATOM * n + BYTES_PER_Q_POINT * n_q_points as f64) / 1e6
+}
+
+/// Largest number of atoms whose [`estimate_memory_mb`] fits in
+/// `max_memory_mb`, ignoring the few bytes per q point. 0 for a negative or
+/// NaN budget.
+pub fn max_atoms_for_memory(max_memory_mb: f64) -> usize {
+    if max_memory_mb.is_nan() || max_memory_mb <= 0.0 {
+        return 0;
+    }
+    // Positive root of 8 n^2 + 72 n = bytes, nudged to the exact integer
+    // past rounding error.
+    let bytes = max_memory_mb * 1e6;
+    let root = (BYTES_PER_ATOM * BYTES_PER_ATOM + 4.0 * BYTES_PER_ATOM_PAIR * bytes).sqrt();
+    let mut n_atoms = ((root - BYTES_PER_ATOM) / (2.0 * BYTES_PER_ATOM_PAIR)) as usize;
+    while n_atoms > 0 && estimate_memory_mb(n_atoms, 0) > max_memory_mb {
+        n_atoms -= 1;
+    }
+    while n_atoms < usize::MAX && estimate_memory_mb(n_atoms + 1, 0) <= max_memory_mb {
+        n_atoms += 1;
+    }
+    n_atoms
+}
+
+/// Simplified Debye scattering intensity `I(q) = sum_ij sin(q r_ij) / (q r_ij)`
+/// on the [`q_grid`] of `min_q`, `max_q` and `q_step`.
+///
+/// The pairwise distances are computed once and the q values are evaluated in
+/// parallel.
+pub fn dse_core(min_q: f64, max_q: f64, q_step: f64, positions: &[[f64; 3]]) -> Vec<f64> {
+    // Pre-calculate distance matrix once
+    let distances = DistanceMatrix::from_atoms(positions);
+    dse_from_distances(min_q, max_q, q_step, &distances)
+}
+
+/// [`dse_core`] without a [`DistanceMatrix`]: the distances are recomputed
+/// on the fly at every q, so the memory stays O(N) for crystals whose
+/// `8 N^2` byte matrix does not fit in RAM.
+///
+/// The `N` self-pairs `i == j` each contribute exactly `sinc(0) = 1`, so they
+/// are skipped in the loop and `N` is added to every intensity instead. The
+/// q values are evaluated in parallel.
+pub fn dse_ultra_optimized(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+) -> Vec<f64> {
+    q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| ultra_optimized_at_q(q, positions))
+        .collect()
+}
+
+/// [`dse_ultra_optimized`] reporting to `progress` after every `chunk_size` q
+/// points, as in [`dse_core_with_progress`].
+pub fn dse_ultra_optimized_with_progress(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    chunk_size: usize,
+    progress: &dyn ProgressFn,
+) -> Vec<f64> {
+    map_q_chunks(&q_grid(min_q, max_q, q_step), chunk_size, progress, |q| {
+        ultra_optimized_at_q(q, positions)
+    })
+}
+
+/// Intensity of [`dse_ultra_optimized`] at a single q, evaluated serially.
+fn ultra_optimized_at_q(q: f64, positions: &[[f64; 3]]) -> f64 {
+    let distinct_pairs: f64 = positions
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let (before, after) = positions.split_at(i);
+            before
+                .iter()
+                .chain(&after[1..])
+                .map(|b| {
+                    let dx = a[0] - b[0];
+                    let dy = a[1] - b[1];
+                    let dz = a[2] - b[2];
+                    intensity_point_optimized(q, dx * dx + dy * dy + dz * dz)
+                })
+                .sum::<f64>()
+        })
+        .sum();
+    distinct_pairs + positions.len() as f64
+}
+
+/// Default `chunk_size` of [`dse_no_alloc`], among the fastest in
+/// `BENCHMARKS.md`.
+pub const DEFAULT_NO_ALLOC_CHUNK_SIZE: usize = 256;
+
+/// [`dse_ultra_optimized`] with the pair loop blocked into tiles of
+/// `chunk_size` by `chunk_size` atoms.
+///
+/// No distances are stored: memory stays O(N), against the `8 N^2` bytes of
+/// [`dse_core`], at the price of recomputing all `N^2` distances, square
+/// roots included, at every q. As `sin` dominates the pair term this costs
+/// little: on the `BENCHMARKS.md` crystal, 64 to 256 atoms per chunk run as
+/// fast as [`dse_core`], and the tiles, which stay in cache while they are
+/// summed, gain 15–20% over the unblocked loop. The q values are evaluated in
+/// parallel. Fails if `chunk_size` is 0.
+pub fn dse_no_alloc(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    chunk_size: usize,
+) -> Result<Vec<f64>> {
+    if chunk_size == 0 {
+        return Err(Error::InvalidArgument(
+            "chunk_size must be at least 1".to_string(),
+        ));
+    }
+    Ok(q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            positions
+                .chunks(chunk_size)
+                .flat_map(|block_i| {
+                    positions
+                        .chunks(chunk_size)
+                        .map(move |block_j| tile_sum(q, block_i, block_j))
+                })
+                .sum()
+        })
+        .collect())
+}
+
+/// `sum_{a in block_i, b in block_j} sinc(q |a - b|)`, one tile of
+/// [`dse_no_alloc`].
+fn tile_sum(q: f64, block_i: &[[f64; 3]], block_j: &[[f64; 3]]) -> f64 {
+    block_i
+        .iter()
+        .flat_map(|a| {
+            block_j.iter().map(move |b| {
+                let dx = a[0] - b[0];
+                let dy = a[1] - b[1];
+                let dz = a[2] - b[2];
+                intensity_point_optimized(q, dx * dx + dy * dy + dz * dz)
+            })
+        })
+        .sum()
+}
+
+/// [`dse_core`] over each pair once: `I(q) = 2 sum_{i<j} sinc(q r_ij) + N`.
+///
+/// The `N (N - 1) / 2` distances `r_ij` with `i < j` are computed once into a
+/// flat list, which takes `4 N^2` bytes, half of the [`DistanceMatrix`], and
+/// holds `r` rather than `r^2`, so the per-q loop has no square roots. The
+/// self-pairs are added analytically and the q values are evaluated in
+/// parallel.
+pub fn dse_symmetric(min_q: f64, max_q: f64, q_step: f64, positions: &[[f64; 3]]) -> Vec<f64> {
+    let n_atoms = positions.len();
+    let distances: Vec<f64> = (0..n_atoms)
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let a = positions[i];
+            positions[i + 1..].iter().map(move |b| {
+                let dx = a[0] - b[0];
+                let dy = a[1] - b[1];
+                let dz = a[2] - b[2];
+                (dx * dx + dy * dy + dz * dz).sqrt()
+            })
+        })
+        .collect();
+    q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| 2.0 * distances.iter().map(|&r| sinc(q * r)).sum::<f64>() + n_atoms as f64)
+        .collect()
+}
+
+/// Structure factor `S(q) = I(q) / N`, with `I` from [`dse_core`].
+///
+/// Splitting off the `i == j` terms of the Debye sum gives
+/// `S(q) = 1 + (1 / N) sum_{i != j} sin(q r_ij) / (q r_ij)`, so `S` tends to 1
+/// at high q. Fails for an empty crystal.
+pub fn structure_factor(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+) -> Result<Vec<f64>> {
+    if positions.is_empty() {
+        return Err(Error::InvalidArgument(
+            "the structure factor of an empty crystal is undefined".to_string(),
+        ));
+    }
+    let n_atoms = positions.len() as f64;
+    Ok(dse_core(min_q, max_q, q_step, positions)
+        .into_iter()
+        .map(|i| i / n_atoms)
+        .collect())
+}
+
+/// Distinct structure factor `S(q) - 1`, the pair terms `i != j` of
+/// [`structure_factor`] without the self-scattering contribution.
+pub fn distinct_structure_factor(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+) -> Result<Vec<f64>> {
+    Ok(structure_factor(min_q, max_q, q_step, positions)?
+        .into_iter()
+        .map(|s| s - 1.0)
+        .collect())
+}
+
+/// Derivative `dI/ds` at `s = 1` of the [`dse_core`] intensity of the
+/// crystal with every coordinate scaled by `s`.
+///
+/// Each pair term contributes `d/ds sinc(q s r) = cos(q r) - sin(q r) / (q r)`,
+/// which vanishes for the self terms. The atom count is held fixed, so this
+/// is the derivative under a uniform dilation of the crystal.
+pub fn dse_dilation_gradient(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+) -> Vec<f64> {
+    let distances = DistanceMatrix::from_atoms(positions);
+    q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            distances
+                .as_slice()
+                .iter()
+                .map(|&dist_sq| {
+                    let x = q * dist_sq.sqrt();
+                    x.cos() - sinc(x)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Derivative `dI/da` of the [`dse_core`] intensity with respect to the
+/// lattice parameter `a` of a crystal whose coordinates are proportional to
+/// `a`, as for every generator of this crate.
+///
+/// By the chain rule this is [`dse_dilation_gradient`] divided by `a`. The
+/// atom count is held fixed, i.e. the particle grows with `a` instead of
+/// gaining or losing surface atoms.
+pub fn dse_gradient_lattice(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    lattice_param: f64,
+) -> Result<Vec<f64>> {
+    scaled_dilation_gradient(
+        min_q,
+        max_q,
+        q_step,
+        positions,
+        "lattice_param",
+        lattice_param,
+    )
+}
+
+/// Derivative `dI/dL` of the [`dse_core`] intensity with respect to the size
+/// `L` of a particle whose coordinates are proportional to `L`.
+///
+/// [`dse_dilation_gradient`] divided by `L`. Changing the size of a crystal of
+/// fixed lattice adds or removes whole atoms, which has no derivative, so the
+/// size is varied as a continuous dilation at fixed atom count; for fitting,
+/// this is the smooth envelope of the discrete size dependence.
+pub fn dse_gradient_size(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    size: f64,
+) -> Result<Vec<f64>> {
+    scaled_dilation_gradient(min_q, max_q, q_step, positions, "size", size)
+}
+
+fn scaled_dilation_gradient(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    name: &str,
+    scale: f64,
+) -> Result<Vec<f64>> {
+    if scale.is_nan() || scale <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "{} must be positive, got {}",
+            name, scale
+        )));
+    }
+    Ok(dse_dilation_gradient(min_q, max_q, q_step, positions)
+        .into_iter()
+        .map(|g| g / scale)
+        .collect())
+}
+
+/// [`dse_core`] reporting to `progress` after every `chunk_size` q points.
+///
+/// Each chunk is still evaluated in parallel, so `chunk_size` should be at
+/// least the number of rayon threads; 0 is treated as 1.
+pub fn dse_core_with_progress(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    chunk_size: usize,
+    progress: &dyn ProgressFn,
+) -> Vec<f64> {
+    let distances = DistanceMatrix::from_atoms(positions);
+    map_q_chunks(&q_grid(min_q, max_q, q_step), chunk_size, progress, |q| {
+        dse_at_q(q, &distances)
+    })
+}
+
+/// Simplified Debye scattering intensity from a precomputed [`DistanceMatrix`],
+/// skipping the O(N^2) distance construction of [`dse_core`].
+pub fn dse_from_distances(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    distances: &DistanceMatrix,
+) -> Vec<f64> {
+    // Parallel computation over q values
+    q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| dse_at_q(q, distances))
+        .collect()
+}
+
+/// [`dse_core`] for many crystals, in parallel over the crystals.
+///
+/// Each crystal is evaluated serially on one thread, so there is no nested
+/// parallelism. The result is indexed `[crystal][q]`.
+pub fn dse_batch(min_q: f64, max_q: f64, q_step: f64, crystals: &[Atoms]) -> Vec<Vec<f64>> {
+    let q_values = q_grid(min_q, max_q, q_step);
+    crystals
+        .par_iter()
+        .map(|crystal| {
+            let distances = DistanceMatrix::from_atoms(crystal.positions());
+            q_values.iter().map(|&q| dse_at_q(q, &distances)).collect()
+        })
+        .collect()
+}
+
+/// [`dse_core`] of one crystal for several `(min_q, max_q, q_step)` grids,
+/// in parallel over the grids.
+///
+/// The distance matrix is built once; each grid is evaluated serially on one
+/// thread. The result is indexed `[grid][q]`.
+pub fn dse_batch_with_params(params: &[(f64, f64, f64)], positions: &[[f64; 3]]) -> Vec<Vec<f64>> {
+    let distances = DistanceMatrix::from_atoms(positions);
+    params
+        .par_iter()
+        .map(|&(min_q, max_q, q_step)| {
+            q_grid(min_q, max_q, q_step)
+                .iter()
+                .map(|&q| dse_at_q(q, &distances))
+                .collect()
+        })
+        .collect()
+}
+
+/// Simplified Debye scattering intensity `sum_ij sin(q r_ij) / (q r_ij)` at a
+/// single q, evaluated serially.
+pub fn dse_at_q(q: f64, distances: &DistanceMatrix) -> f64 {
+    distances
+        .as_slice()
+        .iter()
+        .map(|&dist_sq| intensity_point_optimized(q, dist_sq))
+        .sum()
+}
+
+/// Evaluate `intensity_at` over `q_values` in parallel, `chunk_size` points
+/// at a time, calling `progress` on this thread after each chunk.
+fn map_q_chunks<F>(
+    q_values: &[f64],
+    chunk_size: usize,
+    progress: &dyn ProgressFn,
+    intensity_at: F,
+) -> Vec<f64>
+where
+    F: Fn(f64) -> f64 + Sync,
+{
+    let mut intensity = Vec::with_capacity(q_values.len());
+    for chunk in q_values.chunks(chunk_size.max(1)) {
+        intensity.par_extend(chunk.par_iter().map(|&q| intensity_at(q)));
+        progress.report(intensity.len(), q_values.len());
+    }
+    intensity
+}
+
+/// Debye scattering intensity with each pair term weighted by `f_i(q) * f_j(q)`.
+///
+/// `element_indices[i]` is the element of atom `i` and `form_factor_table[e]`
+/// holds the form factor of element `e` at every point of the [`q_grid`].
+pub fn dse_with_form_factors(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    element_indices: &[usize],
+    form_factor_table: &[Vec<f64>],
+) -> Result<Vec<f64>> {
+    if element_indices.len() != positions.len() {
+        return Err(Error::InvalidArgument(format!(
+            "element_indices has {} entries but the crystal has {} atoms",
+            element_indices.len(),
+            positions.len()
+        )));
+    }
+    if let Some(&index) = element_indices
+        .iter()
+        .find(|&&index| index >= form_factor_table.len())
+    {
+        return Err(Error::InvalidArgument(format!(
+            "element index {} has no entry in form_factor_table ({} elements)",
+            index,
+            form_factor_table.len()
+        )));
+    }
+
+    let q_values = q_grid(min_q, max_q, q_step);
+    if let Some((element, row)) = form_factor_table
+        .iter()
+        .enumerate()
+        .find(|(_, row)| row.len() < q_values.len())
+    {
+        return Err(Error::InvalidArgument(format!(
+            "form_factor_table[{}] has {} values but the q grid has {} points",
+            element,
+            row.len(),
+            q_values.len()
+        )));
+    }
+
+    let distances = DistanceMatrix::from_atoms(positions);
+
+    let intensity = q_values
+        .par_iter()
+        .enumerate()
+        .map(|(q_index, &q)| {
+            let f: Vec<f64> = element_indices
+                .iter()
+                .map(|&element| form_factor_table[element][q_index])
+                .collect();
+            weighted_pair_sum(q, &distances, &f)
+        })
+        .collect();
+
+    Ok(intensity)
+}
+
+/// `sum_ij w_i * w_j * sin(q r_ij) / (q r_ij)` for per-atom weights `w` at a single q.
+fn weighted_pair_sum(q: f64, distances: &DistanceMatrix, weights: &[f64]) -> f64 {
+    weights
+        .iter()
+        .enumerate()
+        .map(|(i, &w_i)| {
+            let row_sum: f64 = distances
+                .row(i)
+                .iter()
+                .zip(weights)
+                .map(|(&dist_sq, &w_j)| w_j * intensity_point_optimized(q, dist_sq))
+                .sum();
+            w_i * row_sum
+        })
+        .sum()
+}
+
+/// Debye-Waller attenuation `exp(-B * q^2 / (16 pi^2))` for `b_factor` in Å^2
+/// and `q` in 1/nm.
+fn debye_waller_factor(b_factor: f64, q: f64) -> f64 {
+    // B converted from Å^2 to nm^2 to match q in 1/nm.
+    let b_nm_sq = b_factor / 100.0;
+    (-b_nm_sq * q * q / (16.0 * std::f64::consts::PI.powi(2))).exp()
+}
+
+/// Debye scattering intensity with per-atom isotropic Debye-Waller factors.
+///
+/// Each pair term is attenuated by `exp(-0.5 * (B_i + B_j) * q^2 / (16 pi^2))`,
+/// with `b_factors` in Å^2 (one per atom) and q in 1/nm. The self terms are
+/// attenuated as well, i.e. thermal diffuse scattering is not modelled.
+pub fn dse_with_debye_waller(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    b_factors: &[f64],
+) -> Result<Vec<f64>> {
+    if b_factors.len() != positions.len() {
+        return Err(Error::InvalidArgument(format!(
+            "b_factors has {} entries but the crystal has {} atoms",
+            b_factors.len(),
+            positions.len()
+        )));
+    }
+
+    let distances = DistanceMatrix::from_atoms(positions);
+    let intensity = q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            // exp(-0.5 * (B_i + B_j) * s) factorises into per-atom weights exp(-0.5 * B_i * s).
+            let weights: Vec<f64> = b_factors
+                .iter()
+                .map(|&b| debye_waller_factor(0.5 * b, q))
+                .collect();
+            weighted_pair_sum(q, &distances, &weights)
+        })
+        .collect();
+    Ok(intensity)
+}
+
+/// Debye scattering intensity of a multi-species crystal, with each pair term
+/// weighted by `w[s_i] * w[s_j]`.
+///
+/// `species[i]` is the species label of atom `i` and `scattering_weights[s]`
+/// the relative scattering length of species `s`. Weights may be negative, as
+/// for neutron scattering lengths of hydrogen.
+pub fn dse_with_species(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    species: &[u32],
+    scattering_weights: &[f64],
+) -> Result<Vec<f64>> {
+    dse_with_species_with_progress(
+        min_q,
+        max_q,
+        q_step,
+        positions,
+        species,
+        scattering_weights,
+        usize::MAX,
+        &|_, _| {},
+    )
+}
+
+/// [`dse_with_species`] reporting to `progress` after every `chunk_size` q
+/// points, as in [`dse_core_with_progress`].
+#[allow(clippy::too_many_arguments)]
+pub fn dse_with_species_with_progress(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    species: &[u32],
+    scattering_weights: &[f64],
+    chunk_size: usize,
+    progress: &dyn ProgressFn,
+) -> Result<Vec<f64>> {
+    if species.len() != positions.len() {
+        return Err(Error::InvalidArgument(format!(
+            "species has {} entries but the crystal has {} atoms",
+            species.len(),
+            positions.len()
+        )));
+    }
+    let weights = species
+        .iter()
+        .map(|&s| {
+            scattering_weights.get(s as usize).copied().ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "species {} has no entry in scattering_weights ({} species)",
+                    s,
+                    scattering_weights.len()
+                ))
+            })
+        })
+        .collect::<Result<Vec<f64>>>()?;
+
+    let distances = DistanceMatrix::from_atoms(positions);
+    Ok(map_q_chunks(
+        &q_grid(min_q, max_q, q_step),
+        chunk_size,
+        progress,
+        |q| weighted_pair_sum(q, &distances, &weights),
+    ))
+}
+
+/// Partial Debye intensity `I_AB(q) = sum_{i in A} sum_{j in B} sin(q r_ij) /
+/// (q r_ij)` over the ordered pairs with atom `i` of species `species_a` and
+/// atom `j` of species `species_b`.
+///
+/// The self terms are included when `species_a == species_b`, so
+/// that the intensity of [`dse_with_species`] is `sum_AB w_A w_B I_AB` and
+/// varying the weights, as in anomalous SAXS, only rescales the partials.
+/// `I_AB = I_BA`, and a species that no atom has gives zeros. The A-B
+/// distances are computed once and the q values are evaluated in parallel.
+/// Fails unless `species` has one label per atom.
+pub fn partial_dse(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    species: &[u32],
+    species_a: u32,
+    species_b: u32,
+) -> Result<Vec<f64>> {
+    if species.len() != positions.len() {
+        return Err(Error::InvalidArgument(format!(
+            "species has {} entries but the crystal has {} atoms",
+            species.len(),
+            positions.len()
+        )));
+    }
+    let of_species = |label: u32| -> Vec<[f64; 3]> {
+        positions
+            .iter()
+            .zip(species)
+            .filter(|&(_, &s)| s == label)
+            .map(|(&p, _)| p)
+            .collect()
+    };
+    let (atoms_a, atoms_b) = (of_species(species_a), of_species(species_b));
+    let distances_sq: Vec<f64> = atoms_a
+        .par_iter()
+        .flat_map_iter(|a| {
+            atoms_b
+                .iter()
+                .map(move |b| (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2))
+        })
+        .collect();
+    Ok(q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            distances_sq
+                .iter()
+                .map(|&dist_sq| intensity_point_optimized(q, dist_sq))
+                .sum()
+        })
+        .collect())
+}
+
+/// Neutron Debye scattering intensity with each pair term weighted by
+/// `b_i * b_j`.
+///
+/// `scattering_lengths[i]` is the bound coherent scattering length of atom
+/// `i`, e.g. from [`NEUTRON_SCATTERING_LENGTHS`](super::NEUTRON_SCATTERING_LENGTHS),
+/// so the intensity is in fm^2.
+pub fn dse_neutron(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    scattering_lengths: &[f64],
+) -> Result<Vec<f64>> {
+    if scattering_lengths.len() != positions.len() {
+        return Err(Error::InvalidArgument(format!(
+            "scattering_lengths has {} entries but the crystal has {} atoms",
+            scattering_lengths.len(),
+            positions.len()
+        )));
+    }
+    let distances = DistanceMatrix::from_atoms(positions);
+    Ok(q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| weighted_pair_sum(q, &distances, scattering_lengths))
+        .collect())
+}
+
+/// Debye scattering intensity with the same Debye-Waller factor `b_factor`
+/// (Å^2) for every atom, i.e. [`dse_core`] scaled by `exp(-B * q^2 / (16 pi^2))`.
+pub fn dse_with_isotropic_bfactor(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    b_factor: f64,
+) -> Vec<f64> {
+    q_grid(min_q, max_q, q_step)
+        .into_iter()
+        .zip(dse_core(min_q, max_q, q_step, positions))
+        .map(|(q, intensity)| intensity * debye_waller_factor(b_factor, q))
+        .collect()
+}
+
+/// Debye scattering intensity truncated to pairs at most `r_cutoff` apart.
+///
+/// The distances of the `i < j` pairs within the cutoff are collected once and
+/// reused for every q, so after the O(N^2) scan the cost is O(N * k * N_q),
+/// with `k` the average number of neighbours within `r_cutoff`. Returns the
+/// intensities and the fraction of the `N (N - 1) / 2` distinct pairs kept.
+/// The self pairs are always included.
+pub fn dse_with_cutoff(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    r_cutoff: f64,
+) -> Result<(Vec<f64>, f64)> {
+    if r_cutoff.is_nan() || r_cutoff < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "r_cutoff must be non-negative, got {}",
+            r_cutoff
+        )));
+    }
+
+    let cutoff_sq = r_cutoff * r_cutoff;
+    let mut neighbours = Vec::new();
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            let dx = a[0] - b[0];
+            let dy = a[1] - b[1];
+            let dz = a[2] - b[2];
+            let dist_sq = dx * dx + dy * dy + dz * dz;
+            if dist_sq <= cutoff_sq {
+                neighbours.push(dist_sq);
+            }
+        }
+    }
+    let n_atoms = positions.len() as f64;
+    let n_pairs = n_atoms * (n_atoms - 1.0) / 2.0;
+    let fraction = if n_pairs > 0.0 {
+        neighbours.len() as f64 / n_pairs
+    } else {
+        1.0
+    };
+
+    let intensity = q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            let pairs: f64 = neighbours
+                .iter()
+                .map(|&dist_sq| intensity_point_optimized(q, dist_sq))
+                .sum();
+            n_atoms + 2.0 * pairs
+        })
+        .collect();
+    Ok((intensity, fraction))
+}
+
+/// Debye scattering intensity using the pair-distance histogram approximation.
+///
+/// The `i != j` pair distances are binned into `[k*dr, (k+1)*dr)` and every
+/// pair of a bin is evaluated at the bin centre, so the cost is
+/// O(N^2 + N_bins * N_q) instead of O(N^2 * N_q). The `N` self pairs are added
+/// exactly. The binning error scales as O(dr^2) for smooth pair distance
+/// distributions and roughly as O(q * dr) for the discrete distances of a
+/// perfect lattice. Fails unless `dr` is positive and gives at most
+/// [`MAX_DISTANCE_BINS`] bins across the bounding box of `positions`.
+pub fn dse_histogram(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    dr: f64,
+) -> Result<Vec<f64>> {
+    if dr.is_nan() || dr <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "dr must be positive, got {}",
+            dr
+        )));
+    }
+
+    let histogram = pair_distance_histogram(positions, dr)?;
+    let n_atoms = positions.len() as f64;
+
+    let intensity = q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            let pairs: f64 = histogram
+                .iter()
+                .enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(|(k, &count)| {
+                    let r = (k as f64 + 0.5) * dr;
+                    count as f64 * intensity_point_optimized(q, r * r)
+                })
+                .sum();
+            n_atoms + pairs
+        })
+        .collect();
+    Ok(intensity)
+}
+
+/// [`dse_core`] split by pair distance: row `k` holds, at each q of the
+/// grid, the contribution of the ordered pairs with `r_ij` in
+/// `[k shell_dr, (k + 1) shell_dr)`.
+///
+/// Unlike [`dse_histogram`] every pair is evaluated at its exact distance, so
+/// the rows add up to the full intensity to rounding. The `N` self pairs, at
+/// distance 0, belong to row 0, which is always present; the last row is the
+/// shell of the longest distance, and shells without pairs are rows of zeros.
+/// The distances are stored as in [`dse_symmetric`] and the shells are
+/// evaluated in parallel. Fails unless `shell_dr` is positive and gives at
+/// most [`MAX_DISTANCE_BINS`] shells across the bounding box of `positions`.
+pub fn dse_shell_decomposition(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    shell_dr: f64,
+) -> Result<Vec<Vec<f64>>> {
+    if shell_dr.is_nan() || shell_dr <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "shell_dr must be positive, got {}",
+            shell_dr
+        )));
+    }
+    checked_bin_count((bounding_box_diagonal(positions) / shell_dr).ceil() + 1.0)?;
+    let mut shells: Vec<Vec<f64>> = vec![Vec::new()];
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            let r = (0..3)
+                .map(|axis| (a[axis] - b[axis]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            let k = (r / shell_dr) as usize;
+            if k >= shells.len() {
+                shells.resize_with(k + 1, Vec::new);
+            }
+            shells[k].push(r);
+        }
+    }
+    let q_values = q_grid(min_q, max_q, q_step);
+    let n_atoms = positions.len() as f64;
+    Ok(shells
+        .par_iter()
+        .enumerate()
+        .map(|(k, distances)| {
+            let self_pairs = if k == 0 { n_atoms } else { 0.0 };
+            q_values
+                .iter()
+                .map(|&q| 2.0 * distances.iter().map(|&r| sinc(q * r)).sum::<f64>() + self_pairs)
+                .collect()
+        })
+        .collect())
+}
+
+/// Most distance bins [`dse_histogram`], [`dse_shell_decomposition`] and the
+/// g(r) functions allocate; a bin width too fine for the size of the crystal
+/// or the r range fails with [`Error::InvalidArgument`] instead.
+pub const MAX_DISTANCE_BINS: usize = 1 << 24;
+
+/// `bins` as a count, failing unless it is at most [`MAX_DISTANCE_BINS`], which
+/// no NaN or infinite count is.
+pub(crate) fn checked_bin_count(bins: f64) -> Result<usize> {
+    if bins <= MAX_DISTANCE_BINS as f64 {
+        Ok(bins as usize)
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "the bin width gives {} distance bins, more than the {} allowed",
+            bins, MAX_DISTANCE_BINS
+        )))
+    }
+}
+
+/// Diagonal of the bounding box of `positions`, which bounds every pair
+/// distance; 0 without positions.
+fn bounding_box_diagonal(positions: &[[f64; 3]]) -> f64 {
+    let mut lower = [f64::INFINITY; 3];
+    let mut upper = [f64::NEG_INFINITY; 3];
+    for p in positions {
+        for axis in 0..3 {
+            lower[axis] = lower[axis].min(p[axis]);
+            upper[axis] = upper[axis].max(p[axis]);
+        }
+    }
+    if positions.is_empty() {
+        0.0
+    } else {
+        (0..3)
+            .map(|axis| (upper[axis] - lower[axis]).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+/// Histogram of the ordered `i != j` pair distances in bins of width `dr`.
+fn pair_distance_histogram(positions: &[[f64; 3]], dr: f64) -> Result<Vec<u64>> {
+    let r_max = bounding_box_diagonal(positions);
+    let mut histogram = vec![0u64; checked_bin_count((r_max / dr).ceil() + 1.0)?];
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            let dx = a[0] - b[0];
+            let dy = a[1] - b[1];
+            let dz = a[2] - b[2];
+            let r = (dx * dx + dy * dy + dz * dz).sqrt();
+            histogram[(r / dr) as usize] += 2;
+        }
+    }
+    Ok(histogram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intensity_point_is_one_at_zero_qd() {
+        assert_eq!(intensity_point_optimized(0.0, 4.0), 1.0);
+        assert_eq!(intensity_point_optimized(3.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn intensity_point_is_smooth_at_small_qd() {
+        let x: f64 = 1e-7;
+        let expected = 1.0 - x * x / 6.0;
+        assert!((intensity_point_optimized(1.0, x * x) - expected).abs() < 1e-16);
+        // Both sides of the Taylor crossover.
+        let below = sinc(SINC_TAYLOR_THRESHOLD * (1.0 - 1e-9));
+        let above = sinc(SINC_TAYLOR_THRESHOLD);
+        assert!((below - above).abs() < 1e-15);
+    }
+
+    #[test]
+    fn intensity_point_matches_sin_x_over_x_at_large_qd() {
+        let (q, d) = (10.0, 100.0);
+        let x = q * d;
+        let value = intensity_point_optimized(q, d * d);
+        assert!((value - x.sin() / x).abs() < 1e-15);
+        assert!(value.abs() <= 1.0 / x);
+    }
+}
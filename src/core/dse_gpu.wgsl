@@ -0,0 +1,41 @@
+// Debye sum on the GPU: invocation (i, k) writes
+// partial_sums[k * n_atoms + i] = sum_j sin(q_k r_ij) / (q_k r_ij),
+// the contribution of atom i at the k-th q value of the chunk.
+
+struct Params {
+    n_atoms: u32,
+    n_q: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> positions: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read> q_values: array<f32>;
+@group(0) @binding(3) var<storage, read_write> partial_sums: array<f32>;
+
+// Below this |q r| the Taylor series 1 - x^2 / 6 is exact to single precision.
+const SINC_TAYLOR_THRESHOLD: f32 = 1e-3;
+
+fn sinc(x: f32) -> f32 {
+    if abs(x) < SINC_TAYLOR_THRESHOLD {
+        return 1.0 - x * x / 6.0;
+    }
+    return sin(x) / x;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    let k = id.y;
+    if i >= params.n_atoms || k >= params.n_q {
+        return;
+    }
+    let q = q_values[k];
+    let p = positions[i].xyz;
+    var sum = 0.0;
+    for (var j = 0u; j < params.n_atoms; j++) {
+        sum += sinc(q * distance(p, positions[j].xyz));
+    }
+    partial_sums[k * params.n_atoms + i] = sum;
+}
@@ -0,0 +1,330 @@
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use super::{
+    crystal_core, dse_at_q, dse_core, form_factor_cylinder, form_factor_sphere, q_grid, r_factor,
+    DistanceMatrix, Error, Result, Shape,
+};
+
+fn no_form_factor(shape: Shape) -> Error {
+    Error::InvalidArgument(format!(
+        "shape {:?} has no analytic form factor; expected a sphere, cylinder or disk",
+        shape
+    ))
+}
+
+/// Form factor of the continuous particle that a crystal of `shape` and
+/// radius `radius_nm` approximates: [`form_factor_sphere`] for
+/// [`Shape::Sphere`] and [`form_factor_cylinder`] of height
+/// `2 radius_nm aspect_ratio` for [`Shape::Cylinder`] and [`Shape::Disk`].
+fn analytic_form_factor(q_values: &[f64], shape: Shape, radius_nm: f64) -> Result<Vec<f64>> {
+    match shape {
+        Shape::Sphere => form_factor_sphere(q_values, radius_nm),
+        Shape::Cylinder { aspect_ratio } | Shape::Disk { aspect_ratio } => {
+            form_factor_cylinder(q_values, radius_nm, 2.0 * radius_nm * aspect_ratio)
+        }
+        _ => Err(no_form_factor(shape)),
+    }
+}
+
+/// Volume of the particle of [`analytic_form_factor`] divided by the cube
+/// of its radius.
+fn volume_over_radius_cubed(shape: Shape) -> Result<f64> {
+    match shape {
+        Shape::Sphere => Ok(4.0 * PI / 3.0),
+        Shape::Cylinder { aspect_ratio } | Shape::Disk { aspect_ratio } => {
+            Ok(2.0 * PI * aspect_ratio)
+        }
+        _ => Err(no_form_factor(shape)),
+    }
+}
+
+/// `|I / N^2 - P| / P` at each q, for the intensities of `n_atoms` atoms.
+fn relative_errors(intensities: &[f64], n_atoms: usize, form_factor: &[f64]) -> Vec<f64> {
+    let n_sq = (n_atoms as f64).powi(2);
+    intensities
+        .iter()
+        .zip(form_factor)
+        .map(|(&intensity, &p)| (intensity / n_sq - p).abs() / p)
+        .collect()
+}
+
+/// Relative deviation `|I(q) / N^2 - P(q)| / P(q)` of the [`dse_core`]
+/// intensities of a crystal of `N` atoms from the form factor `P` of the
+/// continuous particle of `shape` and radius `radius_nm`, at each q of the
+/// grid.
+///
+/// `P` is [`form_factor_sphere`] for [`Shape::Sphere`] and
+/// [`form_factor_cylinder`] of height `2 radius_nm aspect_ratio` for
+/// [`Shape::Cylinder`] and [`Shape::Disk`]. The deviation measures the
+/// discreteness of the lattice: it grows towards the zeros of `P`, the first
+/// at `q R ~ 4.49` for a sphere, and with the Bragg peaks at high q, so it is
+/// meaningful below the first minimum. Fails for other shapes, if
+/// `radius_nm` is not positive or if there are no atoms.
+pub fn dse_finite_size_error(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    shape: Shape,
+    radius_nm: f64,
+) -> Result<Vec<f64>> {
+    if positions.is_empty() {
+        return Err(Error::InvalidArgument(
+            "positions must contain at least one atom".to_string(),
+        ));
+    }
+    let form_factor = analytic_form_factor(&q_grid(min_q, max_q, q_step), shape, radius_nm)?;
+    let intensities = dse_core(min_q, max_q, q_step, positions);
+    Ok(relative_errors(&intensities, positions.len(), &form_factor))
+}
+
+/// Largest [`dse_finite_size_error`] over the q grid of a crystal built by
+/// [`crystal_core`] with `shape` and `size` for each of `lattice_params`.
+///
+/// Each crystal of `N` atoms is compared with the particle of its own volume
+/// `N lattice_param^3`, whose radius is close to but below `size / 2`, so
+/// that the error measures the discreteness of the lattice rather than the
+/// atoms missing at the rim. It falls, not quite monotonically, as the
+/// lattice gets finer relative to the particle. The lattice parameters are
+/// evaluated in parallel, each serially on one thread. Fails as
+/// [`dse_finite_size_error`] for shapes without an analytic form factor, if
+/// `size` or a lattice parameter is not positive or if a crystal is empty.
+pub fn convergence_with_size(
+    shape: Shape,
+    lattice_params: &[f64],
+    size: f64,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+) -> Result<Vec<f64>> {
+    let volume_factor = volume_over_radius_cubed(shape)?;
+    if size.is_nan() || size <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "size must be positive, got {}",
+            size
+        )));
+    }
+    let q_values = q_grid(min_q, max_q, q_step);
+    lattice_params
+        .par_iter()
+        .map(|&lattice_param| {
+            if lattice_param.is_nan() || lattice_param <= 0.0 {
+                return Err(Error::InvalidArgument(format!(
+                    "lattice parameters must be positive, got {}",
+                    lattice_param
+                )));
+            }
+            let crystal = crystal_core(shape, lattice_param, size)?;
+            if crystal.is_empty() {
+                return Err(Error::InvalidArgument(format!(
+                    "the crystal of lattice parameter {} and size {} has no atoms",
+                    lattice_param, size
+                )));
+            }
+            let volume = crystal.len() as f64 * lattice_param.powi(3);
+            let radius = (volume / volume_factor).cbrt();
+            let form_factor = analytic_form_factor(&q_values, shape, radius)?;
+            let distances = DistanceMatrix::from_atoms(crystal.positions());
+            let intensities: Vec<f64> = q_values.iter().map(|&q| dse_at_q(q, &distances)).collect();
+            Ok(relative_errors(&intensities, crystal.len(), &form_factor)
+                .into_iter()
+                .fold(0.0, f64::max))
+        })
+        .collect()
+}
+
+/// [`r_factor`] between the [`dse_core`] curves of consecutive sizes, the
+/// curve of `sizes[i + 1]` as observed and that of `sizes[i]` as calculated,
+/// for [`crystal_core`] crystals of `shape` and `lattice_param`.
+///
+/// The optimal scale factor of the R-factor absorbs the growth of `I` as
+/// `N^2`, so it compares the shapes of the curves only; it falls roughly as
+/// the relative change of size as the crystals grow. Returns one value per
+/// pair, `sizes.len() - 1` in all. The sizes are evaluated in parallel, each
+/// serially on one thread. Fails unless `lattice_param` is positive, there
+/// are at least two strictly increasing positive sizes and no crystal is
+/// empty, if two consecutive sizes give the same crystal, whose R-factor of
+/// 0 would say nothing about convergence, and for shapes not sized by
+/// length, as [`dse_size_averaged`](super::dse_size_averaged) does.
+pub fn convergence_plot_data(
+    shape: Shape,
+    lattice_param: f64,
+    sizes: &[f64],
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+) -> Result<Vec<f64>> {
+    if !shape.is_sized_by_length() {
+        return Err(Error::InvalidArgument(
+            "ellipsoid and icosahedron crystals are not sized by length and cannot be compared \
+             across sizes"
+                .to_string(),
+        ));
+    }
+    if lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param must be positive, got {}",
+            lattice_param
+        )));
+    }
+    if sizes.len() < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "sizes must hold at least two sizes to compare, got {}",
+            sizes.len()
+        )));
+    }
+    if let Some(&bad) = sizes.iter().find(|&&size| size.is_nan() || size <= 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "sizes must be positive, got {}",
+            bad
+        )));
+    }
+    if let Some(pair) = sizes.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(Error::InvalidArgument(format!(
+            "sizes must be strictly increasing, got {} then {}",
+            pair[0], pair[1]
+        )));
+    }
+    let q_values = q_grid(min_q, max_q, q_step);
+    let curves = sizes
+        .par_iter()
+        .map(|&size| {
+            let crystal = crystal_core(shape, lattice_param, size)?;
+            if crystal.is_empty() {
+                return Err(Error::InvalidArgument(format!(
+                    "the crystal of lattice parameter {} and size {} has no atoms",
+                    lattice_param, size
+                )));
+            }
+            let distances = DistanceMatrix::from_atoms(crystal.positions());
+            let intensities: Vec<f64> = q_values.iter().map(|&q| dse_at_q(q, &distances)).collect();
+            Ok((crystal.len(), intensities))
+        })
+        .collect::<Result<Vec<(usize, Vec<f64>)>>>()?;
+    curves
+        .windows(2)
+        .zip(sizes.windows(2))
+        .map(|(pair, pair_sizes)| {
+            let ((n_smaller, smaller), (n_larger, larger)) = (&pair[0], &pair[1]);
+            // The crystals only grow, so an equal count is the same crystal.
+            if n_smaller == n_larger {
+                return Err(Error::InvalidArgument(format!(
+                    "sizes {} and {} give the same crystal of {} atoms; space the sizes by \
+                     about a lattice parameter",
+                    pair_sizes[0], pair_sizes[1], n_smaller
+                )));
+            }
+            r_factor(&q_values, larger, smaller, None)
+        })
+        .collect()
+}
+
+/// Smallest of `sizes` whose [`convergence_plot_data`] R-factor against the
+/// next size is below `tolerance`: the size from which a larger crystal no
+/// longer changes the shape of the curve, to that tolerance.
+///
+/// The first such size is returned even if a later pair rises above the
+/// tolerance again. Fails as [`convergence_plot_data`], unless `tolerance`
+/// is positive, or if no pair of consecutive sizes converges.
+pub fn convergence_test(
+    shape: Shape,
+    lattice_param: f64,
+    sizes: &[f64],
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    tolerance: f64,
+) -> Result<f64> {
+    if tolerance.is_nan() || tolerance <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "tolerance must be positive, got {}",
+            tolerance
+        )));
+    }
+    let r_factors = convergence_plot_data(shape, lattice_param, sizes, min_q, max_q, q_step)?;
+    r_factors
+        .iter()
+        .position(|&r| r < tolerance)
+        .map(|i| sizes[i])
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "no consecutive sizes agree to an R-factor below {}; the last pair, sizes {} \
+                 and {}, has {}",
+                tolerance,
+                sizes[sizes.len() - 2],
+                sizes[sizes.len() - 1],
+                r_factors[r_factors.len() - 1]
+            ))
+        })
+}
+
+/// Smallest distance between two atoms, the lattice parameter of a simple
+/// cubic crystal; `None` for fewer than two distinct positions.
+fn nearest_neighbour_distance(positions: &[[f64; 3]]) -> Option<f64> {
+    let closest = positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            positions[i + 1..]
+                .iter()
+                .map(|b| (0..3).map(|axis| (a[axis] - b[axis]).powi(2)).sum::<f64>())
+                .filter(|&distance_sq| distance_sq > 0.0)
+                .fold(f64::INFINITY, f64::min)
+        })
+        .reduce(|| f64::INFINITY, f64::min);
+    closest.is_finite().then(|| closest.sqrt())
+}
+
+/// [`dse_core`] intensities divided by `N^2 P(q)`, with `P` the
+/// [`form_factor_sphere`] of the sphere of the crystal's volume, returned with
+/// the q values.
+///
+/// The volume is `N lattice_param^3`, that of a simple cubic crystal, so the
+/// radius is `R = (3 N / (4 pi))^(1/3) lattice_param`. Without
+/// `lattice_param` the smallest interatomic distance is used. The ratio is
+/// close to 1 below the first zero of `P`, at `q R ~ 4.49`, for a sphere, and
+/// departs from it at lower q the less spherical the crystal is, much like a
+/// normalised Kratky plot. It diverges at the zeros of `P`. Fails if there are
+/// no atoms, `lattice_param` is not positive, or it is missing and there are
+/// fewer than two distinct positions.
+pub fn dse_normalized_by_sphere(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    lattice_param: Option<f64>,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    if positions.is_empty() {
+        return Err(Error::InvalidArgument(
+            "positions must contain at least one atom".to_string(),
+        ));
+    }
+    let lattice_param = match lattice_param {
+        Some(a) if a.is_nan() || a <= 0.0 => {
+            return Err(Error::InvalidArgument(format!(
+                "lattice_param must be positive, got {}",
+                a
+            )))
+        }
+        Some(a) => a,
+        None => nearest_neighbour_distance(positions).ok_or_else(|| {
+            Error::InvalidArgument(
+                "cannot estimate lattice_param from fewer than two distinct atom positions"
+                    .to_string(),
+            )
+        })?,
+    };
+    let volume = positions.len() as f64 * lattice_param.powi(3);
+    let radius = (volume / volume_over_radius_cubed(Shape::Sphere)?).cbrt();
+    let q_values = q_grid(min_q, max_q, q_step);
+    let form_factor = form_factor_sphere(&q_values, radius)?;
+    let n_sq = (positions.len() as f64).powi(2);
+    let ratio = dse_core(min_q, max_q, q_step, positions)
+        .into_iter()
+        .zip(&form_factor)
+        .map(|(intensity, &p)| intensity / (n_sq * p))
+        .collect();
+    Ok((q_values, ratio))
+}
@@ -0,0 +1,111 @@
+/// Cromer-Mann coefficients of the X-ray atomic form factor
+/// `f(s) = sum_i a_i * exp(-b_i * s^2) + c` with `s = sin(theta) / lambda` in 1/Å.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CromerMannCoeffs {
+    pub a: [f64; 4],
+    pub b: [f64; 4],
+    pub c: f64,
+}
+
+/// Cromer-Mann coefficients of neutral atoms from the International Tables for
+/// Crystallography, Vol. C, Table 6.1.1.4.
+pub const CROMER_MANN_TABLE: [(&str, CromerMannCoeffs); 7] = [
+    (
+        "H",
+        CromerMannCoeffs {
+            a: [0.489918, 0.262003, 0.196767, 0.049879],
+            b: [20.6593, 7.74039, 49.5519, 2.20159],
+            c: 0.001305,
+        },
+    ),
+    (
+        "C",
+        CromerMannCoeffs {
+            a: [2.31, 1.02, 1.5886, 0.865],
+            b: [20.8439, 10.2075, 0.5687, 51.6512],
+            c: 0.2156,
+        },
+    ),
+    (
+        "N",
+        CromerMannCoeffs {
+            a: [12.2126, 3.1322, 2.0125, 1.1663],
+            b: [0.0057, 9.8933, 28.9975, 0.5826],
+            c: -11.529,
+        },
+    ),
+    (
+        "O",
+        CromerMannCoeffs {
+            a: [3.0485, 2.2868, 1.5463, 0.867],
+            b: [13.2771, 5.7011, 0.3239, 32.9089],
+            c: 0.2508,
+        },
+    ),
+    (
+        "Fe",
+        CromerMannCoeffs {
+            a: [11.7695, 7.3573, 3.5222, 2.3045],
+            b: [4.7611, 0.3072, 15.3535, 76.8805],
+            c: 1.0369,
+        },
+    ),
+    (
+        "Ag",
+        CromerMannCoeffs {
+            a: [19.2808, 16.6885, 4.8045, 1.0463],
+            b: [0.6446, 7.4726, 24.6605, 99.8156],
+            c: 5.179,
+        },
+    ),
+    (
+        "Au",
+        CromerMannCoeffs {
+            a: [16.8819, 18.5913, 25.5582, 5.86],
+            b: [0.4611, 8.6216, 1.4826, 36.3956],
+            c: 12.0658,
+        },
+    ),
+];
+
+/// Look up the built-in Cromer-Mann coefficients of an element by symbol.
+pub fn cromer_mann_coeffs(symbol: &str) -> Option<&'static CromerMannCoeffs> {
+    CROMER_MANN_TABLE
+        .iter()
+        .find(|(s, _)| *s == symbol)
+        .map(|(_, coeffs)| coeffs)
+}
+
+/// Cromer-Mann atomic form factor at scattering vector magnitude `q` in 1/nm.
+pub fn cromer_mann(q: f64, coeffs: &CromerMannCoeffs) -> f64 {
+    // s = q / (4 pi) with q converted from 1/nm to 1/Å to match the tabulated b_i (Å^2).
+    let s = q / (4.0 * std::f64::consts::PI * 10.0);
+    let s_sq = s * s;
+    coeffs
+        .a
+        .iter()
+        .zip(&coeffs.b)
+        .map(|(&a, &b)| a * (-b * s_sq).exp())
+        .sum::<f64>()
+        + coeffs.c
+}
+
+/// Bound coherent neutron scattering lengths in fm (Sears, Neutron News 3,
+/// 1992). Unlike X-ray form factors they do not depend on q and can be
+/// negative.
+pub const NEUTRON_SCATTERING_LENGTHS: [(&str, f64); 5] = [
+    ("H", -3.739),
+    ("D", 6.671),
+    ("C", 6.646),
+    ("N", 9.36),
+    ("O", 5.803),
+];
+
+/// Look up the coherent neutron scattering length (fm) of an element or
+/// isotope symbol.
+pub fn neutron_scattering_length(symbol: &str) -> Option<f64> {
+    NEUTRON_SCATTERING_LENGTHS
+        .iter()
+        .find(|(s, _)| *s == symbol)
+        .map(|&(_, b)| b)
+}
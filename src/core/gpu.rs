@@ -0,0 +1,206 @@
+use std::sync::mpsc;
+
+use wgpu::util::DeviceExt;
+
+use super::{center_crystal, q_grid, Error, Result};
+
+/// WGSL compute shader of [`dse_gpu`], with one invocation per atom and q.
+const DSE_SHADER: &str = include_str!("dse_gpu.wgsl");
+
+/// Invocations per workgroup along the atom axis, as in `@workgroup_size`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Largest number of workgroups per dimension of a dispatch guaranteed by
+/// WebGPU.
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65_535;
+
+/// [`dse_core`](super::dse_core) on the GPU, through a WGPU compute shader.
+///
+/// The positions are centred and uploaded once in single precision. Each
+/// dispatch covers a 2D grid of `(atom i, q index)` invocations, each of which
+/// sums `sinc(q r_ij)` over all atoms `j`, computing the distances on the fly,
+/// so no `N x N` matrix is stored. The per-atom sums are read back and added
+/// in double precision. As many q values are sent per dispatch as the
+/// output buffer allows for the device's storage buffer limits.
+///
+/// The kernel runs in `f32`, so the accuracy is that of
+/// [`dse_core_f32`](super::dse_core_f32) rather than of the `f64` code. Fails
+/// with [`Error::Gpu`] if no adapter or device is available.
+pub fn dse_gpu(min_q: f64, max_q: f64, q_step: f64, positions: &[[f64; 3]]) -> Result<Vec<f64>> {
+    let q_values = q_grid(min_q, max_q, q_step);
+    if positions.is_empty() || q_values.is_empty() {
+        return Ok(vec![0.0; q_values.len()]);
+    }
+    pollster::block_on(dse_gpu_async(&q_values, positions))
+}
+
+async fn dse_gpu_async(q_values: &[f64], positions: &[[f64; 3]]) -> Result<Vec<f64>> {
+    let gpu_error = |err: &dyn std::fmt::Display| Error::Gpu(err.to_string());
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| gpu_error(&err))?;
+    let limits = adapter.limits();
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            label: Some("fast_dse"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| gpu_error(&err))?;
+
+    let n_atoms = positions.len();
+    let row_bytes = 4 * n_atoms as u64;
+    let output_limit = limits
+        .max_storage_buffer_binding_size
+        .min(limits.max_buffer_size);
+    let q_per_chunk = (output_limit / row_bytes).min(MAX_WORKGROUPS_PER_DIMENSION as u64) as usize;
+    let atom_groups = n_atoms.div_ceil(WORKGROUP_SIZE as usize);
+    if q_per_chunk == 0 || atom_groups > MAX_WORKGROUPS_PER_DIMENSION as usize {
+        return Err(Error::Gpu(format!(
+            "{} atoms exceed the buffer limits of the GPU",
+            n_atoms
+        )));
+    }
+
+    // Centring keeps the coordinates small, which matters in single precision.
+    let centred = center_crystal(positions);
+    let position_bytes: Vec<u8> = centred
+        .positions()
+        .iter()
+        .flat_map(|p| [p[0] as f32, p[1] as f32, p[2] as f32, 0.0])
+        .flat_map(f32::to_ne_bytes)
+        .collect();
+    let positions_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("positions"),
+        size: position_bytes.len() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("positions staging"),
+        contents: &position_bytes,
+        usage: wgpu::BufferUsages::COPY_SRC,
+    });
+    let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("params"),
+        size: 16,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let q_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("q values"),
+        size: 4 * q_per_chunk.min(q_values.len()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let output_size = row_bytes * q_per_chunk.min(q_values.len()) as u64;
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("partial sums"),
+        size: output_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: output_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("dse"),
+        source: wgpu::ShaderSource::Wgsl(DSE_SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("dse"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("dse"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: positions_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: q_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: output_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(
+        &staging_buffer,
+        0,
+        &positions_buffer,
+        0,
+        position_bytes.len() as u64,
+    );
+    queue.submit([encoder.finish()]);
+
+    let mut intensities = Vec::with_capacity(q_values.len());
+    for chunk in q_values.chunks(q_per_chunk) {
+        let params = [n_atoms as u32, chunk.len() as u32, 0, 0];
+        let params_bytes: Vec<u8> = params.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let q_bytes: Vec<u8> = chunk
+            .iter()
+            .flat_map(|&q| (q as f32).to_ne_bytes())
+            .collect();
+        queue.write_buffer(&params_buffer, 0, &params_bytes);
+        queue.write_buffer(&q_buffer, 0, &q_bytes);
+
+        let chunk_bytes = row_bytes * chunk.len() as u64;
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(atom_groups as u32, chunk.len() as u32, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &readback_buffer, 0, chunk_bytes);
+        queue.submit([encoder.finish()]);
+
+        let slice = readback_buffer.slice(..chunk_bytes);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|err| gpu_error(&err))?;
+        receiver
+            .recv()
+            .map_err(|err| gpu_error(&err))?
+            .map_err(|err| gpu_error(&err))?;
+        {
+            let view = slice.get_mapped_range().map_err(|err| gpu_error(&err))?;
+            intensities.extend(view.chunks_exact(row_bytes as usize).map(|row| {
+                row.chunks_exact(4)
+                    .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                    .sum::<f64>()
+            }));
+        }
+        readback_buffer.unmap();
+    }
+    Ok(intensities)
+}
@@ -0,0 +1,170 @@
+use rayon::prelude::*;
+
+use super::dse::sinc;
+use super::{dse_at_q, dse_core, q_grid, Atoms, DistanceMatrix, Error, Result};
+
+/// Debye intensity of a crystal that is updated as atoms are added and
+/// removed, e.g. along a trajectory where few atoms change per step.
+///
+/// [`update`](Self::update) adds and subtracts only the pair terms involving
+/// the changed atoms, O(k * N * N_q) for k changed atoms instead of the
+/// O(N^2 * N_q) of [`dse_core`]. Only the positions are stored, not an
+/// `N x N` distance matrix, since every update needs distances to the changed
+/// atoms only. Rounding errors accumulate over many updates;
+/// [`rebuild`](Self::rebuild) recomputes the intensity from scratch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalDse {
+    q_values: Vec<f64>,
+    positions: Atoms,
+    intensity: Vec<f64>,
+}
+
+impl IncrementalDse {
+    /// Start from the [`dse_core`] intensity of `positions` on the
+    /// [`q_grid`] of `min_q`, `max_q` and `q_step`.
+    pub fn new(min_q: f64, max_q: f64, q_step: f64, positions: &[[f64; 3]]) -> Self {
+        Self {
+            q_values: q_grid(min_q, max_q, q_step),
+            positions: positions.iter().copied().collect(),
+            intensity: dse_core(min_q, max_q, q_step, positions),
+        }
+    }
+
+    /// Remove the atoms at `removed_indices`, then append `added_atoms`, and
+    /// update the intensity accordingly.
+    ///
+    /// Indices refer to the atoms before the update; the remaining atoms keep
+    /// their order. Fails, leaving `self` unchanged, if an index is out of
+    /// range or repeated.
+    pub fn update(&mut self, added_atoms: &[[f64; 3]], removed_indices: &[usize]) -> Result<()> {
+        let mut removed = vec![false; self.positions.len()];
+        for &i in removed_indices {
+            match removed.get_mut(i) {
+                None => {
+                    return Err(Error::InvalidArgument(format!(
+                        "removed index {} is out of range for {} atoms",
+                        i,
+                        self.positions.len()
+                    )))
+                }
+                Some(true) => {
+                    return Err(Error::InvalidArgument(format!(
+                        "atom {} is removed more than once",
+                        i
+                    )))
+                }
+                Some(flag) => *flag = true,
+            }
+        }
+        let (gone, kept): (Vec<_>, Vec<_>) = self
+            .positions
+            .iter()
+            .zip(&removed)
+            .partition(|(_, &removed)| removed);
+        let gone: Vec<[f64; 3]> = gone.into_iter().map(|(p, _)| p).collect();
+        let kept: Atoms = kept.into_iter().map(|(p, _)| p).collect();
+        let kept_positions = kept.positions();
+
+        self.intensity
+            .par_iter_mut()
+            .zip(&self.q_values)
+            .for_each(|(intensity, &q)| {
+                *intensity += 2.0 * cross_sum(q, added_atoms, kept_positions)
+                    + cross_sum(q, added_atoms, added_atoms)
+                    - 2.0 * cross_sum(q, &gone, kept_positions)
+                    - cross_sum(q, &gone, &gone);
+            });
+        self.positions = kept;
+        for &p in added_atoms {
+            self.positions.push(p);
+        }
+        Ok(())
+    }
+
+    /// Recompute the intensity of the current atoms as [`dse_core`] does,
+    /// discarding the rounding errors accumulated by [`update`](Self::update).
+    pub fn rebuild(&mut self) {
+        let distances = DistanceMatrix::from_atoms(self.positions.positions());
+        self.intensity = self
+            .q_values
+            .par_iter()
+            .map(|&q| dse_at_q(q, &distances))
+            .collect();
+    }
+
+    /// Current intensity at each q value.
+    pub fn intensity(&self) -> &[f64] {
+        &self.intensity
+    }
+
+    /// The q values of the intensity.
+    pub fn q_values(&self) -> &[f64] {
+        &self.q_values
+    }
+
+    /// Current atom positions.
+    pub fn positions(&self) -> &Atoms {
+        &self.positions
+    }
+}
+
+/// `sum_{i in a, j in b} sin(q r_ij) / (q r_ij)`, evaluated serially.
+fn cross_sum(q: f64, a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    a.iter()
+        .map(|p| {
+            b.iter()
+                .map(|r| {
+                    let dist_sq =
+                        (p[0] - r[0]).powi(2) + (p[1] - r[1]).powi(2) + (p[2] - r[2]).powi(2);
+                    sinc(q * dist_sq.sqrt())
+                })
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+/// Change `I(q, r + delta_r) - I(q, r)` of the [`dse_core`] intensity when the
+/// atom at `atom_index` moves by `displacement`, on the [`q_grid`] of
+/// `min_q`, `max_q` and `q_step`.
+///
+/// Pairs not involving the moved atom keep their distances and cancel in the
+/// difference, so only the `2 (N - 1)` terms of its row are evaluated:
+/// O(N * N_q) instead of the O(N^2 * N_q) of two full sums, without the
+/// `N x N` distance matrix. The q values are evaluated in parallel. Fails if
+/// `atom_index` is out of range.
+pub fn dse_differential(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    atom_index: usize,
+    displacement: [f64; 3],
+) -> Result<Vec<f64>> {
+    let Some(&old) = positions.get(atom_index) else {
+        return Err(Error::InvalidArgument(format!(
+            "atom_index {} is out of range for {} atoms",
+            atom_index,
+            positions.len()
+        )));
+    };
+    let new = [0, 1, 2].map(|axis| old[axis] + displacement[axis]);
+    let distance = |a: [f64; 3], b: &[f64; 3]| {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+    };
+    let (old_distances, new_distances): (Vec<f64>, Vec<f64>) = positions
+        .iter()
+        .enumerate()
+        .filter(|&(j, _)| j != atom_index)
+        .map(|(_, r)| (distance(old, r), distance(new, r)))
+        .unzip();
+    Ok(q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| {
+            2.0 * old_distances
+                .iter()
+                .zip(&new_distances)
+                .map(|(&r_old, &r_new)| sinc(q * r_new) - sinc(q * r_old))
+                .sum::<f64>()
+        })
+        .collect())
+}
@@ -0,0 +1,805 @@
+use std::io::BufRead;
+
+use super::{Atoms, Error, Result};
+
+/// Ångströms per nanometer.
+const ANGSTROM_PER_NM: f64 = 10.0;
+
+/// Most atoms reserved up front from a count in the file. The count is not
+/// trusted: a larger one is read row by row and fails with [`Error::Parse`]
+/// where the rows run out, instead of aborting on the allocation.
+const MAX_PREALLOCATED_ATOMS: usize = 1 << 20;
+
+/// Parse the positions of the first frame of an XYZ file.
+///
+/// The first line holds the atom count, the second a comment, followed by one
+/// `SYMBOL X Y Z` row per atom with coordinates in Å. Extra columns, as written
+/// by extended XYZ, are ignored, as are the element symbols. Positions are
+/// returned in nanometers.
+pub fn crystal_from_xyz(content: &str) -> Result<Atoms> {
+    let mut lines = content.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    let (_, count_line) = lines.next().ok_or_else(|| Error::Parse {
+        line: 1,
+        message: "missing atom count".to_string(),
+    })?;
+    let n_atoms: usize = count_line.trim().parse().map_err(|_| Error::Parse {
+        line: 1,
+        message: format!("expected an atom count, got '{}'", count_line.trim()),
+    })?;
+    // Comment line
+    lines.next();
+
+    let mut atoms = Atoms::with_capacity(n_atoms.min(MAX_PREALLOCATED_ATOMS));
+    for _ in 0..n_atoms {
+        let (line_number, line) = lines.next().ok_or_else(|| Error::Parse {
+            line: content.lines().count() + 1,
+            message: format!("expected {} atoms, found {}", n_atoms, atoms.len()),
+        })?;
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() < 4 {
+            return Err(Error::Parse {
+                line: line_number,
+                message: format!(
+                    "expected 'SYMBOL X Y Z', got {} column(s): '{}'",
+                    columns.len(),
+                    line.trim()
+                ),
+            });
+        }
+        let mut position = [0.0; 3];
+        for (axis, column) in columns[1..4].iter().enumerate() {
+            let value: f64 = column.parse().map_err(|_| Error::Parse {
+                line: line_number,
+                message: format!("invalid coordinate '{}'", column),
+            })?;
+            position[axis] = value / ANGSTROM_PER_NM;
+        }
+        atoms.push(position);
+    }
+    Ok(atoms)
+}
+
+/// Parse positions from comma-separated `x,y,z` rows in nanometers.
+///
+/// Blank lines and lines starting with `#` are skipped, and so is the first
+/// remaining line when its first three fields are not all numbers (a header
+/// such as `x,y,z`). Columns after z are ignored. Unlike XYZ and CIF, the
+/// coordinates are read as nanometers, the units of every `crystal_*`
+/// function, so positions saved with `numpy.savetxt(path, positions,
+/// delimiter=',')` round-trip.
+pub fn crystal_from_csv(content: &str) -> Result<Atoms> {
+    Ok(csv_rows::<3>(content, "x,y,z")?.into_iter().collect())
+}
+
+/// CSV with a `q,intensity` header and one row per q point, readable by
+/// [`iq_from_csv`].
+pub fn iq_to_csv(q_values: &[f64], intensities: &[f64]) -> String {
+    let mut csv = String::from("q,intensity\n");
+    for (q, i) in q_values.iter().zip(intensities) {
+        csv.push_str(&format!("{},{}\n", q, i));
+    }
+    csv
+}
+
+/// Parse `(q_values, intensities)` from comma-separated `q,intensity` rows,
+/// with the header and comment rules of [`crystal_from_csv`].
+pub fn iq_from_csv(content: &str) -> Result<(Vec<f64>, Vec<f64>)> {
+    Ok(csv_rows::<2>(content, "q,intensity")?
+        .into_iter()
+        .map(|[q, i]| (q, i))
+        .unzip())
+}
+
+/// The first `N` numeric fields of every comma-separated row of `content`.
+///
+/// Blank lines and `#` comments are skipped, as is a first row whose fields
+/// are not all numbers. `layout` names the expected fields in error messages.
+fn csv_rows<const N: usize>(content: &str, layout: &str) -> Result<Vec<[f64; N]>> {
+    let mut rows = Vec::new();
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    'rows: for (row, (line_number, line)) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < N {
+            return Err(Error::Parse {
+                line: line_number,
+                message: format!(
+                    "expected '{}', got {} field(s): '{}'",
+                    layout,
+                    fields.len(),
+                    line
+                ),
+            });
+        }
+        let mut values = [0.0; N];
+        for (value, field) in values.iter_mut().zip(&fields) {
+            match field.parse() {
+                Ok(parsed) => *value = parsed,
+                Err(_) if row == 0 => continue 'rows,
+                Err(_) => {
+                    return Err(Error::Parse {
+                        line: line_number,
+                        message: format!("invalid number '{}'", field),
+                    })
+                }
+            }
+        }
+        rows.push(values);
+    }
+    Ok(rows)
+}
+
+/// Lattice vectors of a cell with lengths `a`, `b`, `c` and angles `alpha`,
+/// `beta`, `gamma` (degrees), with `a` along x and `b` in the xy plane.
+fn cell_vectors([a, b, c]: [f64; 3], [alpha, beta, gamma]: [f64; 3]) -> Result<[[f64; 3]; 3]> {
+    // Exact for right angles, so orthogonal cells have no 1e-17 off-axis components.
+    let cos_deg = |angle: f64| {
+        if angle == 90.0 {
+            0.0
+        } else {
+            angle.to_radians().cos()
+        }
+    };
+    let (cos_alpha, cos_beta, cos_gamma) = (cos_deg(alpha), cos_deg(beta), cos_deg(gamma));
+    let sin_gamma = (1.0 - cos_gamma * cos_gamma).sqrt();
+    let volume_factor = 1.0 - cos_alpha.powi(2) - cos_beta.powi(2) - cos_gamma.powi(2)
+        + 2.0 * cos_alpha * cos_beta * cos_gamma;
+    if volume_factor <= 0.0 || sin_gamma.abs() < 1e-12 {
+        return Err(Error::InvalidArgument(format!(
+            "the cell angles {}, {}, {} do not describe a cell with a volume",
+            alpha, beta, gamma
+        )));
+    }
+    Ok([
+        [a, 0.0, 0.0],
+        [b * cos_gamma, b * sin_gamma, 0.0],
+        [
+            c * cos_beta,
+            c * (cos_alpha - cos_beta * cos_gamma) / sin_gamma,
+            c * volume_factor.sqrt() / sin_gamma,
+        ],
+    ])
+}
+
+/// A CIF token and the line it starts on. Quoted strings and text fields are
+/// never tags or keywords.
+struct CifToken<'a> {
+    line: usize,
+    text: &'a str,
+    quoted: bool,
+}
+
+impl CifToken<'_> {
+    fn is_tag(&self) -> bool {
+        !self.quoted && self.text.starts_with('_')
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        !self.quoted
+            && self
+                .text
+                .get(..keyword.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Split CIF content into tokens, dropping comments.
+fn cif_tokens(content: &str) -> Result<Vec<CifToken<'_>>> {
+    let mut tokens = Vec::new();
+    let mut lines = content.lines().enumerate().map(|(i, line)| (i + 1, line));
+    while let Some((line_number, line)) = lines.next() {
+        if let Some(first) = line.strip_prefix(';') {
+            // Semicolon-delimited text field, closed by a line starting with ';'.
+            let mut closed = false;
+            for (_, text_line) in lines.by_ref() {
+                if text_line.starts_with(';') {
+                    closed = true;
+                    break;
+                }
+            }
+            if !closed {
+                return Err(Error::Parse {
+                    line: line_number,
+                    message: "unterminated text field".to_string(),
+                });
+            }
+            tokens.push(CifToken {
+                line: line_number,
+                text: first,
+                quoted: true,
+            });
+            continue;
+        }
+
+        let mut rest = line.trim_start();
+        while !rest.is_empty() && !rest.starts_with('#') {
+            let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"');
+            let (text, remainder, quoted) = match quote {
+                Some(quote) => {
+                    // A closing quote only ends the string when followed by whitespace.
+                    let body = &rest[1..];
+                    let end = body
+                        .char_indices()
+                        .find(|&(i, c)| {
+                            c == quote
+                                && body[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                        })
+                        .map(|(i, _)| i)
+                        .ok_or_else(|| Error::Parse {
+                            line: line_number,
+                            message: "unterminated quoted string".to_string(),
+                        })?;
+                    (&body[..end], &body[end + 1..], true)
+                }
+                None => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..], false)
+                }
+            };
+            tokens.push(CifToken {
+                line: line_number,
+                text,
+                quoted,
+            });
+            rest = remainder.trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Numeric CIF value, ignoring a standard uncertainty such as `5.431(2)`.
+fn cif_number(token: &CifToken<'_>, tag: &str) -> Result<f64> {
+    let text = token.text.split('(').next().unwrap_or_default();
+    text.parse().map_err(|_| Error::Parse {
+        line: token.line,
+        message: format!("invalid numeric value '{}' for {}", token.text, tag),
+    })
+}
+
+/// Parse the atom sites of the first data block of a CIF file.
+///
+/// Reads the cell from `_cell_length_a/b/c` (Å) and `_cell_angle_alpha/beta/gamma`
+/// (degrees) and the sites from the `_atom_site_fract_x/y/z` loop, converts the
+/// fractional coordinates to Cartesian with `a` along x and `b` in the xy
+/// plane, and returns positions in nanometers. Only the listed sites are
+/// returned: symmetry operations are not applied, so the file must list every
+/// atom of the cell (space group P1). Standard uncertainties such as
+/// `5.431(2)` are ignored, as are the element labels.
+pub fn crystal_from_cif(content: &str) -> Result<Atoms> {
+    let tokens = cif_tokens(content)?;
+    let start = tokens
+        .iter()
+        .position(|token| token.is_keyword("data_"))
+        .ok_or_else(|| Error::Parse {
+            line: 1,
+            message: "no data_ block found".to_string(),
+        })?;
+    let block_line = tokens[start].line;
+    let block_name = &tokens[start].text["data_".len()..];
+    let end = tokens[start + 1..]
+        .iter()
+        .position(|token| token.is_keyword("data_"))
+        .map_or(tokens.len(), |offset| start + 1 + offset);
+    let block = &tokens[start + 1..end];
+
+    let mut items = Vec::new();
+    let mut loops: Vec<(Vec<&CifToken<'_>>, Vec<&CifToken<'_>>)> = Vec::new();
+    let mut i = 0;
+    while i < block.len() {
+        let token = &block[i];
+        if token.is_keyword("loop_") {
+            let mut tags = Vec::new();
+            i += 1;
+            while i < block.len() && block[i].is_tag() {
+                tags.push(&block[i]);
+                i += 1;
+            }
+            let mut values = Vec::new();
+            while i < block.len() && !block[i].is_tag() && !block[i].is_keyword("loop_") {
+                values.push(&block[i]);
+                i += 1;
+            }
+            if tags.is_empty() || values.len() % tags.len() != 0 {
+                return Err(Error::Parse {
+                    line: token.line,
+                    message: format!(
+                        "loop_ has {} value(s), not a multiple of its {} tag(s)",
+                        values.len(),
+                        tags.len()
+                    ),
+                });
+            }
+            loops.push((tags, values));
+        } else if token.is_tag() {
+            let value = block
+                .get(i + 1)
+                .filter(|value| !value.is_tag() && !value.is_keyword("loop_"))
+                .ok_or_else(|| Error::Parse {
+                    line: token.line,
+                    message: format!("{} has no value", token.text),
+                })?;
+            items.push((token, value));
+            i += 2;
+        } else {
+            return Err(Error::Parse {
+                line: token.line,
+                message: format!("unexpected value '{}' outside a loop_", token.text),
+            });
+        }
+    }
+
+    let missing = |tag: &str| Error::Parse {
+        line: block_line,
+        message: format!("data block '{}' has no {}", block_name, tag),
+    };
+    let item = |tag: &str| -> Result<f64> {
+        let (_, value) = items
+            .iter()
+            .find(|(name, _)| name.text.eq_ignore_ascii_case(tag))
+            .ok_or_else(|| missing(tag))?;
+        cif_number(value, tag)
+    };
+    let lengths = [
+        item("_cell_length_a")?,
+        item("_cell_length_b")?,
+        item("_cell_length_c")?,
+    ];
+    let angles = [
+        item("_cell_angle_alpha")?,
+        item("_cell_angle_beta")?,
+        item("_cell_angle_gamma")?,
+    ];
+    let vectors = cell_vectors(lengths.map(|length| length / ANGSTROM_PER_NM), angles)?;
+
+    const FRACT_TAGS: [&str; 3] = [
+        "_atom_site_fract_x",
+        "_atom_site_fract_y",
+        "_atom_site_fract_z",
+    ];
+    let column = |tags: &[&CifToken<'_>], tag: &str| {
+        tags.iter()
+            .position(|name| name.text.eq_ignore_ascii_case(tag))
+    };
+    let (tags, values) = loops
+        .iter()
+        .find(|(tags, _)| column(tags, FRACT_TAGS[0]).is_some())
+        .ok_or_else(|| missing("_atom_site_fract_x loop"))?;
+    let mut columns = [0; 3];
+    for (axis, tag) in FRACT_TAGS.iter().enumerate() {
+        columns[axis] = column(tags, tag).ok_or_else(|| missing(tag))?;
+    }
+
+    let mut atoms = Atoms::with_capacity(values.len() / tags.len());
+    for row in values.chunks(tags.len()) {
+        let mut fractional = [0.0; 3];
+        for (axis, &col) in columns.iter().enumerate() {
+            fractional[axis] = cif_number(row[col], FRACT_TAGS[axis])?;
+        }
+        let mut position = [0.0; 3];
+        for (f, vector) in fractional.iter().zip(&vectors) {
+            for axis in 0..3 {
+                position[axis] += f * vector[axis];
+            }
+        }
+        atoms.push(position);
+    }
+    Ok(atoms)
+}
+
+/// Parse the positions and element symbols of a VASP POSCAR or CONTCAR file.
+///
+/// Line 1 is a comment, line 2 the scale factor, lines 3 to 5 the lattice
+/// vectors in Å, line 6 the element symbols and line 7 the number of atoms of
+/// each (the VASP 5 layout; VASP 4 files without symbols are rejected). An
+/// optional `Selective dynamics` line is followed by the coordinate mode,
+/// `Direct` (fractional) or `Cartesian` (Å, also `K`), of which only the
+/// first letter counts, and one row per atom in the order of the symbols.
+/// Columns after the three coordinates, such as the `T T F` flags, are
+/// ignored. A positive scale factor multiplies the lattice vectors and the
+/// Cartesian coordinates; a negative one is the cell volume in Å^3 instead.
+/// Returns the positions in nanometers and the symbol of each atom.
+pub fn crystal_from_poscar(content: &str) -> Result<(Atoms, Vec<String>)> {
+    let mut lines = content.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut next_line = |expected: &str| {
+        lines.next().ok_or_else(|| Error::Parse {
+            line: content.lines().count() + 1,
+            message: format!("unexpected end of file, expected {}", expected),
+        })
+    };
+    let numbers = |line_number: usize, line: &str, count: usize, expected: &str| {
+        let fields: Vec<&str> = line.split_whitespace().take(count).collect();
+        if fields.len() < count {
+            return Err(Error::Parse {
+                line: line_number,
+                message: format!("expected {}, got '{}'", expected, line.trim()),
+            });
+        }
+        fields
+            .iter()
+            .map(|field| {
+                field.parse::<f64>().map_err(|_| Error::Parse {
+                    line: line_number,
+                    message: format!("invalid number '{}' in {}", field, expected),
+                })
+            })
+            .collect::<Result<Vec<f64>>>()
+    };
+
+    next_line("a comment line")?;
+    let (scale_line, line) = next_line("the scale factor")?;
+    let scale = numbers(scale_line, line, 1, "the scale factor")?[0];
+    if scale == 0.0 || !scale.is_finite() {
+        return Err(Error::Parse {
+            line: scale_line,
+            message: format!("the scale factor must be non-zero, got {}", scale),
+        });
+    }
+    let mut lattice = [[0.0; 3]; 3];
+    for vector in &mut lattice {
+        let (line_number, line) = next_line("a lattice vector")?;
+        let values = numbers(line_number, line, 3, "a lattice vector 'X Y Z'")?;
+        vector.copy_from_slice(&values);
+    }
+    let [a, b, c] = lattice;
+    let volume = (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+        + a[2] * (b[0] * c[1] - b[1] * c[0]))
+        .abs();
+    if volume == 0.0 {
+        return Err(Error::Parse {
+            line: scale_line + 1,
+            message: "the lattice vectors do not describe a cell with a volume".to_string(),
+        });
+    }
+    // Å per unit of the listed vectors and Cartesian coordinates.
+    let scale = if scale > 0.0 {
+        scale
+    } else {
+        (-scale / volume).cbrt()
+    };
+    let lattice = lattice.map(|vector| vector.map(|x| x * scale / ANGSTROM_PER_NM));
+
+    let (symbols_line, line) = next_line("the element symbols")?;
+    let symbols: Vec<&str> = line.split_whitespace().collect();
+    if symbols.is_empty() || symbols.iter().any(|s| s.parse::<f64>().is_ok()) {
+        return Err(Error::Parse {
+            line: symbols_line,
+            message: format!(
+                "expected the element symbols of the VASP 5 format, got '{}'",
+                line.trim()
+            ),
+        });
+    }
+    let (counts_line, line) = next_line("the atom counts")?;
+    let counts: Vec<&str> = line.split_whitespace().collect();
+    if counts.len() != symbols.len() {
+        return Err(Error::Parse {
+            line: counts_line,
+            message: format!(
+                "expected {} atom count(s), one per element symbol, got '{}'",
+                symbols.len(),
+                line.trim()
+            ),
+        });
+    }
+    let counts = counts
+        .iter()
+        .map(|count| {
+            count.parse::<usize>().map_err(|_| Error::Parse {
+                line: counts_line,
+                message: format!("invalid atom count '{}'", count),
+            })
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    let n_atoms = counts
+        .iter()
+        .try_fold(0usize, |total, &count| total.checked_add(count))
+        .ok_or_else(|| Error::Parse {
+            line: counts_line,
+            message: format!(
+                "the atom counts '{}' add up to more than {}",
+                line.trim(),
+                usize::MAX
+            ),
+        })?;
+
+    let (mut mode_line, mut line) = next_line("the coordinate mode")?;
+    if line.trim_start().starts_with(['S', 's']) {
+        (mode_line, line) = next_line("the coordinate mode")?;
+    }
+    let cartesian = match line.trim_start().chars().next() {
+        Some('C' | 'c' | 'K' | 'k') => true,
+        Some('D' | 'd') => false,
+        _ => {
+            return Err(Error::Parse {
+                line: mode_line,
+                message: format!(
+                    "expected the coordinate mode 'Direct' or 'Cartesian', got '{}'",
+                    line.trim()
+                ),
+            })
+        }
+    };
+
+    let mut atoms = Atoms::with_capacity(n_atoms.min(MAX_PREALLOCATED_ATOMS));
+    let mut labels = Vec::with_capacity(n_atoms.min(MAX_PREALLOCATED_ATOMS));
+    for (symbol, &count) in symbols.iter().zip(&counts) {
+        for _ in 0..count {
+            let (line_number, line) = lines.next().ok_or_else(|| Error::Parse {
+                line: content.lines().count() + 1,
+                message: format!("expected {} atoms, found {}", n_atoms, atoms.len()),
+            })?;
+            let values = numbers(line_number, line, 3, "coordinates 'X Y Z'")?;
+            let position = if cartesian {
+                [0, 1, 2].map(|axis| values[axis] * scale / ANGSTROM_PER_NM)
+            } else {
+                [0, 1, 2].map(|axis| (0..3).map(|k| values[k] * lattice[k][axis]).sum())
+            };
+            atoms.push(position);
+            labels.push(symbol.to_string());
+        }
+    }
+    Ok((atoms, labels))
+}
+
+/// Coordinate columns of a LAMMPS dump, in order of preference: unwrapped,
+/// wrapped and scaled.
+const LAMMPS_COORDINATE_COLUMNS: [[&str; 3]; 4] = [
+    ["xu", "yu", "zu"],
+    ["x", "y", "z"],
+    ["xs", "ys", "zs"],
+    ["xsu", "ysu", "zsu"],
+];
+
+/// Simulation box of a LAMMPS dump frame, for converting scaled coordinates.
+struct LammpsCell {
+    origin: [f64; 3],
+    /// Edge vectors `a = (lx, 0, 0)`, `b = (xy, ly, 0)` and `c = (xz, yz, lz)`.
+    edges: [[f64; 3]; 3],
+}
+
+impl LammpsCell {
+    /// Box from the `lo hi` bounds of x, y and z and the tilt factors
+    /// `[xy, xz, yz]`, all zero for an orthogonal box.
+    ///
+    /// Triclinic dumps give the bounding box of the tilted cell, from which
+    /// the cell itself is recovered as described in the LAMMPS `Howto
+    /// triclinic` documentation.
+    fn new(bounds: [[f64; 2]; 3], [xy, xz, yz]: [f64; 3]) -> Self {
+        let x_shifts = [0.0, xy, xz, xy + xz];
+        let x_min = x_shifts.iter().copied().fold(0.0, f64::min);
+        let x_max = x_shifts.iter().copied().fold(0.0, f64::max);
+        let (xlo, xhi) = (bounds[0][0] - x_min, bounds[0][1] - x_max);
+        let (ylo, yhi) = (bounds[1][0] - yz.min(0.0), bounds[1][1] - yz.max(0.0));
+        let [zlo, zhi] = bounds[2];
+        Self {
+            origin: [xlo, ylo, zlo],
+            edges: [
+                [xhi - xlo, 0.0, 0.0],
+                [xy, yhi - ylo, 0.0],
+                [xz, yz, zhi - zlo],
+            ],
+        }
+    }
+
+    /// `origin + s_x a + s_y b + s_z c` for scaled coordinates `s`.
+    fn to_absolute(&self, scaled: [f64; 3]) -> [f64; 3] {
+        let mut position = self.origin;
+        for (s, edge) in scaled.iter().zip(&self.edges) {
+            for (p, e) in position.iter_mut().zip(edge) {
+                *p += s * e;
+            }
+        }
+        position
+    }
+}
+
+/// Lazy reader of the frames of a LAMMPS text dump file (`dump atom` or
+/// `dump custom`), yielding the positions of one frame at a time so that the
+/// trajectory never has to fit in memory.
+///
+/// Each frame must list its `ITEM: TIMESTEP`, `ITEM: NUMBER OF ATOMS`,
+/// `ITEM: BOX BOUNDS` and `ITEM: ATOMS` sections in that order. Coordinates are
+/// read from the `xu yu zu`, `x y z`, `xs ys zs` or `xsu ysu zsu` columns,
+/// whichever comes first in that list; scaled coordinates are converted with
+/// the box bounds and, for triclinic boxes, the tilt factors. Lengths are taken to be in Å, as
+/// in the `metal` and `real` unit styles, and returned in nanometers. Atoms
+/// keep the order of the file; sort the dump by `id` (`dump_modify sort id`)
+/// so that the same index means the same atom in every frame. After the
+/// first error the iterator ends.
+pub struct LammpsDumpFrames<B> {
+    lines: std::io::Lines<B>,
+    line_number: usize,
+    failed: bool,
+}
+
+impl<B: BufRead> LammpsDumpFrames<B> {
+    /// Read frames from `reader`, e.g. a `BufReader` of the dump file.
+    pub fn new(reader: B) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_number: 0,
+            failed: false,
+        }
+    }
+
+    /// The next line that is not blank, with its 1-based line number.
+    fn next_line(&mut self) -> Option<Result<(usize, String)>> {
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => return Some(Ok((self.line_number, line))),
+                Err(err) => {
+                    return Some(Err(Error::Parse {
+                        line: self.line_number,
+                        message: format!("failed to read line: {}", err),
+                    }))
+                }
+            }
+        }
+        None
+    }
+
+    /// The next line, failing at the end of the input with `expected`.
+    fn expect_line(&mut self, expected: &str) -> Result<(usize, String)> {
+        self.next_line().unwrap_or_else(|| {
+            Err(Error::Parse {
+                line: self.line_number + 1,
+                message: format!("unexpected end of file, expected {}", expected),
+            })
+        })
+    }
+
+    /// The next line, which must start with `ITEM: <item>`; returns the rest
+    /// of the header.
+    fn expect_item(&mut self, item: &str) -> Result<String> {
+        let (line_number, line) = self.expect_line(&format!("'ITEM: {}'", item))?;
+        line.trim()
+            .strip_prefix("ITEM:")
+            .map(str::trim)
+            .and_then(|rest| rest.strip_prefix(item))
+            .map(|rest| rest.trim().to_string())
+            .ok_or_else(|| Error::Parse {
+                line: line_number,
+                message: format!("expected 'ITEM: {}', got '{}'", item, line.trim()),
+            })
+    }
+
+    fn read_frame(&mut self, first_line: (usize, String)) -> Result<Atoms> {
+        let (line_number, line) = first_line;
+        if line.trim() != "ITEM: TIMESTEP" {
+            return Err(Error::Parse {
+                line: line_number,
+                message: format!("expected 'ITEM: TIMESTEP', got '{}'", line.trim()),
+            });
+        }
+        self.expect_line("the timestep")?;
+
+        self.expect_item("NUMBER OF ATOMS")?;
+        let (line_number, line) = self.expect_line("the number of atoms")?;
+        let n_atoms: usize = line.trim().parse().map_err(|_| Error::Parse {
+            line: line_number,
+            message: format!("expected an atom count, got '{}'", line.trim()),
+        })?;
+
+        let bounds_header = self.expect_item("BOX BOUNDS")?;
+        let triclinic = bounds_header.split_whitespace().any(|flag| flag == "xy");
+        let mut bounds = [[0.0; 2]; 3];
+        // Tilt factors xy, xz and yz, the third value of each bounds line.
+        let mut tilt = [0.0; 3];
+        for (bound, tilt) in bounds.iter_mut().zip(&mut tilt) {
+            let (line_number, line) = self.expect_line("the box bounds")?;
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| Error::Parse {
+                    line: line_number,
+                    message: format!("invalid box bounds '{}'", line.trim()),
+                })?;
+            let expected = if triclinic { 3 } else { 2 };
+            if values.len() < expected {
+                return Err(Error::Parse {
+                    line: line_number,
+                    message: format!(
+                        "expected '{}' box bounds, got '{}'",
+                        if triclinic { "lo hi tilt" } else { "lo hi" },
+                        line.trim()
+                    ),
+                });
+            }
+            *bound = [values[0], values[1]];
+            if triclinic {
+                *tilt = values[2];
+            }
+        }
+        let cell = LammpsCell::new(bounds, tilt);
+
+        let atoms_header = self.expect_item("ATOMS")?;
+        let columns: Vec<&str> = atoms_header.split_whitespace().collect();
+        let header_line = self.line_number;
+        let (names, indices) = LAMMPS_COORDINATE_COLUMNS
+            .iter()
+            .find_map(|names| {
+                let indices = names.map(|name| columns.iter().position(|&c| c == name));
+                match indices {
+                    [Some(x), Some(y), Some(z)] => Some((names, [x, y, z])),
+                    _ => None,
+                }
+            })
+            .ok_or_else(|| Error::Parse {
+                line: header_line,
+                message: format!(
+                    "ITEM: ATOMS has no x y z, xu yu zu, xs ys zs or xsu ysu zsu columns: '{}'",
+                    atoms_header
+                ),
+            })?;
+        let scaled = names[0].starts_with("xs");
+
+        let mut atoms = Atoms::with_capacity(n_atoms.min(MAX_PREALLOCATED_ATOMS));
+        for _ in 0..n_atoms {
+            let (line_number, line) = self.expect_line(&format!("{} atoms", n_atoms))?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let mut position = [0.0; 3];
+            for (axis, &index) in indices.iter().enumerate() {
+                let field = fields.get(index).ok_or_else(|| Error::Parse {
+                    line: line_number,
+                    message: format!(
+                        "expected {} column(s), got {}: '{}'",
+                        columns.len(),
+                        fields.len(),
+                        line.trim()
+                    ),
+                })?;
+                position[axis] = field.parse().map_err(|_| Error::Parse {
+                    line: line_number,
+                    message: format!("invalid coordinate '{}'", field),
+                })?;
+            }
+            if scaled {
+                position = cell.to_absolute(position);
+            }
+            atoms.push(position.map(|x| x / ANGSTROM_PER_NM));
+        }
+        Ok(atoms)
+    }
+}
+
+impl<B: BufRead> Iterator for LammpsDumpFrames<B> {
+    type Item = Result<Atoms>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let frame = match self.next_line()? {
+            Ok(first_line) => self.read_frame(first_line),
+            Err(err) => Err(err),
+        };
+        self.failed = frame.is_err();
+        Some(frame)
+    }
+}
+
+/// All frames of the LAMMPS text dump in `content`, read as by
+/// [`LammpsDumpFrames`].
+pub fn parse_lammps_dump(content: &str) -> Result<Vec<Atoms>> {
+    LammpsDumpFrames::new(content.as_bytes()).collect()
+}
+
+/// The first frame of the LAMMPS text dump in `content`, read as by
+/// [`LammpsDumpFrames`]; the rest of the content is not parsed.
+pub fn parse_lammps_dump_first_frame(content: &str) -> Result<Atoms> {
+    LammpsDumpFrames::new(content.as_bytes())
+        .next()
+        .unwrap_or_else(|| {
+            Err(Error::Parse {
+                line: 1,
+                message: "the dump contains no frames".to_string(),
+            })
+        })
+}
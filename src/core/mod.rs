@@ -0,0 +1,163 @@
+//! Pure Rust implementation of the crystal generators and Debye scattering
+//! calculations, independent of PyO3.
+//!
+//! Positions are `[x, y, z]` triples in nanometers and q values are in 1/nm.
+
+mod absorption;
+mod adaptive;
+mod amorphous;
+mod analysis;
+mod anisotropic;
+mod atoms;
+mod background;
+mod benchmark;
+mod conversion;
+mod correlation;
+mod crystal;
+mod defects;
+mod distance_matrix;
+mod dse;
+mod finite_size;
+mod form_factor;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod incremental;
+mod io;
+mod particle_form_factor;
+mod peaks;
+mod periodic;
+mod polydispersity;
+mod precision;
+mod progress;
+mod resolution;
+mod rotation;
+mod scan;
+mod scherrer;
+mod simd;
+mod smoothing;
+mod surface;
+mod trajectory;
+
+use std::fmt;
+
+pub use absorption::{
+    absorption_correction, attenuation_data, linear_attenuation_coefficient, AttenuationData,
+    MASS_ATTENUATION_TABLE,
+};
+pub use adaptive::{
+    auto_adaptive_q_grid, dse_adaptive, dse_auto_adaptive, dse_custom_q, dse_linspace,
+    dse_logspace_q, ADAPTIVE_REFINEMENT, ADAPTIVE_WINDOW_HALF_WIDTH,
+};
+pub use amorphous::{crystal_amorphous, RANDOM_CLOSE_PACKING};
+pub use analysis::{
+    apply_correction, chi_squared, extrapolate_i0, guinier_fit, guinier_plot_data, kratky_peak,
+    kratky_transform, lorentz_polarization, normalise_intensity, normalise_to_forward_scattering,
+    porod_fit, porod_invariant, porod_invariant_contributions, r_factor, scale_to_absolute,
+    subtract_background, GUINIER_EXTRAPOLATION_MAX_Q_RG, POROD_EXTRAPOLATION_WARNING_FRACTION,
+};
+pub use anisotropic::{dse_3d, structure_amplitude, structure_amplitude_batch};
+pub use atoms::Atoms;
+pub use background::{
+    dse_with_background, fit_background_parameters, fit_polynomial_background,
+    fit_power_law_background,
+};
+pub use benchmark::{benchmark_dse, BENCHMARK_RELATIVE_TOLERANCE};
+pub use conversion::{q_to_twotheta, resample_iq, twotheta_to_q};
+pub use correlation::{
+    apply_modification_function, average_coordination, compute_pair_distances,
+    coordination_histogram, coordination_numbers, fourier_transform_to_iq,
+    lorch_modification_function, number_density, partial_pair_correlation, pdf_from_iq,
+    pdf_lorch_transform, radial_distribution_function,
+};
+pub use crystal::{
+    center_crystal, crystal_core, crystal_core_shell, crystal_cuboctahedron, crystal_cylinder,
+    crystal_decahedron, crystal_ellipsoid, crystal_hollow_sphere, crystal_icosahedron,
+    crystal_nanorod, crystal_slab, crystal_slab_with_surface_relaxation,
+    crystal_truncated_octahedron, crystal_wulff, from_fractional_coordinates, replicate_crystal,
+    to_cartesian_from_fractional, to_fractional_coordinates, translate_crystal, CubicLattice,
+    Shape, HCP_IDEAL_C_OVER_A,
+};
+pub use defects::{
+    apply_anisotropic_disorder, apply_thermal_disorder, crystal_random_alloy,
+    crystal_stacking_faults, crystal_with_substitutions, crystal_with_vacancies,
+};
+pub use distance_matrix::DistanceMatrix;
+pub use dse::{
+    distinct_structure_factor, dse_at_q, dse_batch, dse_batch_with_params, dse_core,
+    dse_core_with_progress, dse_dilation_gradient, dse_from_distances, dse_gradient_lattice,
+    dse_gradient_size, dse_histogram, dse_neutron, dse_no_alloc, dse_shell_decomposition,
+    dse_symmetric, dse_ultra_optimized, dse_ultra_optimized_with_progress, dse_with_cutoff,
+    dse_with_debye_waller, dse_with_form_factors, dse_with_isotropic_bfactor, dse_with_species,
+    dse_with_species_with_progress, estimate_memory_mb, max_atoms_for_memory, max_q_for_resolution,
+    min_q_from_crystal_size, partial_dse, q_grid, q_linspace, q_logspace, structure_factor,
+    DEFAULT_NO_ALLOC_CHUNK_SIZE, GUINIER_RESOLVED_Q_RG, MAX_DISTANCE_BINS,
+};
+pub use finite_size::{
+    convergence_plot_data, convergence_test, convergence_with_size, dse_finite_size_error,
+    dse_normalized_by_sphere,
+};
+pub use form_factor::{
+    cromer_mann, cromer_mann_coeffs, neutron_scattering_length, CromerMannCoeffs,
+    CROMER_MANN_TABLE, NEUTRON_SCATTERING_LENGTHS,
+};
+#[cfg(feature = "gpu")]
+pub use gpu::dse_gpu;
+pub use incremental::{dse_differential, IncrementalDse};
+pub use io::{
+    crystal_from_cif, crystal_from_csv, crystal_from_poscar, crystal_from_xyz, iq_from_csv,
+    iq_to_csv, parse_lammps_dump, parse_lammps_dump_first_frame, LammpsDumpFrames,
+};
+pub use particle_form_factor::{form_factor_cylinder, form_factor_ellipsoid, form_factor_sphere};
+pub use peaks::{
+    assign_miller, find_peaks, lattice_parameter_from_peak, PeakInfo, PEAK_SEARCH_WINDOW,
+};
+pub use periodic::{dse_periodic, PeriodicCrystal};
+pub use polydispersity::dse_size_averaged;
+pub use precision::{dse_core_f32, dse_mixed_precision};
+pub use progress::ProgressFn;
+pub use resolution::{apply_instrument_broadening, dse_with_instrument_broadening};
+pub use rotation::{
+    crystal_grain_aggregate, crystal_polycrystalline, dse_orientation_averaged,
+    dse_powder_average_explicit, rotate_crystal, rotation_matrix_x, rotation_matrix_y,
+    rotation_matrix_z, Matrix3, GRAIN_OVERLAP_DISTANCE,
+};
+pub use scan::chi_squared_map;
+pub use scherrer::{scherrer_analysis, SCHERRER_FIT_HALF_WIDTH};
+pub use simd::dse_simd;
+pub use smoothing::{moving_average_smooth, savitzky_golay_smooth};
+pub use surface::{classify_surface_atoms, surface_fraction};
+pub use trajectory::{dse_trajectory_average, dse_trajectory_average_streaming};
+
+/// Errors returned by the fallible functions of the [`core`](self) API.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// An argument is outside its valid domain.
+    InvalidArgument(String),
+    /// Structure file content could not be parsed.
+    Parse {
+        /// 1-based line number of the offending line.
+        line: usize,
+        message: String,
+    },
+    /// The GPU backend could not run a computation, e.g. because no adapter
+    /// is available.
+    Gpu(String),
+    /// Two implementations of the same computation gave different results.
+    Mismatch(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(message) => f.write_str(message),
+            Error::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Error::Gpu(message) => write!(f, "GPU error: {}", message),
+            Error::Mismatch(message) => write!(f, "implementations disagree: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result type of the [`core`](self) API.
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,168 @@
+use std::f64::consts::{FRAC_2_PI, FRAC_PI_2, FRAC_PI_4};
+
+use rayon::prelude::*;
+
+use super::dse::sinc;
+use super::{Error, Result};
+
+/// Below this `x`, [`sphere_amplitude`] uses its Taylor series, where
+/// `sin(x) - x cos(x)` loses precision to cancellation.
+const SPHERE_TAYLOR_THRESHOLD: f64 = 1e-2;
+
+/// Smallest number of Simpson intervals of the orientation averages.
+const MIN_ORIENTATION_INTERVALS: usize = 64;
+
+/// Simpson intervals per unit of `q * size`, enough to follow the
+/// oscillations of the integrand over the orientation range.
+const ORIENTATION_INTERVALS_PER_Q_SIZE: f64 = 8.0;
+
+/// Normalised amplitude `3 (sin x - x cos x) / x^3` of a uniform sphere at
+/// `x = q R`, 1 at `x = 0`.
+fn sphere_amplitude(x: f64) -> f64 {
+    if x.abs() < SPHERE_TAYLOR_THRESHOLD {
+        let x2 = x * x;
+        1.0 - x2 / 10.0 * (1.0 - x2 / 28.0)
+    } else {
+        3.0 * (x.sin() - x * x.cos()) / x.powi(3)
+    }
+}
+
+/// Bessel function of the first kind `J1(x)`, from the rational and
+/// asymptotic approximations of Numerical Recipes (`bessj1`), accurate to
+/// about 1e-8.
+fn bessel_j1(x: f64) -> f64 {
+    let ax = x.abs();
+    if ax < 8.0 {
+        let y = x * x;
+        let numerator = x
+            * (72362614232.0
+                + y * (-7895059235.0
+                    + y * (242396853.1
+                        + y * (-2972611.439 + y * (15704.48260 + y * (-30.16036606))))));
+        let denominator = 144725228442.0
+            + y * (2300535178.0 + y * (18583304.74 + y * (99447.43394 + y * (376.9991397 + y))));
+        numerator / denominator
+    } else {
+        let z = 8.0 / ax;
+        let y = z * z;
+        let xx = ax - 3.0 * FRAC_PI_4;
+        let p = 1.0
+            + y * (0.183105e-2
+                + y * (-0.3516396496e-4 + y * (0.2457520174e-5 + y * (-0.240337019e-6))));
+        let q = 0.04687499995
+            + y * (-0.2002690873e-3
+                + y * (0.8449199096e-5 + y * (-0.88228987e-6 + y * 0.105787412e-6)));
+        let value = (FRAC_2_PI / ax).sqrt() * (xx.cos() * p - z * xx.sin() * q);
+        if x < 0.0 {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+/// Composite Simpson integral of `f` over `[a, b]` with `n_intervals`, which
+/// is rounded up to an even number.
+fn simpson(a: f64, b: f64, n_intervals: usize, f: impl Fn(f64) -> f64) -> f64 {
+    let n = n_intervals + n_intervals % 2;
+    let h = (b - a) / n as f64;
+    let interior: f64 = (1..n)
+        .map(|i| {
+            let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+            weight * f(a + i as f64 * h)
+        })
+        .sum();
+    (f(a) + interior + f(b)) * h / 3.0
+}
+
+/// Number of Simpson intervals of an orientation average at `q` for a
+/// particle of largest dimension `size`.
+fn orientation_intervals(q: f64, size: f64) -> usize {
+    MIN_ORIENTATION_INTERVALS.max((ORIENTATION_INTERVALS_PER_Q_SIZE * q * size).ceil() as usize)
+}
+
+fn check_length(name: &str, value: f64) -> Result<()> {
+    if value.is_nan() || value <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "{} must be positive, got {}",
+            name, value
+        )));
+    }
+    Ok(())
+}
+
+/// Form factor `P(q) = [3 (sin qR - qR cos qR) / (qR)^3]^2` of a uniform
+/// sphere of radius `radius_nm`, normalised to `P(0) = 1`.
+///
+/// The atomistic [`dse_core`](super::dse_core) of a sphere divided by `N^2`
+/// converges to it at low q as the number of atoms grows.
+pub fn form_factor_sphere(q_values: &[f64], radius_nm: f64) -> Result<Vec<f64>> {
+    check_length("radius_nm", radius_nm)?;
+    Ok(q_values
+        .iter()
+        .map(|&q| sphere_amplitude(q * radius_nm).powi(2))
+        .collect())
+}
+
+/// Orientation-averaged form factor of a uniform cylinder of radius
+/// `radius_nm` and height `height_nm`, normalised to `P(0) = 1`.
+///
+/// `P(q) = int_0^1 [2 J1(q R sqrt(1 - u^2)) / (q R sqrt(1 - u^2)) * sinc(q H u / 2)]^2 du`,
+/// with `u` the cosine of the angle between q and the cylinder axis,
+/// integrated by Simpson's rule with more intervals at higher `q`. The q
+/// values are evaluated in parallel.
+pub fn form_factor_cylinder(q_values: &[f64], radius_nm: f64, height_nm: f64) -> Result<Vec<f64>> {
+    check_length("radius_nm", radius_nm)?;
+    check_length("height_nm", height_nm)?;
+    let size = radius_nm.max(height_nm);
+    Ok(q_values
+        .par_iter()
+        .map(|&q| {
+            simpson(0.0, 1.0, orientation_intervals(q, size), |u| {
+                let radial = q * radius_nm * (1.0 - u * u).sqrt();
+                let radial = if radial < 1e-8 {
+                    1.0
+                } else {
+                    2.0 * bessel_j1(radial) / radial
+                };
+                (radial * sinc(q * height_nm * u / 2.0)).powi(2)
+            })
+        })
+        .collect())
+}
+
+/// Orientation-averaged form factor of a uniform triaxial ellipsoid with
+/// semi-axes `a_nm`, `b_nm` and `c_nm`, normalised to `P(0) = 1`.
+///
+/// `P(q)` is the average of the sphere form factor at the radius
+/// `r = sqrt(a^2 cos^2 p (1 - m^2) + b^2 sin^2 p (1 - m^2) + c^2 m^2)` seen
+/// along each direction, with `m` the cosine of the polar angle, over
+/// `m in [0, 1]` and azimuth `p in [0, pi/2]`. Both integrals use Simpson's
+/// rule, and the q values are evaluated in parallel. Equal semi-axes give
+/// [`form_factor_sphere`].
+pub fn form_factor_ellipsoid(
+    q_values: &[f64],
+    a_nm: f64,
+    b_nm: f64,
+    c_nm: f64,
+) -> Result<Vec<f64>> {
+    check_length("a_nm", a_nm)?;
+    check_length("b_nm", b_nm)?;
+    check_length("c_nm", c_nm)?;
+    let size = a_nm.max(b_nm).max(c_nm);
+    Ok(q_values
+        .par_iter()
+        .map(|&q| {
+            let n = orientation_intervals(q, size);
+            simpson(0.0, 1.0, n, |m| {
+                let sin_sq = 1.0 - m * m;
+                simpson(0.0, FRAC_PI_2, n, |p| {
+                    let (sin_p, cos_p) = p.sin_cos();
+                    let r_sq = sin_sq * (a_nm * a_nm * cos_p * cos_p + b_nm * b_nm * sin_p * sin_p)
+                        + c_nm * c_nm * m * m;
+                    sphere_amplitude(q * r_sq.sqrt()).powi(2)
+                }) / FRAC_PI_2
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,299 @@
+use std::f64::consts::PI;
+
+use super::analysis::{check_same_length, parabolic_peak};
+use super::{CubicLattice, Error, Result};
+
+/// A peak of a scattering curve found by [`find_peaks`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakInfo {
+    /// Position of the maximum in 1/nm, refined by parabolic interpolation.
+    pub position_q: f64,
+    /// Height of the peak above the higher of its two surrounding minima.
+    pub prominence: f64,
+    /// Full width in 1/nm at half prominence, linearly interpolated.
+    pub fwhm: f64,
+    /// Miller indices of the closest allowed reflection, if assigned with
+    /// [`assign_miller`].
+    pub miller_assignment: Option<[i32; 3]>,
+}
+
+/// Local maxima of `intensities` with prominence at least `min_prominence`
+/// and [`PeakInfo::fwhm`] at least `min_width_q`, in order of q.
+///
+/// The prominence of a maximum is its height above the higher of the two
+/// minima between it and the nearest higher sample (or the end of the curve)
+/// on either side; a flat top counts as one maximum at its centre. The
+/// `miller_assignment` of every peak is `None`. `q_values` must be
+/// increasing.
+pub fn find_peaks(
+    q_values: &[f64],
+    intensities: &[f64],
+    min_prominence: f64,
+    min_width_q: f64,
+) -> Result<Vec<PeakInfo>> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    if q_values.windows(2).any(|w| w[1] <= w[0]) {
+        return Err(Error::InvalidArgument(
+            "q_values must be strictly increasing".to_string(),
+        ));
+    }
+    let y = intensities;
+    let n = y.len();
+    let mut peaks = Vec::new();
+    let mut i = 1;
+    while i + 1 < n {
+        if y[i] <= y[i - 1] {
+            i += 1;
+            continue;
+        }
+        // Skip a flat top; it is a maximum only if the curve falls after it.
+        let mut end = i;
+        while end + 1 < n && y[end + 1] == y[i] {
+            end += 1;
+        }
+        if end + 1 == n || y[end + 1] > y[i] {
+            i = end + 1;
+            continue;
+        }
+
+        let height = y[i];
+        let mut left = i;
+        let mut left_min = height;
+        while left > 0 && y[left - 1] <= height {
+            left -= 1;
+            left_min = left_min.min(y[left]);
+        }
+        let mut right = end;
+        let mut right_min = height;
+        while right + 1 < n && y[right + 1] <= height {
+            right += 1;
+            right_min = right_min.min(y[right]);
+        }
+        let prominence = height - left_min.max(right_min);
+
+        let half = height - prominence / 2.0;
+        let crossing = |inner: usize, outer: usize| {
+            let t = (y[inner] - half) / (y[inner] - y[outer]);
+            q_values[inner] + t * (q_values[outer] - q_values[inner])
+        };
+        let mut a = i;
+        while a > left && y[a - 1] > half {
+            a -= 1;
+        }
+        let q_left = if a > left {
+            crossing(a, a - 1)
+        } else {
+            q_values[left]
+        };
+        let mut b = end;
+        while b < right && y[b + 1] > half {
+            b += 1;
+        }
+        let q_right = if b < right {
+            crossing(b, b + 1)
+        } else {
+            q_values[right]
+        };
+        let fwhm = q_right - q_left;
+
+        let position_q = if end == i {
+            parabolic_peak(&q_values[i - 1..=i + 1], &y[i - 1..=i + 1]).0
+        } else {
+            (q_values[i] + q_values[end]) / 2.0
+        };
+        if prominence >= min_prominence && fwhm >= min_width_q {
+            peaks.push(PeakInfo {
+                position_q,
+                prominence,
+                fwhm,
+                miller_assignment: None,
+            });
+        }
+        i = end + 1;
+    }
+    Ok(peaks)
+}
+
+/// Miller indices `h >= k >= l >= 0` of the reflection of `lattice` allowed
+/// closest to `q_peak`, with `q_hkl = 2 pi sqrt(h^2 + k^2 + l^2) / lattice_param`.
+///
+/// Ties go to the lower `h^2 + k^2 + l^2`. Fails unless `q_peak` and
+/// `lattice_param` are positive.
+pub fn assign_miller(q_peak: f64, lattice_param: f64, lattice: CubicLattice) -> Result<[i32; 3]> {
+    if q_peak.is_nan() || q_peak <= 0.0 || lattice_param.is_nan() || lattice_param <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "q_peak and lattice_param must be positive, got {} and {}",
+            q_peak, lattice_param
+        )));
+    }
+    let q_unit = 2.0 * PI / lattice_param;
+    // (2n, 0, 0) is allowed on every lattice, so some reflection lies within
+    // q_unit of q_peak and none with h > q_peak / q_unit + 1 can be closer.
+    let h_max = (q_peak / q_unit).floor() as i32 + 2;
+    let mut best: Option<([i32; 3], f64)> = None;
+    for h in 1..=h_max {
+        for k in 0..=h {
+            for l in 0..=k {
+                if !lattice.allows([h, k, l]) {
+                    continue;
+                }
+                let q = q_unit * ((h * h + k * k + l * l) as f64).sqrt();
+                let distance = (q - q_peak).abs();
+                if best.is_none_or(|(_, d)| distance < d) {
+                    best = Some(([h, k, l], distance));
+                }
+            }
+        }
+    }
+    Ok(best.map(|(hkl, _)| hkl).unwrap_or([2, 0, 0]))
+}
+
+/// Relative half-width of the q window around the expected `q_hkl` that
+/// [`lattice_parameter_from_peak`] searches for the peak.
+pub const PEAK_SEARCH_WINDOW: f64 = 0.1;
+
+/// Cubic lattice parameter `a = 2 pi sqrt(h^2 + k^2 + l^2) / q_peak` from
+/// the `hkl` peak, returned with its standard uncertainty as `(a, sigma_a)`.
+///
+/// The peak is the most prominent [`find_peaks`] maximum within
+/// [`PEAK_SEARCH_WINDOW`] of `q_hkl = 2 pi sqrt(h^2 + k^2 + l^2) /
+/// lattice_param_initial`. Its centre is refined by fitting a Gaussian to the
+/// contiguous points above half prominence, as a parabola in `ln I`
+/// (Caruana's method). The uncertainty of the centre is propagated from the
+/// covariance of the parabola coefficients, scaled by the residual variance,
+/// so it reflects how well a Gaussian describes the peak. Fails unless the
+/// window holds a peak with at least 4 positive points above half
+/// prominence.
+pub fn lattice_parameter_from_peak(
+    q_values: &[f64],
+    intensities: &[f64],
+    hkl: [i32; 3],
+    lattice_param_initial: f64,
+) -> Result<(f64, f64)> {
+    if lattice_param_initial.is_nan() || lattice_param_initial <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "lattice_param_initial must be positive, got {}",
+            lattice_param_initial
+        )));
+    }
+    let hkl_norm = (hkl.iter().map(|&h| (h * h) as f64).sum::<f64>()).sqrt();
+    if hkl_norm == 0.0 {
+        return Err(Error::InvalidArgument(
+            "hkl must not be (0, 0, 0)".to_string(),
+        ));
+    }
+    let q_expected = 2.0 * PI * hkl_norm / lattice_param_initial;
+    let peak = find_peaks(q_values, intensities, 0.0, 0.0)?
+        .into_iter()
+        .filter(|p| (p.position_q - q_expected).abs() <= PEAK_SEARCH_WINDOW * q_expected)
+        .max_by(|a, b| a.prominence.total_cmp(&b.prominence))
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "no peak within {} of the expected q_hkl = {}",
+                PEAK_SEARCH_WINDOW * q_expected,
+                q_expected
+            ))
+        })?;
+
+    let centre = q_values.partition_point(|&q| q < peak.position_q);
+    let top = [centre.saturating_sub(1), centre.min(q_values.len() - 1)]
+        .into_iter()
+        .max_by(|&a, &b| intensities[a].total_cmp(&intensities[b]))
+        .unwrap_or(centre);
+    let half = intensities[top] - peak.prominence / 2.0;
+    let above = |i: usize| intensities[i] > half && intensities[i] > 0.0;
+    let mut first = top;
+    while first > 0 && above(first - 1) {
+        first -= 1;
+    }
+    let mut last = top;
+    while last + 1 < q_values.len() && above(last + 1) {
+        last += 1;
+    }
+    let (q_peak, sigma_q) = gaussian_centre(
+        &q_values[first..=last],
+        &intensities[first..=last],
+        q_values[top],
+    )?;
+    let lattice_param = 2.0 * PI * hkl_norm / q_peak;
+    Ok((lattice_param, lattice_param * sigma_q / q_peak))
+}
+
+/// Centre of the Gaussian through `(q, I)` and its standard uncertainty, from
+/// the least-squares parabola `ln I = c0 + c1 t + c2 t^2` in `t = q - origin`.
+fn gaussian_centre(q_values: &[f64], intensities: &[f64], origin: f64) -> Result<(f64, f64)> {
+    let n = q_values.len();
+    if n < 4 {
+        return Err(Error::InvalidArgument(format!(
+            "the peak fit needs at least 4 points above half prominence, got {}",
+            n
+        )));
+    }
+    let points: Vec<(f64, f64)> = q_values
+        .iter()
+        .zip(intensities)
+        .map(|(&q, &i)| (q - origin, i.ln()))
+        .collect();
+    // Normal matrix `sum t^(j+k)` and right-hand side `sum t^j ln I`.
+    let mut moments = [0.0; 5];
+    let mut rhs = [0.0; 3];
+    for &(t, y) in &points {
+        for (j, m) in moments.iter_mut().enumerate() {
+            *m += t.powi(j as i32);
+        }
+        for (j, r) in rhs.iter_mut().enumerate() {
+            *r += t.powi(j as i32) * y;
+        }
+    }
+    let normal = [
+        [moments[0], moments[1], moments[2]],
+        [moments[1], moments[2], moments[3]],
+        [moments[2], moments[3], moments[4]],
+    ];
+    let inverse = invert_3x3(&normal).ok_or_else(|| {
+        Error::InvalidArgument("the peak fit is singular; the q values coincide".to_string())
+    })?;
+    let c: Vec<f64> = inverse
+        .iter()
+        .map(|row| row.iter().zip(&rhs).map(|(a, b)| a * b).sum())
+        .collect();
+    if c[2] >= 0.0 {
+        return Err(Error::InvalidArgument(
+            "the peak fit is not concave; the points do not form a peak".to_string(),
+        ));
+    }
+    let residual: f64 = points
+        .iter()
+        .map(|&(t, y)| (y - (c[0] + c[1] * t + c[2] * t * t)).powi(2))
+        .sum();
+    let variance = residual / (n - 3) as f64;
+
+    let centre = -c[1] / (2.0 * c[2]);
+    // Gradient of the centre with respect to (c1, c2).
+    let gradient = [-1.0 / (2.0 * c[2]), c[1] / (2.0 * c[2] * c[2])];
+    let centre_variance = variance
+        * (gradient[0] * gradient[0] * inverse[1][1]
+            + 2.0 * gradient[0] * gradient[1] * inverse[1][2]
+            + gradient[1] * gradient[1] * inverse[2][2]);
+    Ok((origin + centre, centre_variance.sqrt()))
+}
+
+/// Inverse of a 3x3 matrix by cofactors, or `None` if it is singular.
+fn invert_3x3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant: f64 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    if determinant == 0.0 || !determinant.is_finite() {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = cofactor(j, i) / determinant;
+        }
+    }
+    Some(inverse)
+}
@@ -0,0 +1,59 @@
+use rayon::prelude::*;
+
+use super::{dse_at_q, q_grid, Atoms, DistanceMatrix, Error, Result};
+
+/// Atoms of one periodic cell of a bulk crystal, in an orthorhombic box
+/// repeated along x, y and z.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicCrystal {
+    atoms: Atoms,
+    box_lengths: [f64; 3],
+}
+
+impl PeriodicCrystal {
+    /// Periodic cell holding `positions`, with edges `box_lengths` in
+    /// nanometers. Atoms outside the box are allowed; only their separations
+    /// modulo the box matter.
+    ///
+    /// Fails unless every box length is positive and finite.
+    pub fn new(positions: &[[f64; 3]], box_lengths: [f64; 3]) -> Result<Self> {
+        if let Some(&l) = box_lengths.iter().find(|l| !l.is_finite() || **l <= 0.0) {
+            return Err(Error::InvalidArgument(format!(
+                "box lengths must be positive and finite, got {}",
+                l
+            )));
+        }
+        Ok(Self {
+            atoms: positions.iter().copied().collect(),
+            box_lengths,
+        })
+    }
+
+    /// Atoms of the cell.
+    pub fn atoms(&self) -> &Atoms {
+        &self.atoms
+    }
+
+    /// Box edges along x, y and z.
+    pub fn box_lengths(&self) -> [f64; 3] {
+        self.box_lengths
+    }
+}
+
+/// Debye sum of a periodic cell with every pair at its minimum-image
+/// distance, on the [`q_grid`] of `min_q`, `max_q` and `q_step`.
+///
+/// Unlike [`dse_core`](super::dse_core) for a finite particle, the result has
+/// no particle surface: it approximates the bulk scattering per cell of the
+/// infinitely repeated crystal. Pair correlations are cut off at the nearest
+/// image, i.e. at most half a box length per axis, so the curve is reliable
+/// for q well above `2 pi / L` only; towards q = 0 it still rises to `N^2`,
+/// which is an artefact of the truncation rather than forward scattering.
+pub fn dse_periodic(min_q: f64, max_q: f64, q_step: f64, crystal: &PeriodicCrystal) -> Vec<f64> {
+    let distances =
+        DistanceMatrix::from_atoms_minimum_image(crystal.atoms.positions(), crystal.box_lengths);
+    q_grid(min_q, max_q, q_step)
+        .par_iter()
+        .map(|&q| dse_at_q(q, &distances))
+        .collect()
+}
@@ -0,0 +1,121 @@
+use rayon::prelude::*;
+
+use super::{crystal_core, dse_at_q, q_grid, DistanceMatrix, Error, Result, Shape};
+
+/// Convergence tolerance of the Newton iteration for Gauss-Hermite nodes.
+const GAUSS_HERMITE_TOLERANCE: f64 = 3e-14;
+
+/// Largest number of Newton steps per Gauss-Hermite node.
+const GAUSS_HERMITE_MAX_ITERATIONS: usize = 20;
+
+/// Nodes and weights of the `n`-point Gauss-Hermite rule, for integrals
+/// `integral f(x) exp(-x^2) dx ~ sum w_k f(x_k)`.
+///
+/// Nodes are found by Newton iteration on the orthonormal Hermite recurrence,
+/// starting from the asymptotic estimates of Numerical Recipes (`gauher`).
+fn gauss_hermite(n: usize) -> Vec<(f64, f64)> {
+    let pi_m4 = std::f64::consts::PI.powf(-0.25);
+    let nf = n as f64;
+    let mut nodes = vec![(0.0, 0.0); n];
+    let mut z: f64 = 0.0;
+    for i in 0..n.div_ceil(2) {
+        z = match i {
+            0 => (2.0 * nf + 1.0).sqrt() - 1.85575 * (2.0 * nf + 1.0).powf(-1.0 / 6.0),
+            1 => z - 1.14 * nf.powf(0.426) / z,
+            2 => 1.86 * z - 0.86 * nodes[0].0,
+            3 => 1.91 * z - 0.91 * nodes[1].0,
+            _ => 2.0 * z - nodes[i - 2].0,
+        };
+        let mut derivative = 0.0;
+        for _ in 0..GAUSS_HERMITE_MAX_ITERATIONS {
+            let (mut p1, mut p2) = (pi_m4, 0.0);
+            for j in 1..=n {
+                let jf = j as f64;
+                let p3 = p2;
+                p2 = p1;
+                p1 = z * (2.0 / jf).sqrt() * p2 - ((jf - 1.0) / jf).sqrt() * p3;
+            }
+            derivative = (2.0 * nf).sqrt() * p2;
+            let step = p1 / derivative;
+            z -= step;
+            if step.abs() < GAUSS_HERMITE_TOLERANCE {
+                break;
+            }
+        }
+        let weight = 2.0 / (derivative * derivative);
+        nodes[i] = (z, weight);
+        nodes[n - 1 - i] = (-z, weight);
+    }
+    nodes
+}
+
+/// [`dse_core`](super::dse_core) averaged over a log-normal distribution of
+/// crystal sizes.
+///
+/// `ln L` is normally distributed with standard deviation `sigma_log` and the
+/// mean of `L` is `mean_size`. The distribution is sampled at the `n_sizes`
+/// Gauss-Hermite nodes, a crystal of each size is built with
+/// [`crystal_core`], and the intensities are averaged with volume weights
+/// `p(L) L^3`, normalised to sum to one. The sizes are evaluated in parallel,
+/// each serially on one thread.
+///
+/// Only shapes whose size is set by `length` are accepted, so not
+/// [`Shape::Ellipsoid`] or [`Shape::Icosahedron`].
+#[allow(clippy::too_many_arguments)]
+pub fn dse_size_averaged(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    shape: Shape,
+    lattice_param: f64,
+    mean_size: f64,
+    sigma_log: f64,
+    n_sizes: usize,
+) -> Result<Vec<f64>> {
+    if !shape.is_sized_by_length() {
+        return Err(Error::InvalidArgument(
+            "ellipsoid and icosahedron crystals are not sized by length and cannot be \
+             size-averaged"
+                .to_string(),
+        ));
+    }
+    if mean_size.is_nan() || mean_size <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "mean_size must be positive, got {}",
+            mean_size
+        )));
+    }
+    if sigma_log.is_nan() || sigma_log < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "sigma_log must be non-negative, got {}",
+            sigma_log
+        )));
+    }
+    if n_sizes == 0 {
+        return Err(Error::InvalidArgument(
+            "n_sizes must be at least 1".to_string(),
+        ));
+    }
+
+    let mu = mean_size.ln() - sigma_log * sigma_log / 2.0;
+    let q_values = q_grid(min_q, max_q, q_step);
+    let weighted: Vec<(f64, Vec<f64>)> = gauss_hermite(n_sizes)
+        .into_par_iter()
+        .map(|(x, w)| {
+            let size = (mu + std::f64::consts::SQRT_2 * sigma_log * x).exp();
+            let crystal = crystal_core(shape, lattice_param, size)?;
+            let distances = DistanceMatrix::from_atoms(crystal.positions());
+            let intensities = q_values.iter().map(|&q| dse_at_q(q, &distances)).collect();
+            Ok((w * size.powi(3), intensities))
+        })
+        .collect::<Result<_>>()?;
+
+    let total_weight: f64 = weighted.iter().map(|(w, _)| w).sum();
+    let mut average = vec![0.0; q_values.len()];
+    for (w, intensities) in &weighted {
+        for (a, i) in average.iter_mut().zip(intensities) {
+            *a += w / total_weight * i;
+        }
+    }
+    Ok(average)
+}
@@ -0,0 +1,15 @@
+/// Receiver of progress updates from long-running DSE computations.
+///
+/// `report` is called on the thread that started the computation, after each
+/// chunk of q points, never from the rayon worker threads. Implemented for
+/// every `Fn(usize, usize)` closure.
+pub trait ProgressFn {
+    /// Called with the number of completed q points and the total number.
+    fn report(&self, completed: usize, total: usize);
+}
+
+impl<F: Fn(usize, usize)> ProgressFn for F {
+    fn report(&self, completed: usize, total: usize) {
+        self(completed, total)
+    }
+}
@@ -0,0 +1,107 @@
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+use super::analysis::check_same_length;
+use super::{dse_core, q_grid, Error, Result};
+
+/// Half-width of the truncated Gaussian kernel, in standard deviations.
+///
+/// The weight beyond `8 sigma_q` is below `exp(-32) ~ 1e-14` of the peak.
+const KERNEL_HALF_WIDTH_SIGMAS: f64 = 8.0;
+
+/// Largest relative deviation of a q step from the mean step for a grid to
+/// count as uniform.
+const UNIFORM_GRID_TOLERANCE: f64 = 1e-6;
+
+/// `intensities` convolved with the Gaussian resolution function
+/// `R(q, q') = exp(-(q - q')^2 / (2 sigma_q^2)) / (sigma_q sqrt(2 pi))`.
+///
+/// The integral `I_smeared(q) = integral I(q') R(q, q') dq'` is evaluated as a
+/// discrete convolution over the q grid, which must therefore be uniformly
+/// spaced, as [`q_grid`] is. The convolution is done by FFT in
+/// O(N log N). Near the ends of the grid the kernel is cut off by the missing
+/// data, so each point is divided by the part of the kernel weight that lies
+/// on the grid; a constant curve stays constant and the integrated intensity
+/// of a peak away from the ends is preserved.
+pub fn apply_instrument_broadening(
+    q_values: &[f64],
+    intensities: &[f64],
+    sigma_q: f64,
+) -> Result<Vec<f64>> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    if sigma_q.is_nan() || sigma_q <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "sigma_q must be positive, got {}",
+            sigma_q
+        )));
+    }
+    let n = q_values.len();
+    if n < 2 {
+        return Ok(intensities.to_vec());
+    }
+    let q_step = (q_values[n - 1] - q_values[0]) / (n - 1) as f64;
+    if q_step <= 0.0
+        || q_values
+            .windows(2)
+            .any(|w| ((w[1] - w[0]) - q_step).abs() > UNIFORM_GRID_TOLERANCE * q_step)
+    {
+        return Err(Error::InvalidArgument(
+            "q_values must be increasing and uniformly spaced".to_string(),
+        ));
+    }
+
+    let half_width = ((KERNEL_HALF_WIDTH_SIGMAS * sigma_q / q_step).ceil() as usize).min(n - 1);
+    // Zero padding to n + half_width keeps the circular convolution from
+    // wrapping the end of the data onto its start.
+    let len = n + half_width;
+    let mut kernel = vec![Complex::new(0.0, 0.0); len];
+    for k in 0..=half_width {
+        let dq = k as f64 * q_step;
+        let weight = (-dq * dq / (2.0 * sigma_q * sigma_q)).exp();
+        kernel[k].re = weight;
+        kernel[(len - k) % len].re = weight;
+    }
+    let mut signal = zero_padded(intensities.iter().copied(), len);
+    let mut coverage = zero_padded(std::iter::repeat_n(1.0, n), len);
+
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(len);
+    let inverse = planner.plan_fft_inverse(len);
+    forward.process(&mut kernel);
+    for buffer in [&mut signal, &mut coverage] {
+        forward.process(buffer);
+        for (value, k) in buffer.iter_mut().zip(&kernel) {
+            *value *= k;
+        }
+        inverse.process(buffer);
+    }
+    // The FFT scale factor 1 / len and the kernel normalisation cancel in
+    // the ratio.
+    Ok(signal
+        .iter()
+        .zip(&coverage)
+        .take(n)
+        .map(|(s, c)| s.re / c.re)
+        .collect())
+}
+
+/// `values` as complex numbers, followed by zeros up to length `len`.
+fn zero_padded(values: impl Iterator<Item = f64>, len: usize) -> Vec<Complex<f64>> {
+    let mut buffer: Vec<Complex<f64>> = values.map(|v| Complex::new(v, 0.0)).collect();
+    buffer.resize(len, Complex::new(0.0, 0.0));
+    buffer
+}
+
+/// [`dse_core`] broadened by a Gaussian instrument resolution of standard
+/// deviation `sigma_q`, as [`apply_instrument_broadening`] does on the
+/// [`q_grid`] of `min_q`, `max_q` and `q_step`.
+pub fn dse_with_instrument_broadening(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    sigma_q: f64,
+) -> Result<Vec<f64>> {
+    let intensities = dse_core(min_q, max_q, q_step, positions);
+    apply_instrument_broadening(&q_grid(min_q, max_q, q_step), &intensities, sigma_q)
+}
@@ -0,0 +1,269 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::{crystal_core, q_grid, Atoms, Error, Result, Shape};
+
+/// 3x3 rotation matrix, row-major.
+pub type Matrix3 = [[f64; 3]; 3];
+
+/// Largest deviation of `R^T R` from the identity accepted as a rotation.
+const ORTHOGONALITY_TOLERANCE: f64 = 1e-6;
+
+/// Rotation by `angle` radians about the x axis.
+pub fn rotation_matrix_x(angle: f64) -> Matrix3 {
+    let (sin, cos) = angle.sin_cos();
+    [[1.0, 0.0, 0.0], [0.0, cos, -sin], [0.0, sin, cos]]
+}
+
+/// Rotation by `angle` radians about the y axis.
+pub fn rotation_matrix_y(angle: f64) -> Matrix3 {
+    let (sin, cos) = angle.sin_cos();
+    [[cos, 0.0, sin], [0.0, 1.0, 0.0], [-sin, 0.0, cos]]
+}
+
+/// Rotation by `angle` radians about the z axis.
+pub fn rotation_matrix_z(angle: f64) -> Matrix3 {
+    let (sin, cos) = angle.sin_cos();
+    [[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]]
+}
+
+/// Apply `p' = R p` to every atom.
+///
+/// Fails unless every entry of `R^T R - I` is within 1e-6 of zero. Reflections
+/// (determinant -1) are accepted.
+pub fn rotate_crystal(positions: &[[f64; 3]], rotation: &Matrix3) -> Result<Atoms> {
+    for i in 0..3 {
+        for j in 0..3 {
+            let dot: f64 = (0..3).map(|k| rotation[k][i] * rotation[k][j]).sum();
+            let expected = if i == j { 1.0 } else { 0.0 };
+            let deviation = (dot - expected).abs();
+            if deviation.is_nan() || deviation > ORTHOGONALITY_TOLERANCE {
+                return Err(Error::InvalidArgument(format!(
+                    "rotation matrix is not orthogonal: (R^T R)[{}][{}] = {}",
+                    i, j, dot
+                )));
+            }
+        }
+    }
+    Ok(positions.iter().map(|p| apply(rotation, p)).collect())
+}
+
+fn apply(rotation: &Matrix3, p: &[f64; 3]) -> [f64; 3] {
+    rotation.map(|row| row[0] * p[0] + row[1] * p[1] + row[2] * p[2])
+}
+
+/// Rotation drawn uniformly from SO(3), from a uniform random unit quaternion
+/// (Shoemake, Graphics Gems III, 1992).
+fn random_rotation(rng: &mut SmallRng) -> Matrix3 {
+    let (u1, u2, u3): (f64, f64, f64) = (rng.random(), rng.random(), rng.random());
+    let tau = std::f64::consts::TAU;
+    let (a, b) = ((1.0 - u1).sqrt(), u1.sqrt());
+    let (w, x, y, z) = (
+        a * (tau * u2).sin(),
+        a * (tau * u2).cos(),
+        b * (tau * u3).sin(),
+        b * (tau * u3).cos(),
+    );
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ]
+}
+
+/// Concatenation of grains, each rotated by [`rotate_crystal`] with its own
+/// matrix before it is appended, in the order given.
+///
+/// The rotations are about the origin, so each grain should be centred where
+/// its rotation is meant to pivot, e.g. with
+/// [`center_crystal`](super::center_crystal), and translated afterwards.
+/// Fails as [`rotate_crystal`] if a matrix is not orthogonal.
+pub fn crystal_grain_aggregate(grains: &[(Atoms, Matrix3)]) -> Result<Atoms> {
+    let mut aggregate = Atoms::with_capacity(grains.iter().map(|(grain, _)| grain.len()).sum());
+    for (grain, rotation) in grains {
+        for &p in rotate_crystal(grain.positions(), rotation)?.positions() {
+            aggregate.push(p);
+        }
+    }
+    Ok(aggregate)
+}
+
+/// Fraction of `lattice_param` below which [`crystal_polycrystalline`] drops
+/// an atom of a grain that comes too close to one of an earlier grain.
+pub const GRAIN_OVERLAP_DISTANCE: f64 = 0.5;
+
+/// Polycrystal of `n_grains` randomly oriented spherical grains of diameter
+/// `grain_size` inside a cube of side `total_size`.
+///
+/// Each grain is the [`Shape::Sphere`] crystal of `lattice_param` and
+/// `grain_size`, rotated about its centre by a rotation drawn uniformly from
+/// SO(3) and centred at a uniformly random point at least `grain_size / 2`
+/// from the faces of the cube `[0, total_size]^3`, so that grains stay inside
+/// it. Grains are added in turn and an atom closer than
+/// [`GRAIN_OVERLAP_DISTANCE`] `* lattice_param` to an atom of an earlier
+/// grain is dropped, which leaves the grain boundaries free of unphysically
+/// short pairs. The random orientations broaden the Bragg peaks as in a
+/// powder. The overlap check compares every pair, O(N^2) like the Debye sum.
+/// The rotations and centres are drawn from a [`SmallRng`] seeded from
+/// `seed`. Fails unless `lattice_param` and `grain_size` are positive and
+/// `total_size >= grain_size`.
+pub fn crystal_polycrystalline(
+    lattice_param: f64,
+    grain_size: f64,
+    total_size: f64,
+    n_grains: usize,
+    seed: u64,
+) -> Result<Atoms> {
+    for (name, value) in [("lattice_param", lattice_param), ("grain_size", grain_size)] {
+        if value.is_nan() || value <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be positive, got {}",
+                name, value
+            )));
+        }
+    }
+    if total_size.is_nan() || total_size < grain_size {
+        return Err(Error::InvalidArgument(format!(
+            "total_size must be at least grain_size = {}, got {}",
+            grain_size, total_size
+        )));
+    }
+    let radius = grain_size / 2.0;
+    let grain: Vec<[f64; 3]> = crystal_core(Shape::Sphere, lattice_param, grain_size)?
+        .positions()
+        .iter()
+        .map(|p| p.map(|x| x - radius))
+        .collect();
+    let overlap_sq = (GRAIN_OVERLAP_DISTANCE * lattice_param).powi(2);
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut polycrystal: Vec<[f64; 3]> = Vec::with_capacity(n_grains * grain.len());
+    for _ in 0..n_grains {
+        let rotation = random_rotation(&mut rng);
+        let centre: [f64; 3] =
+            std::array::from_fn(|_| radius + (total_size - grain_size) * rng.random::<f64>());
+        let earlier = &polycrystal;
+        let kept: Vec<[f64; 3]> = grain
+            .par_iter()
+            .map(|p| {
+                let rotated = apply(&rotation, p);
+                [0, 1, 2].map(|axis| rotated[axis] + centre[axis])
+            })
+            .filter(|p| {
+                earlier.iter().all(|q| {
+                    (0..3).map(|axis| (p[axis] - q[axis]).powi(2)).sum::<f64>() >= overlap_sq
+                })
+            })
+            .collect();
+        polycrystal.extend(kept);
+    }
+    Ok(polycrystal.into_iter().collect())
+}
+
+/// Monte Carlo orientation average of the single-crystal intensity
+/// `I(q) = |sum_j exp(i q n . r_j)|^2`.
+///
+/// The crystal is rotated by `n_orientations` rotations drawn uniformly from
+/// SO(3) with a [`SmallRng`] seeded from `seed`, and the intensity along the
+/// fixed scattering direction `n = z` is averaged. The Debye equation is the
+/// exact orientation average, so the result converges to
+/// [`dse_core`](super::dse_core) with a statistical error falling as
+/// `1 / sqrt(n_orientations)`. Each orientation costs O(N * N_q) instead of
+/// O(N^2 * N_q), which makes this a cheap estimate for very large crystals and
+/// a way to study the orientation spread of single-particle patterns.
+pub fn dse_orientation_averaged(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    n_orientations: usize,
+    seed: u64,
+) -> Vec<f64> {
+    let q_values = q_grid(min_q, max_q, q_step);
+    if n_orientations == 0 {
+        return vec![0.0; q_values.len()];
+    }
+    let mut rng = SmallRng::seed_from_u64(seed);
+    // n . r_j for n = R^T z, i.e. the z component of the rotated atom R r_j.
+    let projections: Vec<Vec<f64>> = (0..n_orientations)
+        .map(|_| {
+            let rotation = random_rotation(&mut rng);
+            positions.iter().map(|p| apply(&rotation, p)[2]).collect()
+        })
+        .collect();
+
+    average_over_directions(&q_values, &projections)
+}
+
+/// Orientation average of the single-crystal intensity
+/// `I(q) = |sum_j exp(i q n . r_j)|^2` over `n_orientations` directions `n`
+/// on a Fibonacci spiral.
+///
+/// This is the deterministic counterpart of [`dse_orientation_averaged`]: the
+/// directions `n_k = (sqrt(1 - z_k^2) cos(phi_k), sqrt(1 - z_k^2) sin(phi_k),
+/// z_k)` with `z_k = 1 - (2k + 1) / n_orientations` and `phi_k` advancing by
+/// the golden angle cover the sphere evenly, so the result converges to
+/// [`dse_core`](super::dse_core) faster than the `1 / sqrt(n_orientations)`
+/// of random sampling. Comparing the two cross-validates the Debye sum. The
+/// cost is O(N * N_q * n_orientations).
+pub fn dse_powder_average_explicit(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: &[[f64; 3]],
+    n_orientations: usize,
+) -> Vec<f64> {
+    let q_values = q_grid(min_q, max_q, q_step);
+    if n_orientations == 0 {
+        return vec![0.0; q_values.len()];
+    }
+    let golden_angle = std::f64::consts::PI * (3.0 - 5.0_f64.sqrt());
+    let projections: Vec<Vec<f64>> = (0..n_orientations)
+        .map(|k| {
+            let z = 1.0 - (2 * k + 1) as f64 / n_orientations as f64;
+            let rho = (1.0 - z * z).sqrt();
+            let (sin, cos) = (golden_angle * k as f64).sin_cos();
+            let n = [rho * cos, rho * sin, z];
+            positions
+                .iter()
+                .map(|p| n[0] * p[0] + n[1] * p[1] + n[2] * p[2])
+                .collect()
+        })
+        .collect();
+
+    average_over_directions(&q_values, &projections)
+}
+
+/// Mean over `projections` of `|sum_j exp(i q n . r_j)|^2` at each q, where
+/// every projection holds the `n . r_j` of one direction `n`, computed in
+/// parallel over q.
+fn average_over_directions(q_values: &[f64], projections: &[Vec<f64>]) -> Vec<f64> {
+    q_values
+        .par_iter()
+        .map(|&q| {
+            let total: f64 = projections
+                .iter()
+                .map(|projection| {
+                    let (re, im) = projection.iter().fold((0.0, 0.0), |(re, im), &z| {
+                        let (sin, cos) = (q * z).sin_cos();
+                        (re + cos, im + sin)
+                    });
+                    re * re + im * im
+                })
+                .sum();
+            total / projections.len() as f64
+        })
+        .collect()
+}
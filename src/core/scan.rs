@@ -0,0 +1,64 @@
+use rayon::prelude::*;
+
+use super::analysis::{check_same_length, least_squares_weights};
+use super::{crystal_core, dse_at_q, q_grid, DistanceMatrix, Error, Result, Shape};
+
+/// `chi^2 = sum_q (I_obs - I_calc)^2 / sigma^2` of [`crystal_core`] crystals
+/// of `shape` at every `(lattice_param, size)` pair of the two grids, indexed
+/// `[i_lattice_param][i_size]`.
+///
+/// `i_obs` and `sigma_obs` are given on the [`q_grid`] of `min_q`, `max_q`
+/// and `q_step`. No scale factor is fitted, unlike in
+/// [`chi_squared`](super::chi_squared), so `i_obs` must be on the scale of
+/// [`dse_core`](super::dse_core). The lattice parameters are evaluated in
+/// parallel; each crystal's DSE runs serially on one thread, as in
+/// [`dse_size_averaged`](super::dse_size_averaged), whose shape restriction
+/// also applies.
+#[allow(clippy::too_many_arguments)]
+pub fn chi_squared_map(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    shape: Shape,
+    lattice_params: &[f64],
+    sizes: &[f64],
+    i_obs: &[f64],
+    sigma_obs: &[f64],
+) -> Result<Vec<Vec<f64>>> {
+    if !shape.is_sized_by_length() {
+        return Err(Error::InvalidArgument(
+            "ellipsoid and icosahedron crystals are not sized by length and cannot be scanned"
+                .to_string(),
+        ));
+    }
+    for (name, values) in [("lattice_params", lattice_params), ("sizes", sizes)] {
+        if let Some(&bad) = values.iter().find(|&&v| v.is_nan() || v <= 0.0) {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be positive, got {}",
+                name, bad
+            )));
+        }
+    }
+    let q_values = q_grid(min_q, max_q, q_step);
+    check_same_length("the q grid", &q_values, "i_obs", i_obs)?;
+    let weights = least_squares_weights("i_obs", i_obs, Some(sigma_obs))?;
+
+    lattice_params
+        .par_iter()
+        .map(|&lattice_param| {
+            sizes
+                .iter()
+                .map(|&size| {
+                    let crystal = crystal_core(shape, lattice_param, size)?;
+                    let distances = DistanceMatrix::from_atoms(crystal.positions());
+                    Ok(q_values
+                        .iter()
+                        .zip(i_obs)
+                        .zip(&weights)
+                        .map(|((&q, &obs), &w)| w * (obs - dse_at_q(q, &distances)).powi(2))
+                        .sum())
+                })
+                .collect()
+        })
+        .collect()
+}
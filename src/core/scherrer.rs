@@ -0,0 +1,289 @@
+use std::f64::consts::{LN_2, PI};
+
+use super::analysis::check_same_length;
+use super::background::solve_augmented;
+use super::{find_peaks, Error, Result, PEAK_SEARCH_WINDOW};
+
+/// Half-width of the window fitted by [`scherrer_analysis`], in units of the
+/// [`find_peaks`] FWHM of the peak; the window also stops at the minima on
+/// either side.
+pub const SCHERRER_FIT_HALF_WIDTH: f64 = 3.0;
+
+/// Largest number of Levenberg-Marquardt steps of the Voigt fit.
+const VOIGT_MAX_ITERATIONS: usize = 200;
+
+/// Relative decrease of the residual sum of squares below which the Voigt
+/// fit has converged.
+const VOIGT_TOLERANCE: f64 = 1e-12;
+
+/// Relative step of the finite-difference Jacobian of the Voigt fit.
+const VOIGT_JACOBIAN_STEP: f64 = 1e-7;
+
+/// FWHM of the Voigt profile with Gaussian and Lorentzian FWHMs `fg` and
+/// `fl`, in the approximation of Thompson, Cox and Hastings (1987).
+fn tch_fwhm(fg: f64, fl: f64) -> f64 {
+    (fg.powi(5)
+        + 2.69269 * fg.powi(4) * fl
+        + 2.42843 * fg.powi(3) * fl.powi(2)
+        + 4.47163 * fg.powi(2) * fl.powi(3)
+        + 0.07842 * fg * fl.powi(4)
+        + fl.powi(5))
+    .powf(0.2)
+}
+
+/// Voigt profile of parameters `[centre, amplitude, fg, fl]` at `q`, as the
+/// Thompson-Cox-Hastings pseudo-Voigt: a mixture of a Lorentzian and a
+/// Gaussian of the common FWHM [`tch_fwhm`], accurate to about 1%. The
+/// widths enter by absolute value so that the fit cannot make them negative.
+fn voigt(q: f64, parameters: &[f64; 4]) -> f64 {
+    let [centre, amplitude, fg, fl] = *parameters;
+    let fwhm = tch_fwhm(fg.abs(), fl.abs());
+    let ratio = fl.abs() / fwhm;
+    let eta = ratio * (1.36603 - ratio * (0.47719 - ratio * 0.11116));
+    let t = ((q - centre) / fwhm).powi(2);
+    amplitude * (eta / (1.0 + 4.0 * t) + (1.0 - eta) * (-4.0 * LN_2 * t).exp())
+}
+
+/// Inverse of a square matrix, column by column with [`solve_augmented`], or
+/// `None` if it is singular.
+fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let size = matrix.len();
+    let columns: Vec<Vec<f64>> = (0..size)
+        .map(|column| {
+            let augmented = matrix
+                .iter()
+                .enumerate()
+                .map(|(i, row)| {
+                    let mut row = row.clone();
+                    row.push(if i == column { 1.0 } else { 0.0 });
+                    row
+                })
+                .collect();
+            solve_augmented(augmented)
+        })
+        .collect();
+    if columns.iter().flatten().any(|x| !x.is_finite()) {
+        return None;
+    }
+    Some(
+        (0..size)
+            .map(|i| columns.iter().map(|column| column[i]).collect())
+            .collect(),
+    )
+}
+
+/// Least-squares fit of [`voigt`] to `(q, I)` by Levenberg-Marquardt from
+/// `initial`, returning the parameters and their covariance scaled by the
+/// residual variance.
+fn fit_voigt(
+    q_values: &[f64],
+    intensities: &[f64],
+    initial: [f64; 4],
+) -> Result<([f64; 4], Vec<Vec<f64>>)> {
+    let scale = [initial[2], initial[1].abs(), initial[2], initial[2]];
+    let residual_sum = |parameters: &[f64; 4]| -> f64 {
+        q_values
+            .iter()
+            .zip(intensities)
+            .map(|(&q, &i)| (i - voigt(q, parameters)).powi(2))
+            .sum()
+    };
+    // Normal matrix `J^T J` and gradient `J^T r` of the residuals `r`.
+    let normal_equations = |parameters: &[f64; 4]| -> (Vec<Vec<f64>>, Vec<f64>) {
+        let mut normal = vec![vec![0.0; 4]; 4];
+        let mut gradient = vec![0.0; 4];
+        for (&q, &i) in q_values.iter().zip(intensities) {
+            let derivatives: Vec<f64> = (0..4)
+                .map(|k| {
+                    let h = VOIGT_JACOBIAN_STEP * parameters[k].abs().max(scale[k]);
+                    let (mut above, mut below) = (*parameters, *parameters);
+                    above[k] += h;
+                    below[k] -= h;
+                    (voigt(q, &above) - voigt(q, &below)) / (2.0 * h)
+                })
+                .collect();
+            let residual = i - voigt(q, parameters);
+            for j in 0..4 {
+                gradient[j] += derivatives[j] * residual;
+                for k in 0..4 {
+                    normal[j][k] += derivatives[j] * derivatives[k];
+                }
+            }
+        }
+        (normal, gradient)
+    };
+
+    let mut parameters = initial;
+    let mut chi_squared = residual_sum(&parameters);
+    let mut damping = 1e-3;
+    for _ in 0..VOIGT_MAX_ITERATIONS {
+        let (normal, gradient) = normal_equations(&parameters);
+        let mut improved = None;
+        while damping < 1e12 {
+            let augmented = (0..4)
+                .map(|j| {
+                    let mut row = normal[j].clone();
+                    row[j] *= 1.0 + damping;
+                    row.push(gradient[j]);
+                    row
+                })
+                .collect();
+            let step = solve_augmented(augmented);
+            let mut trial = parameters;
+            for (p, s) in trial.iter_mut().zip(&step) {
+                *p += s;
+            }
+            let trial_chi_squared = residual_sum(&trial);
+            if trial_chi_squared < chi_squared {
+                improved = Some((trial, trial_chi_squared));
+                damping /= 10.0;
+                break;
+            }
+            damping *= 10.0;
+        }
+        let Some((trial, trial_chi_squared)) = improved else {
+            break;
+        };
+        let decrease = (chi_squared - trial_chi_squared) / chi_squared;
+        parameters = trial;
+        chi_squared = trial_chi_squared;
+        if decrease < VOIGT_TOLERANCE {
+            break;
+        }
+    }
+
+    let (normal, _) = normal_equations(&parameters);
+    let variance = chi_squared / (q_values.len() - 4) as f64;
+    let covariance = invert(&normal).ok_or_else(|| {
+        Error::InvalidArgument("the Voigt fit is singular; the peak is not resolved".to_string())
+    })?;
+    Ok((
+        parameters,
+        covariance
+            .into_iter()
+            .map(|row| row.into_iter().map(|c| c * variance).collect())
+            .collect(),
+    ))
+}
+
+/// Coherence length `L = K lambda / (beta cos theta)` in nanometers from the
+/// width of the peak near `peak_q`, by the Scherrer equation, returned with
+/// its standard uncertainty as `(L, sigma_L)`.
+///
+/// The peak is the most prominent [`find_peaks`] maximum within
+/// [`PEAK_SEARCH_WINDOW`] of `peak_q`. The points up to
+/// [`SCHERRER_FIT_HALF_WIDTH`] FWHMs from it, and no further than the minima
+/// on either side, are fitted with a Voigt profile of centre, amplitude and
+/// Gaussian and Lorentzian widths by Levenberg-Marquardt, after subtracting
+/// the straight line through the ends of the window. The FWHM in q of the
+/// Thompson-Cox-Hastings pseudo-Voigt is converted to the width `beta` in
+/// `2theta` radians at `wavelength_nm`, and `sigma_L` follows from the
+/// covariance of the widths, scaled by the residual variance. To first order
+/// `L = 2 pi K / FWHM_q` whatever the wavelength. Fails unless
+/// `wavelength_nm` and `scherrer_k` are positive, the window holds a peak
+/// with at least 5 points and the peak is reachable at `wavelength_nm`.
+pub fn scherrer_analysis(
+    q_values: &[f64],
+    intensities: &[f64],
+    peak_q: f64,
+    wavelength_nm: f64,
+    scherrer_k: f64,
+) -> Result<(f64, f64)> {
+    check_same_length("q_values", q_values, "intensities", intensities)?;
+    for (name, value) in [
+        ("peak_q", peak_q),
+        ("wavelength_nm", wavelength_nm),
+        ("scherrer_k", scherrer_k),
+    ] {
+        if value.is_nan() || value <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be positive, got {}",
+                name, value
+            )));
+        }
+    }
+    let peak = find_peaks(q_values, intensities, 0.0, 0.0)?
+        .into_iter()
+        .filter(|p| (p.position_q - peak_q).abs() <= PEAK_SEARCH_WINDOW * peak_q)
+        .max_by(|a, b| a.prominence.total_cmp(&b.prominence))
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "no peak within {} of peak_q = {}",
+                PEAK_SEARCH_WINDOW * peak_q,
+                peak_q
+            ))
+        })?;
+
+    let centre = q_values.partition_point(|&q| q < peak.position_q);
+    let top = [centre.saturating_sub(1), centre.min(q_values.len() - 1)]
+        .into_iter()
+        .max_by(|&a, &b| intensities[a].total_cmp(&intensities[b]))
+        .unwrap_or(centre);
+    let reach = SCHERRER_FIT_HALF_WIDTH * peak.fwhm;
+    let mut first = top;
+    while first > 0
+        && intensities[first - 1] <= intensities[first]
+        && peak.position_q - q_values[first - 1] <= reach
+    {
+        first -= 1;
+    }
+    let mut last = top;
+    while last + 1 < q_values.len()
+        && intensities[last + 1] <= intensities[last]
+        && q_values[last + 1] - peak.position_q <= reach
+    {
+        last += 1;
+    }
+    let n = last - first + 1;
+    if n < 5 {
+        return Err(Error::InvalidArgument(format!(
+            "the Voigt fit needs at least 5 points across the peak, got {}",
+            n
+        )));
+    }
+
+    let (q_fit, i_fit) = (&q_values[first..=last], &intensities[first..=last]);
+    let slope = (i_fit[n - 1] - i_fit[0]) / (q_fit[n - 1] - q_fit[0]);
+    let above_baseline: Vec<f64> = q_fit
+        .iter()
+        .zip(i_fit)
+        .map(|(&q, &i)| i - (i_fit[0] + slope * (q - q_fit[0])))
+        .collect();
+    // Equal Gaussian and Lorentzian widths of the measured FWHM.
+    let width = peak.fwhm / tch_fwhm(1.0, 1.0);
+    let height = intensities[top] - (i_fit[0] + slope * (q_values[top] - q_fit[0]));
+    let (parameters, covariance) = fit_voigt(
+        q_fit,
+        &above_baseline,
+        [peak.position_q, height, width, width],
+    )?;
+
+    let [centre_q, _, fg, fl] = parameters;
+    let (fg, fl) = (fg.abs(), fl.abs());
+    let fwhm_q = tch_fwhm(fg, fl);
+    // Gradient of the FWHM with respect to the two widths.
+    let h = VOIGT_JACOBIAN_STEP * fwhm_q;
+    let gradient = [
+        (tch_fwhm(fg + h, fl) - tch_fwhm((fg - h).abs(), fl)) / (2.0 * h),
+        (tch_fwhm(fg, fl + h) - tch_fwhm(fg, (fl - h).abs())) / (2.0 * h),
+    ];
+    let fwhm_variance = gradient[0] * gradient[0] * covariance[2][2]
+        + 2.0 * gradient[0] * gradient[1] * covariance[2][3]
+        + gradient[1] * gradient[1] * covariance[3][3];
+
+    let q_limit = 4.0 * PI / wavelength_nm;
+    let (q_low, q_high) = (centre_q - fwhm_q / 2.0, centre_q + fwhm_q / 2.0);
+    if q_low < 0.0 || q_high > q_limit {
+        return Err(Error::InvalidArgument(format!(
+            "the peak from q = {} to {} is outside [0, {}], the q reachable at wavelength {} nm",
+            q_low, q_high, q_limit, wavelength_nm
+        )));
+    }
+    let theta = (centre_q / q_limit).asin();
+    let beta = 2.0 * ((q_high / q_limit).asin() - (q_low / q_limit).asin());
+    let coherence_length = scherrer_k * wavelength_nm / (beta * theta.cos());
+    Ok((
+        coherence_length,
+        coherence_length * fwhm_variance.sqrt() / fwhm_q,
+    ))
+}
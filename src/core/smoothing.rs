@@ -0,0 +1,79 @@
+use super::background::solve_augmented;
+use super::{Error, Result};
+
+/// `intensities` smoothed by a Savitzky-Golay filter: at every point, the
+/// value at the centre of the least-squares polynomial of degree `poly_order`
+/// through the `window_size` points around it.
+///
+/// The fit is a fixed convolution whose coefficients are the first row of the
+/// pseudo-inverse of the window's Vandermonde matrix, computed once. The
+/// filter keeps peak heights and widths better than a moving average of the
+/// same width. The array is mirrored about its first and last points (so that
+/// `I[-i] = I[i]`) to keep the length. Fails unless `window_size` is odd,
+/// larger than `poly_order` and at most the number of intensities.
+pub fn savitzky_golay_smooth(
+    intensities: &[f64],
+    window_size: usize,
+    poly_order: usize,
+) -> Result<Vec<f64>> {
+    if window_size.is_multiple_of(2) || window_size <= poly_order || window_size > intensities.len()
+    {
+        return Err(Error::InvalidArgument(format!(
+            "window_size must be odd, larger than poly_order ({}) and at most the number of \
+             intensities ({}), got {}",
+            poly_order,
+            intensities.len(),
+            window_size
+        )));
+    }
+    let half = (window_size / 2) as isize;
+    // Offsets scaled to [-1, 1] keep the normal equations well conditioned.
+    let offsets: Vec<f64> = (-half..=half)
+        .map(|j| j as f64 / half.max(1) as f64)
+        .collect();
+    let size = poly_order + 1;
+    let mut matrix = vec![vec![0.0; size + 1]; size];
+    for (j, row) in matrix.iter_mut().enumerate() {
+        for (k, value) in row.iter_mut().take(size).enumerate() {
+            *value = offsets.iter().map(|t| t.powi((j + k) as i32)).sum();
+        }
+    }
+    matrix[0][size] = 1.0;
+    let solution = solve_augmented(matrix);
+    let coefficients: Vec<f64> = offsets
+        .iter()
+        .map(|t| solution.iter().rev().fold(0.0, |value, &c| value * t + c))
+        .collect();
+
+    let n = intensities.len() as isize;
+    let mirrored = |i: isize| {
+        let i = if i < 0 { -i } else { i };
+        let i = if i >= n { 2 * (n - 1) - i } else { i };
+        intensities[i as usize]
+    };
+    Ok((0..n)
+        .map(|i| {
+            coefficients
+                .iter()
+                .zip(-half..=half)
+                .map(|(c, j)| c * mirrored(i + j))
+                .sum()
+        })
+        .collect())
+}
+
+/// `intensities` averaged over a centred window of `window` points, with the
+/// mirrored edges of [`savitzky_golay_smooth`].
+///
+/// This is the Savitzky-Golay filter of order 0. Fails unless `window` is odd
+/// and at most the number of intensities.
+pub fn moving_average_smooth(intensities: &[f64], window: usize) -> Result<Vec<f64>> {
+    if window.is_multiple_of(2) || window > intensities.len() {
+        return Err(Error::InvalidArgument(format!(
+            "window must be odd and at most the number of intensities ({}), got {}",
+            intensities.len(),
+            window
+        )));
+    }
+    savitzky_golay_smooth(intensities, window, 0)
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::{Error, Result};
+
+/// Random displacement, as a fraction of the bounding-box diagonal, applied
+/// to the points the hull is built from. Lattices are full of coplanar and
+/// collinear atoms; the jitter puts them in general position so that no hull
+/// facet is degenerate.
+const HULL_JITTER: f64 = 1e-9;
+
+/// Depth, as a fraction of the bounding-box diagonal, below which an atom
+/// counts as lying on the hull, well above the error the jitter introduces.
+/// Point sets thinner than this are flat.
+const SURFACE_TOLERANCE: f64 = 1e-6;
+
+/// Distance in front of a facet, as a fraction of the bounding-box diagonal,
+/// below which a point is taken as behind it: above rounding error, far below
+/// the jitter, so that no fan facet is flat.
+const HULL_VISIBILITY: f64 = 1e-12;
+
+/// Facet of a convex hull: the plane `normal . x = offset`, with a unit
+/// `normal` pointing out of the hull.
+#[derive(Debug, Clone, Copy)]
+struct Facet {
+    vertices: [usize; 3],
+    normal: [f64; 3],
+    offset: f64,
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+impl Facet {
+    /// Facet through `vertices`, oriented so that `interior` lies behind it.
+    fn new(points: &[[f64; 3]], vertices: [usize; 3], interior: [f64; 3]) -> Self {
+        let [a, b, c] = vertices.map(|v| points[v]);
+        let n = cross(sub(b, a), sub(c, a));
+        let length = norm(n);
+        let mut facet = Facet {
+            vertices,
+            normal: n.map(|x| x / length),
+            offset: dot(n, a) / length,
+        };
+        if facet.signed_distance(interior) > 0.0 {
+            facet.vertices.swap(1, 2);
+            facet.normal = facet.normal.map(|x| -x);
+            facet.offset = -facet.offset;
+        }
+        facet
+    }
+
+    /// Distance of `p` in front of the facet, negative behind it.
+    fn signed_distance(&self, p: [f64; 3]) -> f64 {
+        dot(self.normal, p) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Index of the point farthest from the origin of `distance`.
+fn farthest(points: &[[f64; 3]], distance: impl Fn([f64; 3]) -> f64) -> (usize, f64) {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| (i, distance(p)))
+        .fold((0, f64::NEG_INFINITY), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+}
+
+/// Facets of the convex hull of `points`, built incrementally from an initial
+/// tetrahedron: each point in front of some facets replaces them by a fan of
+/// facets from their horizon to the point. The points must be in general
+/// position, and `diagonal` is the length of their bounding-box diagonal.
+/// `None` if they are coplanar to within [`SURFACE_TOLERANCE`].
+fn convex_hull(points: &[[f64; 3]], diagonal: f64) -> Option<Vec<Facet>> {
+    let tolerance = SURFACE_TOLERANCE * diagonal;
+    if points.len() < 4 {
+        return None;
+    }
+    // Initial tetrahedron of well-separated extreme points.
+    let (i0, _) = farthest(points, |p| -p[0]);
+    let (i1, d1) = farthest(points, |p| norm(sub(p, points[i0])));
+    if d1 <= tolerance {
+        return None;
+    }
+    let axis = sub(points[i1], points[i0]);
+    let (i2, d2) = farthest(points, |p| {
+        norm(cross(axis, sub(p, points[i0]))) / norm(axis)
+    });
+    if d2 <= tolerance {
+        return None;
+    }
+    let base_normal = cross(axis, sub(points[i2], points[i0]));
+    let (i3, d3) = farthest(points, |p| {
+        dot(base_normal, sub(p, points[i0])).abs() / norm(base_normal)
+    });
+    if d3 <= tolerance {
+        return None;
+    }
+    let corners = [i0, i1, i2, i3];
+    let interior =
+        [0, 1, 2].map(|axis| corners.iter().map(|&i| points[i][axis]).sum::<f64>() / 4.0);
+    let mut facets: Vec<Facet> = [[i0, i1, i2], [i0, i1, i3], [i0, i2, i3], [i1, i2, i3]]
+        .into_iter()
+        .map(|vertices| Facet::new(points, vertices, interior))
+        .collect();
+    let visibility = HULL_VISIBILITY * diagonal;
+
+    for (i, &p) in points.iter().enumerate() {
+        if corners.contains(&i) {
+            continue;
+        }
+        let (visible, kept): (Vec<Facet>, Vec<Facet>) = facets
+            .into_iter()
+            .partition(|facet| facet.signed_distance(p) > visibility);
+        facets = kept;
+        if visible.is_empty() {
+            continue;
+        }
+        let visible_edges: HashSet<(usize, usize)> =
+            visible.iter().flat_map(Facet::edges).collect();
+        for facet in &visible {
+            for (a, b) in facet.edges() {
+                if !visible_edges.contains(&(b, a)) {
+                    facets.push(Facet::new(points, [a, b, i], interior));
+                }
+            }
+        }
+    }
+    Some(facets)
+}
+
+/// Whether each atom lies within `probe_radius` of the surface of the
+/// crystal, approximated by the convex hull of `positions`.
+///
+/// A probe sphere of that radius rolling over the hull reaches the atoms
+/// whose distance to the nearest hull facet is at most `probe_radius`, so
+/// the atoms of the hull facets themselves are always flagged and a probe of
+/// about one lattice spacing also flags the layer beneath. The hull misses
+/// concave features such as the cavity of a hollow sphere, whose atoms count
+/// as bulk. Atoms that are all coplanar are all surface. Fails unless
+/// `probe_radius >= 0`.
+pub fn classify_surface_atoms(positions: &[[f64; 3]], probe_radius: f64) -> Result<Vec<bool>> {
+    if probe_radius.is_nan() || probe_radius < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "probe_radius must be non-negative, got {}",
+            probe_radius
+        )));
+    }
+    let (lower, upper) = positions.iter().fold(
+        ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]),
+        |(lower, upper), p| {
+            (
+                [0, 1, 2].map(|axis| lower[axis].min(p[axis])),
+                [0, 1, 2].map(|axis| upper[axis].max(p[axis])),
+            )
+        },
+    );
+    let diagonal = norm(sub(upper, lower)).max(1.0);
+    let mut rng = SmallRng::seed_from_u64(0);
+    let jittered: Vec<[f64; 3]> = positions
+        .iter()
+        .map(|p| p.map(|x| x + HULL_JITTER * diagonal * rng.random_range(-1.0..1.0)))
+        .collect();
+    let Some(facets) = convex_hull(&jittered, diagonal) else {
+        return Ok(vec![true; positions.len()]);
+    };
+    Ok(positions
+        .par_iter()
+        .map(|&p| {
+            let depth = facets
+                .iter()
+                .map(|facet| -facet.signed_distance(p))
+                .fold(f64::INFINITY, f64::min);
+            depth <= probe_radius + SURFACE_TOLERANCE * diagonal
+        })
+        .collect())
+}
+
+/// Fraction of the atoms flagged by [`classify_surface_atoms`].
+///
+/// For compact shapes it scales as `N^(-1/3)`. Fails unless there is at least
+/// one atom and `probe_radius >= 0`.
+pub fn surface_fraction(positions: &[[f64; 3]], probe_radius: f64) -> Result<f64> {
+    if positions.is_empty() {
+        return Err(Error::InvalidArgument(
+            "positions must contain at least one atom".to_string(),
+        ));
+    }
+    let surface = classify_surface_atoms(positions, probe_radius)?;
+    Ok(surface.iter().filter(|&&is_surface| is_surface).count() as f64 / positions.len() as f64)
+}
@@ -0,0 +1,85 @@
+use super::{dse_batch, dse_core, q_grid, Atoms, Error, Result};
+
+/// Mean and standard deviation at each q of the [`dse_core`] intensities of
+/// the frames of a trajectory, returning `(mean, std)`.
+///
+/// The frames are evaluated in parallel as by [`dse_batch`], so all of them
+/// and their intensities are held in memory; see
+/// [`dse_trajectory_average_streaming`] for long trajectories. The standard
+/// deviation is the population one, dividing by the number of frames.
+/// Fails without frames.
+pub fn dse_trajectory_average(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    frames: &[Atoms],
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut accumulator = MeanVariance::new(q_grid(min_q, max_q, q_step).len());
+    for intensity in dse_batch(min_q, max_q, q_step, frames) {
+        accumulator.add(&intensity);
+    }
+    accumulator.finish()
+}
+
+/// [`dse_trajectory_average`] over frames that are read one at a time, e.g.
+/// from [`LammpsDumpFrames`](super::LammpsDumpFrames), so that only one frame
+/// is in memory.
+///
+/// Each frame is evaluated with [`dse_core`], in parallel over q, and folded
+/// into running means and variances (Welford's algorithm). Fails at the first
+/// frame that is an error, or without frames.
+pub fn dse_trajectory_average_streaming<I>(
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    frames: I,
+) -> Result<(Vec<f64>, Vec<f64>)>
+where
+    I: IntoIterator<Item = Result<Atoms>>,
+{
+    let mut accumulator = MeanVariance::new(q_grid(min_q, max_q, q_step).len());
+    for frame in frames {
+        accumulator.add(&dse_core(min_q, max_q, q_step, frame?.positions()));
+    }
+    accumulator.finish()
+}
+
+/// Running mean and variance of equally long samples, element-wise, by
+/// Welford's algorithm.
+struct MeanVariance {
+    count: usize,
+    mean: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl MeanVariance {
+    fn new(len: usize) -> Self {
+        Self {
+            count: 0,
+            mean: vec![0.0; len],
+            sum_sq: vec![0.0; len],
+        }
+    }
+
+    fn add(&mut self, sample: &[f64]) {
+        self.count += 1;
+        let n = self.count as f64;
+        for ((mean, sum_sq), &x) in self.mean.iter_mut().zip(&mut self.sum_sq).zip(sample) {
+            let delta = x - *mean;
+            *mean += delta / n;
+            *sum_sq += delta * (x - *mean);
+        }
+    }
+
+    /// `(mean, population standard deviation)`.
+    fn finish(self) -> Result<(Vec<f64>, Vec<f64>)> {
+        if self.count == 0 {
+            return Err(Error::InvalidArgument(
+                "the trajectory has no frames".to_string(),
+            ));
+        }
+        let n = self.count as f64;
+        let std = self.sum_sq.iter().map(|s| (s / n).sqrt()).collect();
+        Ok((self.mean, std))
+    }
+}
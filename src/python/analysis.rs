@@ -0,0 +1,1037 @@
+use std::ffi::CString;
+
+use pyo3::exceptions::PyUserWarning;
+use pyo3::prelude::*;
+
+use super::crystal::parse_cubic_lattice;
+use crate::core;
+
+/// Normalise intensities by the square of the atom count.
+///
+/// For a crystal of N identical atoms the Debye intensity tends to N^2 as q -> 0, so the
+/// normalised curve starts at 1.
+///
+/// Args:
+///     intensities (list[float]): Intensity values, e.g. `dse_optimized(...).intensity`
+///     n_atoms (int): Number of atoms N in the crystal
+///
+/// Returns:
+///     list[float]: intensities / N^2
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> result = dse_optimized(0.1, 10.0, 0.1, positions)
+///     >>> normalised = normalise_intensity(result.intensity, len(positions))
+///     >>> normalise_intensity(dse_optimized(0.1, 1.0, 0.3, [[0.0, 0.0, 0.0]]).intensity, 1)
+///     [1.0, 1.0, 1.0, 1.0]
+#[pyfunction]
+pub(super) fn normalise_intensity(intensities: Vec<f64>, n_atoms: usize) -> Vec<f64> {
+    core::normalise_intensity(&intensities, n_atoms)
+}
+
+/// Subtract a background curve, e.g. solvent scattering, from a signal.
+///
+/// Args:
+///     signal (list[float]): Measured or computed intensities
+///     background (list[float]): Background intensities on the same q grid
+///
+/// Returns:
+///     list[float]: signal - background, element-wise
+///
+/// Raises:
+///     ValueError: If signal and background have different lengths
+///
+/// Examples:
+///     >>> subtract_background([3.0, 2.0], [1.0, 1.5])
+///     [2.0, 0.5]
+#[pyfunction]
+pub(super) fn subtract_background(signal: Vec<f64>, background: Vec<f64>) -> PyResult<Vec<f64>> {
+    Ok(core::subtract_background(&signal, &background)?)
+}
+
+/// Fit a polynomial background to the data outside the signal regions.
+///
+/// Fits a polynomial of the given degree in q by weighted least squares to the points outside
+/// exclude_q_ranges, and evaluates it at every q value; pass the result to
+/// `subtract_background`. Weights are 1 / sigma^2, or equal without sigma.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Measured intensities on the same q grid
+///     degree (int): Degree of the polynomial, e.g. 0 for a constant or 1 for a linear background
+///     exclude_q_ranges (list[tuple[float, float]]): Inclusive (q_low, q_high) ranges in 1/nm
+///         containing the signal, e.g. the Bragg peaks
+///     sigma (list[float], optional): Standard uncertainties of the intensities
+///
+/// Returns:
+///     list[float]: The fitted background at each q value
+///
+/// Raises:
+///     ValueError: If the lengths differ, a sigma is not positive or no more than degree
+///         distinct q values lie outside the excluded ranges
+///
+/// Examples:
+///     >>> background = fit_polynomial_background(q, I, 2, [(25.0, 32.0), (42.0, 53.0)])
+///     >>> signal = subtract_background(I, background)
+#[pyfunction]
+#[pyo3(signature = (q_values, intensities, degree, exclude_q_ranges, sigma=None))]
+pub(super) fn fit_polynomial_background(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    degree: usize,
+    exclude_q_ranges: Vec<(f64, f64)>,
+    sigma: Option<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    Ok(core::fit_polynomial_background(
+        &q_values,
+        &intensities,
+        degree,
+        &exclude_q_ranges,
+        sigma.as_deref(),
+    )?)
+}
+
+/// Fit a power-law background I_bg = A * q^n at high q, where the signal is weak.
+///
+/// Same as `porod_fit(q_values, intensities, q_low_cutoff, inf)`.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Measured intensities on the same q grid
+///     q_low_cutoff (float): Lowest q of the fit in 1/nm
+///
+/// Returns:
+///     tuple[float, float]: (A, n)
+///
+/// Raises:
+///     ValueError: If the lengths differ or fewer than 2 distinct q values with I > 0 are at
+///         q >= q_low_cutoff
+///
+/// Examples:
+///     >>> a, n = fit_power_law_background(q, I, 3.0)
+///     >>> signal = subtract_background(I, [a * x ** n for x in q])
+#[pyfunction]
+pub(super) fn fit_power_law_background(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    q_low_cutoff: f64,
+) -> PyResult<(f64, f64)> {
+    Ok(core::fit_power_law_background(
+        &q_values,
+        &intensities,
+        q_low_cutoff,
+    )?)
+}
+
+/// Fit the background terms of `dse_with_background` to the residuals of a model.
+///
+/// Least-squares fit of I_obs - I_model = incoherent_bg + thermal_bg_coeff * q^2, a straight
+/// line in q^2.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     i_obs (list[float]): Observed intensities
+///     i_model (list[float]): Model intensities without background on the same q grid
+///
+/// Returns:
+///     tuple[float, float]: (incoherent_bg, thermal_bg_coeff)
+///
+/// Raises:
+///     ValueError: If the lengths differ or fewer than 2 distinct q^2 values are given
+///
+/// Examples:
+///     >>> i_model = dse_optimized(0.1, 10.0, 0.1, positions).intensity
+///     >>> incoherent_bg, thermal_bg_coeff = fit_background_parameters(q, i_obs, i_model)
+#[pyfunction]
+pub(super) fn fit_background_parameters(
+    q_values: Vec<f64>,
+    i_obs: Vec<f64>,
+    i_model: Vec<f64>,
+) -> PyResult<(f64, f64)> {
+    Ok(core::fit_background_parameters(
+        &q_values, &i_obs, &i_model,
+    )?)
+}
+
+/// Scale intensities to absolute units.
+///
+/// Applies I_abs = I * concentration * contrast^2.
+///
+/// Args:
+///     intensities (list[float]): Intensities per particle, e.g. from `normalise_intensity`
+///     concentration (float): Particle number concentration
+///     contrast (float): Scattering length density contrast between particle and solvent
+///
+/// Returns:
+///     list[float]: Intensities in absolute units
+///
+/// Examples:
+///     >>> scale_to_absolute([1.0, 0.5], 2.0, 3.0)
+///     [18.0, 9.0]
+#[pyfunction]
+pub(super) fn scale_to_absolute(
+    intensities: Vec<f64>,
+    concentration: f64,
+    contrast: f64,
+) -> Vec<f64> {
+    core::scale_to_absolute(&intensities, concentration, contrast)
+}
+
+/// Compute the Lorentz-polarization factor of powder diffraction at each q value.
+///
+/// LP = (1 + (1 - p) * cos^2(2 theta)) / (sin^2(theta) * cos(theta)) with
+/// theta = arcsin(q * wavelength / (4 pi)). Raw diffraction intensities are divided by LP,
+/// see `apply_correction`, before comparing them with the DSE.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm, all positive
+///     wavelength_nm (float): Wavelength of the radiation in nanometers, e.g. 0.15406 for Cu K-alpha
+///     polarization_fraction (float): 0 for an unpolarised beam (laboratory sources), 1 for a
+///         beam fully polarised perpendicular to the scattering plane (synchrotrons), which
+///         removes the cos^2(2 theta) term
+///
+/// Returns:
+///     list[float]: LP at each q value
+///
+/// Raises:
+///     ValueError: If the wavelength is not positive, polarization_fraction is outside [0, 1],
+///         or a q value is not positive or would need 2 theta >= 180 degrees
+///         (q >= 4 pi / wavelength)
+///
+/// Examples:
+///     >>> lp = lorentz_polarization(q_measured, 0.15406, 0.0)
+///     >>> corrected = apply_correction(i_measured, lp)
+#[pyfunction]
+pub(super) fn lorentz_polarization(
+    q_values: Vec<f64>,
+    wavelength_nm: f64,
+    polarization_fraction: f64,
+) -> PyResult<Vec<f64>> {
+    Ok(core::lorentz_polarization(
+        &q_values,
+        wavelength_nm,
+        polarization_fraction,
+    )?)
+}
+
+/// Convert q values to scattering angles 2 theta in degrees.
+///
+/// 2 theta = 2 * arcsin(q * wavelength / (4 pi)), for comparing DSE curves with powder
+/// diffractometer data recorded against 2 theta.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     wavelength_nm (float): Wavelength of the radiation in nanometers, e.g. 0.15406 for Cu K-alpha
+///
+/// Returns:
+///     list[float]: 2 theta in degrees at each q value
+///
+/// Raises:
+///     ValueError: If the wavelength is not positive or a q value is negative or above
+///         4 pi / wavelength, which is not physically reachable
+///
+/// Examples:
+///     >>> twotheta = q_to_twotheta(q, 0.15406)
+#[pyfunction]
+pub(super) fn q_to_twotheta(q_values: Vec<f64>, wavelength_nm: f64) -> PyResult<Vec<f64>> {
+    Ok(core::q_to_twotheta(&q_values, wavelength_nm)?)
+}
+
+/// Convert scattering angles 2 theta in degrees to q values.
+///
+/// q = 4 pi * sin(theta) / wavelength, the inverse of `q_to_twotheta`.
+///
+/// Args:
+///     twotheta_deg (list[float]): Scattering angles 2 theta in degrees
+///     wavelength_nm (float): Wavelength of the radiation in nanometers
+///
+/// Returns:
+///     list[float]: q in 1/nm at each angle
+///
+/// Raises:
+///     ValueError: If the wavelength is not positive
+///
+/// Examples:
+///     >>> q_measured = twotheta_to_q(twotheta_measured, 0.15406)
+#[pyfunction]
+pub(super) fn twotheta_to_q(twotheta_deg: Vec<f64>, wavelength_nm: f64) -> PyResult<Vec<f64>> {
+    Ok(core::twotheta_to_q(&twotheta_deg, wavelength_nm)?)
+}
+
+/// Linearly interpolate an I(q) curve onto another q grid.
+///
+/// Puts a DSE curve and experimental data recorded on different q grids on the same points,
+/// e.g. before `r_factor` or `chi_squared`.
+///
+/// Args:
+///     q_in (list[float]): q values of the curve, strictly increasing
+///     i_in (list[float]): Intensity at each q_in
+///     q_out (list[float]): q values to interpolate at, within [q_in[0], q_in[-1]]
+///
+/// Returns:
+///     list[float]: Interpolated intensity at each q_out
+///
+/// Raises:
+///     ValueError: If q_in and i_in have different lengths, q_in has fewer than two values or
+///         is not strictly increasing, or a q_out value lies outside the range of q_in
+///
+/// Examples:
+///     >>> q_measured = twotheta_to_q(twotheta_measured, 0.15406)
+///     >>> result = dse_optimized(1.0, 60.0, 0.01, positions)
+///     >>> i_model = resample_iq(result.q, result.intensity, q_measured)
+#[pyfunction]
+pub(super) fn resample_iq(q_in: Vec<f64>, i_in: Vec<f64>, q_out: Vec<f64>) -> PyResult<Vec<f64>> {
+    Ok(core::resample_iq(&q_in, &i_in, &q_out)?)
+}
+
+/// Correct intensities for the absorption of a thick sample in transmission geometry.
+///
+/// I_corrected = I / exp(-mu * t / cos(theta)) with theta = arcsin(q * wavelength / (4 pi)),
+/// so the correction grows with q as the path through the sample lengthens. This matters for
+/// thick samples and high-Z materials.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Measured intensity at each q value
+///     mu (float): Linear attenuation coefficient in 1/nm, e.g. from
+///         `linear_attenuation_coefficient`
+///     thickness (float): Sample thickness t in nanometers
+///     wavelength_nm (float): Wavelength of the radiation in nanometers
+///
+/// Returns:
+///     list[float]: Absorption-corrected intensity at each q value
+///
+/// Raises:
+///     ValueError: If the lists have different lengths, mu or thickness is negative, the
+///         wavelength is not positive, or a q value is negative or at least 4 pi / wavelength
+///
+/// Examples:
+///     >>> mu = linear_attenuation_coefficient('Au', 8.04)
+///     >>> corrected = absorption_correction(q_measured, i_measured, mu, 500.0, 0.15406)
+#[pyfunction]
+pub(super) fn absorption_correction(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    mu: f64,
+    thickness: f64,
+    wavelength_nm: f64,
+) -> PyResult<Vec<f64>> {
+    Ok(core::absorption_correction(
+        &q_values,
+        &intensities,
+        mu,
+        thickness,
+        wavelength_nm,
+    )?)
+}
+
+/// Divide intensities by correction factors, element-wise.
+///
+/// Args:
+///     intensities (list[float]): Measured intensities
+///     correction_factors (list[float]): Factor to divide each intensity by, e.g. from
+///         `lorentz_polarization`
+///
+/// Returns:
+///     list[float]: intensities / correction_factors
+///
+/// Raises:
+///     ValueError: If the two lists have different lengths
+///
+/// Examples:
+///     >>> apply_correction([4.0, 9.0], [2.0, 3.0])
+///     [2.0, 3.0]
+#[pyfunction]
+pub(super) fn apply_correction(
+    intensities: Vec<f64>,
+    correction_factors: Vec<f64>,
+) -> PyResult<Vec<f64>> {
+    Ok(core::apply_correction(&intensities, &correction_factors)?)
+}
+
+/// Smear a curve with a Gaussian instrument resolution function.
+///
+/// Computes I_smeared(q) = integral I(q') R(q, q') dq' with the normalised Gaussian
+/// R(q, q') = exp(-(q - q')^2 / (2 sigma_q^2)) / (sigma_q sqrt(2 pi)), as an FFT convolution
+/// over the q grid. Near the ends of the grid each point is divided by the part of the kernel
+/// that lies on the grid, so a constant curve stays constant.
+///
+/// Args:
+///     q_values (list[float]): Uniformly spaced, increasing q values in 1/nm, e.g. `result.q`
+///     intensities (list[float]): Intensity at each q value
+///     sigma_q (float): Standard deviation of the resolution function in 1/nm
+///
+/// Returns:
+///     list[float]: The broadened intensities on the same q grid
+///
+/// Raises:
+///     ValueError: If the lengths differ, sigma_q is not positive or the q grid is not uniform
+///
+/// Examples:
+///     >>> result = dse_optimized(5.0, 60.0, 0.05, crystal('fcc', 0.408, 2.0))
+///     >>> measured = apply_instrument_broadening(result.q, result.intensity, 0.3)
+#[pyfunction]
+pub(super) fn apply_instrument_broadening(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    sigma_q: f64,
+) -> PyResult<Vec<f64>> {
+    Ok(core::apply_instrument_broadening(
+        &q_values,
+        &intensities,
+        sigma_q,
+    )?)
+}
+
+/// Smooth noisy intensities with a Savitzky-Golay filter.
+///
+/// Each point is replaced by the value of the least-squares polynomial of degree poly_order
+/// fitted to the window_size points centred on it. Unlike a moving average this keeps peak
+/// heights and widths, so it suits data going into `find_peaks` or `pdf_from_iq`. The data is
+/// mirrored about its first and last points, so the length is preserved.
+///
+/// Args:
+///     intensities (list[float]): Intensities on a uniform q grid
+///     window_size (int): Odd number of points in each fit
+///     poly_order (int): Degree of the fitted polynomial, below window_size
+///
+/// Returns:
+///     list[float]: The smoothed intensities
+///
+/// Raises:
+///     ValueError: If window_size is even, not larger than poly_order or longer than the data
+///
+/// Examples:
+///     >>> smoothed = savitzky_golay_smooth(i_obs, 11, 3)
+#[pyfunction]
+pub(super) fn savitzky_golay_smooth(
+    intensities: Vec<f64>,
+    window_size: usize,
+    poly_order: usize,
+) -> PyResult<Vec<f64>> {
+    Ok(core::savitzky_golay_smooth(
+        &intensities,
+        window_size,
+        poly_order,
+    )?)
+}
+
+/// Smooth intensities with a centred moving average.
+///
+/// Same as `savitzky_golay_smooth(intensities, window, 0)`. Edges are mirrored, so the length
+/// is preserved.
+///
+/// Args:
+///     intensities (list[float]): Intensities on a uniform q grid
+///     window (int): Odd number of points averaged
+///
+/// Returns:
+///     list[float]: The smoothed intensities
+///
+/// Raises:
+///     ValueError: If window is even or longer than the data
+///
+/// Examples:
+///     >>> moving_average_smooth([3.0, 0.0, 0.0, 6.0], 3)
+///     [1.0, 1.0, 2.0, 2.0]
+#[pyfunction]
+pub(super) fn moving_average_smooth(intensities: Vec<f64>, window: usize) -> PyResult<Vec<f64>> {
+    Ok(core::moving_average_smooth(&intensities, window)?)
+}
+
+/// Extract the radius of gyration with a Guinier fit.
+///
+/// Fits the Guinier approximation I(q) = I_0 * exp(-R_g^2 * q^2 / 3) as a straight line in
+/// (q^2, ln I). Points with I <= 0 are ignored. Starting from the three lowest-q points, the
+/// fit range is refined iteratively to the points with q * R_g < q_max_rg.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid
+///     q_max_rg (float): Upper limit of q * R_g for the fit range. The rule of thumb is 1.3
+///         for compact particles; elongated particles need smaller values
+///
+/// Returns:
+///     tuple[float, float]: (R_g, I_0), with R_g in nanometers
+///
+/// Raises:
+///     ValueError: If the lengths differ, fewer than 3 points are valid, or ln I does not
+///         decrease with q^2
+///
+/// Examples:
+///     >>> q, I = dse_optimized_with_q(0.05, 2.0, 0.05, crystal('sphere', 0.4, 10.0))
+///     >>> rg, i0 = guinier_fit(q, I, 1.3)
+#[pyfunction]
+pub(super) fn guinier_fit(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    q_max_rg: f64,
+) -> PyResult<(f64, f64)> {
+    Ok(core::guinier_fit(&q_values, &intensities, q_max_rg)?)
+}
+
+/// Points used by `guinier_fit`, for plotting the Guinier fit.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid
+///     q_max_rg (float): Upper limit of q * R_g for the fit range, as in `guinier_fit`
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q^2, ln I) of the selected points
+///
+/// Raises:
+///     ValueError: As for `guinier_fit`
+///
+/// Examples:
+///     >>> q_sq, ln_i = guinier_plot_data(q, I, 1.3)
+///     >>> plt.plot(q_sq, ln_i, 'o')
+#[pyfunction]
+pub(super) fn guinier_plot_data(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    q_max_rg: f64,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    Ok(core::guinier_plot_data(&q_values, &intensities, q_max_rg)?)
+}
+
+/// Emit a UserWarning if the lowest q is outside the Guinier region of the curve.
+fn warn_outside_guinier_region(py: Python<'_>, q_min_rg: f64) -> PyResult<()> {
+    if q_min_rg > core::GUINIER_EXTRAPOLATION_MAX_Q_RG {
+        let message = CString::new(format!(
+            "q_min * R_g = {:.3} exceeds {}; the Guinier extrapolation of I(0) is unreliable",
+            q_min_rg,
+            core::GUINIER_EXTRAPOLATION_MAX_Q_RG
+        ))?;
+        PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1)?;
+    }
+    Ok(())
+}
+
+/// Extrapolate the forward scattering I(0) with a Guinier fit over the lowest-q decade.
+///
+/// Fits ln I(q) = ln I_0 - R_g^2 * q^2 / 3 to the points with q > 0 and I > 0 between the
+/// lowest such q, q_min, and 10 * q_min.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid
+///
+/// Returns:
+///     float: The extrapolated zero-q intensity I_0
+///
+/// Raises:
+///     ValueError: If the lengths differ, fewer than 3 distinct q values are valid, or ln I
+///         does not decrease with q^2
+///
+/// Warns:
+///     UserWarning: If q_min * R_g > 0.3, where the Guinier approximation breaks down
+///
+/// Examples:
+///     >>> result = dse_optimized(0.02, 2.0, 0.02, crystal('sphere', 0.4, 5.0))
+///     >>> i0 = extrapolate_i0(result.q, result.intensity)
+#[pyfunction]
+pub(super) fn extrapolate_i0(
+    py: Python<'_>,
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+) -> PyResult<f64> {
+    let (i0, q_min_rg) = core::extrapolate_i0(&q_values, &intensities)?;
+    warn_outside_guinier_region(py, q_min_rg)?;
+    Ok(i0)
+}
+
+/// Rescale a scattering curve so that its forward scattering I(0) equals n_atoms^2.
+///
+/// I(0) is extrapolated as in `extrapolate_i0`. For a monodisperse suspension I(0) is
+/// N^2 * f(0)^2 * c, so the rescaled curve is the intensity per particle in units of f(0)^2
+/// and can be put on an absolute scale with `scale_to_absolute`.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid, in arbitrary units
+///     n_atoms (int): Number of atoms N of the particle
+///
+/// Returns:
+///     list[float]: The intensities scaled by n_atoms^2 / I(0)
+///
+/// Raises:
+///     ValueError: As for `extrapolate_i0`
+///
+/// Warns:
+///     UserWarning: If q_min * R_g > 0.3, where the Guinier approximation breaks down
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> result = dse_optimized(0.02, 2.0, 0.02, positions)
+///     >>> calibrated = normalise_to_forward_scattering(result.q, measured, len(positions))
+#[pyfunction]
+pub(super) fn normalise_to_forward_scattering(
+    py: Python<'_>,
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    n_atoms: usize,
+) -> PyResult<Vec<f64>> {
+    let (_, q_min_rg) = core::extrapolate_i0(&q_values, &intensities)?;
+    warn_outside_guinier_region(py, q_min_rg)?;
+    Ok(core::normalise_to_forward_scattering(
+        &q_values,
+        &intensities,
+        n_atoms,
+    )?)
+}
+
+/// Transform a scattering curve into its Kratky representation (q, q^2 * I(q)).
+///
+/// Kratky plots show the folding state of proteins and polymers (a bell shape for compact
+/// particles, a plateau for unfolded chains) and are also used for nanoparticle shape analysis.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q, q^2 * I)
+///
+/// Raises:
+///     ValueError: If the lengths differ
+///
+/// Examples:
+///     >>> q, I = dse_optimized_with_q(0.05, 3.0, 0.01, crystal('sphere', 0.4, 8.0))
+///     >>> q, kratky = kratky_transform(q, I)
+#[pyfunction]
+pub(super) fn kratky_transform(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    Ok(core::kratky_transform(&q_values, &intensities)?)
+}
+
+/// Locate the maximum of a Kratky curve.
+///
+/// The largest q^2 * I sample and its two neighbours are interpolated with a parabola, so the
+/// peak position is resolved between grid points; a maximum at either end of the range is
+/// returned as is. The Guinier approximation gives q_peak * R_g = sqrt(3) for compact
+/// particles; for a homogeneous sphere the exact value is 1.612.
+///
+/// Args:
+///     q (list[float]): q values in 1/nm
+///     kratky_intensity (list[float]): q^2 * I on the same grid, e.g. from `kratky_transform`
+///
+/// Returns:
+///     tuple[float, float]: (q_peak, peak_value)
+///
+/// Raises:
+///     ValueError: If the lengths differ or the curve is empty
+///
+/// Examples:
+///     >>> q_peak, _ = kratky_peak(*kratky_transform(q, I))
+///     >>> rg_estimate = 3 ** 0.5 / q_peak  # the Guinier estimate; 1.612 / q_peak for a sphere
+#[pyfunction]
+pub(super) fn kratky_peak(q: Vec<f64>, kratky_intensity: Vec<f64>) -> PyResult<(f64, f64)> {
+    Ok(core::kratky_peak(&q, &kratky_intensity)?)
+}
+
+/// Fit the high-q Porod region with a power law I(q) = K_p * q^n.
+///
+/// Fits a straight line to (ln q, ln I) over the points with q_min_porod <= q <= q_max_porod,
+/// ignoring points with I <= 0. For particles with sharp interfaces n is close to -4 and K_p is
+/// proportional to the surface area; a shallower decay indicates surface roughness.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Intensities on the same q grid
+///     q_min_porod (float): Lower end of the fit range in 1/nm
+///     q_max_porod (float): Upper end of the fit range in 1/nm
+///
+/// Returns:
+///     tuple[float, float]: (K_p, porod_exponent)
+///
+/// Raises:
+///     ValueError: If the lengths differ or fewer than 2 distinct q values are in range
+///
+/// Examples:
+///     >>> q, I = dse_optimized_with_q(0.1, 10.0, 0.05, crystal('sphere', 0.4, 10.0))
+///     >>> k_p, exponent = porod_fit(q, I, 2.0, 6.0)
+#[pyfunction]
+pub(super) fn porod_fit(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    q_min_porod: f64,
+    q_max_porod: f64,
+) -> PyResult<(f64, f64)> {
+    Ok(core::porod_fit(
+        &q_values,
+        &intensities,
+        q_min_porod,
+        q_max_porod,
+    )?)
+}
+
+/// Porod invariant Q* = integral of q^2 I(q) dq from 0 to infinity.
+///
+/// The measured range is integrated with the trapezoid rule. Without extrapolations the
+/// integral stops at the ends of the data, so the curve should start near q = 0 and extend
+/// into the Porod region. `low_q_extrapolation` adds the integral from 0 to the lowest q of
+/// the Guinier law I_0 * exp(-R_g^2 * q^2 / 3) fitted by `guinier_fit` with q_max_rg = 1.3;
+/// `high_q_extrapolation` adds K_p / q_max, the integral of the Porod law K_p / q^4 beyond the
+/// largest q, with K_p the mean of q^4 I(q) over q >= 0.8 * q_max. Q* is proportional to
+/// phi * (1 - phi) times the contrast squared, phi the volume fraction.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm, in increasing order
+///     intensities (list[float]): Intensities on the same q grid
+///     low_q_extrapolation (bool): Add the Guinier extrapolation below the lowest q
+///     high_q_extrapolation (bool): Add the Porod extrapolation above the largest q
+///
+/// Returns:
+///     float: Q* in the units of I times nm^-3
+///
+/// Raises:
+///     ValueError: If the lengths differ or an extrapolation cannot be fitted
+///
+/// Warns:
+///     UserWarning: If either extrapolation contributes more than 20% of Q*
+#[pyfunction]
+#[pyo3(signature = (q_values, intensities, low_q_extrapolation=false, high_q_extrapolation=false))]
+pub(super) fn porod_invariant(
+    py: Python<'_>,
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    low_q_extrapolation: bool,
+    high_q_extrapolation: bool,
+) -> PyResult<f64> {
+    let (measured, low_q, high_q) = core::porod_invariant_contributions(
+        &q_values,
+        &intensities,
+        low_q_extrapolation,
+        high_q_extrapolation,
+    )?;
+    let total = measured + low_q + high_q;
+    for (name, contribution) in [("Guinier", low_q), ("Porod", high_q)] {
+        let fraction = contribution / total;
+        if fraction > core::POROD_EXTRAPOLATION_WARNING_FRACTION {
+            let message = CString::new(format!(
+                "the {} extrapolation contributes {:.1}% of the Porod invariant, more than {}%; \
+                 extend the measured q range",
+                name,
+                100.0 * fraction,
+                100.0 * core::POROD_EXTRAPOLATION_WARNING_FRACTION
+            ))?;
+            PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1)?;
+        }
+    }
+    Ok(total)
+}
+
+/// Weighted R-factor between observed and calculated intensities.
+///
+/// R_w = sqrt(sum(w * (I_obs - scale * I_calc)^2) / sum(w * I_obs^2)), with w = 1 / sigma^2
+/// (or 1 without sigma) and scale = sum(w * I_obs * I_calc) / sum(w * I_calc^2) the
+/// least-squares optimal scale factor, so I_calc may be in arbitrary units.
+///
+/// Args:
+///     q_values (list[float]): q values of the data
+///     i_obs (list[float]): Observed intensities
+///     i_calc (list[float]): Calculated intensities on the same q grid, e.g.
+///         dse_optimized(...).intensity
+///     sigma (list[float], optional): Standard uncertainties of i_obs
+///
+/// Returns:
+///     float: R_w, 0 for a perfect fit
+///
+/// Raises:
+///     ValueError: If the lengths differ, a sigma is not positive or i_calc is zero everywhere
+///
+/// Examples:
+///     >>> r_factor([1.0, 2.0], [2.0, 4.0], [1.0, 2.0])
+///     0.0
+#[pyfunction]
+#[pyo3(signature = (q_values, i_obs, i_calc, sigma=None))]
+pub(super) fn r_factor(
+    q_values: Vec<f64>,
+    i_obs: Vec<f64>,
+    i_calc: Vec<f64>,
+    sigma: Option<Vec<f64>>,
+) -> PyResult<f64> {
+    Ok(core::r_factor(
+        &q_values,
+        &i_obs,
+        &i_calc,
+        sigma.as_deref(),
+    )?)
+}
+
+/// Chi-squared between observed and calculated intensities.
+///
+/// chi^2 = sum(w * (I_obs - scale * I_calc)^2), with the weights and optimal scale factor of
+/// `r_factor`. With sigma given this is the maximum-likelihood objective for Gaussian errors;
+/// divide by the number of points minus fitted parameters for the reduced chi-squared.
+///
+/// Args:
+///     q_values (list[float]): q values of the data
+///     i_obs (list[float]): Observed intensities
+///     i_calc (list[float]): Calculated intensities on the same q grid
+///     sigma (list[float], optional): Standard uncertainties of i_obs
+///
+/// Returns:
+///     float: chi^2
+///
+/// Raises:
+///     ValueError: As for `r_factor`
+#[pyfunction]
+#[pyo3(signature = (q_values, i_obs, i_calc, sigma=None))]
+pub(super) fn chi_squared(
+    q_values: Vec<f64>,
+    i_obs: Vec<f64>,
+    i_calc: Vec<f64>,
+    sigma: Option<Vec<f64>>,
+) -> PyResult<f64> {
+    Ok(core::chi_squared(
+        &q_values,
+        &i_obs,
+        &i_calc,
+        sigma.as_deref(),
+    )?)
+}
+
+/// A peak of a scattering curve, as returned by `find_peaks`.
+#[pyclass(name = "PeakInfo", frozen)]
+pub(super) struct PyPeakInfo {
+    inner: core::PeakInfo,
+}
+
+#[pymethods]
+impl PyPeakInfo {
+    /// Position of the maximum in 1/nm, refined by parabolic interpolation.
+    #[getter]
+    fn position_q(&self) -> f64 {
+        self.inner.position_q
+    }
+
+    /// Height of the peak above the higher of its two surrounding minima.
+    #[getter]
+    fn prominence(&self) -> f64 {
+        self.inner.prominence
+    }
+
+    /// Full width in 1/nm at half prominence.
+    #[getter]
+    fn fwhm(&self) -> f64 {
+        self.inner.fwhm
+    }
+
+    /// Miller indices (h, k, l) of the closest allowed reflection, or None if not assigned.
+    #[getter]
+    fn miller_assignment(&self) -> Option<(i32, i32, i32)> {
+        self.inner.miller_assignment.map(|[h, k, l]| (h, k, l))
+    }
+
+    fn __repr__(&self) -> String {
+        let miller = match self.inner.miller_assignment {
+            Some([h, k, l]) => format!("({}, {}, {})", h, k, l),
+            None => "None".to_string(),
+        };
+        format!(
+            "PeakInfo(position_q={}, prominence={}, fwhm={}, miller_assignment={})",
+            self.inner.position_q, self.inner.prominence, self.inner.fwhm, miller
+        )
+    }
+}
+
+/// Find the Bragg peaks of a scattering curve.
+///
+/// A peak is a local maximum whose prominence, its height above the higher of the minima
+/// separating it from higher parts of the curve, is at least min_prominence, and whose full
+/// width at half prominence is at least min_width_q. A flat top counts as one peak at its
+/// centre. With lattice_param and crystal_system given, each peak is indexed with
+/// `assign_miller`.
+///
+/// Args:
+///     q_values (list[float]): Strictly increasing q values in 1/nm
+///     intensities (list[float]): Intensity at each q value
+///     min_prominence (float): Smallest prominence of a reported peak, in units of intensity
+///     min_width_q (float): Smallest FWHM of a reported peak in 1/nm
+///     lattice_param (float, optional): Cubic lattice parameter in nanometers for indexing
+///     crystal_system (str, optional): 'sc' (or 'cube'), 'fcc' or 'bcc' for indexing
+///
+/// Returns:
+///     list[PeakInfo]: The peaks in order of q
+///
+/// Raises:
+///     ValueError: If the lengths differ, q_values is not strictly increasing, only one of
+///         lattice_param and crystal_system is given, or either is invalid
+///
+/// Examples:
+///     >>> result = dse_optimized(5.0, 60.0, 0.05, crystal('fcc', 0.408, 2.0))
+///     >>> peaks = find_peaks(result.q, result.intensity, 300.0, 1.0, 0.408, 'fcc')
+///     >>> [p.miller_assignment for p in peaks]
+///     [(1, 1, 1), (2, 2, 0), (3, 1, 1)]
+#[pyfunction]
+#[pyo3(signature = (q_values, intensities, min_prominence, min_width_q, lattice_param=None, crystal_system=None))]
+pub(super) fn find_peaks(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    min_prominence: f64,
+    min_width_q: f64,
+    lattice_param: Option<f64>,
+    crystal_system: Option<&str>,
+) -> PyResult<Vec<PyPeakInfo>> {
+    let indexing = match (lattice_param, crystal_system) {
+        (Some(a), Some(system)) => Some((a, parse_cubic_lattice(system)?)),
+        (None, None) => None,
+        _ => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "lattice_param and crystal_system must be given together",
+            ));
+        }
+    };
+    let mut peaks = core::find_peaks(&q_values, &intensities, min_prominence, min_width_q)?;
+    if let Some((a, lattice)) = indexing {
+        for peak in &mut peaks {
+            peak.miller_assignment = Some(core::assign_miller(peak.position_q, a, lattice)?);
+        }
+    }
+    Ok(peaks
+        .into_iter()
+        .map(|inner| PyPeakInfo { inner })
+        .collect())
+}
+
+/// Index a peak with the closest reflection allowed on a cubic lattice.
+///
+/// Searches h >= k >= l >= 0 for the reflection minimising |q_hkl - q_peak|, with
+/// q_hkl = 2 pi sqrt(h^2 + k^2 + l^2) / lattice_param. FCC allows h, k, l all even or all
+/// odd and BCC h + k + l even; ties go to the lower h^2 + k^2 + l^2.
+///
+/// Args:
+///     q_peak (float): Peak position in 1/nm
+///     lattice_param (float): Cubic lattice parameter in nanometers
+///     crystal_system (str): 'sc' (or 'cube'), 'fcc' or 'bcc'
+///
+/// Returns:
+///     tuple[int, int, int]: Miller indices (h, k, l)
+///
+/// Raises:
+///     ValueError: If q_peak or lattice_param is not positive, or crystal_system is unknown
+///
+/// Examples:
+///     >>> assign_miller(2 * math.pi * math.sqrt(3) / 0.408, 0.408, 'fcc')
+///     (1, 1, 1)
+///     >>> assign_miller(2 * math.pi * math.sqrt(2) / 0.3, 0.3, 'bcc')
+///     (1, 1, 0)
+#[pyfunction]
+pub(super) fn assign_miller(
+    q_peak: f64,
+    lattice_param: f64,
+    crystal_system: &str,
+) -> PyResult<(i32, i32, i32)> {
+    let lattice = parse_cubic_lattice(crystal_system)?;
+    let [h, k, l] = core::assign_miller(q_peak, lattice_param, lattice)?;
+    Ok((h, k, l))
+}
+
+/// Measure the lattice parameter from the position of a Bragg peak.
+///
+/// The most prominent peak within 10% of q_hkl = 2 pi sqrt(h^2 + k^2 + l^2) /
+/// lattice_param_initial is located with `find_peaks`, its centre is refined by fitting a
+/// Gaussian to the points above half prominence (a parabola in ln I), and the result is
+/// inverted to lattice_param = 2 pi sqrt(h^2 + k^2 + l^2) / q_peak. The uncertainty is
+/// propagated from the covariance of the fit.
+///
+/// Args:
+///     q_values (list[float]): Strictly increasing q values in 1/nm
+///     intensities (list[float]): Intensity at each q value
+///     hkl (tuple[int, int, int]): Miller indices of the peak
+///     crystal_system (str): Indexing formula; only 'cubic' is supported
+///     lattice_param_initial (float): Estimate of the lattice parameter in nanometers, used to
+///         locate the peak
+///
+/// Returns:
+///     tuple[float, float]: (lattice_param, uncertainty) in nanometers
+///
+/// Raises:
+///     ValueError: If crystal_system is not 'cubic', hkl is (0, 0, 0), no peak lies in the
+///         search window, or fewer than 4 positive points lie above half prominence
+///
+/// Note:
+///     Finite crystals shift and broaden their Bragg peaks, so the result for a few-nanometre
+///     particle can differ from the lattice parameter it was built with by more than the fit
+///     uncertainty.
+///
+/// Examples:
+///     >>> q, I = dse_optimized_with_q(20.0, 35.0, 0.02, crystal('fcc', 0.408, 3.0))
+///     >>> a, sigma_a = lattice_parameter_from_peak(q, I, (1, 1, 1), 'cubic', 0.4)
+#[pyfunction]
+pub(super) fn lattice_parameter_from_peak(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    hkl: (i32, i32, i32),
+    crystal_system: &str,
+    lattice_param_initial: f64,
+) -> PyResult<(f64, f64)> {
+    if crystal_system != "cubic" {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown crystal system: '{}'. Supported crystal systems: 'cubic'",
+            crystal_system
+        )));
+    }
+    let (h, k, l) = hkl;
+    Ok(core::lattice_parameter_from_peak(
+        &q_values,
+        &intensities,
+        [h, k, l],
+        lattice_param_initial,
+    )?)
+}
+
+/// Estimate the coherence length of a crystal from the width of a peak (Scherrer equation).
+///
+/// The most prominent peak within 10% of peak_q is located with `find_peaks`. The points up to
+/// 3 FWHMs from it, stopping at the minima on either side, are fitted with a Voigt profile
+/// (centre, amplitude, Gaussian and Lorentzian widths) by Levenberg-Marquardt iteration, after
+/// subtracting the straight line through the ends of the window. The FWHM of the fit in q is
+/// converted to the width beta in 2theta radians at wavelength_nm and the Scherrer equation
+/// L = K * lambda / (beta * cos(theta)) gives the coherence length. The uncertainty is
+/// propagated from the covariance of the fitted widths.
+///
+/// Args:
+///     q_values (list[float]): Strictly increasing q values in 1/nm
+///     intensities (list[float]): Intensity at each q value
+///     peak_q (float): Approximate position of the peak in 1/nm
+///     wavelength_nm (float): Wavelength of the radiation in nanometers
+///     scherrer_k (float): Scherrer shape factor K, about 0.9 for spherical crystallites
+///
+/// Returns:
+///     tuple[float, float]: (coherence_length, uncertainty) in nanometers
+///
+/// Raises:
+///     ValueError: If peak_q, wavelength_nm or scherrer_k is not positive, no peak lies within
+///         10% of peak_q, fewer than 5 points lie across the peak, or the peak is not reachable
+///         at wavelength_nm
+///
+/// Note:
+///     The Voigt profile is the Thompson-Cox-Hastings pseudo-Voigt, accurate to about 1%. To
+///     first order L = 2 pi K / FWHM_q whatever the wavelength, which only enters through the
+///     curvature of the q to 2theta conversion.
+///
+/// Examples:
+///     >>> q, I = dse_optimized_with_q(20.0, 35.0, 0.02, crystal('fcc', 0.408, 4.0))
+///     >>> L, sigma_L = scherrer_analysis(q, I, 26.7, 0.15406, 0.9)
+#[pyfunction]
+#[pyo3(signature = (q_values, intensities, peak_q, wavelength_nm, scherrer_k=0.9))]
+pub(super) fn scherrer_analysis(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    peak_q: f64,
+    wavelength_nm: f64,
+    scherrer_k: f64,
+) -> PyResult<(f64, f64)> {
+    Ok(core::scherrer_analysis(
+        &q_values,
+        &intensities,
+        peak_q,
+        wavelength_nm,
+        scherrer_k,
+    )?)
+}
@@ -0,0 +1,414 @@
+use pyo3::prelude::*;
+
+use super::atoms_from_py;
+use super::dse::check_q_range;
+use super::threads::install;
+use crate::core;
+
+/// Compute the pair-distance spectrum of a crystal.
+///
+/// Distances equal up to 1e-6 nm are grouped. Counts are over ordered pairs (i, j), so
+/// sum(counts * sinc(q * distances)) reproduces `dse_optimized`, and the spectrum can be
+/// compared directly with the bins used by `dse_histogram`.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     include_self (bool): Whether to include the N zero-distance self pairs
+///
+/// Returns:
+///     tuple[list[float], list[int]]: (distances, counts), with the unique distances in
+///         nanometers sorted ascending and the number of pairs at each distance
+///
+/// Examples:
+///     >>> distances, counts = compute_pair_distances(crystal('cube', 1.0, 2.0), False)
+///     >>> distances[:2], counts[:2]
+///     ([1.0, 1.414214], [24, 24])
+#[pyfunction]
+pub(super) fn compute_pair_distances(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    include_self: bool,
+) -> PyResult<(Vec<f64>, Vec<u64>)> {
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::compute_pair_distances(atoms.positions(), include_self))
+    }))
+}
+
+/// Compute the radial distribution function g(r) of a crystal.
+///
+/// Pairwise distances are binned into a histogram of width dr, and bin k is normalised by
+/// 4*pi*r_k^2*dr*rho*N, where rho = N / V is the number density with V the volume of the
+/// bounding box of the crystal (see `number_density`).
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     r_min (float): Lower edge of the first bin in nanometers
+///     r_max (float): Upper edge of the last bin in nanometers
+///     dr (float): Bin width in nanometers
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (r_values, g_r) with r_values the bin centres
+///
+/// Raises:
+///     ValueError: If the bins are invalid, r_max is infinite, there are more than 2**24 bins or
+///         the crystal is flat (zero bounding box volume)
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> r, g = radial_distribution_function(positions, 0.0, 2.0, 0.01)
+#[pyfunction]
+pub(super) fn radial_distribution_function(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::radial_distribution_function(atoms.positions(), r_min, r_max, dr))
+    })?)
+}
+
+/// Compute the partial radial distribution function g_AB(r) of a multi-species crystal.
+///
+/// The distances of the ordered pairs (i, j), i != j, with atom i of species_a and atom j of
+/// species_b are binned as in `radial_distribution_function`, and bin k is normalised by
+/// 4*pi*r_k^2*dr*rho*c_B*N_A, with N_A the number of atoms of species A and c_B the fraction of
+/// atoms of species B. The total g(r) is sum_AB c_A * c_B * g_AB(r).
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///     species (list[int]): Species label of each atom
+///     r_min (float): Lower edge of the first bin in nanometers
+///     r_max (float): Upper edge of the last bin in nanometers
+///     dr (float): Bin width in nanometers
+///     species_a (int): Species of the central atoms
+///     species_b (int): Species of the neighbouring atoms
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (r_values, g_ab) with r_values the bin centres
+///
+/// Raises:
+///     ValueError: If species does not have one label per atom, no atom has species_a or
+///         species_b, the bins are invalid or the crystal is flat
+///
+/// Examples:
+///     >>> positions, species = crystal_random_alloy('fcc', 0.408, 4.0, 0.5, seed=1)
+///     >>> r, g_ab = partial_pair_correlation(positions, species, 0.0, 2.0, 0.01, 0, 1)
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn partial_pair_correlation(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    species: Vec<u32>,
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    species_a: u32,
+    species_b: u32,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::partial_pair_correlation(
+                atoms.positions(),
+                &species,
+                r_min,
+                r_max,
+                dr,
+                species_a,
+                species_b,
+            )
+        })
+    })?)
+}
+
+/// Reconstruct the Debye intensity from a radial distribution function.
+///
+/// Evaluates I(q) = N * (1 + integral g(r) * sin(qr)/(qr) * 4*pi*r^2*rho dr) with the trapezoid
+/// rule. Applied to the output of `radial_distribution_function` this is a sanity check
+/// against the direct `dse_optimized`; the difference comes from the histogram bin width and
+/// the r range.
+///
+/// Args:
+///     r_values (list[float]): r grid in nanometers
+///     g_r (list[float]): g(r) on the r grid
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     rho (float): Number density used to normalise g(r), in atoms per nm^3
+///     n_atoms (int): Number of atoms N used to normalise g(r)
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q_values, intensities)
+///
+/// Raises:
+///     ValueError: If r_values and g_r have different lengths or the q range is invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> r, g = radial_distribution_function(positions, 0.0, 7.0, 0.005)
+///     >>> q, I = fourier_transform_to_iq(r, g, 1.0, 30.0, 0.1, number_density(positions), len(positions))
+#[pyfunction]
+pub(super) fn fourier_transform_to_iq(
+    r_values: Vec<f64>,
+    g_r: Vec<f64>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    rho: f64,
+    n_atoms: usize,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    check_q_range(min_q, max_q, q_step)?;
+    Ok(core::fourier_transform_to_iq(
+        &r_values, &g_r, min_q, max_q, q_step, rho, n_atoms,
+    )?)
+}
+
+/// Compute the reduced pair distribution function G(r) of a scattering curve.
+///
+/// Follows the standard PDF workflow (as in PDFgetX3): S(q) = I(q) / N, F(q) = q * (S(q) - 1),
+/// multiplied by the Lorch modification function sin(pi*q/q_max) / (pi*q/q_max) to suppress
+/// the truncation ripples, and G(r) = (2/pi) * integral F(q) * sin(q*r) dq by the trapezoid
+/// rule, i.e. `pdf_lorch_transform` of I(q) / N with q_max the largest q. Peaks of G(r) sit at the interatomic distances of the crystal; the resolution is
+/// limited by the largest q, so use q_max of at least 50 1/nm for sharp peaks.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm, e.g. `dse_optimized(...).q`
+///     intensities (list[float]): Intensities on the same q grid
+///     r_min (float): Smallest r in nanometers
+///     r_max (float): Largest r in nanometers
+///     dr (float): Step of the r grid in nanometers
+///     n_atoms (int): Number of atoms N, used to compute S(q) = I(q) / N
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (r_values, G(r)), with G(r) in 1/nm^2
+///
+/// Raises:
+///     ValueError: If the lengths differ, the r grid is invalid, n_atoms is 0 or no q value is
+///         positive
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> result = dse_optimized(0.1, 80.0, 0.05, positions)
+///     >>> r, G = pdf_from_iq(result.q, result.intensity, 0.1, 1.5, 0.005, len(positions))
+#[pyfunction]
+pub(super) fn pdf_from_iq(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    n_atoms: usize,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    Ok(core::pdf_from_iq(
+        &q_values,
+        &intensities,
+        r_min,
+        r_max,
+        dr,
+        n_atoms,
+    )?)
+}
+
+/// Compute the Lorch modification function M(q) = sin(pi*q/q_max) / (pi*q/q_max).
+///
+/// Multiplying F(q) = q * (S(q) - 1) by M(q) before the Fourier transform to G(r) suppresses
+/// the termination ripples caused by truncating the data at q_max, at the cost of slightly
+/// broader peaks. M falls from 1 at q = 0 to 0 at q_max.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     q_max (float): Truncation q in 1/nm, usually the largest measured q
+///
+/// Returns:
+///     list[float]: M(q) at each q value
+///
+/// Raises:
+///     ValueError: If q_max is not positive
+///
+/// Examples:
+///     >>> result = dse_optimized(0.1, 80.0, 0.05, crystal('fcc', 0.408, 3.0))
+///     >>> m = lorch_modification_function(result.q, max(result.q))
+#[pyfunction]
+pub(super) fn lorch_modification_function(q_values: Vec<f64>, q_max: f64) -> PyResult<Vec<f64>> {
+    Ok(core::lorch_modification_function(&q_values, q_max)?)
+}
+
+/// Multiply a scattering curve point by point by a modification function.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     intensities (list[float]): Values on the same q grid, e.g. F(q) = q * (S(q) - 1)
+///     modification (list[float]): Modification function on the same q grid, e.g. from
+///         `lorch_modification_function`
+///
+/// Returns:
+///     list[float]: intensities[i] * modification[i]
+///
+/// Raises:
+///     ValueError: If the lengths differ
+///
+/// Examples:
+///     >>> m = lorch_modification_function(q, max(q))
+///     >>> f_modified = apply_modification_function(q, f, m)
+#[pyfunction]
+pub(super) fn apply_modification_function(
+    q_values: Vec<f64>,
+    intensities: Vec<f64>,
+    modification: Vec<f64>,
+) -> PyResult<Vec<f64>> {
+    Ok(core::apply_modification_function(
+        &q_values,
+        &intensities,
+        &modification,
+    )?)
+}
+
+/// Compute the reduced pair distribution function G(r) of a structure factor with the Lorch
+/// modification function.
+///
+/// G(r) = (2/pi) * integral_0^q_max q * (S(q) - 1) * M(q) * sin(q*r) dq by the trapezoid rule,
+/// with M the `lorch_modification_function` of q_max, as in PDFgetX3 and xPDFsuite. Points above
+/// q_max are left out, so a smaller q_max trades resolution for fewer ripples on noisy data.
+/// `pdf_from_iq` is the same transform of I(q) / N with q_max the largest q.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     structure_factor (list[float]): S(q) on the same q grid, tending to 1 at high q, e.g.
+///         from `structure_factor`
+///     r_min (float): Smallest r in nanometers
+///     r_max (float): Largest r in nanometers
+///     dr (float): Step of the r grid in nanometers
+///     q_max (float): Truncation q of the transform in 1/nm
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (r_values, G(r)), with G(r) in 1/nm^2
+///
+/// Raises:
+///     ValueError: If the lengths differ, the r grid is invalid, q_max is not positive or
+///         fewer than two q values lie in [0, q_max]
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> q = dse_optimized(0.1, 80.0, 0.05, positions).q
+///     >>> s = structure_factor(0.1, 80.0, 0.05, positions)
+///     >>> r, G = pdf_lorch_transform(q, s, 0.1, 1.5, 0.005, 60.0)
+#[pyfunction]
+pub(super) fn pdf_lorch_transform(
+    q_values: Vec<f64>,
+    structure_factor: Vec<f64>,
+    r_min: f64,
+    r_max: f64,
+    dr: f64,
+    q_max: f64,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    Ok(core::pdf_lorch_transform(
+        &q_values,
+        &structure_factor,
+        r_min,
+        r_max,
+        dr,
+        q_max,
+    )?)
+}
+
+/// Count the neighbours of every atom within a cutoff distance.
+///
+/// Useful to check that a generated structure is chemically reasonable before computing
+/// expensive DSE patterns: in a perfect FCC crystal a cutoff between the first and second
+/// neighbour shells (e.g. 0.35 nm for gold) gives 12 for bulk atoms and less at the surface.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     cutoff (float): Largest neighbour distance in nanometers, inclusive
+///
+/// Returns:
+///     list[int]: Number of other atoms within cutoff of each atom
+///
+/// Raises:
+///     ValueError: If cutoff is negative or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> max(coordination_numbers(crystal('fcc', 0.408, 4.0), 0.35))
+///     12
+#[pyfunction]
+pub(super) fn coordination_numbers(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    cutoff: f64,
+) -> PyResult<Vec<usize>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| install(|| core::coordination_numbers(atoms.positions(), cutoff)))?)
+}
+
+/// Average number of neighbours within a cutoff distance.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     cutoff (float): Largest neighbour distance in nanometers, inclusive
+///
+/// Returns:
+///     float: Mean of `coordination_numbers(positions, cutoff)`
+///
+/// Raises:
+///     ValueError: If the crystal is empty, cutoff is negative, or an atom does not have
+///         exactly three finite coordinates
+///
+/// Examples:
+///     >>> cn = average_coordination(crystal_icosahedron(0.408, 3), 0.31)
+#[pyfunction]
+pub(super) fn average_coordination(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    cutoff: f64,
+) -> PyResult<f64> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| install(|| core::average_coordination(atoms.positions(), cutoff)))?)
+}
+
+/// Histogram of the coordination numbers within a cutoff distance.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     cutoff (float): Largest neighbour distance in nanometers, inclusive
+///
+/// Returns:
+///     list[tuple[int, int]]: (coordination_number, count) pairs sorted by coordination number,
+///         omitting numbers that do not occur
+///
+/// Raises:
+///     ValueError: If cutoff is negative or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> coordination_histogram(crystal_cuboctahedron(0.408, 1), 0.3)
+///     [(5, 12), (12, 1)]
+#[pyfunction]
+pub(super) fn coordination_histogram(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    cutoff: f64,
+) -> PyResult<Vec<(usize, usize)>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| install(|| core::coordination_histogram(atoms.positions(), cutoff)))?)
+}
+
+/// Number density of a crystal.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     float: N / V in atoms per nm^3, with V the volume of the bounding box of the crystal.
+///         0 for an empty or flat crystal
+///
+/// Examples:
+///     >>> rho = number_density(crystal('cube', 1.0, 5.0))
+#[pyfunction]
+pub(super) fn number_density(crystal: Vec<Vec<f64>>) -> PyResult<f64> {
+    Ok(core::number_density(atoms_from_py(&crystal)?.positions()))
+}
@@ -0,0 +1,1585 @@
+use pyo3::prelude::*;
+
+use super::threads::install;
+use super::{atoms_from_py, atoms_to_py};
+use crate::core::{self, Atoms, Matrix3, Shape};
+
+/// Atom positions of a multi-species crystal together with a species label per atom.
+///
+/// Pass it to `dse_optimized` with `scattering_weights` to weight every pair by the relative
+/// scattering lengths of the two species, e.g. for binary alloys or neutron scattering where
+/// weights can differ in sign.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     species (list[int]): Species label of each atom, indexing into `scattering_weights`
+///
+/// Raises:
+///     ValueError: If positions and species have different lengths
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.38, 3.0)
+///     >>> species = [i % 2 for i in range(len(positions))]
+///     >>> atoms = CrystalAtoms(positions, species)
+///     >>> intensities = dse_optimized(1.0, 30.0, 0.1, atoms, scattering_weights=[79.0, 29.0])
+#[pyclass(name = "CrystalAtoms", frozen)]
+pub(super) struct PyCrystalAtoms {
+    pub(super) atoms: Atoms,
+    pub(super) species: Vec<u32>,
+}
+
+#[pymethods]
+impl PyCrystalAtoms {
+    #[new]
+    fn new(positions: Vec<Vec<f64>>, species: Vec<u32>) -> PyResult<Self> {
+        if positions.len() != species.len() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "positions has {} atoms but species has {} labels",
+                positions.len(),
+                species.len()
+            )));
+        }
+        Ok(Self {
+            atoms: atoms_from_py(&positions)?,
+            species,
+        })
+    }
+
+    /// Atom positions as a list of [x, y, z] coordinates.
+    #[getter]
+    fn positions(&self) -> Vec<Vec<f64>> {
+        atoms_to_py(&self.atoms)
+    }
+
+    /// Species label of each atom.
+    #[getter]
+    fn species(&self) -> Vec<u32> {
+        self.species.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.atoms.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("CrystalAtoms(n_atoms={})", self.atoms.len())
+    }
+}
+
+/// One periodic cell of a bulk crystal, repeated along x, y and z.
+///
+/// Pass it to `dse_periodic` to compute bulk scattering with the minimum image convention,
+/// as for a simulation box of a molecular dynamics run, instead of the finite particle
+/// that `dse_optimized` models.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] of the atoms of the cell
+///     box_lengths (tuple[float, float, float]): Edges (L_x, L_y, L_z) of the orthorhombic box
+///         in nanometers
+///
+/// Raises:
+///     ValueError: If a box length is not positive and finite, or an atom does not have exactly
+///         three finite coordinates
+///
+/// Examples:
+///     >>> cell = PeriodicCrystal(crystal('fcc', 0.408, 4.08), (4.08, 4.08, 4.08))
+#[pyclass(name = "PeriodicCrystal", frozen)]
+pub(super) struct PyPeriodicCrystal {
+    pub(super) inner: core::PeriodicCrystal,
+}
+
+#[pymethods]
+impl PyPeriodicCrystal {
+    #[new]
+    fn new(positions: Vec<Vec<f64>>, box_lengths: (f64, f64, f64)) -> PyResult<Self> {
+        let atoms = atoms_from_py(&positions)?;
+        let (x, y, z) = box_lengths;
+        Ok(Self {
+            inner: core::PeriodicCrystal::new(atoms.positions(), [x, y, z])?,
+        })
+    }
+
+    /// Atom positions of the cell as a list of [x, y, z] coordinates.
+    #[getter]
+    fn positions(&self) -> Vec<Vec<f64>> {
+        atoms_to_py(self.inner.atoms())
+    }
+
+    /// Box edges (L_x, L_y, L_z) in nanometers.
+    #[getter]
+    fn box_lengths(&self) -> (f64, f64, f64) {
+        let [x, y, z] = self.inner.box_lengths();
+        (x, y, z)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.atoms().len()
+    }
+
+    fn __repr__(&self) -> String {
+        let [x, y, z] = self.inner.box_lengths();
+        format!(
+            "PeriodicCrystal(n_atoms={}, box_lengths=({}, {}, {}))",
+            self.inner.atoms().len(),
+            x,
+            y,
+            z
+        )
+    }
+}
+
+/// Wrap atom positions into a periodic cell for bulk scattering with `dse_periodic`.
+///
+/// Same as the `PeriodicCrystal(positions, box_lengths)` constructor.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] of the atoms of the cell
+///     box_lengths (tuple[float, float, float]): Edges (L_x, L_y, L_z) of the orthorhombic box
+///         in nanometers
+///
+/// Returns:
+///     PeriodicCrystal: The periodic cell
+///
+/// Raises:
+///     ValueError: If a box length is not positive and finite, or an atom does not have exactly
+///         three finite coordinates
+///
+/// Examples:
+///     >>> cell = periodic_crystal(crystal('fcc', 0.408, 4.08), (4.08, 4.08, 4.08))
+///     >>> intensities = dse_periodic(5.0, 50.0, 0.05, cell)
+#[pyfunction]
+pub(super) fn periodic_crystal(
+    positions: Vec<Vec<f64>>,
+    box_lengths: (f64, f64, f64),
+) -> PyResult<PyPeriodicCrystal> {
+    PyPeriodicCrystal::new(positions, box_lengths)
+}
+
+/// Generate a crystal lattice structure.
+///
+/// Args:
+///     shape (str): Shape or structure of the crystal. 'cube', 'sphere', 'cylinder', 'disk' and
+///         'ellipsoid' give simple cubic lattices filling that shape; 'fcc', 'bcc' and 'hcp' give
+///         face-centred cubic, body-centred cubic and hexagonal close-packed lattices filling a
+///         cube; 'icosahedron' gives a Mackay icosahedron (see `crystal_icosahedron`) and
+///         'truncated_octahedron' an FCC truncated octahedron (see
+///         `crystal_truncated_octahedron`)
+///     lattice_param (float): Lattice parameter (spacing between lattice points) in nanometers.
+///         For 'hcp' this is the in-plane parameter a
+///     length (float): Size of the crystal structure in nanometers (the diameter for
+///         'cylinder' and 'disk'). For 'icosahedron' the number of shells, truncated to an
+///         integer, and for 'truncated_octahedron' the edge length
+///     c_over_a (float, optional): c/a ratio for 'hcp'. Defaults to the ideal sqrt(8/3).
+///         Ignored for other shapes
+///     aspect_ratio (float, optional): Height divided by diameter, required for 'cylinder'
+///         and 'disk'. Must be below 1 for 'disk'. Ignored for other shapes
+///     semi_axes (tuple[float, float, float], optional): Semi-axes (a, b, c) in nanometers for
+///         'ellipsoid'. Defaults to a sphere of diameter length. Ignored for other shapes
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: If shape is not one of the supported shapes, lattice_param or c_over_a is
+///         not positive, or aspect_ratio is missing or out of range, or a semi-axis is not
+///         positive
+///
+/// Examples:
+///     >>> crystal('cube', 1.0, 5.0)
+///     [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], ...]
+///     >>> copper = crystal('fcc', 0.352, 5.0)
+///     >>> nanorod = crystal('cylinder', 0.4, 4.0, aspect_ratio=3.0)
+///     >>> prolate = crystal('ellipsoid', 0.4, 0.0, semi_axes=(2.0, 2.0, 5.0))
+///     >>> len(crystal('icosahedron', 0.408, 2))
+///     55
+#[pyfunction]
+#[pyo3(signature = (shape, lattice_param, length, c_over_a=None, aspect_ratio=None, semi_axes=None))]
+pub(super) fn crystal(
+    shape: &str,
+    lattice_param: f64,
+    length: f64,
+    c_over_a: Option<f64>,
+    aspect_ratio: Option<f64>,
+    semi_axes: Option<(f64, f64, f64)>,
+) -> PyResult<Vec<Vec<f64>>> {
+    let shape = parse_shape(shape, length, c_over_a, aspect_ratio, semi_axes)?;
+    let atoms = core::crystal_core(shape, lattice_param, length)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// The [`Shape`] named by `shape`, with the optional parameters of `crystal`.
+pub(super) fn parse_shape(
+    shape: &str,
+    length: f64,
+    c_over_a: Option<f64>,
+    aspect_ratio: Option<f64>,
+    semi_axes: Option<(f64, f64, f64)>,
+) -> PyResult<Shape> {
+    let required_aspect_ratio = || {
+        aspect_ratio.ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "shape '{}' requires aspect_ratio",
+                shape
+            ))
+        })
+    };
+    Ok(match shape {
+        "cube" => Shape::Cube,
+        "sphere" => Shape::Sphere,
+        "fcc" => Shape::Fcc,
+        "bcc" => Shape::Bcc,
+        "hcp" => Shape::Hcp {
+            c_over_a: c_over_a.unwrap_or(core::HCP_IDEAL_C_OVER_A),
+        },
+        "cylinder" => Shape::Cylinder {
+            aspect_ratio: required_aspect_ratio()?,
+        },
+        "disk" => Shape::Disk {
+            aspect_ratio: required_aspect_ratio()?,
+        },
+        "ellipsoid" => {
+            let (a, b, c) = semi_axes.unwrap_or((length / 2.0, length / 2.0, length / 2.0));
+            Shape::Ellipsoid { a, b, c }
+        }
+        "icosahedron" => Shape::Icosahedron {
+            num_shells: length as usize,
+        },
+        "truncated_octahedron" => Shape::TruncatedOctahedron,
+        _ => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown shape: '{}'. Supported shapes: 'cube', 'sphere', 'fcc', 'bcc', 'hcp', \
+                 'cylinder', 'disk', 'ellipsoid', 'icosahedron', 'truncated_octahedron'",
+                shape
+            )));
+        }
+    })
+}
+
+/// Parse the name of a cubic Bravais lattice: 'simple_cubic' (or 'sc', 'cube'), 'fcc' or 'bcc'.
+pub(super) fn parse_cubic_lattice(name: &str) -> PyResult<core::CubicLattice> {
+    Ok(match name {
+        "simple_cubic" | "sc" | "cube" => core::CubicLattice::Simple,
+        "fcc" => core::CubicLattice::FaceCentred,
+        "bcc" => core::CubicLattice::BodyCentred,
+        _ => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown cubic lattice: '{}'. Supported lattices: 'simple_cubic' (or 'sc', \
+                 'cube'), 'fcc', 'bcc'",
+                name
+            )));
+        }
+    })
+}
+
+/// Generate a simple cubic crystal inside an upright cylinder.
+///
+/// The cylinder axis runs along z through (diameter/2, diameter/2); a lattice point is kept
+/// when (x - d/2)^2 + (y - d/2)^2 <= (d/2)^2 and 0 <= z <= height.
+///
+/// Args:
+///     lattice_param (float): Lattice parameter (spacing between lattice points) in nanometers
+///     diameter (float): Diameter of the cylinder in nanometers
+///     height (float): Height of the cylinder in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: If diameter or height is not positive
+///
+/// Examples:
+///     >>> nanorod = crystal_cylinder(0.4, 4.0, 12.0)
+///     >>> platelet = crystal_cylinder(0.4, 10.0, 1.0)
+#[pyfunction]
+pub(super) fn crystal_cylinder(
+    lattice_param: f64,
+    diameter: f64,
+    height: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_cylinder(lattice_param, diameter, height)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a simple cubic crystal inside a nanorod with hemispherical end caps.
+///
+/// The standard SAXS model of CdSe or gold nanorods: a cylinder of the given diameter along z,
+/// through (diameter/2, diameter/2), closed by two hemispheres of radius diameter/2 so that
+/// the rod spans 0 <= z <= total_length. In the body, from z = diameter/2 to
+/// total_length - diameter/2, a lattice point is kept when its distance from the axis is at
+/// most diameter/2; in the caps, when its distance from the cap centre is.
+///
+/// Args:
+///     lattice_param (float): Lattice parameter (spacing between lattice points) in nanometers
+///     diameter (float): Diameter of the rod and of its caps in nanometers
+///     total_length (float): Length of the rod including both caps in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: If diameter is not positive or total_length does not exceed it
+///
+/// Examples:
+///     >>> rod = crystal_nanorod(0.4, 4.0, 16.0)
+#[pyfunction]
+pub(super) fn crystal_nanorod(
+    lattice_param: f64,
+    diameter: f64,
+    total_length: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_nanorod(lattice_param, diameter, total_length)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a crystalline slab for surface scattering.
+///
+/// floor(dimension / lattice_param) conventional cells are tiled along x, y and z from the
+/// origin. A slab much thinner along z than along x and y scatters anisotropically, e.g. in
+/// `dse_3d` for grazing-incidence geometries.
+///
+/// Args:
+///     lattice_param (float): Cubic lattice parameter in nanometers
+///     width (float): Extent along x in nanometers
+///     height (float): Extent along y in nanometers
+///     thickness (float): Extent along z in nanometers
+///     structure (str): 'simple_cubic' (or 'sc'), 'fcc' or 'bcc'
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: If a dimension is not positive or the structure is unknown
+///
+/// Examples:
+///     >>> slab = crystal_slab(0.408, 8.0, 8.0, 1.0, 'fcc')
+#[pyfunction]
+pub(super) fn crystal_slab(
+    lattice_param: f64,
+    width: f64,
+    height: f64,
+    thickness: f64,
+    structure: &str,
+) -> PyResult<Vec<Vec<f64>>> {
+    let lattice = parse_cubic_lattice(structure)?;
+    let atoms = core::crystal_slab(lattice_param, width, height, thickness, lattice)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a crystalline slab whose surface layers are relaxed inward.
+///
+/// Same as `crystal_slab`, with the outermost n_relaxed_layers atomic layers on both z
+/// surfaces compressed: each spacing between the outermost n_relaxed_layers + 1 layers is
+/// scaled by 1 - relaxation_fraction. A negative relaxation_fraction expands the surface.
+///
+/// Args:
+///     lattice_param (float): Cubic lattice parameter in nanometers
+///     width (float): Extent along x in nanometers
+///     height (float): Extent along y in nanometers
+///     thickness (float): Extent along z in nanometers
+///     n_relaxed_layers (int): Number of relaxed atomic layers on each surface
+///     relaxation_fraction (float): Fractional contraction of the surface layer spacings
+///     structure (str): 'simple_cubic' (or 'sc'), 'fcc' or 'bcc'. Defaults to 'simple_cubic'
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] of the relaxed slab
+///
+/// Raises:
+///     ValueError: If a dimension is not positive, relaxation_fraction is not below 1, or the
+///         slab has no more than 2 * n_relaxed_layers layers
+///
+/// Examples:
+///     >>> slab = crystal_slab_with_surface_relaxation(0.408, 8.0, 8.0, 2.0, 2, 0.05, 'fcc')
+#[pyfunction]
+#[pyo3(signature = (lattice_param, width, height, thickness, n_relaxed_layers, relaxation_fraction, structure="simple_cubic"))]
+pub(super) fn crystal_slab_with_surface_relaxation(
+    lattice_param: f64,
+    width: f64,
+    height: f64,
+    thickness: f64,
+    n_relaxed_layers: usize,
+    relaxation_fraction: f64,
+    structure: &str,
+) -> PyResult<Vec<Vec<f64>>> {
+    let lattice = parse_cubic_lattice(structure)?;
+    let atoms = core::crystal_slab_with_surface_relaxation(
+        lattice_param,
+        width,
+        height,
+        thickness,
+        lattice,
+        n_relaxed_layers,
+        relaxation_fraction,
+    )?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Read atom positions from the content of an XYZ file.
+///
+/// The expected layout is the standard (extended) XYZ format: the first line is the atom
+/// count, the second a comment, then one `SYMBOL X Y Z` row per atom. Columns after Z are
+/// ignored, as are the element symbols for now. Only the first frame is read.
+///
+/// Args:
+///     xyz_content (str): Text content of the XYZ file, with coordinates in Å
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] in nanometers (Å divided by 10)
+///
+/// Raises:
+///     ValueError: If the atom count is missing, a row has fewer than four columns or a
+///         non-numeric coordinate, or there are fewer rows than atoms. The message includes
+///         the offending line number
+///
+/// Examples:
+///     >>> with open('nanoparticle.xyz') as f:
+///     ...     positions = crystal_from_xyz(f.read())
+///     >>> intensities = dse_optimized(0.1, 10.0, 0.1, positions)
+#[pyfunction]
+pub(super) fn crystal_from_xyz(xyz_content: &str) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_from_xyz(xyz_content)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Read the atom positions of every frame of a LAMMPS text dump.
+///
+/// Dumps written by `dump atom` or `dump custom` are supported, with the coordinates taken
+/// from the xu yu zu, x y z, xs ys zs or xsu ysu zsu columns (the first available in that
+/// order). Scaled coordinates are converted with the box bounds and, for triclinic boxes, the
+/// xy, xz and yz tilt factors. All frames are held in memory; `dse_trajectory_average_lammps`
+/// streams a dump file instead.
+///
+/// Args:
+///     dump_content (str): Text content of the dump, with lengths in Å
+///
+/// Returns:
+///     list[list[list[float]]]: Positions of each frame as [x, y, z] coordinates in nanometers
+///         (Å divided by 10), indexed [frame][atom]
+///
+/// Raises:
+///     ValueError: If a frame cannot be parsed. The message includes the offending line number
+///
+/// Examples:
+///     >>> with open('dump.lammpstrj') as f:
+///     ...     frames = parse_lammps_dump(f.read())
+///     >>> mean_I, std_I = dse_trajectory_average(frames, 1.0, 50.0, 0.1)
+#[pyfunction]
+pub(super) fn parse_lammps_dump(dump_content: &str) -> PyResult<Vec<Vec<Vec<f64>>>> {
+    let frames = core::parse_lammps_dump(dump_content)?;
+    Ok(frames.iter().map(atoms_to_py).collect())
+}
+
+/// Read the atom positions of the first frame of a LAMMPS text dump.
+///
+/// Same as `parse_lammps_dump(dump_content)[0]`, without parsing the later frames.
+///
+/// Args:
+///     dump_content (str): Text content of the dump, with lengths in Å
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] in nanometers (Å divided by 10)
+///
+/// Raises:
+///     ValueError: If the dump has no frames or the first frame cannot be parsed
+///
+/// Examples:
+///     >>> with open('dump.lammpstrj') as f:
+///     ...     positions = parse_lammps_dump_first_frame(f.read())
+///     >>> intensities = dse_optimized(1.0, 50.0, 0.1, positions)
+#[pyfunction]
+pub(super) fn parse_lammps_dump_first_frame(dump_content: &str) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::parse_lammps_dump_first_frame(dump_content)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Read atom positions from the content of a CSV file.
+///
+/// Each row holds x,y,z in nanometers; columns after z are ignored. Blank lines and lines
+/// starting with # are skipped, and a non-numeric first row is treated as a header.
+///
+/// Args:
+///     csv_content (str): Text content of the CSV file, with coordinates in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] in nanometers
+///
+/// Raises:
+///     ValueError: If a row has fewer than three fields or a non-numeric coordinate. The
+///         message includes the offending line number
+///
+/// Examples:
+///     >>> numpy.savetxt('positions.csv', crystal('sphere', 0.4, 5.0), delimiter=',')
+///     >>> with open('positions.csv') as f:
+///     ...     positions = crystal_from_csv(f.read())
+#[pyfunction]
+pub(super) fn crystal_from_csv(csv_content: &str) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_from_csv(csv_content)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a simple cubic crystal inside an ellipsoid.
+///
+/// The ellipsoid has semi-axes a, b and c along x, y and z and is centred at (a, b, c); a
+/// lattice point is kept when (x - a)^2/a^2 + (y - b)^2/b^2 + (z - c)^2/c^2 <= 1. With
+/// a = b = c = radius this reproduces crystal('sphere', lattice_param, 2 * radius).
+///
+/// Args:
+///     lattice_param (float): Lattice parameter (spacing between lattice points) in nanometers
+///     a (float): Semi-axis along x in nanometers
+///     b (float): Semi-axis along y in nanometers
+///     c (float): Semi-axis along z in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: If a semi-axis is not positive
+///
+/// Note:
+///     The lattice is scanned over a cube of side 2 * max(a, b, c), so for very asymmetric
+///     aspect ratios the step count, and the time and memory needed, is dominated by the
+///     longest axis.
+///
+/// Examples:
+///     >>> prolate = crystal_ellipsoid(0.4, 2.0, 2.0, 5.0)
+///     >>> oblate = crystal_ellipsoid(0.4, 5.0, 5.0, 2.0)
+#[pyfunction]
+pub(super) fn crystal_ellipsoid(
+    lattice_param: f64,
+    a: f64,
+    b: f64,
+    c: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_ellipsoid(lattice_param, a, b, c)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a simple cubic crystal inside a hollow spherical shell (nanoshell).
+///
+/// A lattice point is kept when inner_radius^2 <= |p - centre|^2 <= outer_radius^2, with the
+/// centre at (outer_radius, outer_radius, outer_radius). The points are the same as those of
+/// crystal('sphere', lattice_param, 2 * outer_radius) minus the inner core.
+///
+/// Args:
+///     lattice_param (float): Lattice parameter (spacing between lattice points) in nanometers
+///     inner_radius (float): Radius of the hollow core in nanometers
+///     outer_radius (float): Outer radius of the shell in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing lattice points
+///
+/// Raises:
+///     ValueError: Unless 0 < inner_radius < outer_radius
+///
+/// Examples:
+///     >>> shell = crystal_hollow_sphere(0.408, 3.0, 5.0)
+///     >>> filled = crystal('sphere', 0.408, 10.0)
+///     >>> q, I_shell = dse_optimized_with_q(0.1, 5.0, 0.05, shell)
+///     >>> _, I_filled = dse_optimized_with_q(0.1, 5.0, 0.05, filled)
+///     >>> # The shell's first minimum moves to lower q and its oscillations are stronger
+#[pyfunction]
+pub(super) fn crystal_hollow_sphere(
+    lattice_param: f64,
+    inner_radius: f64,
+    outer_radius: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_hollow_sphere(lattice_param, inner_radius, outer_radius)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a two-species core-shell nanoparticle, e.g. Au@Ag or Fe3O4@Au.
+///
+/// Two FCC lattices are centred on the particle centre (R, R, R), with
+/// R = core_radius + shell_thickness, so that each has an atom there. Core atoms of the
+/// core_lattice_param lattice fill the sphere of core_radius and shell atoms of the
+/// shell_lattice_param lattice the shell out to R. The lattices are independent, so with
+/// different parameters the interface is incoherent.
+///
+/// Args:
+///     core_lattice_param (float): FCC lattice parameter of the core in nanometers
+///     shell_lattice_param (float): FCC lattice parameter of the shell in nanometers
+///     core_radius (float): Radius of the core in nanometers
+///     shell_thickness (float): Thickness of the shell in nanometers, 0 for a bare core
+///
+/// Returns:
+///     tuple[list[list[float]], list[int]]: (positions, species) with the core atoms first,
+///         species 0 for the core and 1 for the shell
+///
+/// Raises:
+///     ValueError: Unless the lattice parameters and core_radius are positive and
+///         shell_thickness >= 0
+///
+/// Examples:
+///     >>> positions, species = crystal_core_shell(0.408, 0.409, 2.0, 1.0)
+///     >>> lengths = [7.63 if s == 0 else 5.92 for s in species]  # Au and Ag, in fm
+///     >>> intensities = dse_neutron(1.0, 30.0, 0.1, positions, lengths)
+#[pyfunction]
+pub(super) fn crystal_core_shell(
+    core_lattice_param: f64,
+    shell_lattice_param: f64,
+    core_radius: f64,
+    shell_thickness: f64,
+) -> PyResult<(Vec<Vec<f64>>, Vec<u32>)> {
+    let (atoms, species) = core::crystal_core_shell(
+        core_lattice_param,
+        shell_lattice_param,
+        core_radius,
+        shell_thickness,
+    )?;
+    Ok((atoms_to_py(&atoms), species))
+}
+
+/// Generate a Mackay icosahedron nanoparticle.
+///
+/// The particle is built shell by shell around a central atom: shell k places 10k^2 + 2 atoms
+/// on the vertices, edges and faces of an icosahedron scaled by k, giving the closed-shell
+/// magic numbers 1, 13, 55, 147, 309 for 0 to 4 shells. Within each shell atoms are spaced by
+/// the FCC nearest-neighbour distance lattice_param / sqrt(2); the radial spacing is about 5%
+/// shorter, as in the real Mackay structure. The particle is centred at the origin.
+///
+/// Args:
+///     lattice_param (float): FCC lattice parameter in nanometers, e.g. 0.408 for gold
+///     num_shells (int): Number of closed shells around the central atom
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> len(crystal_icosahedron(0.408, 1))
+///     13
+///     >>> gold_309 = crystal_icosahedron(0.408, 4)
+#[pyfunction]
+pub(super) fn crystal_icosahedron(lattice_param: f64, num_shells: usize) -> Vec<Vec<f64>> {
+    atoms_to_py(&core::crystal_icosahedron(lattice_param, num_shells))
+}
+
+/// Generate a cuboctahedral FCC nanoparticle.
+///
+/// The particle holds the FCC lattice points inside a cuboctahedron, the closed-shell
+/// structure bounded by eight {111} and six {100} facets. It is built shell by shell around
+/// a central atom: shell k adds 10k^2 + 2 atoms, giving the magic numbers 1, 13, 55, 147,
+/// 309, 561, i.e. (10n^3 + 15n^2 + 11n + 3) / 3 atoms for n shells. The particle is centred
+/// at the origin.
+///
+/// Args:
+///     lattice_param (float): FCC lattice parameter in nanometers, e.g. 0.392 for platinum
+///     n_shells (int): Number of closed shells around the central atom
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> len(crystal_cuboctahedron(0.392, 2))
+///     55
+///     >>> platinum_561 = crystal_cuboctahedron(0.392, 5)
+#[pyfunction]
+pub(super) fn crystal_cuboctahedron(lattice_param: f64, n_shells: usize) -> Vec<Vec<f64>> {
+    atoms_to_py(&core::crystal_cuboctahedron(lattice_param, n_shells))
+}
+
+/// Generate a regular truncated octahedral FCC nanoparticle.
+///
+/// The truncated octahedron is the Wulff shape of an FCC metal with equal {100} and {111}
+/// surface energies: eight hexagonal {111} and six square {100} facets whose edges all have
+/// the same length. The edge is rounded to a whole number s of nearest-neighbour distances
+/// lattice_param / sqrt(2); s = 1 to 5 give the magic numbers 38, 201, 586, 1289, 2406. The
+/// particle is centred at the origin, on an atom for even s and on an octahedral hole for odd
+/// s. `crystal('truncated_octahedron', lattice_param, edge_length)` is equivalent.
+///
+/// Args:
+///     lattice_param (float): FCC lattice parameter in nanometers, e.g. 0.408 for gold
+///     edge_length (float): Length of every edge in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If lattice_param is not positive or edge_length is below half the
+///         nearest-neighbour distance
+///
+/// Examples:
+///     >>> len(crystal_truncated_octahedron(0.408, 0.408 / 2 ** 0.5))
+///     38
+///     >>> gold_1289 = crystal_truncated_octahedron(0.408, 1.16)
+#[pyfunction]
+pub(super) fn crystal_truncated_octahedron(
+    lattice_param: f64,
+    edge_length: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_truncated_octahedron(lattice_param, edge_length)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate an FCC nanoparticle with the equilibrium shape of the Wulff construction.
+///
+/// Every Miller index stands for its whole family of facets under cubic symmetry, e.g.
+/// (1, 0, 0) for the six {100} facets. Facets lie at distances from the centre proportional
+/// to their surface energies, gamma / gamma_min * size / 2, and the particle holds the FCC
+/// lattice points inside all of them. High-energy families whose facets lie outside the
+/// others do not appear. The particle is centred at the origin.
+///
+/// Args:
+///     lattice_param (float): FCC lattice parameter in nanometers, e.g. 0.408 for gold
+///     hkl_list (list[tuple[int, int, int]]): Miller indices of the facet families
+///     surface_energies (list[float]): Surface energy of each family, in any consistent unit
+///     size (float): Distance in nanometers between opposite facets of the lowest-energy family
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If the lists are empty or of different lengths, an index is (0, 0, 0), or
+///         an energy or the size is not positive
+///
+/// Examples:
+///     >>> # Surface energies of gold in J/m^2: a truncated octahedron
+///     >>> wulff = crystal_wulff(0.408, [(1, 0, 0), (1, 1, 0), (1, 1, 1)], [1.63, 1.70, 1.28], 5.0)
+///     >>> intensities = dse_optimized(1.0, 50.0, 0.1, wulff)
+#[pyfunction]
+pub(super) fn crystal_wulff(
+    lattice_param: f64,
+    hkl_list: Vec<(i32, i32, i32)>,
+    surface_energies: Vec<f64>,
+    size: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let hkl_list: Vec<[i32; 3]> = hkl_list.into_iter().map(|(h, k, l)| [h, k, l]).collect();
+    let atoms = core::crystal_wulff(lattice_param, &hkl_list, &surface_energies, size)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Generate a Marks decahedron nanoparticle, as observed for Au and Pd in the 2-20 nm range.
+///
+/// Five FCC tetrahedra share a fivefold axis along z and are joined by twin planes; atoms on
+/// the twin planes are placed once. The particle is centred at the origin.
+///
+/// Args:
+///     lattice_param (float): FCC lattice parameter in nanometers, e.g. 0.408 for gold
+///     p (int): Number of atoms along the edges of the (100) facets perpendicular to the axis
+///     q (int): Number of atoms along the axis on the (100) facets parallel to it; 1 gives no
+///         such facets, larger values the elongated Ino decahedron
+///     r (int): Depth of the Marks re-entrance at the pentagon corners; 0 gives none
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If p or q is 0
+///
+/// Examples:
+///     >>> [len(crystal_decahedron(0.408, p, 1, 0)) for p in range(1, 5)]
+///     [1, 7, 23, 54]
+///     >>> marks = crystal_decahedron(0.408, 4, 3, 2)
+#[pyfunction]
+pub(super) fn crystal_decahedron(
+    lattice_param: f64,
+    p: usize,
+    q: usize,
+    r: usize,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_decahedron(lattice_param, p, q, r)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Tile a unit cell into a supercell.
+///
+/// Every atom is translated by (ix*a, iy*b, iz*c) for ix in range(nx), iy in range(ny) and
+/// iz in range(nz), so the result has exactly len(positions) * nx * ny * nz atoms.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions of the cell to repeat, in nanometers
+///     nx (int): Number of repeats along x
+///     ny (int): Number of repeats along y
+///     nz (int): Number of repeats along z
+///     a (float): Repeat length along x in nanometers
+///     b (float): Repeat length along y in nanometers
+///     c (float): Repeat length along z in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> cube = replicate_crystal(crystal('cube', 1.0, 1.0), 5, 5, 5, 1.0, 1.0, 1.0)
+///     >>> len(cube)
+///     125
+///     >>> sorted(cube) == sorted(crystal('cube', 1.0, 5.0))
+///     True
+#[pyfunction]
+pub(super) fn replicate_crystal(
+    positions: Vec<Vec<f64>>,
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    a: f64,
+    b: f64,
+    c: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let cell = atoms_from_py(&positions)?;
+    Ok(atoms_to_py(&core::replicate_crystal(
+        cell.positions(),
+        nx,
+        ny,
+        nz,
+        a,
+        b,
+        c,
+    )))
+}
+
+/// Number of atoms in a crystal.
+///
+/// Same as len(positions), after checking that every atom has exactly three finite
+/// coordinates; the N of the Debye sum, e.g. for normalising I(q) by N or N^2.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     int: The number of atoms N
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates
+///
+/// Examples:
+///     >>> n_atoms(crystal('fcc', 0.408, 4.0))
+///     2916
+#[pyfunction]
+pub(super) fn n_atoms(crystal: Vec<Vec<f64>>) -> PyResult<usize> {
+    Ok(atoms_from_py(&crystal)?.len())
+}
+
+/// Shift every atom by the vector (dx, dy, dz).
+///
+/// DSE intensities depend only on pairwise distances, so they are unchanged by translation.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///     dx (float): Shift along x in nanometers
+///     dy (float): Shift along y in nanometers
+///     dz (float): Shift along z in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> translate_crystal([[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]], 0.5, 0.0, -1.0)
+///     [[0.5, 0.0, -1.0], [1.5, 0.0, -1.0]]
+#[pyfunction]
+pub(super) fn translate_crystal(
+    positions: Vec<Vec<f64>>,
+    dx: f64,
+    dy: f64,
+    dz: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(atoms_to_py(&core::translate_crystal(
+        atoms.positions(),
+        [dx, dy, dz],
+    )))
+}
+
+/// Shift the atoms so that their centroid (mean position) is at the origin.
+///
+/// The shapes built by `crystal` start at the origin, e.g. a sphere is centred at
+/// (radius, radius, radius); use this to re-centre them before rotating or combining crystals.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> center_crystal([[0.0, 0.0, 0.0], [2.0, 0.0, 0.0]])
+///     [[-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]]
+#[pyfunction]
+pub(super) fn center_crystal(positions: Vec<Vec<f64>>) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(atoms_to_py(&core::center_crystal(atoms.positions())))
+}
+
+/// Convert positions to fractional coordinates of an orthorhombic cell.
+///
+/// Divides x by a, y by b and z by c, so atoms inside the cell at the origin get coordinates in
+/// [0, 1). Useful to compare structures with different lattice parameters or cell sizes.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///     a (float): Cell edge along x in nanometers
+///     b (float): Cell edge along y in nanometers
+///     c (float): Cell edge along z in nanometers
+///
+/// Returns:
+///     list[list[float]]: Fractional coordinates [x / a, y / b, z / c]
+///
+/// Raises:
+///     ValueError: If a, b or c is not positive
+///
+/// Examples:
+///     >>> to_fractional_coordinates([[0.2, 0.4, 0.6]], 0.4, 0.8, 1.2)
+///     [[0.5, 0.5, 0.5]]
+#[pyfunction]
+pub(super) fn to_fractional_coordinates(
+    positions: Vec<Vec<f64>>,
+    a: f64,
+    b: f64,
+    c: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(atoms_to_py(&core::to_fractional_coordinates(
+        atoms.positions(),
+        a,
+        b,
+        c,
+    )?))
+}
+
+/// Convert fractional coordinates of an orthorhombic cell back to positions.
+///
+/// The inverse of `to_fractional_coordinates`: multiplies x by a, y by b and z by c.
+///
+/// Args:
+///     frac_positions (list[list[float]]): Fractional coordinates
+///     a (float): Cell edge along x in nanometers
+///     b (float): Cell edge along y in nanometers
+///     c (float): Cell edge along z in nanometers
+///
+/// Returns:
+///     list[list[float]]: Atom positions in nanometers
+///
+/// Raises:
+///     ValueError: If a, b or c is not positive
+///
+/// Examples:
+///     >>> from_fractional_coordinates([[0.5, 0.5, 0.5]], 0.4, 0.8, 1.2)
+///     [[0.2, 0.4, 0.6]]
+#[pyfunction]
+pub(super) fn from_fractional_coordinates(
+    frac_positions: Vec<Vec<f64>>,
+    a: f64,
+    b: f64,
+    c: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&frac_positions)?;
+    Ok(atoms_to_py(&core::from_fractional_coordinates(
+        atoms.positions(),
+        a,
+        b,
+        c,
+    )?))
+}
+
+/// Convert fractional coordinates of a general, possibly non-orthogonal, cell to positions.
+///
+/// Each position is r = f_a * a + f_b * b + f_c * c, with a, b and c the lattice vectors given
+/// as the rows of lattice_matrix.
+///
+/// Args:
+///     frac_positions (list[list[float]]): Fractional coordinates [f_a, f_b, f_c]
+///     lattice_matrix (list[list[float]]): 3x3 matrix whose rows are the Cartesian lattice
+///         vectors a, b and c in nanometers
+///
+/// Returns:
+///     list[list[float]]: Atom positions in nanometers
+///
+/// Raises:
+///     ValueError: If lattice_matrix is not 3x3 or is singular, i.e. its lattice vectors are
+///         coplanar
+///
+/// Examples:
+///     >>> hexagonal = [[0.25, 0.0, 0.0], [-0.125, 0.2165, 0.0], [0.0, 0.0, 0.41]]
+///     >>> positions = to_cartesian_from_fractional([[1 / 3, 2 / 3, 0.5]], hexagonal)
+#[pyfunction]
+pub(super) fn to_cartesian_from_fractional(
+    frac_positions: Vec<Vec<f64>>,
+    lattice_matrix: Vec<Vec<f64>>,
+) -> PyResult<Vec<Vec<f64>>> {
+    let lattice = matrix_from_py("lattice_matrix", &lattice_matrix)?;
+    let atoms = atoms_from_py(&frac_positions)?;
+    Ok(atoms_to_py(&core::to_cartesian_from_fractional(
+        atoms.positions(),
+        &lattice,
+    )?))
+}
+
+/// Flag the atoms on the surface of a crystal.
+///
+/// A simplified solvent-accessible surface: the surface is approximated by the convex hull of
+/// the positions, and an atom is on it when a probe sphere of radius probe_radius rolling over
+/// the hull reaches it, i.e. when its distance to the nearest hull facet is at most
+/// probe_radius. Atoms on the hull facets are always flagged; a probe of about one lattice
+/// spacing also flags the layer beneath.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///     probe_radius (float): Probe radius in nanometers, at least 0
+///
+/// Returns:
+///     list[bool]: True for each surface atom
+///
+/// Raises:
+///     ValueError: If probe_radius is negative
+///
+/// Note:
+///     Concave features are missed: the atoms lining the cavity of a hollow sphere count as
+///     bulk. Atoms that are all coplanar are all flagged.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 6.0)
+///     >>> surface = classify_surface_atoms(positions, 0.2)
+///     >>> surface_positions = [p for p, s in zip(positions, surface) if s]
+#[pyfunction]
+pub(super) fn classify_surface_atoms(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    probe_radius: f64,
+) -> PyResult<Vec<bool>> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::classify_surface_atoms(atoms.positions(), probe_radius))
+    })?)
+}
+
+/// Fraction of the atoms of a crystal on its surface, as flagged by `classify_surface_atoms`.
+///
+/// For compact shapes it scales as N^(-1/3), which checks that a generated structure has the
+/// expected surface-to-volume ratio.
+///
+/// Args:
+///     positions (list[list[float]]): Atom positions in nanometers
+///     probe_radius (float): Probe radius in nanometers, at least 0
+///
+/// Returns:
+///     float: Number of surface atoms divided by the number of atoms
+///
+/// Raises:
+///     ValueError: If there are no atoms or probe_radius is negative
+///
+/// Examples:
+///     >>> small = surface_fraction(crystal('sphere', 0.4, 4.0), 0.2)
+///     >>> large = surface_fraction(crystal('sphere', 0.4, 8.0), 0.2)
+#[pyfunction]
+pub(super) fn surface_fraction(
+    py: Python<'_>,
+    positions: Vec<Vec<f64>>,
+    probe_radius: f64,
+) -> PyResult<f64> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| install(|| core::surface_fraction(atoms.positions(), probe_radius)))?)
+}
+
+/// Generate an amorphous (glass-like) structure of randomly placed hard-sphere atoms.
+///
+/// Atoms are placed one at a time at uniformly random positions in the cube
+/// [0, box_size)^3, rejecting any position closer than min_distance to an atom already placed
+/// (random sequential addition, with a cell grid for the neighbour check). The structure has a
+/// well-defined nearest-neighbour distance but no long-range order, so `dse_optimized` gives
+/// the broad diffuse peaks of an amorphous material instead of Bragg peaks.
+///
+/// Args:
+///     n_atoms (int): Number of atoms to place
+///     box_size (float): Side of the cubic box in nanometers
+///     min_distance (float): Smallest allowed distance between two atoms in nanometers
+///     seed (int): Seed of the random number generator; the same seed gives the same structure
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If box_size is not positive, min_distance is negative, the packing fraction
+///         n_atoms * (4 pi / 3) * (min_distance / 2)^3 / box_size^3 exceeds random close packing
+///         (0.64), or the box fills up before all atoms are placed. Random sequential addition
+///         jams near a packing fraction of 0.38
+///
+/// Examples:
+///     >>> glass = crystal_amorphous(1000, 3.0, 0.2, seed=1)
+///     >>> intensities = dse_optimized(1.0, 60.0, 0.2, glass)
+#[pyfunction]
+pub(super) fn crystal_amorphous(
+    n_atoms: usize,
+    box_size: f64,
+    min_distance: f64,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_amorphous(n_atoms, box_size, min_distance, seed)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Randomly remove a fraction of the atoms of a crystal to model vacancies.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     vacancy_fraction (float): Fraction of atoms to remove, in [0, 1]. round(fraction * N)
+///         atoms are removed
+///     seed (int): Seed of the random number generator; the same seed removes the same sites
+///
+/// Returns:
+///     list[list[float]]: The remaining atom positions, in their original order
+///
+/// Raises:
+///     ValueError: If vacancy_fraction is outside [0, 1]
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> defective = crystal_with_vacancies(positions, 0.05, seed=42)
+#[pyfunction]
+pub(super) fn crystal_with_vacancies(
+    positions: Vec<Vec<f64>>,
+    vacancy_fraction: f64,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    let remaining = core::crystal_with_vacancies(atoms.positions(), vacancy_fraction, seed)?;
+    Ok(atoms_to_py(&remaining))
+}
+
+/// Displace every atom by a random isotropic Gaussian offset to model thermal disorder.
+///
+/// Each Cartesian coordinate of each atom gets an independent N(0, sigma_nm^2) displacement.
+/// The result can be passed directly to `dse_optimized`; averaging the intensities of many
+/// seeds reproduces the Debye-Waller attenuation exp(-q^2 sigma_nm^2) of the distinct pair
+/// terms, while the self-scattering term N is not attenuated.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     sigma_nm (float): Standard deviation of the displacement along each axis, in nanometers
+///     seed (int): Seed of the random number generator; the same seed gives the same displacements
+///
+/// Returns:
+///     list[list[float]]: The displaced atom positions, in their original order
+///
+/// Raises:
+///     ValueError: If sigma_nm is negative or not finite
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> disordered = apply_thermal_disorder(positions, 0.01, seed=42)
+///     >>> intensities = dse_optimized(1.0, 50.0, 0.1, disordered)
+#[pyfunction]
+pub(super) fn apply_thermal_disorder(
+    positions: Vec<Vec<f64>>,
+    sigma_nm: f64,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    let disordered = core::apply_thermal_disorder(atoms.positions(), sigma_nm, seed)?;
+    Ok(atoms_to_py(&disordered))
+}
+
+/// Displace every atom by a random Gaussian offset with a different width along each axis.
+///
+/// The anisotropic counterpart of `apply_thermal_disorder`, e.g. for uniaxial systems whose
+/// atoms vibrate more along the c axis than in the basal plane.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     sigma_x (float): Standard deviation of the displacement along x, in nanometers
+///     sigma_y (float): Standard deviation of the displacement along y, in nanometers
+///     sigma_z (float): Standard deviation of the displacement along z, in nanometers
+///     seed (int): Seed of the random number generator; the same seed gives the same displacements
+///
+/// Returns:
+///     list[list[float]]: The displaced atom positions, in their original order
+///
+/// Raises:
+///     ValueError: If a standard deviation is negative or not finite
+///
+/// Examples:
+///     >>> positions = crystal('hcp', 0.32, 4.0)
+///     >>> disordered = apply_anisotropic_disorder(positions, 0.005, 0.005, 0.015, seed=1)
+#[pyfunction]
+pub(super) fn apply_anisotropic_disorder(
+    positions: Vec<Vec<f64>>,
+    sigma_x: f64,
+    sigma_y: f64,
+    sigma_z: f64,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = atoms_from_py(&positions)?;
+    let disordered =
+        core::apply_anisotropic_disorder(atoms.positions(), sigma_x, sigma_y, sigma_z, seed)?;
+    Ok(atoms_to_py(&disordered))
+}
+
+/// Generate a random substitutional alloy: a crystal whose sites are occupied at random by two species.
+///
+/// The positions are those of `crystal(shape, lattice_param, length)`. Each site is then
+/// labelled species 0 (A) with probability species_a_fraction and species 1 (B) otherwise,
+/// independently, so the A fraction fluctuates binomially about the requested one.
+///
+/// Args:
+///     shape (str): Crystal shape, as for `crystal`
+///     lattice_param (float): Lattice parameter in nanometers
+///     length (float): Size of the crystal in nanometers, as for `crystal`
+///     species_a_fraction (float): Probability that a site is species A, in [0, 1]
+///     seed (int): Seed of the random number generator; the same seed gives the same alloy
+///
+/// Returns:
+///     tuple[list[list[float]], list[int]]: (positions, species) with species 0 for A and 1
+///         for B
+///
+/// Raises:
+///     ValueError: If the shape is unknown or needs extra parameters, species_a_fraction is
+///         outside [0, 1], or the crystal parameters are invalid
+///
+/// Examples:
+///     >>> positions, species = crystal_random_alloy('fcc', 0.408, 4.0, 0.5, seed=3)
+///     >>> alloy = CrystalAtoms(positions, species)
+///     >>> intensities = dse_optimized(1.0, 30.0, 0.1, alloy, scattering_weights=[79.0, 47.0])
+#[pyfunction]
+pub(super) fn crystal_random_alloy(
+    shape: &str,
+    lattice_param: f64,
+    length: f64,
+    species_a_fraction: f64,
+    seed: u64,
+) -> PyResult<(Vec<Vec<f64>>, Vec<u32>)> {
+    let shape = parse_shape(shape, length, None, None, None)?;
+    let (atoms, species) =
+        core::crystal_random_alloy(shape, lattice_param, length, species_a_fraction, seed)?;
+    Ok((atoms_to_py(&atoms), species))
+}
+
+/// Generate a close-packed cylindrical crystal with random stacking faults.
+///
+/// The crystal is built layer by layer along z from hexagonal (111) layers of an FCC crystal
+/// of cubic lattice parameter lattice_param: triangular nets of nearest-neighbour distance
+/// a / sqrt(2), a / sqrt(3) apart, each on one of the three lateral sites A, B or C. Each new
+/// layer continues the FCC sequence A -> B -> C -> A with probability 1 - fault_probability and
+/// otherwise steps back (B -> A), a deformation fault, after which the sequence continues from
+/// the new layer. fault_probability = 0 gives FCC (ABCABC), 1 its twin (ACBACB) and 0.5 a
+/// random close-packed stacking.
+///
+/// Args:
+///     lattice_param (float): Cubic lattice parameter of the unfaulted FCC crystal in
+///         nanometers
+///     diameter (float): Diameter of the cylinder in nanometers
+///     n_layers (int): Number of close-packed layers, at least 1
+///     fault_probability (float): Probability of a fault at each new layer, in [0, 1]
+///     seed (int): Seed of the random number generator; the same seed gives the same stacking
+///
+/// Returns:
+///     list[list[float]]: Atom positions in nanometers, with the cylinder axis at
+///         (diameter/2, diameter/2) and the first layer at z = 0
+///
+/// Raises:
+///     ValueError: If lattice_param or diameter is not positive, n_layers is 0 or
+///         fault_probability is outside [0, 1]
+///
+/// Note:
+///     Stacking faults broaden some FCC reflections and add diffuse streaks between the FCC
+///     and HCP Bragg peaks of the DSE.
+///
+/// Examples:
+///     >>> faulted = crystal_stacking_faults(0.408, 4.0, 17, 0.1, seed=1)
+///     >>> intensities = dse_optimized(10.0, 40.0, 0.05, faulted)
+#[pyfunction]
+pub(super) fn crystal_stacking_faults(
+    lattice_param: f64,
+    diameter: f64,
+    n_layers: usize,
+    fault_probability: f64,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms =
+        core::crystal_stacking_faults(lattice_param, diameter, n_layers, fault_probability, seed)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Randomly relabel a fraction of the atoms of one species to model substitutional defects.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     species (list[int]): Species label of each atom
+///     substitution_fraction (float): Fraction of the from_species atoms to relabel, in [0, 1]
+///     from_species (int): Species label to substitute
+///     to_species (int): Species label given to the substituted atoms
+///     seed (int): Seed of the random number generator; the same seed substitutes the same sites
+///
+/// Returns:
+///     CrystalAtoms: The same positions with the substituted species labels
+///
+/// Raises:
+///     ValueError: If substitution_fraction is outside [0, 1] or positions and species have
+///         different lengths
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> alloy = crystal_with_substitutions(positions, [0] * len(positions), 0.25, 0, 1, seed=7)
+///     >>> intensities = dse_optimized(1.0, 30.0, 0.1, alloy, scattering_weights=[79.0, 29.0])
+#[pyfunction]
+pub(super) fn crystal_with_substitutions(
+    positions: Vec<Vec<f64>>,
+    species: Vec<u32>,
+    substitution_fraction: f64,
+    from_species: u32,
+    to_species: u32,
+    seed: u64,
+) -> PyResult<PyCrystalAtoms> {
+    let crystal = PyCrystalAtoms::new(positions, species)?;
+    let species = core::crystal_with_substitutions(
+        &crystal.species,
+        substitution_fraction,
+        from_species,
+        to_species,
+        seed,
+    )?;
+    Ok(PyCrystalAtoms {
+        atoms: crystal.atoms,
+        species,
+    })
+}
+
+/// Parse atom positions from the content of a CIF (Crystallographic Information File).
+///
+/// Reads the cell parameters (_cell_length_a/b/c in Å, _cell_angle_alpha/beta/gamma in
+/// degrees) and the _atom_site_fract_x/y/z loop of the first data_ block, and converts the
+/// fractional coordinates to Cartesian positions with a along x and b in the xy plane.
+///
+/// Args:
+///     cif_content (str): Text content of the CIF file
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] in nanometers
+///
+/// Raises:
+///     ValueError: If a required field is missing or a value cannot be parsed. The message
+///         includes the line number
+///
+/// Note:
+///     Symmetry operations are not applied: only the listed sites are returned, so the file
+///     should list every atom of the cell (space group P1, as exported by VESTA with
+///     "P1" symmetry). Combine with `replicate_crystal` to build a nanocrystal from the cell.
+///
+/// Examples:
+///     >>> with open('NaCl_P1.cif') as f:
+///     ...     cell = crystal_from_cif(f.read())
+///     >>> positions = replicate_crystal(cell, 10, 10, 10, 0.564, 0.564, 0.564)
+#[pyfunction]
+pub(super) fn crystal_from_cif(cif_content: &str) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = core::crystal_from_cif(cif_content)?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Parse atom positions from a CIF file on disk.
+///
+/// Same as `crystal_from_cif`, reading the content from path.
+///
+/// Args:
+///     path (str): Path of the CIF file
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] in nanometers
+///
+/// Raises:
+///     OSError: If the file cannot be read
+///     ValueError: As for `crystal_from_cif`
+///
+/// Examples:
+///     >>> cell = crystal_from_cif_file('NaCl_P1.cif')
+#[pyfunction]
+pub(super) fn crystal_from_cif_file(path: &str) -> PyResult<Vec<Vec<f64>>> {
+    let content = std::fs::read_to_string(path)?;
+    crystal_from_cif(&content)
+}
+
+/// Parse atom positions and element symbols from the content of a VASP POSCAR or CONTCAR
+/// file.
+///
+/// Reads the scale factor (line 2), the lattice vectors in Å (lines 3-5), the element
+/// symbols (line 6), the atom counts (line 7), an optional "Selective dynamics" line, the
+/// coordinate mode ("Direct" or "Cartesian") and one row per atom. Direct (fractional)
+/// coordinates are converted with the lattice vectors; Cartesian ones are scaled by the
+/// scale factor. A negative scale factor is the cell volume in Å³.
+///
+/// Args:
+///     content (str): Text content of the POSCAR file
+///
+/// Returns:
+///     tuple[list[list[float]], list[str]]: The positions [x, y, z] in nanometers and the
+///         element symbol of each atom
+///
+/// Raises:
+///     ValueError: If a line is missing or cannot be parsed, or the file is in the VASP 4
+///         format without element symbols. The message includes the line number
+///
+/// Note:
+///     As for `crystal_from_cif`, the positions are those of one cell; combine with
+///     `replicate_crystal` to build a nanocrystal.
+///
+/// Examples:
+///     >>> with open('CONTCAR') as f:
+///     ...     positions, elements = crystal_from_poscar(f.read())
+///     >>> kinds = sorted(set(elements))
+///     >>> cell = CrystalAtoms(positions, [kinds.index(e) for e in elements])
+#[pyfunction]
+pub(super) fn crystal_from_poscar(content: &str) -> PyResult<(Vec<Vec<f64>>, Vec<String>)> {
+    let (atoms, labels) = core::crystal_from_poscar(content)?;
+    Ok((atoms_to_py(&atoms), labels))
+}
+
+/// Apply a rigid rotation p' = R @ p to every atom of a crystal.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     rotation_matrix (list[list[float]]): 3x3 orthogonal matrix R, as a list of rows, e.g.
+///         from `rotation_matrix_z`
+///
+/// Returns:
+///     list[list[float]]: The rotated positions
+///
+/// Raises:
+///     ValueError: If rotation_matrix is not 3x3 or not orthogonal (|R^T R - I| >= 1e-6)
+///
+/// Examples:
+///     >>> import math
+///     >>> rotated = rotate_crystal(crystal('cube', 1.0, 3.0), rotation_matrix_z(math.pi / 4))
+#[pyfunction]
+pub(super) fn rotate_crystal(
+    positions: Vec<Vec<f64>>,
+    rotation_matrix: Vec<Vec<f64>>,
+) -> PyResult<Vec<Vec<f64>>> {
+    let rotation = matrix_from_py("rotation_matrix", &rotation_matrix)?;
+    let atoms = atoms_from_py(&positions)?;
+    Ok(atoms_to_py(&core::rotate_crystal(
+        atoms.positions(),
+        &rotation,
+    )?))
+}
+
+/// `(positions, rotation_matrix)` of one grain of `crystal_grain_aggregate`.
+type GrainSpec = (Vec<Vec<f64>>, Vec<Vec<f64>>);
+
+/// Combine several grains, each rotated by its own matrix, into one aggregate.
+///
+/// Each grain is rotated as by `rotate_crystal`, about the origin, and the results are
+/// concatenated in the order given. Centre a grain first, e.g. with `center_crystal`, to
+/// rotate it about its own centre, and translate it into place afterwards.
+///
+/// Args:
+///     grain_specs (list[tuple[list[list[float]], list[list[float]]]]): (positions,
+///         rotation_matrix) pairs, each matrix a 3x3 list of rows
+///
+/// Returns:
+///     list[list[float]]: The rotated positions of all grains
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, or a matrix is
+///         not 3x3 or not orthogonal
+///
+/// Examples:
+///     >>> import math
+///     >>> grain = center_crystal(crystal('fcc', 0.408, 3.0))
+///     >>> upper = translate_crystal(grain, 0.0, 0.0, 3.5)  # still centred on the z axis
+///     >>> twist_bicrystal = crystal_grain_aggregate([
+///     ...     (grain, rotation_matrix_z(0.0)),
+///     ...     (upper, rotation_matrix_z(math.pi / 6)),
+///     ... ])
+#[pyfunction]
+pub(super) fn crystal_grain_aggregate(grain_specs: Vec<GrainSpec>) -> PyResult<Vec<Vec<f64>>> {
+    let grains = grain_specs
+        .iter()
+        .map(|(positions, rotation_matrix)| {
+            Ok((
+                atoms_from_py(positions)?,
+                matrix_from_py("rotation_matrix", rotation_matrix)?,
+            ))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(atoms_to_py(&core::crystal_grain_aggregate(&grains)?))
+}
+
+/// Generate a polycrystal of randomly oriented spherical grains inside a cube.
+///
+/// Each of the n_grains grains is `crystal('sphere', lattice_param, grain_size)`, rotated
+/// about its centre by a uniformly random rotation and centred at a random point at least
+/// grain_size / 2 from the faces of the cube of side total_size. Grains are added in turn, and
+/// an atom closer than 0.5 * lattice_param to an atom of an earlier grain is dropped. The random
+/// orientations give a powder-like pattern with broadened Bragg peaks. The overlap check
+/// compares every pair of atoms, in parallel with the GIL released.
+///
+/// Args:
+///     lattice_param (float): Lattice parameter of the grains in nanometers
+///     grain_size (float): Diameter of each grain in nanometers
+///     total_size (float): Side of the enclosing cube in nanometers, at least grain_size
+///     n_grains (int): Number of grains
+///     seed (int): Seed of the random number generator; the same seed gives the same sample
+///
+/// Returns:
+///     list[list[float]]: List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: Unless lattice_param and grain_size are positive and
+///         total_size >= grain_size
+///
+/// Examples:
+///     >>> sample = crystal_polycrystalline(0.4, 3.0, 8.0, 10, seed=1)
+///     >>> intensities = dse_optimized(1.0, 40.0, 0.05, sample)
+#[pyfunction]
+pub(super) fn crystal_polycrystalline(
+    py: Python<'_>,
+    lattice_param: f64,
+    grain_size: f64,
+    total_size: f64,
+    n_grains: usize,
+    seed: u64,
+) -> PyResult<Vec<Vec<f64>>> {
+    let atoms = py.allow_threads(|| {
+        install(|| {
+            core::crystal_polycrystalline(lattice_param, grain_size, total_size, n_grains, seed)
+        })
+    })?;
+    Ok(atoms_to_py(&atoms))
+}
+
+/// Convert a 3x3 list of rows from Python, failing with a ValueError naming `name` unless it is
+/// 3x3.
+fn matrix_from_py(name: &str, rows: &[Vec<f64>]) -> PyResult<Matrix3> {
+    if rows.len() != 3 || rows.iter().any(|row| row.len() != 3) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "{} must be 3x3, got {} row(s) of length(s) {:?}",
+            name,
+            rows.len(),
+            rows.iter().map(Vec::len).collect::<Vec<_>>()
+        )));
+    }
+    Ok([0, 1, 2].map(|i| [0, 1, 2].map(|j| rows[i][j])))
+}
+
+fn matrix_to_py(matrix: Matrix3) -> Vec<Vec<f64>> {
+    matrix.iter().map(|row| row.to_vec()).collect()
+}
+
+/// Rotation matrix about the x axis.
+///
+/// Args:
+///     angle_rad (float): Rotation angle in radians, counter-clockwise looking down the axis
+///
+/// Returns:
+///     list[list[float]]: 3x3 rotation matrix as a list of rows, for `rotate_crystal`
+#[pyfunction]
+pub(super) fn rotation_matrix_x(angle_rad: f64) -> Vec<Vec<f64>> {
+    matrix_to_py(core::rotation_matrix_x(angle_rad))
+}
+
+/// Rotation matrix about the y axis.
+///
+/// Args:
+///     angle_rad (float): Rotation angle in radians, counter-clockwise looking down the axis
+///
+/// Returns:
+///     list[list[float]]: 3x3 rotation matrix as a list of rows, for `rotate_crystal`
+#[pyfunction]
+pub(super) fn rotation_matrix_y(angle_rad: f64) -> Vec<Vec<f64>> {
+    matrix_to_py(core::rotation_matrix_y(angle_rad))
+}
+
+/// Rotation matrix about the z axis.
+///
+/// Args:
+///     angle_rad (float): Rotation angle in radians, counter-clockwise looking down the axis
+///
+/// Returns:
+///     list[list[float]]: 3x3 rotation matrix as a list of rows, for `rotate_crystal`
+#[pyfunction]
+pub(super) fn rotation_matrix_z(angle_rad: f64) -> Vec<Vec<f64>> {
+    matrix_to_py(core::rotation_matrix_z(angle_rad))
+}
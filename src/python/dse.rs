@@ -0,0 +1,2836 @@
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::crystal::{parse_shape, PyCrystalAtoms, PyPeriodicCrystal};
+use super::iq::PyIqResult;
+use super::threads::install;
+use super::{atoms_from_py, atoms_to_py, check_finite_positions};
+use crate::core;
+
+/// Crystal argument of `dse_optimized`: plain positions or a `CrystalAtoms`.
+#[derive(FromPyObject)]
+pub(super) enum CrystalInput<'py> {
+    Atoms(PyRef<'py, PyCrystalAtoms>),
+    Positions(Vec<Vec<f64>>),
+}
+
+/// Fail with a ValueError unless `min_q <= max_q` and `q_step > 0`, all finite.
+pub(super) fn check_q_range(min_q: f64, max_q: f64, q_step: f64) -> PyResult<()> {
+    if !(min_q.is_finite() && max_q.is_finite() && q_step.is_finite()) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "min_q, max_q and q_step must be finite, got {}, {} and {}",
+            min_q, max_q, q_step
+        )));
+    }
+    if min_q > max_q {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "min_q must not exceed max_q, got {} > {}",
+            min_q, max_q
+        )));
+    }
+    if q_step <= 0.0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "q_step must be positive, got {}",
+            q_step
+        )));
+    }
+    Ok(())
+}
+
+/// Default of `set_memory_warning_threshold_mb`, 2 GB.
+const DEFAULT_MEMORY_WARNING_THRESHOLD_MB: f64 = 2000.0;
+
+/// Bits of the `f64` threshold set by `set_memory_warning_threshold_mb`.
+static MEMORY_WARNING_THRESHOLD_MB: AtomicU64 =
+    AtomicU64::new(DEFAULT_MEMORY_WARNING_THRESHOLD_MB.to_bits());
+
+/// Emit a UserWarning if `estimate_memory_mb` of a distance-matrix computation exceeds the
+/// threshold of `set_memory_warning_threshold_mb`.
+fn warn_if_memory_exceeds_threshold(
+    py: Python<'_>,
+    n_atoms: usize,
+    n_q_points: usize,
+) -> PyResult<()> {
+    let threshold = f64::from_bits(MEMORY_WARNING_THRESHOLD_MB.load(Ordering::Relaxed));
+    let estimate = core::estimate_memory_mb(n_atoms, n_q_points);
+    if estimate <= threshold {
+        return Ok(());
+    }
+    let message = format!(
+        "{} atoms need an estimated {:.1} MB for the distance matrix, above the warning \
+         threshold of {:.1} MB; dse_ultra_optimized does not store the matrix",
+        n_atoms, estimate, threshold
+    );
+    let message = CString::new(message).expect("the message has no NUL byte");
+    PyErr::warn(
+        py,
+        &py.get_type::<pyo3::exceptions::PyUserWarning>(),
+        &message,
+        1,
+    )
+}
+
+/// Estimated peak memory in megabytes (1e6 bytes) of `dse_optimized`.
+///
+/// Dominated by the 8 * n_atoms^2 bytes of the distance matrix, plus the positions and the q
+/// and intensity arrays. Use it to check that a crystal fits in RAM before running it.
+///
+/// Args:
+///     n_atoms (int): Number of atoms of the crystal
+///     n_q_points (int): Number of q values
+///
+/// Returns:
+///     float: Estimated peak memory in MB
+///
+/// Examples:
+///     >>> estimate_memory_mb(15625, 1000)
+///     1954.266
+#[pyfunction]
+pub(super) fn estimate_memory_mb(n_atoms: usize, n_q_points: usize) -> f64 {
+    core::estimate_memory_mb(n_atoms, n_q_points)
+}
+
+/// Largest number of atoms whose `estimate_memory_mb` fits in max_memory_mb.
+///
+/// Args:
+///     max_memory_mb (float): Memory budget in megabytes (1e6 bytes)
+///
+/// Returns:
+///     int: Maximum number of atoms, 0 for a budget that is not positive
+///
+/// Examples:
+///     >>> max_atoms_for_memory(8000.0)
+///     31618
+#[pyfunction]
+pub(super) fn max_atoms_for_memory(max_memory_mb: f64) -> usize {
+    core::max_atoms_for_memory(max_memory_mb)
+}
+
+/// Set the estimated memory above which `dse_optimized` emits a UserWarning.
+///
+/// `dse_optimized` stores the full distance matrix, so large crystals can exhaust the RAM.
+/// Before computing it compares `estimate_memory_mb` with this threshold, 2000 MB by default.
+/// Pass `float('inf')` to disable the warning, or silence it with the `warnings` module.
+///
+/// Args:
+///     threshold_mb (float): Threshold in megabytes (1e6 bytes)
+///
+/// Raises:
+///     ValueError: If threshold_mb is not positive
+///
+/// Examples:
+///     >>> set_memory_warning_threshold_mb(8000.0)
+///     >>> get_memory_warning_threshold_mb()
+///     8000.0
+#[pyfunction]
+pub(super) fn set_memory_warning_threshold_mb(threshold_mb: f64) -> PyResult<()> {
+    if threshold_mb.is_nan() || threshold_mb <= 0.0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "threshold_mb must be positive, got {}",
+            threshold_mb
+        )));
+    }
+    MEMORY_WARNING_THRESHOLD_MB.store(threshold_mb.to_bits(), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Estimated memory in megabytes above which `dse_optimized` emits a UserWarning.
+///
+/// Returns:
+///     float: The threshold set with `set_memory_warning_threshold_mb`, 2000.0 by default
+#[pyfunction]
+pub(super) fn get_memory_warning_threshold_mb() -> f64 {
+    f64::from_bits(MEMORY_WARNING_THRESHOLD_MB.load(Ordering::Relaxed))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values.
+///
+/// Computes scattering intensity over a range of q values using the simplified Debye formula.
+/// This optimized implementation pre-calculates distances and uses parallel computation
+/// via Rayon for improved performance. Does not include the factor atomic scattering factors.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]] | CrystalAtoms): List of 3D coordinates [x, y, z]
+///         representing atom positions, or a CrystalAtoms with a species label per atom.
+///         Plain positions are treated as all species 0
+///     scattering_weights (list[float], optional): Relative scattering length of each species.
+///         Each pair term is multiplied by the weights of its two species. Defaults to equal
+///         weights of 1
+///     progress_callback (Callable[[int, int], object], optional): Called with
+///         (completed_q_points, total_q_points) after each chunk of q points
+///     progress_chunk_size (int, optional): Number of q points per chunk. Defaults to the
+///         number of Rayon threads; smaller chunks give finer updates but less parallelism
+///
+/// Returns:
+///     IqResult: Intensity values at each q point min_q + i * q_step, up to and including
+///         max_q when it lies on the grid, together with those q values
+///
+/// Raises:
+///     ValueError: If the crystal is empty, an atom does not have exactly three finite
+///         coordinates, min_q > max_q, q_step is not positive, or a species label has no
+///         entry in scattering_weights
+///     Exception: The first exception raised by progress_callback, once the computation has
+///         finished; the callback is not called again after it raised
+///
+/// Warns:
+///     UserWarning: If `estimate_memory_mb` exceeds the `set_memory_warning_threshold_mb`
+///         threshold, 2000 MB by default, as the n_atoms^2 distance matrix may not fit in RAM;
+///         `dse_ultra_optimized` computes the same intensities without it
+///
+/// Note:
+///     The intensity at each q is calculated as the sum of sin(q*r)/(q*r) over all
+///     pairwise distances r in the crystal structure. Use `.intensity` or `.to_numpy()` on
+///     the result for the plain values, or `dse_optimized_with_q` for a (q, I) tuple of lists.
+///     The GIL is released during the computation, so other Python threads keep running. The
+///     progress callback re-acquires it and is invoked from one thread at a time, between
+///     chunks, while no parallel work is running.
+///
+/// Examples:
+///     >>> positions = crystal('cube', 1.0, 5.0)
+///     >>> intensities = dse_optimized(0.1, 10.0, 0.1, positions)
+///     >>> from tqdm import tqdm
+///     >>> with tqdm(total=100) as bar:
+///     ...     def update(done, total):
+///     ...         bar.n = done
+///     ...         bar.refresh()
+///     ...     intensities = dse_optimized(0.1, 10.0, 0.1, positions, progress_callback=update)
+///     >>> alloy = CrystalAtoms(positions, [i % 2 for i in range(len(positions))])
+///     >>> neutron = dse_optimized(0.1, 10.0, 0.1, alloy, scattering_weights=[-3.739, 6.646])
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (
+    min_q,
+    max_q,
+    q_step,
+    crystal,
+    scattering_weights=None,
+    progress_callback=None,
+    progress_chunk_size=None,
+))]
+pub(super) fn dse_optimized(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: CrystalInput<'_>,
+    scattering_weights: Option<Vec<f64>>,
+    progress_callback: Option<PyObject>,
+    progress_chunk_size: Option<usize>,
+) -> PyResult<PyIqResult> {
+    check_q_range(min_q, max_q, q_step)?;
+    let owned;
+    let (positions, species) = match &crystal {
+        CrystalInput::Positions(crystal) => {
+            owned = atoms_from_py(crystal)?;
+            (owned.positions(), Cow::Owned(vec![0; owned.len()]))
+        }
+        CrystalInput::Atoms(crystal) => (
+            crystal.atoms.positions(),
+            Cow::Borrowed(crystal.species.as_slice()),
+        ),
+    };
+    if positions.is_empty() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "crystal must contain at least one atom",
+        ));
+    }
+    let n_q_points = core::q_grid(min_q, max_q, q_step).len();
+    warn_if_memory_exceeds_threshold(py, positions.len(), n_q_points)?;
+    let intensity = with_progress_callback(
+        py,
+        progress_callback,
+        progress_chunk_size,
+        |chunk_size, progress| {
+            install(|| match &scattering_weights {
+                None => Ok(core::dse_core_with_progress(
+                    min_q, max_q, q_step, positions, chunk_size, progress,
+                )),
+                Some(weights) => core::dse_with_species_with_progress(
+                    min_q, max_q, q_step, positions, &species, weights, chunk_size, progress,
+                ),
+            })
+        },
+    )??;
+    PyIqResult::new(core::q_grid(min_q, max_q, q_step), intensity)
+}
+
+/// Run `compute` with the GIL released, passing it the q chunk size and a progress receiver
+/// that forwards to `progress_callback`.
+///
+/// Without a callback the whole grid is one chunk. The GIL is re-acquired for each callback,
+/// which runs between chunks, while no parallel work is in flight, so blocking on the GIL
+/// cannot stall the pool. The first exception raised by the callback is returned once
+/// `compute` has finished, and the callback is not called again after it.
+fn with_progress_callback<R: Send>(
+    py: Python<'_>,
+    progress_callback: Option<PyObject>,
+    progress_chunk_size: Option<usize>,
+    compute: impl FnOnce(usize, &(dyn core::ProgressFn + Sync)) -> R + Send,
+) -> PyResult<R> {
+    let chunk_size = match progress_callback {
+        Some(_) => progress_chunk_size.unwrap_or_else(|| install(rayon::current_num_threads)),
+        None => usize::MAX,
+    };
+    let callback_error = Mutex::new(None);
+    let progress = |completed: usize, total: usize| {
+        let (Some(callback), mut error) = (&progress_callback, callback_error.lock().unwrap())
+        else {
+            return;
+        };
+        if error.is_none() {
+            *error = Python::with_gil(|py| callback.call1(py, (completed, total)).err());
+        }
+    };
+    let result = py.allow_threads(|| compute(chunk_size, &progress));
+    match callback_error.into_inner().unwrap() {
+        Some(error) => Err(error),
+        None => Ok(result),
+    }
+}
+
+/// Calculate the partial DSE intensity of one pair of species of a multi-species crystal.
+///
+/// Sums sin(q*r)/(q*r) over the ordered pairs (i, j) with atom i of species_a and atom j of
+/// species_b, including the self terms when the two species are the same. The species-weighted
+/// `dse_optimized` intensity is sum_AB w_A * w_B * I_AB, so the partials of a crystal can be
+/// computed once and recombined for any contrast, e.g. in anomalous SAXS contrast variation.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     positions (list[list[float]]): Atom positions in nanometers
+///     species (list[int]): Species label of each atom
+///     species_a (int): Species of the first atom of each pair
+///     species_b (int): Species of the second atom of each pair
+///
+/// Returns:
+///     list[float]: I_AB at each q point, on the same grid as `dse_optimized`; zeros if no
+///         atom has one of the species
+///
+/// Raises:
+///     ValueError: If species does not have one label per atom or an atom does not have
+///         exactly three finite coordinates
+///
+/// Examples:
+///     >>> positions, species = crystal_random_alloy('fcc', 0.408, 3.0, 0.5, seed=1)
+///     >>> i_aa = partial_dse(0.1, 40.0, 0.1, positions, species, 0, 0)
+///     >>> i_ab = partial_dse(0.1, 40.0, 0.1, positions, species, 0, 1)
+///     >>> i_bb = partial_dse(0.1, 40.0, 0.1, positions, species, 1, 1)
+///     >>> total = [a * a * aa + 2 * a * b * ab + b * b * bb for aa, ab, bb in zip(i_aa, i_ab, i_bb)]
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn partial_dse(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    positions: Vec<Vec<f64>>,
+    species: Vec<u32>,
+    species_a: u32,
+    species_b: u32,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&positions)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::partial_dse(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                &species,
+                species_a,
+                species_b,
+            )
+        })
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values together with their q values.
+///
+/// Same computation as `dse_optimized`, but also returns the exact q values used, so there is
+/// no need to rebuild the grid (e.g. with `numpy.arange`, which excludes max_q) on the
+/// Python side.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude), included when it lies on
+///         the grid
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q_values, intensities) of the same length
+///
+/// Raises:
+///     ValueError: As for `dse_optimized`
+///
+/// Examples:
+///     >>> positions = crystal('cube', 1.0, 5.0)
+///     >>> q, I = dse_optimized_with_q(0.1, 10.0, 0.1, positions)
+#[pyfunction]
+pub(super) fn dse_optimized_with_q(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    if atoms.is_empty() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "crystal must contain at least one atom",
+        ));
+    }
+    let q_values = core::q_grid(min_q, max_q, q_step);
+    let intensity =
+        py.allow_threads(|| install(|| core::dse_core(min_q, max_q, q_step, atoms.positions())));
+    Ok((q_values, intensity))
+}
+
+/// The q values `min_q + i * q_step` used by `dse_optimized` and the other q_step-based functions.
+///
+/// max_q is included when it lies on the grid, up to floating-point rounding, unlike
+/// `numpy.arange`. Pair it with intensities computed for the same arguments.
+///
+/// Args:
+///     min_q (float): Minimum q value in 1/nm
+///     max_q (float): Maximum q value in 1/nm, included when it lies on the grid
+///     q_step (float): Step size between q values
+///
+/// Returns:
+///     list[float]: The q values, of the same length as the intensities of `dse_optimized`
+///
+/// Raises:
+///     ValueError: If the bounds or step are not finite, min_q > max_q or q_step is not
+///         positive
+///
+/// Examples:
+///     >>> q_values(0.1, 0.5, 0.1)
+///     [0.1, 0.2, 0.30000000000000004, 0.4, 0.5]
+#[pyfunction]
+pub(super) fn q_values(min_q: f64, max_q: f64, q_step: f64) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    Ok(core::q_grid(min_q, max_q, q_step))
+}
+
+/// Exactly n_points evenly spaced q values from min_q to max_q, both included.
+///
+/// Matches `numpy.linspace(min_q, max_q, n_points)`. Use it when the number of points matters
+/// more than a round step, e.g. to match a measured curve.
+///
+/// Args:
+///     min_q (float): Minimum q value in 1/nm
+///     max_q (float): Maximum q value in 1/nm
+///     n_points (int): Number of q values, at least 2
+///
+/// Returns:
+///     list[float]: The q values, the last exactly max_q
+///
+/// Raises:
+///     ValueError: Unless min_q <= max_q are finite and n_points >= 2
+///
+/// Examples:
+///     >>> q_values_linspace(0.0, 1.0, 5)
+///     [0.0, 0.25, 0.5, 0.75, 1.0]
+#[pyfunction]
+pub(super) fn q_values_linspace(min_q: f64, max_q: f64, n_points: usize) -> PyResult<Vec<f64>> {
+    Ok(core::q_linspace(min_q, max_q, n_points)?)
+}
+
+/// Calculate DSE intensities on exactly n_points evenly spaced q values.
+///
+/// The q values are those of `q_values_linspace(min_q, max_q, n_points)`; the intensities
+/// are those `dse_optimized` gives at the same q.
+///
+/// Args:
+///     min_q (float): Minimum q value in 1/nm
+///     max_q (float): Maximum q value in 1/nm, included in the grid
+///     n_points (int): Number of q values, at least 2
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     IqResult: The q values and their intensities
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, or as for
+///         `q_values_linspace`
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> result = dse_linspace(0.1, 10.0, 500, positions)
+///     >>> len(result.q)
+///     500
+#[pyfunction]
+pub(super) fn dse_linspace(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    n_points: usize,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<PyIqResult> {
+    let atoms = atoms_from_py(&crystal)?;
+    let (q, intensity) = py.allow_threads(|| {
+        install(|| core::dse_linspace(min_q, max_q, n_points, atoms.positions()))
+    })?;
+    PyIqResult::new(q, intensity)
+}
+
+/// Calculate DSE intensities on n_points logarithmically spaced q values.
+///
+/// The q values are exp(linspace(ln(min_q), ln(max_q), n_points)), denser at low q where a
+/// SAXS curve on a log-q plot has the most structure, with min_q and max_q both included.
+///
+/// Args:
+///     min_q (float): Minimum q value in 1/nm, positive
+///     max_q (float): Maximum q value in 1/nm, included in the grid
+///     n_points (int): Number of q values, at least 2
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q_values, intensities) of the same length
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, or unless
+///         0 < min_q <= max_q are finite and n_points >= 2
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> q, I = dse_logspace_q(0.01, 10.0, 200, positions)
+#[pyfunction]
+pub(super) fn dse_logspace_q(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    n_points: usize,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_logspace_q(min_q, max_q, n_points, atoms.positions()))
+    })?)
+}
+
+/// Calculate DSE intensities at arbitrary q values.
+///
+/// Useful to compare with experimental data recorded on a non-uniform grid: the intensities
+/// are those `dse_optimized` gives at the same q, in the order given. The distance matrix is
+/// built once for all q values.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm, finite and non-negative, in any order
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity at each q value
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, or a q value is
+///         negative or not finite
+///
+/// Examples:
+///     >>> measured = IqResult.from_csv('measurement.csv')
+///     >>> I_model = dse_custom_q(measured.q, crystal('sphere', 0.408, 5.0))
+#[pyfunction]
+pub(super) fn dse_custom_q(
+    py: Python<'_>,
+    q_values: Vec<f64>,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| install(|| core::dse_custom_q(&q_values, atoms.positions())))?)
+}
+
+/// Calculate DSE intensities on several q segments with their own steps.
+///
+/// Uniform q spacing wastes points in featureless regions and undersamples narrow peaks. Each
+/// (q_start, q_end, q_step) segment is evaluated on its own grid, as for `dse_optimized`, and
+/// the results are concatenated in the order given; a q shared by two abutting segments
+/// appears twice. The distance matrix is built once for all segments.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     q_ranges (list[tuple[float, float, float]]): (q_start, q_end, q_step) segments in 1/nm
+///
+/// Returns:
+///     IqResult: The concatenated q values and intensities
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, or a segment
+///         has q_start > q_end, a non-finite bound or a step that is not positive
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> result = dse_adaptive(positions, [(1.0, 14.0, 0.2), (14.0, 17.0, 0.01)])
+#[pyfunction]
+pub(super) fn dse_adaptive(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    q_ranges: Vec<(f64, f64, f64)>,
+) -> PyResult<PyIqResult> {
+    let atoms = atoms_from_py(&crystal)?;
+    let (q, intensity) =
+        py.allow_threads(|| install(|| core::dse_adaptive(atoms.positions(), &q_ranges)))?;
+    PyIqResult::new(q, intensity)
+}
+
+/// Calculate DSE intensities on a q grid refined around the Bragg peaks of a cubic lattice.
+///
+/// The peaks are expected at 2 pi sqrt(h^2 + k^2 + l^2) / lattice_param, the simple cubic
+/// positions, which include those of FCC and BCC. Windows of full width
+/// 4 pi / lattice_param * 0.1 around them are sampled 10 times more finely than the rest of
+/// [min_q, max_q], with the steps chosen so that the grid holds about target_n_points points.
+/// For the same number of evaluations this resolves the peaks much better than a uniform grid.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     min_q (float): Minimum q value in 1/nm, at least 0
+///     max_q (float): Maximum q value in 1/nm, included in the grid
+///     target_n_points (int): Number of q values to aim for, at least 2
+///     lattice_param (float): Cubic lattice parameter in nanometers that locates the peaks
+///
+/// Returns:
+///     IqResult: The strictly increasing q values and their intensities
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, the q range is
+///         not 0 <= min_q < max_q, lattice_param is not positive or target_n_points < 2
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> result = dse_auto_adaptive(positions, 5.0, 50.0, 500, 0.408)
+#[pyfunction]
+pub(super) fn dse_auto_adaptive(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    min_q: f64,
+    max_q: f64,
+    target_n_points: usize,
+    lattice_param: f64,
+) -> PyResult<PyIqResult> {
+    let atoms = atoms_from_py(&crystal)?;
+    let (q, intensity) = py.allow_threads(|| {
+        install(|| {
+            core::dse_auto_adaptive(
+                atoms.positions(),
+                min_q,
+                max_q,
+                target_n_points,
+                lattice_param,
+            )
+        })
+    })?;
+    PyIqResult::new(q, intensity)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values without a distance matrix.
+///
+/// Same result as `dse_optimized`, but the pair distances are recomputed at every q instead of
+/// being stored, so the memory stays proportional to the number of atoms. Use it for crystals
+/// whose 8 * n_atoms^2 byte distance matrix does not fit in RAM, see `estimate_memory_mb`.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     progress_callback (Callable[[int, int], object], optional): Called with
+///         (completed_q_points, total_q_points) after each chunk of q points
+///     progress_chunk_size (int, optional): Number of q points per chunk. Defaults to the
+///         number of Rayon threads; smaller chunks give finer updates but less parallelism
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, min_q > max_q or
+///         q_step is not positive
+///     Exception: The first exception raised by progress_callback, once the computation has
+///         finished; the callback is not called again after it raised
+///
+/// Note:
+///     The N self-pairs contribute exactly 1 each, so they are added analytically and only
+///     the pairs of distinct atoms are summed. The GIL is released during the computation and
+///     re-acquired for each progress callback, as in `dse_optimized`.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 10.0)
+///     >>> intensities = dse_ultra_optimized(0.1, 10.0, 0.1, positions)
+///     >>> intensities = dse_ultra_optimized(
+///     ...     0.1, 10.0, 0.1, positions, progress_callback=lambda done, total: print(done, total)
+///     ... )
+#[pyfunction]
+#[pyo3(signature = (min_q, max_q, q_step, crystal, progress_callback=None, progress_chunk_size=None))]
+pub(super) fn dse_ultra_optimized(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    progress_callback: Option<PyObject>,
+    progress_chunk_size: Option<usize>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    with_progress_callback(
+        py,
+        progress_callback,
+        progress_chunk_size,
+        |chunk_size, progress| {
+            install(|| {
+                core::dse_ultra_optimized_with_progress(
+                    min_q,
+                    max_q,
+                    q_step,
+                    atoms.positions(),
+                    chunk_size,
+                    progress,
+                )
+            })
+        },
+    )
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values in blocks, without
+/// storing distances.
+///
+/// Memory-vs-time trade-off: `dse_optimized` stores the 8 * n_atoms^2 byte distance matrix
+/// and reuses it at every q, while this function recomputes every pair distance at every q
+/// and keeps memory proportional to the number of atoms. Because evaluating sin dominates
+/// the pair term, the recomputation costs little; see `BENCHMARKS.md`. The double sum is
+/// split into tiles of chunk_size x chunk_size atoms that stay in cache while they are summed.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     chunk_size (int, optional): Atoms per block of the tiled pair loop. Defaults to 256
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, min_q > max_q,
+///         q_step is not positive or chunk_size is 0
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 10.0)
+///     >>> intensities = dse_no_alloc(0.1, 10.0, 0.1, positions, chunk_size=128)
+#[pyfunction]
+#[pyo3(signature = (min_q, max_q, q_step, crystal, chunk_size=core::DEFAULT_NO_ALLOC_CHUNK_SIZE))]
+pub(super) fn dse_no_alloc(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    chunk_size: usize,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_no_alloc(min_q, max_q, q_step, atoms.positions(), chunk_size))
+    })?)
+}
+
+/// Time every exact DSE implementation on the same input and check that they agree.
+///
+/// Helps choose a variant for a given crystal size and q grid: `dse_serial` (the `dse_optimized`
+/// sum evaluated one q after the other), `dse_optimized`, `dse_ultra_optimized`,
+/// `dse_symmetric`, `dse_no_alloc` with its default chunk size and `dse_simd` are each called
+/// n_repeats times. It doubles as a regression test: every intensity must agree with
+/// `dse_optimized` to 1e-9, relative to the larger of the value and the number of atoms.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     n_repeats (int, optional): Calls of each implementation to average over. Defaults to 1
+///
+/// Returns:
+///     dict[str, float]: Mean wall-clock seconds per call of each implementation, by name
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, min_q > max_q,
+///         q_step is not positive or n_repeats is 0
+///     RuntimeError: If an implementation disagrees with `dse_optimized`
+///
+/// Note:
+///     The GIL is released while timing, and the implementations run in the pool selected
+///     with `set_thread_count`.
+///
+/// Examples:
+///     >>> timings = benchmark_dse(1.0, 50.0, 1.0, crystal('fcc', 0.408, 3.0), n_repeats=3)
+///     >>> fastest = min(timings, key=timings.get)
+#[pyfunction]
+#[pyo3(signature = (min_q, max_q, q_step, crystal, n_repeats=1))]
+pub(super) fn benchmark_dse<'py>(
+    py: Python<'py>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    n_repeats: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    let timings = py.allow_threads(|| {
+        install(|| core::benchmark_dse(min_q, max_q, q_step, atoms.positions(), n_repeats))
+    })?;
+    let dict = PyDict::new(py);
+    for (name, seconds) in timings {
+        dict.set_item(name, seconds)?;
+    }
+    Ok(dict)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values over each pair once.
+///
+/// Every pair appears twice in the double sum of `dse_optimized`, so the intensity is
+/// I(q) = 2 * sum_{i<j} sin(q*r_ij)/(q*r_ij) + N. Only the N*(N-1)/2 distances with i < j
+/// are stored, half the memory of the full distance matrix, which makes it about twice as
+/// fast for the same result.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, min_q > max_q or
+///         q_step is not positive
+///
+/// Note:
+///     The GIL is released during the computation. See `BENCHMARKS.md` for timings against
+///     `dse_optimized`.
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> intensities = dse_symmetric(1.0, 50.0, 1.0, positions)
+#[pyfunction]
+pub(super) fn dse_symmetric(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py
+        .allow_threads(|| install(|| core::dse_symmetric(min_q, max_q, q_step, atoms.positions()))))
+}
+
+/// Calculate the derivative dI(q)/d(lattice_param) of the DSE intensity analytically.
+///
+/// For a crystal whose coordinates are proportional to the lattice parameter a, as for every
+/// generator of this module, each pair term contributes (cos(qr) - sin(qr)/(qr)) / a. The atom
+/// count is held fixed. Together with `dse_gradient_size` this gives the Jacobian for
+/// gradient-based fitting of the lattice parameter to measured data, computed in one pass over
+/// the pairs with the q values evaluated in parallel.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     lattice_param (float): Lattice parameter the crystal was generated with, in nanometers
+///
+/// Returns:
+///     list[float]: dI/d(lattice_param) at each q point from min_q to max_q, in 1/nm
+///
+/// Raises:
+///     ValueError: If lattice_param is not positive, the q range is invalid, or an atom does
+///         not have exactly three finite coordinates
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> grad = dse_gradient_lattice(1.0, 50.0, 0.1, positions, 0.408)
+#[pyfunction]
+pub(super) fn dse_gradient_lattice(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    lattice_param: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_gradient_lattice(min_q, max_q, q_step, atoms.positions(), lattice_param)
+        })
+    })?)
+}
+
+/// Calculate the derivative dI(q)/d(size) of the DSE intensity analytically.
+///
+/// Adding or removing surface atoms has no derivative, so the size L is varied as a continuous
+/// dilation of the particle at fixed atom count: each pair term contributes
+/// (cos(qr) - sin(qr)/(qr)) / L. This is the smooth envelope of the discrete size dependence,
+/// which is what gradient-based size fitting needs.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     size (float): Size the crystal was generated with, in nanometers
+///
+/// Returns:
+///     list[float]: dI/d(size) at each q point from min_q to max_q, in 1/nm
+///
+/// Raises:
+///     ValueError: If size is not positive, the q range is invalid, or an atom does not have
+///         exactly three finite coordinates
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 6.0)
+///     >>> grad = dse_gradient_size(0.1, 5.0, 0.05, positions, 6.0)
+#[pyfunction]
+pub(super) fn dse_gradient_size(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    size: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_gradient_size(min_q, max_q, q_step, atoms.positions(), size))
+    })?)
+}
+
+/// Orders of the nearest-neighbour spacing reached by the default `max_q` of `dse_from_file`.
+const AUTO_Q_OVERSAMPLING: f64 = 2.0;
+
+/// Number of q points between min_q and max_q given by the default `q_step` of `dse_from_file`.
+const AUTO_Q_POINTS: usize = 500;
+
+/// Load atom positions from a structure file and calculate its DSE intensities in one call.
+///
+/// The format is detected from the file extension (.xyz, .cif or .csv, case-insensitive)
+/// unless given explicitly. The positions are read as by `crystal_from_xyz`,
+/// `crystal_from_cif` or `crystal_from_csv` and the intensities computed as by
+/// `dse_optimized_with_q`, with the GIL released.
+///
+/// Any of min_q, max_q and q_step that is not given is chosen from the crystal: min_q defaults
+/// to `min_q_from_crystal_size(positions)`, and max_q to `max_q_for_resolution(d_nn, 2.0)` for
+/// the nearest-neighbour distance d_nn, which reaches twice the q of the nearest-neighbour
+/// spacing. q_step defaults to (max_q - min_q) / 499, about 500 points whatever the size of
+/// the crystal, so it needs min_q < max_q.
+///
+/// Args:
+///     path (str): Path of the structure file
+///     min_q (float, optional): Minimum q value (scattering vector magnitude)
+///     max_q (float, optional): Maximum q value (scattering vector magnitude)
+///     q_step (float, optional): Step size between q values
+///     format (str, optional): One of 'xyz', 'cif' or 'csv', overriding the extension
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q_values, intensities)
+///
+/// Raises:
+///     OSError: If the file cannot be read
+///     ValueError: If the format is unknown, the file cannot be parsed, a coordinate is not
+///         finite, the q range is invalid, or a q default is needed but the file has fewer than
+///         two distinct positions
+///
+/// Examples:
+///     >>> q, I = dse_from_file('nanoparticle.xyz', 0.1, 10.0, 0.01)
+///     >>> q, I = dse_from_file('positions.txt', 0.1, 10.0, 0.01, format='csv')
+///     >>> q, I = dse_from_file('nanoparticle.xyz')
+#[pyfunction]
+#[pyo3(signature = (path, min_q=None, max_q=None, q_step=None, format=None))]
+pub(super) fn dse_from_file(
+    py: Python<'_>,
+    path: &str,
+    min_q: Option<f64>,
+    max_q: Option<f64>,
+    q_step: Option<f64>,
+    format: Option<&str>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let format = match format {
+        Some(format) => format.to_ascii_lowercase(),
+        None => std::path::Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase(),
+    };
+    let parse = match format.as_str() {
+        "xyz" => core::crystal_from_xyz,
+        "cif" => core::crystal_from_cif,
+        "csv" => core::crystal_from_csv,
+        _ => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "cannot detect the format of '{}': expected 'xyz', 'cif' or 'csv', got '{}'",
+                path, format
+            )))
+        }
+    };
+    let content = std::fs::read_to_string(path)?;
+    let atoms = parse(&content)?;
+    check_finite_positions(&atoms)?;
+    let distances =
+        py.allow_threads(|| install(|| core::DistanceMatrix::from_atoms(atoms.positions())));
+    let min_q = min_q.map_or_else(|| core::min_q_from_crystal_size(atoms.positions()), Ok)?;
+    let max_q = match max_q {
+        Some(max_q) => max_q,
+        None => {
+            let nearest_sq = distances
+                .as_slice()
+                .iter()
+                .copied()
+                .filter(|&d| d > 0.0)
+                .fold(f64::INFINITY, f64::min);
+            if nearest_sq.is_infinite() {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "max_q cannot be chosen for fewer than two distinct positions",
+                ));
+            }
+            core::max_q_for_resolution(nearest_sq.sqrt(), AUTO_Q_OVERSAMPLING)?
+        }
+    };
+    let q_step = q_step.unwrap_or((max_q - min_q) / (AUTO_Q_POINTS - 1) as f64);
+    check_q_range(min_q, max_q, q_step)?;
+    let q_values = core::q_grid(min_q, max_q, q_step);
+    let intensity =
+        py.allow_threads(|| install(|| core::dse_from_distances(min_q, max_q, q_step, &distances)));
+    Ok((q_values, intensity))
+}
+
+/// Minimum q that resolves the Guinier region of a crystal, `1 / (5 R_g)`.
+///
+/// Choosing min_q too large cuts off the Guinier region. This returns the q at which
+/// `q * R_g = 0.2`, with the radius of gyration `R_g = sqrt(mean(|r|^2) - |mean(r)|^2)` of the
+/// atom positions, a safe lower end of the q range.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     float: Minimum q in 1/nm
+///
+/// Raises:
+///     ValueError: If there are no atoms or they all coincide
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> min_q = min_q_from_crystal_size(positions)
+#[pyfunction]
+pub(super) fn min_q_from_crystal_size(positions: Vec<Vec<f64>>) -> PyResult<f64> {
+    let atoms = atoms_from_py(&positions)?;
+    Ok(core::min_q_from_crystal_size(atoms.positions())?)
+}
+
+/// Maximum q that reaches `oversampling` orders of a lattice spacing, `2 pi oversampling / a`.
+///
+/// Args:
+///     lattice_param (float): Lattice spacing a in nanometers
+///     oversampling (float): Orders of the spacing to reach, e.g. 2.0 for the q of a / 2
+///
+/// Returns:
+///     float: Maximum q in 1/nm
+///
+/// Raises:
+///     ValueError: If lattice_param or oversampling is not positive
+///
+/// Examples:
+///     >>> max_q = max_q_for_resolution(0.408, 3.0)
+#[pyfunction]
+pub(super) fn max_q_for_resolution(lattice_param: f64, oversampling: f64) -> PyResult<f64> {
+    Ok(core::max_q_for_resolution(lattice_param, oversampling)?)
+}
+
+/// Calculate DSE intensities averaged over a log-normal distribution of crystal sizes.
+///
+/// Real nanoparticle samples are polydisperse. The size L is log-normally distributed, with
+/// ln L normal with standard deviation sigma_log and mean_size the mean of L. The distribution
+/// is sampled at n_sizes Gauss-Hermite quadrature nodes; a crystal of each size is built as by
+/// `crystal(shape, lattice_param, L)` and its intensities are averaged with volume weights
+/// p(L) * L^3, normalised to sum to one. The sizes are processed in parallel with the GIL
+/// released.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     shape (str): Shape as for `crystal`; 'ellipsoid' and 'icosahedron' are not supported
+///     lattice_param (float): Lattice parameter in nanometers
+///     mean_size (float): Mean crystal size in nanometers
+///     sigma_log (float): Standard deviation of ln L; 0 gives a monodisperse sample
+///     n_sizes (int): Number of quadrature nodes; 5-10 are usually enough for sigma_log < 0.3
+///     c_over_a (float, optional): c/a ratio for 'hcp', as for `crystal`
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     list[float]: Polydisperse-averaged intensities on the q grid
+///
+/// Raises:
+///     ValueError: If the shape is unknown or not sized by length, mean_size is not positive,
+///         sigma_log is negative or n_sizes is 0
+///
+/// Examples:
+///     >>> intensities = dse_size_averaged(0.1, 10.0, 0.05, 'sphere', 0.4, 5.0, 0.2, 8)
+#[pyfunction]
+#[pyo3(signature = (
+    min_q, max_q, q_step, shape, lattice_param, mean_size, sigma_log, n_sizes,
+    c_over_a=None, aspect_ratio=None
+))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn dse_size_averaged(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    shape: &str,
+    lattice_param: f64,
+    mean_size: f64,
+    sigma_log: f64,
+    n_sizes: usize,
+    c_over_a: Option<f64>,
+    aspect_ratio: Option<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let shape = parse_shape(shape, mean_size, c_over_a, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_size_averaged(
+                min_q,
+                max_q,
+                q_step,
+                shape,
+                lattice_param,
+                mean_size,
+                sigma_log,
+                n_sizes,
+            )
+        })
+    })?)
+}
+
+/// Map chi-squared over a grid of lattice parameters and crystal sizes.
+///
+/// For every (lattice_param, size) pair a crystal is built as by
+/// `crystal(shape, lattice_param, size)` and compared with the data through
+/// chi^2 = sum_q (I_obs - I_calc)^2 / sigma^2. No scale factor is fitted, so i_obs must be on
+/// the scale of `dse_optimized`, where I tends to N^2 as q -> 0; `chi_squared` compares one
+/// model with an optimal scale. The lattice parameters are processed in parallel with the GIL
+/// released.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     shape (str): Shape as for `crystal`; 'ellipsoid' and 'icosahedron' are not supported
+///     lattice_params (list[float]): Lattice parameters to scan, in nanometers
+///     sizes (list[float]): Crystal sizes to scan, in nanometers
+///     i_obs (list[float]): Observed intensities on the q grid of min_q, max_q and q_step
+///     sigma_obs (list[float]): Standard uncertainties of i_obs
+///     c_over_a (float, optional): c/a ratio for 'hcp', as for `crystal`
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     list[list[float]]: chi^2 indexed [i_lattice_param][i_size], ready to plot as a heatmap
+///
+/// Raises:
+///     ValueError: If the shape is unknown or not sized by length, a lattice parameter, size
+///         or sigma is not positive, or i_obs or sigma_obs does not match the q grid
+///
+/// Examples:
+///     >>> lattice_params = [0.38 + 0.005 * i for i in range(9)]
+///     >>> sizes = [3.0 + 0.25 * i for i in range(9)]
+///     >>> chi2 = chi_squared_map(0.1, 10.0, 0.05, 'sphere', lattice_params, sizes, i_obs, sigma)
+///     >>> best = min((c, i, j) for i, row in enumerate(chi2) for j, c in enumerate(row))
+#[pyfunction]
+#[pyo3(signature = (
+    min_q, max_q, q_step, shape, lattice_params, sizes, i_obs, sigma_obs,
+    c_over_a=None, aspect_ratio=None
+))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn chi_squared_map(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    shape: &str,
+    lattice_params: Vec<f64>,
+    sizes: Vec<f64>,
+    i_obs: Vec<f64>,
+    sigma_obs: Vec<f64>,
+    c_over_a: Option<f64>,
+    aspect_ratio: Option<f64>,
+) -> PyResult<Vec<Vec<f64>>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let length = sizes.first().copied().unwrap_or(0.0);
+    let shape = parse_shape(shape, length, c_over_a, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::chi_squared_map(
+                min_q,
+                max_q,
+                q_step,
+                shape,
+                &lattice_params,
+                &sizes,
+                &i_obs,
+                &sigma_obs,
+            )
+        })
+    })?)
+}
+
+/// Relative deviation of the DSE of a crystal from the form factor of the continuous particle.
+///
+/// Returns |I(q) / N^2 - P(q)| / P(q) at each q, with I the `dse_optimized` intensities of the
+/// N atoms and P the analytic form factor of the particle of the given shape and radius:
+/// `form_factor_sphere` for 'sphere' and `form_factor_cylinder` of height
+/// 2 * radius_nm * aspect_ratio for 'cylinder' and 'disk'. The deviation measures the
+/// discreteness of the lattice.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): Atom positions in nanometers
+///     shape (str): 'sphere', 'cylinder' or 'disk'
+///     radius_nm (float): Radius of the continuous particle in nanometers
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     list[float]: Relative deviation at each q point, on the same grid as `dse_optimized`
+///
+/// Raises:
+///     ValueError: If the crystal is empty, the shape has no analytic form factor or
+///         radius_nm is not positive
+///
+/// Note:
+///     The deviation diverges at the zeros of P, the first at q * R ~ 4.49 for a sphere, and
+///     grows with the Bragg peaks at high q; it is meaningful below the first minimum.
+///
+/// Examples:
+///     >>> errors = dse_finite_size_error(0.1, 1.4, 0.05, crystal('sphere', 0.3, 6.0), 'sphere', 3.0)
+#[pyfunction]
+#[pyo3(signature = (min_q, max_q, q_step, crystal, shape, radius_nm, aspect_ratio=None))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn dse_finite_size_error(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    shape: &str,
+    radius_nm: f64,
+    aspect_ratio: Option<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    let shape = parse_shape(shape, 2.0 * radius_nm, None, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_finite_size_error(min_q, max_q, q_step, atoms.positions(), shape, radius_nm)
+        })
+    })?)
+}
+
+/// Largest finite-size error of a crystal for each of several lattice parameters.
+///
+/// For each lattice parameter a crystal of N atoms is built as by
+/// `crystal(shape, lattice_param, size)` and compared by `dse_finite_size_error` with the
+/// particle of its own volume N * lattice_param^3, so that the atoms missing at the rim do not
+/// count as error; the largest deviation over the q grid is returned. It falls, not quite
+/// monotonically, as the lattice gets finer relative to the particle, which shows how many
+/// atoms a DSE needs to reproduce the continuous form factor. The lattice parameters are
+/// processed in parallel with the GIL released.
+///
+/// Args:
+///     shape (str): 'sphere', 'cylinder' or 'disk'
+///     lattice_params (list[float]): Lattice parameters to compare, in nanometers
+///     size (float): Crystal size in nanometers, as for `crystal`
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     list[float]: Largest relative deviation for each lattice parameter
+///
+/// Raises:
+///     ValueError: If the shape has no analytic form factor, size or a lattice parameter is
+///         not positive, or a crystal has no atoms
+///
+/// Examples:
+///     >>> lattice_params = [0.6, 0.5, 0.4, 0.3]
+///     >>> errors = convergence_with_size('sphere', lattice_params, 6.0, 0.1, 1.4, 0.05)
+///     >>> plt.semilogy(lattice_params, errors)
+#[pyfunction]
+#[pyo3(signature = (shape, lattice_params, size, min_q, max_q, q_step, aspect_ratio=None))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn convergence_with_size(
+    py: Python<'_>,
+    shape: &str,
+    lattice_params: Vec<f64>,
+    size: f64,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    aspect_ratio: Option<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let shape = parse_shape(shape, size, None, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::convergence_with_size(shape, &lattice_params, size, min_q, max_q, q_step))
+    })?)
+}
+
+/// R-factor between the DSE curves of consecutive crystal sizes, for a convergence plot.
+///
+/// A crystal is built as by `crystal(shape, lattice_param, size)` for each size, and the
+/// curve of each size is compared with that of the next by `r_factor`, the larger as observed.
+/// The optimal scale factor of the R-factor absorbs the N^2 growth of the intensity, so only
+/// the shapes of the curves are compared; the R-factor falls roughly as the relative change of
+/// size. The sizes are processed in parallel with the GIL released.
+///
+/// Args:
+///     shape (str): Crystal shape, as for `crystal`, other than 'ellipsoid' and 'icosahedron'
+///     lattice_param (float): Lattice parameter in nanometers
+///     sizes (list[float]): Strictly increasing crystal sizes in nanometers, at least two
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     list[float]: R-factor between sizes[i] and sizes[i + 1], len(sizes) - 1 values
+///
+/// Raises:
+///     ValueError: If the shape is not sized by length, lattice_param is not positive, the
+///         sizes are fewer than two, not positive or not strictly increasing, a crystal has
+///         no atoms, or two consecutive sizes give the same crystal, whose R-factor of 0 says
+///         nothing about convergence
+///
+/// Examples:
+///     >>> sizes = [1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0]
+///     >>> r = convergence_plot_data('fcc', 0.408, sizes, 1.0, 40.0, 0.1)
+///     >>> plt.semilogy(sizes[:-1], r)
+#[pyfunction]
+#[pyo3(signature = (shape, lattice_param, sizes, min_q, max_q, q_step, aspect_ratio=None))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn convergence_plot_data(
+    py: Python<'_>,
+    shape: &str,
+    lattice_param: f64,
+    sizes: Vec<f64>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    aspect_ratio: Option<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let shape = parse_shape(shape, 0.0, None, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::convergence_plot_data(shape, lattice_param, &sizes, min_q, max_q, q_step))
+    })?)
+}
+
+/// Smallest crystal size from which a larger crystal no longer changes the DSE curve.
+///
+/// Returns the first size whose `convergence_plot_data` R-factor against the next size is
+/// below tolerance, the minimum model size that gives a converged scattering pattern over
+/// the q range. Consecutive sizes must differ by about a lattice parameter, so that they give
+/// different crystals; their spacing sets the resolution of the answer.
+///
+/// Args:
+///     shape (str): Crystal shape, as for `crystal`, other than 'ellipsoid' and 'icosahedron'
+///     lattice_param (float): Lattice parameter in nanometers
+///     sizes (list[float]): Strictly increasing crystal sizes in nanometers, at least two
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     tolerance (float): R-factor below which two consecutive curves count as converged
+///     aspect_ratio (float, optional): Height divided by diameter for 'cylinder' and 'disk'
+///
+/// Returns:
+///     float: The smallest converged size in nanometers
+///
+/// Raises:
+///     ValueError: As for `convergence_plot_data`, if tolerance is not positive, or if no
+///         pair of consecutive sizes converges. The message gives the R-factor of the last pair
+///
+/// Examples:
+///     >>> sizes = [1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0]
+///     >>> size = convergence_test('fcc', 0.408, sizes, 1.0, 40.0, 0.1, 0.15)
+#[pyfunction]
+#[pyo3(signature = (shape, lattice_param, sizes, min_q, max_q, q_step, tolerance, aspect_ratio=None))]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn convergence_test(
+    py: Python<'_>,
+    shape: &str,
+    lattice_param: f64,
+    sizes: Vec<f64>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    tolerance: f64,
+    aspect_ratio: Option<f64>,
+) -> PyResult<f64> {
+    check_q_range(min_q, max_q, q_step)?;
+    let shape = parse_shape(shape, 0.0, None, aspect_ratio, None)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::convergence_test(
+                shape,
+                lattice_param,
+                &sizes,
+                min_q,
+                max_q,
+                q_step,
+                tolerance,
+            )
+        })
+    })?)
+}
+
+/// Calculate I(q) / (N^2 * P_sphere(q)), the DSE relative to a sphere of the same volume.
+///
+/// P_sphere is `form_factor_sphere` for the radius R = (3V / (4 pi))^(1/3) of the crystal
+/// volume V = N * lattice_param^3, that of a simple cubic crystal. Without lattice_param the
+/// smallest interatomic distance is used. A spherical crystal stays close to 1 below the first
+/// zero of P_sphere at q * R ~ 4.49; other shapes depart from 1 at lower q, which makes this a
+/// shape diagnostic much like a normalised Kratky plot. The ratio diverges at the zeros of
+/// P_sphere.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     lattice_param (float, optional): Lattice parameter in nanometers that sets the volume
+///         per atom, lattice_param^3
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (q_values, ratio) of the same length
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates, the crystal is
+///         empty, lattice_param is not positive, or it is missing and there are fewer than two
+///         distinct positions
+///
+/// Examples:
+///     >>> q, ratio = dse_normalized_by_sphere(0.1, 2.0, 0.05, crystal('cube', 0.4, 6.0))
+///     >>> plt.plot(q, ratio)  # falls below 1 well before the sphere's first minimum
+#[pyfunction]
+#[pyo3(signature = (min_q, max_q, q_step, crystal, lattice_param=None))]
+pub(super) fn dse_normalized_by_sphere(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    lattice_param: Option<f64>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_normalized_by_sphere(min_q, max_q, q_step, atoms.positions(), lattice_param)
+        })
+    })?)
+}
+
+/// Calculate the structure factor S(q) = I(q) / N of a crystal.
+///
+/// Separating the self-scattering terms i == j of the Debye sum gives
+/// S(q) = 1 + (1/N) * sum_{i != j} sin(q*r_ij)/(q*r_ij), which tends to 1 at high q.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: S(q) at each q point, on the same grid as `dse_optimized`
+///
+/// Raises:
+///     ValueError: If the crystal is empty, the q range is invalid, or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> s_q = structure_factor(0.1, 60.0, 0.1, crystal('fcc', 0.408, 4.0))
+#[pyfunction]
+pub(super) fn structure_factor(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::structure_factor(min_q, max_q, q_step, atoms.positions()))
+    })?)
+}
+
+/// Calculate the distinct structure factor S(q) - 1 of a crystal.
+///
+/// The pair terms (1/N) * sum_{i != j} sin(q*r_ij)/(q*r_ij) of `structure_factor`, without the
+/// self-scattering contribution of 1, so the result oscillates around 0 at high q.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: S(q) - 1 at each q point
+///
+/// Raises:
+///     ValueError: As for `structure_factor`
+#[pyfunction]
+pub(super) fn distinct_structure_factor(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::distinct_structure_factor(min_q, max_q, q_step, atoms.positions()))
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values for many crystals.
+///
+/// The crystals are processed in parallel, each one serially on a single thread, which avoids
+/// nested parallelism when screening many small candidate structures. The GIL is released
+/// during the computation.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystals (list[list[list[float]]]): Atom positions of each crystal
+///
+/// Returns:
+///     list[list[float]]: Intensities indexed [crystal_index][q_index]
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> candidates = [crystal('fcc', a, 3.0) for a in (0.40, 0.405, 0.41)]
+///     >>> intensities = dse_batch(1.0, 30.0, 0.1, candidates)
+#[pyfunction]
+pub(super) fn dse_batch(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystals: Vec<Vec<Vec<f64>>>,
+) -> PyResult<Vec<Vec<f64>>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let crystals: Vec<core::Atoms> = crystals
+        .iter()
+        .map(|c| atoms_from_py(c))
+        .collect::<PyResult<_>>()?;
+    Ok(py.allow_threads(|| install(|| core::dse_batch(min_q, max_q, q_step, &crystals))))
+}
+
+/// Average the DSE intensity over the frames of a molecular dynamics trajectory.
+///
+/// Computes `dse_optimized` for every frame, in parallel over the frames with the GIL
+/// released, and returns the mean and standard deviation at each q point. The mean is the
+/// thermal-equilibrium scattering pattern; the standard deviation shows how well it is
+/// converged. All frames are held in memory; use `dse_trajectory_average_lammps` to stream a
+/// long trajectory from a file.
+///
+/// Args:
+///     frames (list[list[list[float]]]): Atom positions of each frame in nanometers; frames may
+///         have different atom counts
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (mean_I, std_I) at each q point from min_q to max_q,
+///         with the population standard deviation over the frames
+///
+/// Raises:
+///     ValueError: If there are no frames, the q range is invalid, or an atom does not have
+///         exactly three finite coordinates
+///
+/// Examples:
+///     >>> ideal = crystal('fcc', 0.408, 3.0)
+///     >>> frames = [apply_thermal_disorder(ideal, 0.01, seed) for seed in range(20)]
+///     >>> mean_I, std_I = dse_trajectory_average(frames, 1.0, 50.0, 0.1)
+#[pyfunction]
+pub(super) fn dse_trajectory_average(
+    py: Python<'_>,
+    frames: Vec<Vec<Vec<f64>>>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    check_q_range(min_q, max_q, q_step)?;
+    let frames: Vec<core::Atoms> = frames
+        .iter()
+        .map(|frame| atoms_from_py(frame))
+        .collect::<PyResult<_>>()?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_trajectory_average(min_q, max_q, q_step, &frames))
+    })?)
+}
+
+/// Average the DSE intensity over the frames of a LAMMPS dump file, reading one frame at a time.
+///
+/// The file is parsed lazily, so the trajectory does not have to fit in memory: each frame is
+/// read, its intensity computed in parallel over q, and folded into running means and
+/// variances. Text dumps written by `dump atom` or `dump custom` are supported, with the
+/// coordinates taken from the xu yu zu, x y z, xs ys zs or xsu ysu zsu columns (the first
+/// available in that order). The GIL is released while the file is processed.
+///
+/// Args:
+///     path (str): Path of the LAMMPS dump file
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///
+/// Returns:
+///     tuple[list[float], list[float]]: (mean_I, std_I) at each q point from min_q to max_q,
+///         with the population standard deviation over the frames
+///
+/// Raises:
+///     OSError: If the file cannot be opened
+///     ValueError: If the file has no frames or a frame cannot be parsed (the message gives the
+///         line number), or the q range is invalid
+///
+/// Note:
+///     Lengths are read as Å, the unit of the LAMMPS `metal` and `real` styles, and converted
+///     to nanometers. Scaled coordinates are converted with the box bounds and, for
+///     triclinic boxes, the xy, xz and yz tilt factors.
+///
+/// Examples:
+///     >>> mean_I, std_I = dse_trajectory_average_lammps('dump.lammpstrj', 1.0, 50.0, 0.1)
+#[pyfunction]
+pub(super) fn dse_trajectory_average_lammps(
+    py: Python<'_>,
+    path: &str,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    check_q_range(min_q, max_q, q_step)?;
+    let file = std::fs::File::open(path)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            let frames = core::LammpsDumpFrames::new(std::io::BufReader::new(file));
+            core::dse_trajectory_average_streaming(min_q, max_q, q_step, frames)
+        })
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values of one crystal on several q grids.
+///
+/// The distance matrix is built once and the grids are processed in parallel, each one
+/// serially on a single thread. The GIL is released during the computation.
+///
+/// Args:
+///     param_list (list[tuple[float, float, float]]): (min_q, max_q, q_step) of each grid
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[list[float]]: Intensities indexed [grid_index][q_index]
+///
+/// Raises:
+///     ValueError: If a q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 5.0)
+///     >>> low, high = dse_batch_with_params([(0.1, 2.0, 0.01), (10.0, 40.0, 0.1)], positions)
+#[pyfunction]
+pub(super) fn dse_batch_with_params(
+    py: Python<'_>,
+    param_list: Vec<(f64, f64, f64)>,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<Vec<f64>>> {
+    for &(min_q, max_q, q_step) in &param_list {
+        check_q_range(min_q, max_q, q_step)?;
+    }
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(
+        py.allow_threads(|| {
+            install(|| core::dse_batch_with_params(&param_list, atoms.positions()))
+        }),
+    )
+}
+
+/// Average the single-crystal scattering intensity over random orientations.
+///
+/// For each of n_orientations rotations drawn uniformly from SO(3), the intensity
+/// |sum_j exp(i q n . r_j)|^2 along a fixed scattering direction n is computed, and the
+/// results are averaged. The Debye equation is the exact orientation average, so this
+/// converges to `dse_optimized` with a statistical error of order 1 / sqrt(n_orientations).
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     n_orientations (int): Number of random orientations
+///     seed (int): Seed of the random number generator
+///
+/// Returns:
+///     list[float]: Orientation-averaged intensity at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     Each orientation costs O(N * N_q) rather than the O(N^2 * N_q) of the Debye sum, so
+///     a few hundred orientations can be cheaper than dse_optimized for very large crystals.
+///     With few orientations the result shows the anisotropy of single-particle patterns.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 6.0)
+///     >>> averaged = dse_orientation_averaged(0.1, 10.0, 0.1, positions, 500, seed=0)
+#[pyfunction]
+pub(super) fn dse_orientation_averaged(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    n_orientations: usize,
+    seed: u64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_orientation_averaged(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                n_orientations,
+                seed,
+            )
+        })
+    }))
+}
+
+/// Average the single-crystal scattering intensity over a Fibonacci spiral of directions.
+///
+/// Deterministic counterpart of `dse_orientation_averaged`: |sum_j exp(i q n . r_j)|^2 is
+/// averaged over n_orientations directions n on a Fibonacci spiral, which covers the sphere
+/// evenly, so the result converges to `dse_optimized` much faster than random sampling. It
+/// cross-validates the Debye sum and connects oriented and powder scattering.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     n_orientations (int): Number of directions on the spiral
+///
+/// Returns:
+///     list[float]: Orientation-averaged intensity at each q point from min_q to max_q
+///
+/// Note:
+///     For a 125-atom cube up to q = 10 1/nm the largest relative deviation from
+///     dse_optimized is about 3e-2 with 100 directions and 5e-6 with 10000.
+///
+/// Examples:
+///     >>> positions = crystal('cube', 0.4, 2.0)
+///     >>> explicit = dse_powder_average_explicit(0.5, 10.0, 0.5, positions, 10000)
+///     >>> debye = dse_optimized(0.5, 10.0, 0.5, positions).intensity
+#[pyfunction]
+pub(super) fn dse_powder_average_explicit(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    n_orientations: usize,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_powder_average_explicit(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                n_orientations,
+            )
+        })
+    }))
+}
+
+/// Calculate the 3D single-crystal scattering pattern I(q_x, q_y, q_z) of an oriented crystal.
+///
+/// Computes I(q) = |sum_j exp(i q . r_j)|^2 for every q vector of the grid spanned by the three
+/// ranges, without orientation averaging, e.g. for oriented single-crystal or fibre diffraction.
+/// The q_x slices are processed in parallel with the GIL released.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     q_x_range (tuple[float, float, float]): (min_q, max_q, q_step) along q_x in 1/nm
+///     q_y_range (tuple[float, float, float]): (min_q, max_q, q_step) along q_y in 1/nm
+///     q_z_range (tuple[float, float, float]): (min_q, max_q, q_step) along q_z in 1/nm
+///
+/// Returns:
+///     list[list[list[float]]]: Intensities indexed [ix][iy][iz], each axis on the same grid as
+///         `dse_optimized_with_q` would use for that range
+///
+/// Raises:
+///     ValueError: If a range has min_q > max_q or a non-positive q_step, or an atom does not
+///         have exactly three finite coordinates
+///
+/// Note:
+///     The cost is O(N * N_qx * N_qy * N_qz), so keep the grid coarse for large crystals.
+///     Convert the nested lists with numpy.asarray(result), which gives an array of shape
+///     (N_qx, N_qy, N_qz).
+///
+/// Examples:
+///     >>> positions = crystal('cube', 0.4, 4.0)
+///     >>> pattern = numpy.asarray(dse_3d(positions, (-20, 20, 0.5), (-20, 20, 0.5), (0, 0, 1)))
+#[pyfunction]
+pub(super) fn dse_3d(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    q_x_range: (f64, f64, f64),
+    q_y_range: (f64, f64, f64),
+    q_z_range: (f64, f64, f64),
+) -> PyResult<Vec<Vec<Vec<f64>>>> {
+    for (min_q, max_q, q_step) in [q_x_range, q_y_range, q_z_range] {
+        check_q_range(min_q, max_q, q_step)?;
+    }
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_3d(atoms.positions(), q_x_range, q_y_range, q_z_range))
+    }))
+}
+
+/// Calculate the complex scattering amplitude A(q) = sum_j exp(i q . r_j) at one q vector.
+///
+/// Coherent scattering experiments such as coherent X-ray diffractive imaging need the phase
+/// as well as the intensity |A(q)|^2 that `dse_3d` returns. All atoms scatter with unit
+/// amplitude and the phase is relative to the origin of the coordinates, so translating the
+/// crystal changes the phase but not |A|.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     q_x (float): x component of the scattering vector in 1/nm
+///     q_y (float): y component of the scattering vector in 1/nm
+///     q_z (float): z component of the scattering vector in 1/nm
+///
+/// Returns:
+///     tuple[float, float]: (Re A, Im A); complex(*result) gives a Python complex number
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates
+///
+/// Examples:
+///     >>> positions = center_crystal(crystal('cube', 0.4, 4.0))
+///     >>> re, im = structure_amplitude(positions, 0.0, 0.0, 0.0)
+///     >>> re == len(positions)
+///     True
+#[pyfunction]
+pub(super) fn structure_amplitude(
+    crystal: Vec<Vec<f64>>,
+    q_x: f64,
+    q_y: f64,
+    q_z: f64,
+) -> PyResult<(f64, f64)> {
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(core::structure_amplitude(
+        atoms.positions(),
+        [q_x, q_y, q_z],
+    ))
+}
+
+/// Calculate the complex scattering amplitude A(q) at each of a list of q vectors.
+///
+/// Same as calling `structure_amplitude` for every q vector, with the q vectors evaluated in
+/// parallel and the GIL released.
+///
+/// Args:
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     q_vectors (list[tuple[float, float, float]]): Scattering vectors (q_x, q_y, q_z) in 1/nm
+///
+/// Returns:
+///     list[tuple[float, float]]: (Re A, Im A) for each q vector
+///
+/// Raises:
+///     ValueError: If an atom does not have exactly three finite coordinates
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> path = [(0.0, 0.0, 0.1 * k) for k in range(200)]
+///     >>> amplitudes = [complex(re, im) for re, im in structure_amplitude_batch(positions, path)]
+#[pyfunction]
+pub(super) fn structure_amplitude_batch(
+    py: Python<'_>,
+    crystal: Vec<Vec<f64>>,
+    q_vectors: Vec<(f64, f64, f64)>,
+) -> PyResult<Vec<(f64, f64)>> {
+    let atoms = atoms_from_py(&crystal)?;
+    let q_vectors: Vec<[f64; 3]> = q_vectors.into_iter().map(|(x, y, z)| [x, y, z]).collect();
+    Ok(py.allow_threads(|| {
+        install(|| core::structure_amplitude_batch(atoms.positions(), &q_vectors))
+    }))
+}
+
+/// Calculate the bulk Debye scattering of a periodic cell with the minimum image convention.
+///
+/// Every pair of atoms of the cell is taken at its nearest periodic image, wrapping each
+/// separation component with dx - L * round(dx / L). This models the scattering per cell of
+/// the infinitely repeated bulk crystal, such as a molecular dynamics box, and is distinct
+/// from `dse_optimized`, which treats the atoms as one finite particle with a surface.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (PeriodicCrystal): The periodic cell, see `periodic_crystal`
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If min_q > max_q, q_step is not positive or a q value is not finite
+///
+/// Note:
+///     Pair correlations are cut off at half a box length, so the curve is reliable only for q
+///     well above 2*pi / L. Towards q = 0 it still rises to N^2; this is an artefact of the
+///     truncation, not forward scattering. Use a box several times larger than the
+///     correlation length of interest.
+///
+/// Examples:
+///     >>> cell = periodic_crystal(crystal('fcc', 0.408, 4.08), (4.08, 4.08, 4.08))
+///     >>> intensities = dse_periodic(5.0, 50.0, 0.05, cell)
+#[pyfunction]
+pub(super) fn dse_periodic(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: PyRef<'_, PyPeriodicCrystal>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let crystal = &crystal.inner;
+    Ok(py.allow_threads(|| install(|| core::dse_periodic(min_q, max_q, q_step, crystal))))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values four pairs at a time.
+///
+/// Same result as `dse_optimized` (to about 1e-10 relative), but each unique pair is visited
+/// once and sin(x)/x is evaluated with a polynomial on four SIMD lanes. Builds with AVX2
+/// enabled (e.g. RUSTFLAGS="-C target-cpu=native") use explicit AVX2 instructions; other builds
+/// use a portable version of the same code.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     On one core, a 2916-atom FCC crystal and 50 q points took 12.6 s with dse_optimized,
+///     4.0 s with the portable build and 0.70 s with the AVX2 build.
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> intensities = dse_simd(1.0, 50.0, 1.0, positions)
+#[pyfunction]
+pub(super) fn dse_simd(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| install(|| core::dse_simd(min_q, max_q, q_step, atoms.positions()))))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values on the GPU.
+///
+/// Meant for large crystals (tens of thousands of atoms), where the CPU versions are too slow
+/// for interactive use. A WGPU compute shader evaluates sin(q r_ij) / (q r_ij) on a 2D grid
+/// of (atom, q) invocations, computing the distances on the fly instead of storing a distance
+/// matrix; the per-atom sums are added in double precision on the CPU. Only available when
+/// fast_dse is built with the `gpu` feature. The GIL is released during the computation.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     RuntimeError: If fast_dse was built without the `gpu` feature, or no GPU adapter is
+///         available
+///     ValueError: If the q range is invalid
+///
+/// Note:
+///     The kernel runs in single precision, so the accuracy is that of `dse_optimized_f32`.
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 12.0)
+///     >>> intensities = dse_gpu(1.0, 50.0, 0.05, positions)
+#[pyfunction]
+pub(super) fn dse_gpu(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    #[cfg(feature = "gpu")]
+    let intensities =
+        Ok(py.allow_threads(|| core::dse_gpu(min_q, max_q, q_step, atoms.positions()))?);
+    #[cfg(not(feature = "gpu"))]
+    let intensities = {
+        let _ = (py, atoms);
+        Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+            "GPU support not compiled in",
+        ))
+    };
+    intensities
+}
+
+/// Precomputed squared pairwise distances of a crystal.
+///
+/// Building the distance matrix is the O(N^2) setup cost of every DSE call. Construct it
+/// once and pass it to `dse_from_distances` to evaluate several q ranges of the same crystal,
+/// e.g. during iterative fitting.
+///
+/// Args:
+///     positions (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Examples:
+///     >>> dm = DistanceMatrix(crystal('sphere', 1.0, 10.0))
+///     >>> low_q = dse_from_distances(0.1, 5.0, 0.01, dm)
+///     >>> high_q = dse_from_distances(5.0, 20.0, 0.01, dm)
+#[pyclass(name = "DistanceMatrix", frozen)]
+pub(super) struct PyDistanceMatrix {
+    inner: core::DistanceMatrix,
+}
+
+#[pymethods]
+impl PyDistanceMatrix {
+    #[new]
+    fn new(positions: Vec<Vec<f64>>) -> PyResult<Self> {
+        let atoms = atoms_from_py(&positions)?;
+        Ok(Self {
+            inner: core::DistanceMatrix::from_atoms(atoms.positions()),
+        })
+    }
+
+    /// Number of atoms the matrix was built from, e.g. for normalising by N^2.
+    #[getter]
+    fn n_atoms(&self) -> usize {
+        self.inner.n_atoms()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("DistanceMatrix(n_atoms={})", self.inner.n_atoms())
+    }
+}
+
+/// Iterator yielding Simplified Debye Scattering Equation (DSE) intensities one q point at a time.
+///
+/// The distance matrix is computed once on construction; each step evaluates the intensity at
+/// the next q value min_q + i * q_step, so results can be processed as they arrive instead of
+/// waiting for the whole curve. Each step runs serially on the calling thread.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     copy.copy() returns an independent iterator that shares the distance matrix and continues
+///     from the same q value.
+///
+/// Examples:
+///     >>> stream = DseStreaming(0.1, 10.0, 0.01, positions)
+///     >>> for q, I in zip(stream.q_values, stream):
+///     ...     print(q, I)
+#[pyclass(name = "DseStreaming", frozen)]
+pub(super) struct PyDseStreaming {
+    distances: Arc<core::DistanceMatrix>,
+    q_values: Vec<f64>,
+    cursor: AtomicUsize,
+}
+
+#[pymethods]
+impl PyDseStreaming {
+    #[new]
+    fn new(min_q: f64, max_q: f64, q_step: f64, crystal: Vec<Vec<f64>>) -> PyResult<Self> {
+        check_q_range(min_q, max_q, q_step)?;
+        let atoms = atoms_from_py(&crystal)?;
+        Ok(Self {
+            distances: Arc::new(core::DistanceMatrix::from_atoms(atoms.positions())),
+            q_values: core::q_grid(min_q, max_q, q_step),
+            cursor: AtomicUsize::new(0),
+        })
+    }
+
+    /// All q values the iterator yields intensities for.
+    #[getter]
+    fn q_values(&self) -> Vec<f64> {
+        self.q_values.clone()
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self) -> Option<f64> {
+        let index = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let &q = self.q_values.get(index)?;
+        Some(core::dse_at_q(q, &self.distances))
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.q_values
+            .len()
+            .saturating_sub(self.cursor.load(Ordering::Relaxed))
+    }
+
+    fn __copy__(&self) -> Self {
+        Self {
+            distances: Arc::clone(&self.distances),
+            q_values: self.q_values.clone(),
+            cursor: AtomicUsize::new(self.cursor.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DseStreaming(n_atoms={}, n_q={})",
+            self.distances.n_atoms(),
+            self.q_values.len()
+        )
+    }
+}
+
+/// Calculate the change in DSE intensity when one atom is moved.
+///
+/// Returns I(q, r + delta_r) - I(q, r) for the displacement delta_r of the atom at atom_index.
+/// Only the pair terms involving the moved atom change, so they are the only ones evaluated:
+/// O(N) per q value instead of the O(N^2) of recomputing both intensities. Useful for the
+/// sensitivity of the intensity to individual atoms. The GIL is released during the computation.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     atom_index (int): Index of the moved atom in crystal
+///     displacement (tuple[float, float, float]): Displacement (dx, dy, dz) in nanometers
+///
+/// Returns:
+///     list[float]: Change in intensity at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If atom_index is out of range or the q range is invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 4.0)
+///     >>> delta_I = dse_differential(1.0, 50.0, 0.1, positions, 0, (0.01, 0.0, 0.0))
+#[pyfunction]
+pub(super) fn dse_differential(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    atom_index: usize,
+    displacement: (f64, f64, f64),
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    let (dx, dy, dz) = displacement;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_differential(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                atom_index,
+                [dx, dy, dz],
+            )
+        })
+    })?)
+}
+
+/// Debye intensity of a crystal that is updated as atoms are added and removed.
+///
+/// For molecular dynamics trajectories or Monte Carlo moves where only a few atoms change per
+/// step, `update` adds and subtracts only the pair terms involving the changed atoms: O(k * N)
+/// per q point for k changed atoms, instead of the O(N^2) of `dse_optimized`. A moved atom is
+/// a removal plus an addition.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     Each update adds rounding error of order 1e-16 * N^2 to the intensity; call `rebuild`
+///     every few thousand updates to recompute it from scratch.
+///
+/// Examples:
+///     >>> dse = IncrementalDSE(0.1, 10.0, 0.1, positions)
+///     >>> dse.update(added_atoms=[[1.0, 1.0, 1.2]], removed_indices=[17])
+///     >>> intensities = dse.current_intensity()
+#[pyclass(name = "IncrementalDSE")]
+pub(super) struct PyIncrementalDse {
+    inner: core::IncrementalDse,
+}
+
+#[pymethods]
+impl PyIncrementalDse {
+    #[new]
+    fn new(
+        py: Python<'_>,
+        min_q: f64,
+        max_q: f64,
+        q_step: f64,
+        crystal: Vec<Vec<f64>>,
+    ) -> PyResult<Self> {
+        check_q_range(min_q, max_q, q_step)?;
+        let atoms = atoms_from_py(&crystal)?;
+        Ok(Self {
+            inner: py.allow_threads(|| {
+                install(|| core::IncrementalDse::new(min_q, max_q, q_step, atoms.positions()))
+            }),
+        })
+    }
+
+    /// Remove atoms, then append new ones, updating the intensity.
+    ///
+    /// Args:
+    ///     added_atoms (list[list[float]]): Positions of the atoms to append
+    ///     removed_indices (list[int]): Indices of the atoms to remove, counted before the
+    ///         update. The remaining atoms keep their order, with added_atoms after them
+    ///
+    /// Raises:
+    ///     ValueError: If an index is out of range or repeated, or an added atom does not have
+    ///         exactly three finite coordinates. The state is left unchanged
+    #[pyo3(signature = (added_atoms=Vec::new(), removed_indices=Vec::new()))]
+    fn update(
+        &mut self,
+        py: Python<'_>,
+        added_atoms: Vec<Vec<f64>>,
+        removed_indices: Vec<usize>,
+    ) -> PyResult<()> {
+        let added = atoms_from_py(&added_atoms)?;
+        let inner = &mut self.inner;
+        py.allow_threads(|| install(|| inner.update(added.positions(), &removed_indices)))?;
+        Ok(())
+    }
+
+    /// Recompute the intensity of the current atoms from scratch, e.g. to discard the
+    /// rounding errors accumulated over many updates.
+    fn rebuild(&mut self, py: Python<'_>) {
+        let inner = &mut self.inner;
+        py.allow_threads(|| install(|| inner.rebuild()));
+    }
+
+    /// Current intensity at each q value.
+    ///
+    /// Returns:
+    ///     list[float]: Intensities on the same grid as `dse_optimized`
+    fn current_intensity(&self) -> Vec<f64> {
+        self.inner.intensity().to_vec()
+    }
+
+    /// q values of the intensity.
+    #[getter]
+    fn q_values(&self) -> Vec<f64> {
+        self.inner.q_values().to_vec()
+    }
+
+    /// Current atom positions, in update order.
+    #[getter]
+    fn positions(&self) -> Vec<Vec<f64>> {
+        atoms_to_py(self.inner.positions())
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.positions().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "IncrementalDSE(n_atoms={}, n_q={})",
+            self.inner.positions().len(),
+            self.inner.q_values().len()
+        )
+    }
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values from a DistanceMatrix.
+///
+/// Same result as `dse_optimized` on the crystal the matrix was built from, without
+/// recomputing the pairwise distances.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     distances (DistanceMatrix): Precomputed distances of the crystal
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid
+///
+/// Examples:
+///     >>> dm = DistanceMatrix(crystal('cube', 1.0, 5.0))
+///     >>> intensities = dse_from_distances(0.1, 10.0, 0.1, dm)
+#[pyfunction]
+pub(super) fn dse_from_distances(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    distances: PyRef<'_, PyDistanceMatrix>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let distances = &distances.inner;
+    Ok(py.allow_threads(|| install(|| core::dse_from_distances(min_q, max_q, q_step, distances))))
+}
+
+/// Calculate Debye Scattering Equation (DSE) intensity values weighted by atomic form factors.
+///
+/// Each pairwise term sin(q*r)/(q*r) is multiplied by f_i(q) * f_j(q), where f is the
+/// q-dependent form factor of the element of atom i or j. Form factors are supplied as a
+/// table evaluated on the same q grid as the output, see `atomic_form_factor`.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     element_indices (list[int]): Element index of each atom, one entry per atom
+///     form_factor_table (list[list[float]]): f(q) values of each element indexed by
+///         element index, evaluated at every q point from min_q to max_q
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If element_indices does not match the number of atoms, an element index
+///         has no entry in form_factor_table, a table entry is shorter than the q grid, or
+///         the q range is invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> f_au = atomic_form_factor('Au', 1.0, 50.0, 0.5)
+///     >>> intensities = dse_with_form_factors(1.0, 50.0, 0.5, positions, [0] * len(positions), [f_au])
+#[pyfunction]
+pub(super) fn dse_with_form_factors(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    element_indices: Vec<usize>,
+    form_factor_table: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_with_form_factors(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                &element_indices,
+                &form_factor_table,
+            )
+        })
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values from nearby pairs only.
+///
+/// Pairs further apart than r_cutoff are dropped from the sum. The pairs within the cutoff
+/// are found once and reused for every q, so the cost after the O(N^2) scan is
+/// O(N * k * N_q), with k the average number of neighbours within r_cutoff.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     r_cutoff (float): Largest pair distance included, in nanometers
+///
+/// Returns:
+///     tuple[list[float], float]: (intensities, fraction), where fraction is the share of the
+///         N * (N - 1) / 2 distinct pairs within the cutoff
+///
+/// Raises:
+///     ValueError: If r_cutoff is negative or the q range is invalid
+///
+/// Note:
+///     Truncating the sum removes the long-range correlations that shape I(q) at low q
+///     (particle size and shape), and the sharp cutoff adds ripples of period about
+///     2*pi / r_cutoff. Use it for the high-q region, or check the result against
+///     dse_optimized on a smaller crystal.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 10.0)
+///     >>> intensities, fraction = dse_with_cutoff(5.0, 30.0, 0.1, positions, 3.0)
+#[pyfunction]
+pub(super) fn dse_with_cutoff(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    r_cutoff: f64,
+) -> PyResult<(Vec<f64>, f64)> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_with_cutoff(min_q, max_q, q_step, atoms.positions(), r_cutoff))
+    })?)
+}
+
+/// Calculate the neutron Debye scattering intensity.
+///
+/// Each pair term is weighted by the coherent scattering lengths of its two atoms,
+/// I(q) = sum_ij b_i * b_j * sin(q*r_ij) / (q*r_ij). Neutron scattering lengths do not depend
+/// on q and can be negative (hydrogen), which makes contrast variation with H/D possible.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     scattering_lengths (list[float]): Bound coherent scattering length of each atom in fm,
+///         e.g. from `neutron_lengths_from_symbols`
+///
+/// Returns:
+///     list[float]: Intensity values in fm^2 at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If scattering_lengths does not have one entry per atom or the q range is
+///         invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.4, 3.0)
+///     >>> lengths = neutron_lengths_from_symbols(['C'] * len(positions))
+///     >>> intensities = dse_neutron(1.0, 30.0, 0.1, positions, lengths)
+#[pyfunction]
+pub(super) fn dse_neutron(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    scattering_lengths: Vec<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_neutron(min_q, max_q, q_step, atoms.positions(), &scattering_lengths))
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values with a pair-distance histogram.
+///
+/// Pair distances are binned into a histogram h[k] counting pairs with distance in
+/// [k*dr, (k+1)*dr), and the intensity is evaluated as I(q) = N + sum_k h[k] * sinc(q * r_k)
+/// with r_k the bin centre. This costs O(N^2 + N_bins * N_q) instead of O(N^2 * N_q), which is
+/// much faster for large crystals and many q points.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     dr (float): Histogram bin width in nanometers. A reasonable default is lattice_param / 10
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If dr is not positive, gives more than 2**24 bins across the crystal or the
+///         q range is invalid
+///
+/// Note:
+///     Evaluating every pair of a bin at its centre is a midpoint rule: for a smooth pair
+///     distance distribution (disordered or polydisperse systems) the approximation error
+///     scales as O(dr^2), i.e. as (q * dr)^2. On a perfect lattice the distances are discrete
+///     and each may sit anywhere in its bin, so the error falls off more slowly, roughly
+///     linearly in q * dr.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 10.0)
+///     >>> intensities = dse_histogram(0.1, 10.0, 0.1, positions, 0.04)
+#[pyfunction]
+pub(super) fn dse_histogram(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    dr: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_histogram(min_q, max_q, q_step, atoms.positions(), dr))
+    })?)
+}
+
+/// Decompose the DSE intensity into the contributions of pair-distance shells.
+///
+/// Row k holds, at each q, the contribution of the ordered pairs with distance in
+/// [k * shell_dr, (k + 1) * shell_dr), each evaluated at its exact distance, so the rows add up
+/// to `dse_optimized` to rounding. Row 0 also holds the N self terms. Plotting the rows shows
+/// which coordination shells build each feature of I(q).
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///     shell_dr (float): Shell width in nanometers
+///
+/// Returns:
+///     list[list[float]]: One row per shell, up to the shell of the longest distance, each
+///         with one value per q; shells without pairs are rows of zeros
+///
+/// Raises:
+///     ValueError: If shell_dr is not positive, gives more than 2**24 shells across the crystal
+///         or the q range is invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 2.0)
+///     >>> shells = dse_shell_decomposition(1.0, 40.0, 0.1, positions, 0.05)
+///     >>> first_neighbours = shells[5]  # pairs at 0.25-0.30 nm, around a / sqrt(2)
+#[pyfunction]
+pub(super) fn dse_shell_decomposition(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    shell_dr: f64,
+) -> PyResult<Vec<Vec<f64>>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_shell_decomposition(min_q, max_q, q_step, atoms.positions(), shell_dr))
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values with Debye-Waller factors.
+///
+/// Thermal vibrations attenuate high-q scattering. Each pair term sin(q*r)/(q*r) is multiplied
+/// by exp(-0.5 * (B_i + B_j) * q^2 / (16*pi^2)), where B_i is the isotropic Debye-Waller
+/// parameter of atom i.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] in nanometers
+///     b_factors (list[float]): Debye-Waller parameter B of each atom in Å^2 (the unit used by
+///         crystallographic tables), one entry per atom. They are converted to nm^2
+///         (1 Å^2 = 0.01 nm^2) internally to match q in 1/nm
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If b_factors does not have one entry per atom or the q range is invalid
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> intensities = dse_with_debye_waller(1.0, 50.0, 0.5, positions, [0.6] * len(positions))
+#[pyfunction]
+pub(super) fn dse_with_debye_waller(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    b_factors: Vec<f64>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_with_debye_waller(min_q, max_q, q_step, atoms.positions(), &b_factors))
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values on top of a background.
+///
+/// Returns I_crystal(q) + incoherent_bg + thermal_bg_coeff * q^2: a flat incoherent term
+/// (Compton scattering, fluorescence) and a thermal diffuse scattering term growing as q^2.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] in nanometers
+///     incoherent_bg (float): Constant background, in units of intensity
+///     thermal_bg_coeff (float): Coefficient of the q^2 background, in units of intensity * nm^2
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 4.0)
+///     >>> intensities = dse_with_background(0.1, 10.0, 0.1, positions, 50.0, 0.5)
+#[pyfunction]
+pub(super) fn dse_with_background(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    incoherent_bg: f64,
+    thermal_bg_coeff: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_with_background(
+                min_q,
+                max_q,
+                q_step,
+                atoms.positions(),
+                incoherent_bg,
+                thermal_bg_coeff,
+            )
+        })
+    }))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values seen through a
+/// Gaussian instrument resolution.
+///
+/// Same as `apply_instrument_broadening` applied to `dse_optimized`.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] in nanometers
+///     sigma_q (float): Standard deviation of the resolution function in 1/nm
+///
+/// Returns:
+///     list[float]: Broadened intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If sigma_q is not positive
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 2.0)
+///     >>> intensities = dse_with_instrument_broadening(5.0, 60.0, 0.05, positions, 0.3)
+#[pyfunction]
+pub(super) fn dse_with_instrument_broadening(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    sigma_q: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| {
+            core::dse_with_instrument_broadening(min_q, max_q, q_step, atoms.positions(), sigma_q)
+        })
+    })?)
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values with one Debye-Waller factor.
+///
+/// Same as `dse_with_debye_waller` with the same B for every atom, which reduces to
+/// `dse_optimized` multiplied by exp(-B * q^2 / (16*pi^2)).
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] in nanometers
+///     b (float): Debye-Waller parameter B in Å^2, converted to nm^2 internally
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Examples:
+///     >>> positions = crystal('fcc', 0.408, 3.0)
+///     >>> intensities = dse_with_isotropic_bfactor(1.0, 50.0, 0.5, positions, 0.6)
+#[pyfunction]
+pub(super) fn dse_with_isotropic_bfactor(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+    b: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_with_isotropic_bfactor(min_q, max_q, q_step, atoms.positions(), b))
+    }))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values in single precision.
+///
+/// Mirrors `dse_optimized`, but positions, distances, sinc terms and sums are all 32-bit
+/// floats. The distance matrix takes half the memory, and the computation is faster, which
+/// suits large parameter searches where sub-percent accuracy is enough.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     On one core, a 5091-atom sphere and 10 q points took 3.2 s against 5.0 s for
+///     dse_optimized. The median relative deviation from dse_optimized was 1.4e-5 and the
+///     maximum 8.4e-5, the largest deviations being near minima of I(q).
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 8.6)
+///     >>> intensities = dse_optimized_f32(1.0, 50.0, 0.1, positions)
+#[pyfunction]
+pub(super) fn dse_optimized_f32(
+    py: Python<'_>,
+    min_q: f32,
+    max_q: f32,
+    q_step: f32,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f32>> {
+    check_q_range(min_q.into(), max_q.into(), q_step.into())?;
+    let positions: Vec<[f32; 3]> = atoms_from_py(&crystal)?
+        .positions()
+        .iter()
+        .map(|p| p.map(|x| x as f32))
+        .collect();
+    Ok(py.allow_threads(|| install(|| core::dse_core_f32(min_q, max_q, q_step, &positions))))
+}
+
+/// Calculate Simplified Debye Scattering Equation (DSE) intensity values with an f32 distance matrix.
+///
+/// Distances are computed and stored in single precision, halving the memory of the distance
+/// matrix, while the sinc terms are evaluated and accumulated in double precision.
+///
+/// Args:
+///     min_q (float): Minimum q value (scattering vector magnitude)
+///     max_q (float): Maximum q value (scattering vector magnitude)
+///     q_step (float): Step size between q values
+///     crystal (list[list[float]]): List of 3D coordinates [x, y, z] representing atom positions
+///
+/// Returns:
+///     list[float]: Intensity values at each q point from min_q to max_q
+///
+/// Raises:
+///     ValueError: If the q range is invalid or an atom does not have exactly three finite
+///         coordinates
+///
+/// Note:
+///     The run time is about that of dse_optimized. The maximum relative deviation from
+///     dse_optimized was 2.5e-5 for a 5091-atom sphere, from rounding the coordinates to f32.
+///
+/// Examples:
+///     >>> positions = crystal('sphere', 0.4, 8.6)
+///     >>> intensities = dse_mixed_precision(1.0, 50.0, 0.1, positions)
+#[pyfunction]
+pub(super) fn dse_mixed_precision(
+    py: Python<'_>,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+    crystal: Vec<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let atoms = atoms_from_py(&crystal)?;
+    Ok(py.allow_threads(|| {
+        install(|| core::dse_mixed_precision(min_q, max_q, q_step, atoms.positions()))
+    }))
+}
@@ -0,0 +1,187 @@
+use pyo3::prelude::*;
+
+use super::dse::check_q_range;
+use crate::core;
+
+/// Evaluate the Cromer-Mann X-ray form factor of an element on a q grid.
+///
+/// Args:
+///     element (str): Element symbol. Built-in elements: H, C, N, O, Fe, Ag, Au
+///     min_q (float): Minimum q value (scattering vector magnitude) in 1/nm
+///     max_q (float): Maximum q value (scattering vector magnitude) in 1/nm
+///     q_step (float): Step size between q values
+///
+/// Returns:
+///     list[float]: f(q) at each q point from min_q to max_q, on the same grid as `dse_optimized`
+///
+/// Raises:
+///     ValueError: If the element is not in the built-in table or the q range is invalid
+///
+/// Examples:
+///     >>> atomic_form_factor('Au', 0.0, 10.0, 1.0)[0]
+///     78.9572
+#[pyfunction]
+pub(super) fn atomic_form_factor(
+    element: &str,
+    min_q: f64,
+    max_q: f64,
+    q_step: f64,
+) -> PyResult<Vec<f64>> {
+    check_q_range(min_q, max_q, q_step)?;
+    let coeffs = core::cromer_mann_coeffs(element).ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "No Cromer-Mann coefficients for element '{}'. Supported elements: {}",
+            element,
+            core::CROMER_MANN_TABLE
+                .iter()
+                .map(|(s, _)| format!("'{}'", s))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })?;
+    Ok(core::q_grid(min_q, max_q, q_step)
+        .into_iter()
+        .map(|q| core::cromer_mann(q, coeffs))
+        .collect())
+}
+
+/// Look up the coherent neutron scattering length of each atom from its element symbol.
+///
+/// Args:
+///     symbols (list[str]): Element or isotope symbol of each atom. Built-in symbols: H, D, C,
+///         N, O
+///
+/// Returns:
+///     list[float]: Bound coherent scattering length of each atom in fm, for `dse_neutron`
+///
+/// Raises:
+///     ValueError: If a symbol is not in the built-in table
+///
+/// Examples:
+///     >>> neutron_lengths_from_symbols(['H', 'D', 'O'])
+///     [-3.739, 6.671, 5.803]
+#[pyfunction]
+pub(super) fn neutron_lengths_from_symbols(symbols: Vec<String>) -> PyResult<Vec<f64>> {
+    symbols
+        .iter()
+        .map(|symbol| {
+            core::neutron_scattering_length(symbol).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "No neutron scattering length for '{}'. Supported symbols: {}",
+                    symbol,
+                    core::NEUTRON_SCATTERING_LENGTHS
+                        .iter()
+                        .map(|(s, _)| format!("'{}'", s))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Look up the linear X-ray attenuation coefficient of an element at a photon energy.
+///
+/// mu = (mu / rho) * rho from a built-in table of mass attenuation coefficients approximated
+/// from NIST data at 5-100 keV, interpolated log-log between the tabulated energies and
+/// absorption edges. The densities are those of the pure elements, with H, N and O as gases at
+/// 20 °C and 1 atm.
+///
+/// Args:
+///     element (str): Element symbol. Built-in elements: H, C, N, O, Fe, Ag, Au
+///     energy_kev (float): Photon energy in keV, from 5 to 100
+///
+/// Returns:
+///     float: Linear attenuation coefficient in 1/nm, for `absorption_correction`
+///
+/// Raises:
+///     ValueError: If the element is not in the built-in table or the energy is outside
+///         5-100 keV
+///
+/// Examples:
+///     >>> mu = linear_attenuation_coefficient('Fe', 8.0)
+#[pyfunction]
+pub(super) fn linear_attenuation_coefficient(element: &str, energy_kev: f64) -> PyResult<f64> {
+    Ok(core::linear_attenuation_coefficient(element, energy_kev)?)
+}
+
+/// Evaluate the analytic form factor of a uniform sphere.
+///
+/// P(q) = [3 * (sin(qR) - qR * cos(qR)) / (qR)^3]^2 with P(0) = 1, the large-N limit of the
+/// atomistic DSE of a sphere divided by N^2, useful for validating it.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     radius_nm (float): Sphere radius R in nanometers
+///
+/// Returns:
+///     list[float]: P(q) at each q value
+///
+/// Raises:
+///     ValueError: If the radius is not positive
+///
+/// Examples:
+///     >>> form_factor_sphere([0.0], 2.0)
+///     [1.0]
+#[pyfunction]
+pub(super) fn form_factor_sphere(q_values: Vec<f64>, radius_nm: f64) -> PyResult<Vec<f64>> {
+    Ok(core::form_factor_sphere(&q_values, radius_nm)?)
+}
+
+/// Evaluate the orientation-averaged analytic form factor of a uniform cylinder.
+///
+/// P(q) = integral over u = cos(alpha) from 0 to 1 of
+/// [2 J1(q R sin(alpha)) / (q R sin(alpha)) * sin(q H cos(alpha) / 2) / (q H cos(alpha) / 2)]^2,
+/// with alpha the angle between q and the cylinder axis, integrated numerically. P(0) = 1.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     radius_nm (float): Cylinder radius R in nanometers
+///     height_nm (float): Cylinder height H in nanometers
+///
+/// Returns:
+///     list[float]: P(q) at each q value
+///
+/// Raises:
+///     ValueError: If the radius or height is not positive
+///
+/// Examples:
+///     >>> p = form_factor_cylinder([0.1, 0.5, 1.0], 2.0, 10.0)
+#[pyfunction]
+pub(super) fn form_factor_cylinder(
+    q_values: Vec<f64>,
+    radius_nm: f64,
+    height_nm: f64,
+) -> PyResult<Vec<f64>> {
+    Ok(core::form_factor_cylinder(&q_values, radius_nm, height_nm)?)
+}
+
+/// Evaluate the orientation-averaged analytic form factor of a uniform triaxial ellipsoid.
+///
+/// The sphere form factor at the radius of the ellipsoid seen along each direction is
+/// averaged over orientations by a two-dimensional numerical quadrature. P(0) = 1, and
+/// equal semi-axes give `form_factor_sphere`.
+///
+/// Args:
+///     q_values (list[float]): q values in 1/nm
+///     a_nm (float): Semi-axis along x in nanometers
+///     b_nm (float): Semi-axis along y in nanometers
+///     c_nm (float): Semi-axis along z in nanometers
+///
+/// Returns:
+///     list[float]: P(q) at each q value
+///
+/// Raises:
+///     ValueError: If a semi-axis is not positive
+///
+/// Examples:
+///     >>> p = form_factor_ellipsoid([0.1, 0.5, 1.0], 2.0, 2.0, 5.0)
+#[pyfunction]
+pub(super) fn form_factor_ellipsoid(
+    q_values: Vec<f64>,
+    a_nm: f64,
+    b_nm: f64,
+    c_nm: f64,
+) -> PyResult<Vec<f64>> {
+    Ok(core::form_factor_ellipsoid(&q_values, a_nm, b_nm, c_nm)?)
+}
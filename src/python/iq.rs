@@ -0,0 +1,163 @@
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyType};
+
+use crate::core;
+
+/// A scattering curve: intensities together with the q values they were computed at.
+///
+/// Returned by `dse_optimized`. Indexing and iteration give (q, I) tuples; the `q` and
+/// `intensity` attributes give the two columns as lists.
+///
+/// Args:
+///     q (list[float]): q values in 1/nm
+///     intensity (list[float]): Intensity at each q value
+///
+/// Raises:
+///     ValueError: If q and intensity have different lengths
+///
+/// Note:
+///     Curves add element-wise (`a + b`) when their q values are identical, and scale by a
+///     number (`2.0 * a`). `to_numpy` needs NumPy; `save_csv` and `IqResult.from_csv`
+///     round-trip the curve through a q,intensity CSV file.
+///
+/// Examples:
+///     >>> result = dse_optimized(0.1, 10.0, 0.1, crystal('sphere', 0.4, 5.0))
+///     >>> q, I = result.to_numpy()
+///     >>> q0, i0 = result[0]
+///     >>> mixture = 0.7 * result + 0.3 * dse_optimized(0.1, 10.0, 0.1, crystal('cube', 0.4, 5.0))
+///     >>> result.save_csv('sphere.csv')
+///     >>> loaded = IqResult.from_csv('sphere.csv')
+#[pyclass(name = "IqResult", frozen)]
+pub(super) struct PyIqResult {
+    /// q values in 1/nm.
+    #[pyo3(get)]
+    pub(super) q: Vec<f64>,
+    /// Intensity at each q value.
+    #[pyo3(get)]
+    pub(super) intensity: Vec<f64>,
+}
+
+#[pymethods]
+impl PyIqResult {
+    #[new]
+    pub(super) fn new(q: Vec<f64>, intensity: Vec<f64>) -> PyResult<Self> {
+        if q.len() != intensity.len() {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "q has {} values but intensity has {}",
+                q.len(),
+                intensity.len()
+            )));
+        }
+        Ok(Self { q, intensity })
+    }
+
+    fn __len__(&self) -> usize {
+        self.q.len()
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<(f64, f64)> {
+        let len = self.q.len() as isize;
+        let position = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&position) {
+            return Err(PyErr::new::<PyIndexError, _>(format!(
+                "index {} out of range for IqResult of length {}",
+                index, len
+            )));
+        }
+        let position = position as usize;
+        Ok((self.q[position], self.intensity[position]))
+    }
+
+    fn __add__(&self, other: PyRef<'_, Self>) -> PyResult<Self> {
+        if self.q != other.q {
+            return Err(PyErr::new::<PyValueError, _>(
+                "cannot add IqResults with different q values",
+            ));
+        }
+        Ok(Self {
+            q: self.q.clone(),
+            intensity: self
+                .intensity
+                .iter()
+                .zip(&other.intensity)
+                .map(|(a, b)| a + b)
+                .collect(),
+        })
+    }
+
+    fn __mul__(&self, factor: f64) -> Self {
+        Self {
+            q: self.q.clone(),
+            intensity: self.intensity.iter().map(|i| i * factor).collect(),
+        }
+    }
+
+    fn __rmul__(&self, factor: f64) -> Self {
+        self.__mul__(factor)
+    }
+
+    /// Convert to a pair of NumPy float64 arrays (q, intensity).
+    ///
+    /// The data is handed to `numpy.frombuffer` through a bytearray, so each column is
+    /// copied once and no Python float objects are created.
+    ///
+    /// Returns:
+    ///     tuple[numpy.ndarray, numpy.ndarray]: (q, intensity)
+    ///
+    /// Raises:
+    ///     ImportError: If NumPy is not installed
+    fn to_numpy(&self, py: Python<'_>) -> PyResult<(PyObject, PyObject)> {
+        let numpy = py.import("numpy")?;
+        let to_array = |values: &[f64]| -> PyResult<PyObject> {
+            let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+            let buffer = PyByteArray::new(py, &bytes);
+            Ok(numpy
+                .call_method1("frombuffer", (buffer, "float64"))?
+                .unbind())
+        };
+        Ok((to_array(&self.q)?, to_array(&self.intensity)?))
+    }
+
+    /// Write the curve to a CSV file with a q,intensity header.
+    ///
+    /// Args:
+    ///     path (str): Path of the file to write
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be written
+    fn save_csv(&self, path: &str) -> PyResult<()> {
+        std::fs::write(path, core::iq_to_csv(&self.q, &self.intensity))?;
+        Ok(())
+    }
+
+    /// Read a curve written by `save_csv`, or any CSV with q,intensity columns.
+    ///
+    /// Blank lines and lines starting with # are skipped, a non-numeric first row is treated
+    /// as a header, and columns after the second are ignored.
+    ///
+    /// Args:
+    ///     path (str): Path of the CSV file
+    ///
+    /// Returns:
+    ///     IqResult: The curve stored in the file
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be read
+    ///     ValueError: If a row has fewer than two fields or a non-numeric value
+    #[classmethod]
+    fn from_csv(_cls: &Bound<'_, PyType>, path: &str) -> PyResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let (q, intensity) = core::iq_from_csv(&content)?;
+        Ok(Self { q, intensity })
+    }
+
+    fn __repr__(&self) -> String {
+        match (self.q.first(), self.q.last()) {
+            (Some(first), Some(last)) => {
+                format!("IqResult(n_q={}, q={}..{})", self.q.len(), first, last)
+            }
+            _ => "IqResult(n_q=0)".to_string(),
+        }
+    }
+}
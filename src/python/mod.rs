@@ -0,0 +1,249 @@
+//! PyO3 bindings exposing the [`core`](crate::core) API as the `fast_dse`
+//! Python module.
+
+mod analysis;
+mod correlation;
+mod crystal;
+mod dse;
+mod form_factor;
+mod iq;
+mod threads;
+
+use pyo3::prelude::*;
+
+use crate::core::{Atoms, Error};
+
+/// A Python module implemented in Rust.
+#[pymodule]
+fn fast_dse(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(crystal::crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_cylinder, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_nanorod, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_slab, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        crystal::crystal_slab_with_surface_relaxation,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_ellipsoid, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_hollow_sphere, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_core_shell, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_icosahedron, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_cuboctahedron, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_truncated_octahedron, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_decahedron, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_wulff, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::replicate_crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::n_atoms, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::translate_crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::center_crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::to_fractional_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::from_fractional_coordinates, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::to_cartesian_from_fractional, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::classify_surface_atoms, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::surface_fraction, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::rotate_crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_grain_aggregate, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_polycrystalline, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::rotation_matrix_x, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::rotation_matrix_y, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::rotation_matrix_z, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_amorphous, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::periodic_crystal, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_with_vacancies, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_with_substitutions, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_random_alloy, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_stacking_faults, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::apply_thermal_disorder, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::apply_anisotropic_disorder, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_from_xyz, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::parse_lammps_dump, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::parse_lammps_dump_first_frame, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_from_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_from_cif, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_from_cif_file, m)?)?;
+    m.add_function(wrap_pyfunction!(crystal::crystal_from_poscar, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_optimized, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::partial_dse, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_optimized_with_q, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::q_values, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::q_values_linspace, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_linspace, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_logspace_q, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_custom_q, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_adaptive, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_auto_adaptive, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_ultra_optimized, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_no_alloc, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::benchmark_dse, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_symmetric, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::min_q_from_crystal_size, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::max_q_for_resolution, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::estimate_memory_mb, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::max_atoms_for_memory, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::set_memory_warning_threshold_mb, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::get_memory_warning_threshold_mb, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_gradient_lattice, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_gradient_size, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::structure_factor, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::distinct_structure_factor, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_trajectory_average, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_trajectory_average_lammps, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_size_averaged, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::chi_squared_map, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_finite_size_error, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::convergence_with_size, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::convergence_plot_data, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::convergence_test, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_normalized_by_sphere, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_batch_with_params, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_orientation_averaged, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_powder_average_explicit, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_3d, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_periodic, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::structure_amplitude, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::structure_amplitude_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_simd, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_gpu, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_optimized_f32, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_mixed_precision, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_from_distances, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_histogram, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_shell_decomposition, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_cutoff, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_neutron, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_form_factors, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_debye_waller, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_instrument_broadening, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_background, m)?)?;
+    m.add_function(wrap_pyfunction!(dse::dse_with_isotropic_bfactor, m)?)?;
+    m.add_class::<dse::PyDistanceMatrix>()?;
+    m.add_class::<dse::PyDseStreaming>()?;
+    m.add_class::<dse::PyIncrementalDse>()?;
+    m.add_function(wrap_pyfunction!(dse::dse_differential, m)?)?;
+    m.add_class::<iq::PyIqResult>()?;
+    m.add_class::<crystal::PyCrystalAtoms>()?;
+    m.add_class::<crystal::PyPeriodicCrystal>()?;
+    m.add_class::<analysis::PyPeakInfo>()?;
+    m.add_function(wrap_pyfunction!(form_factor::atomic_form_factor, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        form_factor::linear_attenuation_coefficient,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(form_factor::form_factor_sphere, m)?)?;
+    m.add_function(wrap_pyfunction!(form_factor::form_factor_cylinder, m)?)?;
+    m.add_function(wrap_pyfunction!(form_factor::form_factor_ellipsoid, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        form_factor::neutron_lengths_from_symbols,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(analysis::normalise_intensity, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::subtract_background, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::fit_polynomial_background, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::fit_power_law_background, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::fit_background_parameters, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::scale_to_absolute, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::lorentz_polarization, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::q_to_twotheta, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::twotheta_to_q, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::resample_iq, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::absorption_correction, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::apply_correction, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::apply_instrument_broadening, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::savitzky_golay_smooth, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::moving_average_smooth, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::guinier_fit, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::guinier_plot_data, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::extrapolate_i0, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        analysis::normalise_to_forward_scattering,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(analysis::kratky_transform, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::kratky_peak, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::porod_fit, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::porod_invariant, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::r_factor, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::chi_squared, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::find_peaks, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::assign_miller, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::lattice_parameter_from_peak, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis::scherrer_analysis, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        correlation::radial_distribution_function,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(correlation::partial_pair_correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::fourier_transform_to_iq, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::pdf_from_iq, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        correlation::lorch_modification_function,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(
+        correlation::apply_modification_function,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(correlation::pdf_lorch_transform, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::coordination_numbers, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::average_coordination, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::coordination_histogram, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::number_density, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::compute_pair_distances, m)?)?;
+    m.add_function(wrap_pyfunction!(threads::set_thread_count, m)?)?;
+    m.add_function(wrap_pyfunction!(threads::get_thread_count, m)?)?;
+    m.add_class::<threads::ThreadCount>()?;
+    m.add_function(wrap_pyfunction!(threads::set_rayon_panic_behavior, m)?)?;
+    m.add_class::<threads::RayonConfig>()?;
+    Ok(())
+}
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        match err {
+            Error::Gpu(_) | Error::Mismatch(_) => {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(err.to_string())
+            }
+            _ => PyErr::new::<pyo3::exceptions::PyValueError, _>(err.to_string()),
+        }
+    }
+}
+
+/// Convert Python `list[list[float]]` positions into [`Atoms`].
+///
+/// Fails with a ValueError naming the first row that does not hold exactly
+/// three finite coordinates, instead of panicking on ragged or 2D input.
+fn atoms_from_py(crystal: &[Vec<f64>]) -> PyResult<Atoms> {
+    if let Some((i, p)) = crystal.iter().enumerate().find(|(_, p)| p.len() != 3) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "atom {} has {} coordinate(s), expected 3 [x, y, z]: {:?}",
+            i,
+            p.len(),
+            p
+        )));
+    }
+    let atoms = crystal.iter().map(|p| [p[0], p[1], p[2]]).collect();
+    check_finite_positions(&atoms)?;
+    Ok(atoms)
+}
+
+/// Fail with `ValueError` unless every coordinate of `atoms` is finite.
+fn check_finite_positions(atoms: &Atoms) -> PyResult<()> {
+    match atoms
+        .iter()
+        .enumerate()
+        .find(|(_, p)| !p.iter().all(|x| x.is_finite()))
+    {
+        Some((i, p)) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "atom {} has a non-finite coordinate: {:?}",
+            i, p
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Convert [`Atoms`] into Python `list[list[float]]` positions.
+fn atoms_to_py(atoms: &Atoms) -> Vec<Vec<f64>> {
+    atoms.positions().iter().map(|p| p.to_vec()).collect()
+}
@@ -0,0 +1,278 @@
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Once, RwLock};
+
+use pyo3::exceptions::{PyRuntimeError, PyUserWarning, PyValueError};
+use pyo3::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// Pool set by `set_thread_count`; `None` means the Rayon global pool.
+static POOL: RwLock<Option<Arc<ThreadPool>>> = RwLock::new(None);
+
+/// Run `op` in the pool selected with `set_thread_count`, or in the Rayon
+/// global pool if none was set.
+///
+/// A worker panic recorded by the `'log'` panic behavior is issued as a Python
+/// warning here, on the calling thread, before the panic resumes.
+pub(super) fn install<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    let pool = POOL.read().unwrap().clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }));
+    result.unwrap_or_else(|payload| {
+        if let Some(report) = PANIC_REPORT
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+        {
+            warn_worker_panic(&report);
+        }
+        panic::resume_unwind(payload)
+    })
+}
+
+/// Issue `report` as a `UserWarning`. The GIL is taken again if the caller
+/// released it; a warning turned into an error by the filters is dropped, since
+/// the panic is about to reach Python anyway.
+fn warn_worker_panic(report: &str) {
+    let message = format!("fast_dse: worker thread panicked: {}", report).replace('\0', "");
+    let message = CString::new(message).expect("NUL bytes were removed");
+    Python::with_gil(|py| {
+        let _ = PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1);
+    });
+}
+
+fn replace_pool(pool: Option<Arc<ThreadPool>>) -> Option<Arc<ThreadPool>> {
+    std::mem::replace(&mut *POOL.write().unwrap(), pool)
+}
+
+fn build_pool(n: usize) -> PyResult<Arc<ThreadPool>> {
+    if n == 0 {
+        return Err(PyValueError::new_err("thread count must be at least 1"));
+    }
+    ThreadPoolBuilder::new()
+        .num_threads(n)
+        .build()
+        .map(Arc::new)
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+}
+
+/// Set the number of threads used by all parallel fast_dse computations.
+///
+/// By default fast_dse uses the Rayon global pool with one thread per CPU core. Inside Dask,
+/// multiprocessing or other multi-threaded workers this oversubscribes the machine; limit it
+/// with a dedicated pool of n threads instead. set_thread_count(1) is also useful to compare
+/// single-threaded and parallel performance when profiling.
+///
+/// Args:
+///     n (int): Number of worker threads, at least 1
+///
+/// Raises:
+///     ValueError: If n is 0
+///     RuntimeError: If the thread pool cannot be created
+///
+/// Examples:
+///     >>> set_thread_count(2)
+///     >>> get_thread_count()
+///     2
+#[pyfunction]
+pub(super) fn set_thread_count(n: usize) -> PyResult<()> {
+    replace_pool(Some(build_pool(n)?));
+    Ok(())
+}
+
+/// Number of threads used by parallel fast_dse computations.
+///
+/// Returns:
+///     int: The size set with `set_thread_count`, or the size of the Rayon global pool
+#[pyfunction]
+pub(super) fn get_thread_count() -> usize {
+    install(rayon::current_num_threads)
+}
+
+/// Context manager limiting fast_dse to n threads within a `with` block.
+///
+/// The previous setting, including the default global pool, is restored on exit.
+///
+/// Args:
+///     n (int): Number of worker threads, at least 1
+///
+/// Examples:
+///     >>> with thread_count(1):
+///     ...     serial = dse_optimized(0.1, 10.0, 0.1, positions)
+#[pyclass(name = "thread_count", frozen)]
+pub(super) struct ThreadCount {
+    pool: Arc<ThreadPool>,
+    previous: Mutex<Option<Option<Arc<ThreadPool>>>>,
+}
+
+#[pymethods]
+impl ThreadCount {
+    #[new]
+    fn new(n: usize) -> PyResult<Self> {
+        Ok(Self {
+            pool: build_pool(n)?,
+            previous: Mutex::new(None),
+        })
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        let previous = replace_pool(Some(slf.pool.clone()));
+        *slf.previous.lock().unwrap() = Some(previous);
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: Option<PyObject>,
+        _exc_value: Option<PyObject>,
+        _traceback: Option<PyObject>,
+    ) {
+        if let Some(previous) = self.previous.lock().unwrap().take() {
+            replace_pool(previous);
+        }
+    }
+}
+
+/// What the panic hook does with a panic on a Rayon worker thread. The panic
+/// itself always reaches Python as an exception; only the report changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum PanicBehavior {
+    /// The original hook prints the full report.
+    Propagate,
+    /// Nothing is printed.
+    Ignore,
+    /// The report is kept in [`PANIC_REPORT`] and issued as a single
+    /// `fast_dse: worker thread panicked` warning by [`install`].
+    Log,
+}
+
+impl PanicBehavior {
+    fn parse(behavior: &str) -> PyResult<Self> {
+        match behavior {
+            "propagate" => Ok(Self::Propagate),
+            "ignore" => Ok(Self::Ignore),
+            "log" => Ok(Self::Log),
+            _ => Err(PyValueError::new_err(format!(
+                "panic behavior must be 'propagate', 'ignore' or 'log', got '{}'",
+                behavior
+            ))),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Ignore,
+            2 => Self::Log,
+            _ => Self::Propagate,
+        }
+    }
+}
+
+/// Behavior set by `set_rayon_panic_behavior` or `RayonConfig`.
+static PANIC_BEHAVIOR: AtomicU8 = AtomicU8::new(PanicBehavior::Propagate as u8);
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Last worker panic recorded under [`PanicBehavior::Log`]. The hook must not
+/// call into Python: the thread waiting on the pool may hold the GIL.
+static PANIC_REPORT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Install, once, a panic hook that applies [`PANIC_BEHAVIOR`] to Rayon worker
+/// threads and defers to the original hook everywhere else, then select
+/// `behavior`. Returns the previous behavior.
+fn replace_panic_behavior(behavior: PanicBehavior) -> PanicBehavior {
+    PANIC_HOOK.call_once(|| {
+        let original = panic::take_hook();
+        panic::set_hook(Box::new(move |info: &PanicHookInfo<'_>| {
+            if rayon::current_thread_index().is_none() {
+                return original(info);
+            }
+            match PanicBehavior::from_u8(PANIC_BEHAVIOR.load(Ordering::Relaxed)) {
+                PanicBehavior::Propagate => original(info),
+                PanicBehavior::Ignore => {}
+                PanicBehavior::Log => {
+                    *PANIC_REPORT.lock().unwrap_or_else(|err| err.into_inner()) =
+                        Some(info.to_string())
+                }
+            }
+        }));
+    });
+    PanicBehavior::from_u8(PANIC_BEHAVIOR.swap(behavior as u8, Ordering::Relaxed))
+}
+
+/// Choose how panics on fast_dse worker threads are reported.
+///
+/// An interrupted or failing parallel computation can make a Rayon worker thread panic. The
+/// panic always reaches Python as an exception, but the worker also prints a report with a
+/// backtrace hint to stderr, which Jupyter shows as confusing red output. The first call
+/// installs a panic hook that handles worker threads only; panics elsewhere keep the original
+/// report.
+///
+/// Args:
+///     behavior (str): 'propagate' (default) prints the original report to stderr, 'ignore'
+///         prints nothing and 'log' issues a single-line UserWarning through the warnings
+///         module instead
+///
+/// Raises:
+///     ValueError: If behavior is not one of the three names
+///
+/// Examples:
+///     >>> set_rayon_panic_behavior('ignore')
+#[pyfunction]
+pub(super) fn set_rayon_panic_behavior(behavior: &str) -> PyResult<()> {
+    replace_panic_behavior(PanicBehavior::parse(behavior)?);
+    Ok(())
+}
+
+/// Context manager applying `set_rayon_panic_behavior` within a `with` block.
+///
+/// The hook is installed on entry and the previous behavior is restored on exit, so the
+/// original reports return after the block.
+///
+/// Args:
+///     behavior (str): 'ignore' (default), 'log' or 'propagate'
+///
+/// Raises:
+///     ValueError: If behavior is not one of the three names
+///
+/// Examples:
+///     >>> with RayonConfig():
+///     ...     result = dse_optimized(0.1, 10.0, 0.1, positions)
+#[pyclass(name = "RayonConfig", frozen)]
+pub(super) struct RayonConfig {
+    behavior: PanicBehavior,
+    previous: Mutex<Option<PanicBehavior>>,
+}
+
+#[pymethods]
+impl RayonConfig {
+    #[new]
+    #[pyo3(signature = (behavior="ignore"))]
+    fn new(behavior: &str) -> PyResult<Self> {
+        Ok(Self {
+            behavior: PanicBehavior::parse(behavior)?,
+            previous: Mutex::new(None),
+        })
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        let previous = replace_panic_behavior(slf.behavior);
+        *slf.previous.lock().unwrap() = Some(previous);
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: Option<PyObject>,
+        _exc_value: Option<PyObject>,
+        _traceback: Option<PyObject>,
+    ) {
+        if let Some(previous) = self.previous.lock().unwrap().take() {
+            replace_panic_behavior(previous);
+        }
+    }
+}
@@ -0,0 +1,1321 @@
+use std::f64::consts::PI;
+
+use fast_dse::core::{
+    absorption_correction, apply_anisotropic_disorder, apply_instrument_broadening,
+    apply_modification_function, apply_thermal_disorder, auto_adaptive_q_grid, benchmark_dse,
+    chi_squared_map, classify_surface_atoms, convergence_plot_data, convergence_test,
+    convergence_with_size, coordination_numbers, crystal_core, crystal_core_shell,
+    crystal_cylinder, crystal_ellipsoid, crystal_from_poscar, crystal_from_xyz,
+    crystal_grain_aggregate, crystal_hollow_sphere, crystal_nanorod, crystal_polycrystalline,
+    crystal_random_alloy, crystal_slab, crystal_slab_with_surface_relaxation,
+    crystal_stacking_faults, crystal_truncated_octahedron, dse_adaptive, dse_auto_adaptive,
+    dse_core, dse_custom_q, dse_differential, dse_finite_size_error, dse_histogram, dse_linspace,
+    dse_logspace_q, dse_no_alloc, dse_normalized_by_sphere, dse_powder_average_explicit,
+    dse_shell_decomposition, dse_symmetric, dse_ultra_optimized, dse_ultra_optimized_with_progress,
+    dse_with_background, dse_with_species, estimate_memory_mb, find_peaks,
+    fit_background_parameters, fit_polynomial_background, fit_power_law_background,
+    form_factor_cylinder, form_factor_ellipsoid, form_factor_sphere, from_fractional_coordinates,
+    kratky_peak, kratky_transform, lattice_parameter_from_peak, linear_attenuation_coefficient,
+    lorch_modification_function, max_atoms_for_memory, max_q_for_resolution,
+    min_q_from_crystal_size, moving_average_smooth, normalise_intensity, parse_lammps_dump,
+    parse_lammps_dump_first_frame, partial_dse, partial_pair_correlation, pdf_from_iq,
+    pdf_lorch_transform, porod_invariant, porod_invariant_contributions, q_grid, q_linspace,
+    q_logspace, q_to_twotheta, radial_distribution_function, resample_iq, rotation_matrix_z,
+    savitzky_golay_smooth, scale_to_absolute, scherrer_analysis, structure_factor,
+    subtract_background, surface_fraction, to_cartesian_from_fractional, to_fractional_coordinates,
+    twotheta_to_q, Atoms, CubicLattice, Shape, GRAIN_OVERLAP_DISTANCE,
+};
+
+/// Radius of the continuous sphere with the volume of `n_atoms` simple cubic
+/// unit cells, which is what a lattice sphere scatters like.
+fn equal_volume_radius(n_atoms: usize, lattice_param: f64) -> f64 {
+    (3.0 * n_atoms as f64 / (4.0 * PI)).cbrt() * lattice_param
+}
+
+#[test]
+fn sphere_matches_analytic_form_factor() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 4.0).unwrap();
+    let n = sphere.len();
+    let radius = equal_volume_radius(n, 0.4);
+    let q_values = q_grid(0.1, 5.0, 0.1);
+    let intensities = dse_core(0.1, 5.0, 0.1, sphere.positions());
+    let analytic = form_factor_sphere(&q_values, radius).unwrap();
+
+    for ((&q, &intensity), &expected) in q_values.iter().zip(&intensities).zip(&analytic) {
+        let normalised = intensity / (n * n) as f64;
+        // The relative error diverges at the zeros of the form factor, so it
+        // is checked only below qR = 4, short of the first zero at 4.49; at
+        // every q the curves agree to 0.1% of the forward scattering.
+        if q * radius < 4.0 {
+            assert!(
+                (normalised - expected).abs() <= 0.05 * expected,
+                "q = {}: DSE {} vs analytic {}",
+                q,
+                normalised,
+                expected
+            );
+        }
+        assert!(
+            (normalised - expected).abs() <= 1e-3,
+            "q = {}: DSE {} vs analytic {}",
+            q,
+            normalised,
+            expected
+        );
+    }
+}
+
+#[test]
+fn sphere_kratky_peak_matches_analytic_value() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 4.0).unwrap();
+    let radius_of_gyration = (3.0_f64 / 5.0).sqrt() * equal_volume_radius(sphere.len(), 0.4);
+    let q_values = q_grid(0.5, 2.0, 0.05);
+    let intensities = dse_core(0.5, 2.0, 0.05, sphere.positions());
+    let (q, kratky) = kratky_transform(&q_values, &intensities).unwrap();
+
+    let (q_peak, _) = kratky_peak(&q, &kratky).unwrap();
+    assert!((q_peak * radius_of_gyration - 1.612).abs() < 0.01);
+}
+
+#[test]
+fn cube_has_125_atoms() {
+    let cube = crystal_core(Shape::Cube, 1.0, 5.0).unwrap();
+    assert_eq!(cube.len(), 125);
+}
+
+#[test]
+fn crystal_core_rejects_a_non_positive_lattice_param() {
+    for shape in [Shape::Cube, Shape::Sphere, Shape::Fcc, Shape::Bcc] {
+        for lattice_param in [0.0, -0.4, f64::NAN] {
+            assert!(matches!(
+                crystal_core(shape, lattice_param, 2.0),
+                Err(fast_dse::core::Error::InvalidArgument(_))
+            ));
+        }
+    }
+}
+
+#[test]
+fn hcp_rejects_a_non_positive_c_over_a() {
+    let ideal = (8.0_f64 / 3.0).sqrt();
+    assert!(!crystal_core(Shape::Hcp { c_over_a: ideal }, 0.3, 1.5)
+        .unwrap()
+        .is_empty());
+    for c_over_a in [0.0, -1.0, f64::NAN] {
+        assert!(crystal_core(Shape::Hcp { c_over_a }, 0.3, 1.5).is_err());
+    }
+}
+
+#[test]
+fn shaped_crystals_reject_nan_dimensions() {
+    let nan = f64::NAN;
+    assert!(crystal_core(Shape::Cylinder { aspect_ratio: nan }, 0.4, 2.0).is_err());
+    assert!(crystal_core(Shape::Disk { aspect_ratio: nan }, 0.4, 2.0).is_err());
+    assert!(crystal_cylinder(0.4, nan, 2.0).is_err());
+    assert!(crystal_cylinder(0.4, 2.0, nan).is_err());
+    assert!(crystal_ellipsoid(0.4, 1.0, nan, 1.0).is_err());
+    assert!(crystal_hollow_sphere(0.4, nan, 2.0).is_err());
+    assert!(crystal_hollow_sphere(0.4, 1.0, nan).is_err());
+    assert!(!crystal_hollow_sphere(0.4, 1.0, 2.0).unwrap().is_empty());
+}
+
+#[test]
+fn single_atom_scatters_one_at_every_q() {
+    let intensities = dse_core(0.0, 10.0, 0.5, &[[0.0, 0.0, 0.0]]);
+    assert!(intensities.iter().all(|&i| i == 1.0));
+}
+
+#[test]
+fn instrument_broadening_turns_a_sharp_peak_into_a_gaussian() {
+    let sigma_q = 0.2;
+    let q_values = q_grid(0.0, 10.0, 0.01);
+    let mut intensities = vec![0.0; q_values.len()];
+    intensities[500] = 1.0;
+
+    let broadened = apply_instrument_broadening(&q_values, &intensities, sigma_q).unwrap();
+    let peaks = find_peaks(&q_values, &broadened, 1e-3, 0.0).unwrap();
+    assert_eq!(peaks.len(), 1);
+    assert!((peaks[0].position_q - 5.0).abs() < 1e-9);
+    let expected_fwhm = 2.0 * (2.0 * 2.0_f64.ln()).sqrt() * sigma_q;
+    assert!((peaks[0].fwhm - expected_fwhm).abs() < 1e-3 * expected_fwhm);
+    let total: f64 = broadened.iter().sum();
+    assert!((total - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn instrument_broadening_keeps_a_constant_curve_constant() {
+    let q_values = q_grid(0.1, 5.0, 0.05);
+    let broadened =
+        apply_instrument_broadening(&q_values, &vec![3.0; q_values.len()], 0.5).unwrap();
+    assert!(broadened.iter().all(|&i| (i - 3.0).abs() < 1e-12));
+}
+
+#[test]
+fn polynomial_background_is_recovered_under_an_excluded_peak() {
+    let q_values = q_grid(1.0, 20.0, 0.1);
+    let background: Vec<f64> = q_values
+        .iter()
+        .map(|q| 50.0 - 3.0 * q + 0.1 * q * q)
+        .collect();
+    let signal: Vec<f64> = q_values
+        .iter()
+        .zip(&background)
+        .map(|(q, b)| b + 200.0 * (-(q - 10.0).powi(2) / 0.1).exp())
+        .collect();
+
+    let fitted = fit_polynomial_background(&q_values, &signal, 2, &[(7.0, 13.0)], None).unwrap();
+    for (f, b) in fitted.iter().zip(&background) {
+        assert!((f - b).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn power_law_background_is_recovered_at_high_q() {
+    let q_values = q_grid(0.1, 5.0, 0.05);
+    let intensities: Vec<f64> = q_values.iter().map(|q| 2.5 * q.powf(-4.0)).collect();
+    let (amplitude, exponent) = fit_power_law_background(&q_values, &intensities, 2.0).unwrap();
+    assert!((amplitude - 2.5).abs() < 1e-9);
+    assert!((exponent + 4.0).abs() < 1e-12);
+}
+
+#[test]
+fn fcc_slab_has_four_atoms_per_cell() {
+    let slab = crystal_slab(1.0, 4.0, 3.0, 2.0, CubicLattice::FaceCentred).unwrap();
+    assert_eq!(slab.len(), 4 * 3 * 2 * 4);
+}
+
+#[test]
+fn surface_relaxation_moves_only_the_outer_layers() {
+    let slab =
+        crystal_slab_with_surface_relaxation(1.0, 2.0, 2.0, 6.0, CubicLattice::Simple, 1, 0.1)
+            .unwrap();
+    let mut layers: Vec<f64> = slab.positions().iter().map(|p| p[2]).collect();
+    layers.sort_by(f64::total_cmp);
+    layers.dedup();
+    let expected = [0.1, 1.0, 2.0, 3.0, 4.0, 4.9];
+    assert_eq!(layers.len(), expected.len());
+    for (z, e) in layers.iter().zip(expected) {
+        assert!((z - e).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn chi_squared_map_is_zero_at_the_true_parameters() {
+    let (min_q, max_q, q_step) = (0.5, 5.0, 0.5);
+    let truth = crystal_core(Shape::Sphere, 0.4, 3.0).unwrap();
+    let i_obs = dse_core(min_q, max_q, q_step, truth.positions());
+    let sigma = vec![1.0; i_obs.len()];
+
+    let map = chi_squared_map(
+        min_q,
+        max_q,
+        q_step,
+        Shape::Sphere,
+        &[0.38, 0.4, 0.42],
+        &[2.6, 3.0, 3.4],
+        &i_obs,
+        &sigma,
+    )
+    .unwrap();
+    assert_eq!(map.len(), 3);
+    assert!(map.iter().all(|row| row.len() == 3));
+    assert!(map[1][1].abs() < 1e-12);
+    let elsewhere = map.iter().flatten().filter(|&&chi2| chi2 > 1.0).count();
+    assert_eq!(elsewhere, 8);
+}
+
+#[test]
+fn explicit_powder_average_converges_to_the_debye_sum() {
+    let cube = crystal_core(Shape::Cube, 0.4, 2.0).unwrap();
+    let debye = dse_core(0.5, 10.0, 0.5, cube.positions());
+    let error = |n_orientations| {
+        let explicit =
+            dse_powder_average_explicit(0.5, 10.0, 0.5, cube.positions(), n_orientations);
+        explicit
+            .iter()
+            .zip(&debye)
+            .map(|(e, d)| ((e - d) / d).abs())
+            .fold(0.0, f64::max)
+    };
+    let (coarse, fine) = (error(100), error(10_000));
+    assert!(fine < coarse);
+    assert!(fine < 1e-4);
+}
+
+#[test]
+fn lattice_parameter_is_recovered_from_a_gaussian_peak() {
+    let lattice_param: f64 = 0.408;
+    let q_111 = 2.0 * PI * 3.0_f64.sqrt() / lattice_param;
+    let q_values = q_grid(20.0, 35.0, 0.02);
+    let intensities: Vec<f64> = q_values
+        .iter()
+        .map(|q| 10.0 + 500.0 * (-(q - q_111).powi(2) / (2.0 * 0.3 * 0.3)).exp())
+        .collect();
+
+    let (a, sigma_a) =
+        lattice_parameter_from_peak(&q_values, &intensities, [1, 1, 1], 0.4).unwrap();
+    assert!((a - lattice_param).abs() < 1e-5);
+    assert!(sigma_a > 0.0 && sigma_a < 1e-5);
+}
+
+#[test]
+fn background_parameters_are_recovered_from_dse_with_background() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 3.0).unwrap();
+    let (min_q, max_q, q_step) = (0.5, 8.0, 0.25);
+    let model = dse_core(min_q, max_q, q_step, sphere.positions());
+    let observed = dse_with_background(min_q, max_q, q_step, sphere.positions(), 40.0, 0.75);
+
+    let (incoherent_bg, thermal_bg_coeff) =
+        fit_background_parameters(&q_grid(min_q, max_q, q_step), &observed, &model).unwrap();
+    assert!((incoherent_bg - 40.0).abs() < 1e-9);
+    assert!((thermal_bg_coeff - 0.75).abs() < 1e-9);
+}
+
+#[test]
+fn savitzky_golay_keeps_a_cubic_away_from_the_edges() {
+    let cubic: Vec<f64> = (0..40)
+        .map(|i| {
+            let x = i as f64 * 0.1;
+            2.0 - x + 0.5 * x * x - 0.05 * x * x * x
+        })
+        .collect();
+    let smoothed = savitzky_golay_smooth(&cubic, 9, 3).unwrap();
+    assert_eq!(smoothed.len(), cubic.len());
+    for (s, c) in smoothed.iter().zip(&cubic).skip(4).take(cubic.len() - 8) {
+        assert!((s - c).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn moving_average_mirrors_the_edges() {
+    let smoothed = moving_average_smooth(&[3.0, 0.0, 0.0, 6.0], 3).unwrap();
+    for (s, e) in smoothed.iter().zip([1.0, 1.0, 2.0, 2.0]) {
+        assert!((s - e).abs() < 1e-12);
+    }
+    assert!(moving_average_smooth(&[1.0, 2.0], 2).is_err());
+}
+
+fn assert_positions(actual: &[[f64; 3]], expected: &[[f64; 3]]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        for (x, y) in a.iter().zip(e) {
+            assert!((x - y).abs() < 1e-12, "{:?} vs {:?}", a, e);
+        }
+    }
+}
+
+#[test]
+fn xyz_rows_are_converted_to_nm_and_must_match_the_count() {
+    let xyz = "2\nwater fragment\nO 0.0 0.0 0.0\nH 9.0 -1.0 2.5 extra\n";
+    let atoms = crystal_from_xyz(xyz).unwrap();
+    assert_positions(atoms.positions(), &[[0.0, 0.0, 0.0], [0.9, -0.1, 0.25]]);
+
+    // A corrupt count must not be trusted for the allocation.
+    let huge = xyz.replacen('2', "99999999999999", 1);
+    assert!(matches!(
+        crystal_from_xyz(&huge),
+        Err(fast_dse::core::Error::Parse { line: 5, .. })
+    ));
+}
+
+#[test]
+fn lammps_dump_frames_are_converted_to_nm() {
+    let dump = "\
+ITEM: TIMESTEP
+0
+ITEM: NUMBER OF ATOMS
+2
+ITEM: BOX BOUNDS pp pp pp
+-5.0 5.0
+0.0 20.0
+0.0 4.0
+ITEM: ATOMS id type xs ys zs
+1 1 0.5 0.5 0.5
+2 1 0.0 0.25 1.0
+ITEM: TIMESTEP
+100
+ITEM: NUMBER OF ATOMS
+1
+ITEM: BOX BOUNDS pp pp pp
+0.0 10.0
+0.0 10.0
+0.0 10.0
+ITEM: ATOMS id type x y z
+1 1 1.0 2.0 3.0
+";
+    let frames = parse_lammps_dump(dump).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_positions(frames[0].positions(), &[[0.0, 1.0, 0.2], [-0.5, 0.5, 0.4]]);
+    assert_positions(frames[1].positions(), &[[0.1, 0.2, 0.3]]);
+    let first = parse_lammps_dump_first_frame(dump).unwrap();
+    assert_positions(first.positions(), frames[0].positions());
+    assert!(parse_lammps_dump_first_frame("").is_err());
+    // A corrupt count must not be trusted for the allocation.
+    let huge = dump.replacen("ATOMS\n2\n", "ATOMS\n99999999999999\n", 1);
+    assert!(matches!(
+        parse_lammps_dump(&huge),
+        Err(fast_dse::core::Error::Parse { .. })
+    ));
+}
+
+#[test]
+fn lammps_dump_scaled_coordinates_in_a_triclinic_box() {
+    // Cell edges a = (10, 0, 0), b = (2, 10, 0) and c = (-1, 3, 10) from the
+    // origin, whose bounding box is x in [-1, 12] and y in [0, 13].
+    let dump = "\
+ITEM: TIMESTEP
+0
+ITEM: NUMBER OF ATOMS
+3
+ITEM: BOX BOUNDS xy xz yz pp pp pp
+-1.0 12.0 2.0
+0.0 13.0 -1.0
+0.0 10.0 3.0
+ITEM: ATOMS id type xs ys zs
+1 1 0.0 0.0 0.0
+2 1 0.5 0.5 0.5
+3 1 0.0 1.0 1.0
+";
+    let atoms = parse_lammps_dump_first_frame(dump).unwrap();
+    assert_positions(
+        atoms.positions(),
+        &[[0.0, 0.0, 0.0], [0.55, 0.65, 0.5], [0.1, 1.3, 1.0]],
+    );
+}
+
+#[test]
+fn min_q_from_crystal_size_resolves_the_guinier_region() {
+    // Two atoms 2 nm apart have R_g = 1 nm.
+    let pair = [[1.0, 5.0, 5.0], [3.0, 5.0, 5.0]];
+    assert!((min_q_from_crystal_size(&pair).unwrap() - 0.2).abs() < 1e-12);
+    assert!(min_q_from_crystal_size(&[[1.0, 2.0, 3.0]]).is_err());
+
+    let sphere = crystal_core(Shape::Sphere, 0.4, 4.0).unwrap();
+    let min_q = min_q_from_crystal_size(sphere.positions()).unwrap();
+    let rg = (3.0_f64 / 5.0).sqrt() * equal_volume_radius(sphere.len(), 0.4);
+    assert!((min_q * rg - 0.2).abs() < 0.01);
+
+    let max_q = max_q_for_resolution(0.5, 2.0).unwrap();
+    assert!((max_q - 8.0 * PI).abs() < 1e-12);
+    assert!(max_q_for_resolution(0.0, 2.0).is_err());
+}
+
+#[test]
+fn dse_ultra_optimized_matches_dse_core() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 3.0).unwrap();
+    let reference = dse_core(0.0, 30.0, 0.25, sphere.positions());
+    let intensities = dse_ultra_optimized(0.0, 30.0, 0.25, sphere.positions());
+    assert_eq!(intensities.len(), reference.len());
+    for (value, expected) in intensities.iter().zip(&reference) {
+        assert!((value - expected).abs() <= 1e-9 * reference[0]);
+    }
+    // I(0) = N^2, with the N self-pairs added analytically.
+    let n_atoms = sphere.len() as f64;
+    assert!((intensities[0] - n_atoms * n_atoms).abs() < 1e-6);
+    assert_eq!(
+        dse_ultra_optimized(0.1, 1.0, 0.1, &[[1.0, 2.0, 3.0]]),
+        vec![1.0; 10]
+    );
+
+    let reports = std::sync::Mutex::new(Vec::new());
+    let progress =
+        |completed: usize, total: usize| reports.lock().unwrap().push((completed, total));
+    let chunked =
+        dse_ultra_optimized_with_progress(0.0, 30.0, 0.25, sphere.positions(), 50, &progress);
+    assert_eq!(chunked, intensities);
+    assert_eq!(
+        *reports.lock().unwrap(),
+        [(50, 121), (100, 121), (121, 121)]
+    );
+}
+
+#[test]
+fn dse_no_alloc_matches_dse_core_for_any_chunk_size() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 2.5).unwrap();
+    let reference = dse_core(0.0, 30.0, 0.5, sphere.positions());
+    // Chunks of one atom, not dividing N, and larger than the crystal.
+    for chunk_size in [1, 7, sphere.len() + 1] {
+        let intensities = dse_no_alloc(0.0, 30.0, 0.5, sphere.positions(), chunk_size).unwrap();
+        assert_eq!(intensities.len(), reference.len());
+        for (value, expected) in intensities.iter().zip(&reference) {
+            assert!((value - expected).abs() <= 1e-9 * reference[0]);
+        }
+    }
+    assert!(dse_no_alloc(0.0, 30.0, 0.5, sphere.positions(), 0).is_err());
+}
+
+#[test]
+fn dse_symmetric_matches_dse_core() {
+    let fcc = crystal_core(Shape::Fcc, 0.408, 2.0).unwrap();
+    let reference = dse_core(0.0, 40.0, 0.5, fcc.positions());
+    let intensities = dse_symmetric(0.0, 40.0, 0.5, fcc.positions());
+    assert_eq!(intensities.len(), reference.len());
+    for (value, expected) in intensities.iter().zip(&reference) {
+        assert!((value - expected).abs() <= 1e-9 * reference[0]);
+    }
+    assert_eq!(
+        dse_symmetric(0.1, 1.0, 0.1, &[[1.0, 2.0, 3.0]]),
+        vec![1.0; 10]
+    );
+    assert!(dse_symmetric(0.1, 1.0, 0.1, &[]).iter().all(|&i| i == 0.0));
+}
+
+#[test]
+fn max_atoms_for_memory_inverts_estimate_memory_mb() {
+    // The 8 N^2 byte distance matrix dominates: 15 625 atoms take about 1.95 GB.
+    let estimate = estimate_memory_mb(15_625, 1000);
+    assert!(estimate > 1953.125 && estimate < 1955.0);
+    assert!(estimate_memory_mb(1000, 2000) > estimate_memory_mb(1000, 1000));
+
+    for budget in [0.001, 1.0, 2000.0, 64_000.0] {
+        let n_atoms = max_atoms_for_memory(budget);
+        assert!(estimate_memory_mb(n_atoms, 0) <= budget);
+        assert!(estimate_memory_mb(n_atoms + 1, 0) > budget);
+    }
+    assert_eq!(max_atoms_for_memory(2000.0), 15_806);
+    assert_eq!(max_atoms_for_memory(-1.0), 0);
+    assert_eq!(max_atoms_for_memory(f64::NAN), 0);
+}
+
+#[test]
+fn linear_attenuation_coefficient_interpolates_the_table() {
+    // Fe at 8 keV: mu / rho = 305.2 cm^2/g and rho = 7.874 g/cm^3.
+    let mu = linear_attenuation_coefficient("Fe", 8.0).unwrap();
+    assert!((mu - 305.2 * 7.874e-7).abs() < 1e-12);
+    // The Fe K edge at 7.112 keV multiplies the absorption.
+    let below = linear_attenuation_coefficient("Fe", 7.1).unwrap();
+    let above = linear_attenuation_coefficient("Fe", 7.12).unwrap();
+    assert!(above > 5.0 * below);
+    assert!(linear_attenuation_coefficient("Xe", 8.0).is_err());
+    assert!(linear_attenuation_coefficient("Fe", 150.0).is_err());
+}
+
+#[test]
+fn absorption_correction_grows_with_the_path_length() {
+    let wavelength = 0.1;
+    let q_theta_60 = 4.0 * PI / wavelength * (PI / 3.0).sin();
+    let corrected =
+        absorption_correction(&[0.0, q_theta_60], &[2.0, 2.0], 1e-4, 5000.0, wavelength).unwrap();
+    assert!((corrected[0] - 2.0 * 0.5_f64.exp()).abs() < 1e-12);
+    assert!((corrected[1] - 2.0 * 1.0_f64.exp()).abs() < 1e-12);
+    assert!(
+        absorption_correction(&[4.0 * PI / wavelength], &[1.0], 1e-4, 1.0, wavelength).is_err()
+    );
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn dse_gpu_matches_dse_core() {
+    let cube = crystal_core(Shape::Cube, 0.4, 2.0).unwrap();
+    let expected = dse_core(0.5, 20.0, 0.5, cube.positions());
+    let intensities = match fast_dse::core::dse_gpu(0.5, 20.0, 0.5, cube.positions()) {
+        Ok(intensities) => intensities,
+        // No GPU adapter in this environment.
+        Err(fast_dse::core::Error::Gpu(_)) => return,
+        Err(err) => panic!("{}", err),
+    };
+    assert_eq!(intensities.len(), expected.len());
+    for (gpu, cpu) in intensities.iter().zip(&expected) {
+        assert!(
+            (gpu - cpu).abs() <= 1e-4 * expected[0],
+            "{} vs {}",
+            gpu,
+            cpu
+        );
+    }
+}
+
+#[test]
+fn random_alloy_composition_is_within_binomial_fluctuations() {
+    let (atoms, species) = crystal_random_alloy(Shape::Fcc, 0.408, 6.0, 0.3, 11).unwrap();
+    assert_eq!(species.len(), atoms.len());
+    assert!(species.iter().all(|&s| s <= 1));
+    let n = atoms.len() as f64;
+    let n_a = species.iter().filter(|&&s| s == 0).count() as f64;
+    let sigma = (n * 0.3 * 0.7).sqrt();
+    assert!(
+        (n_a - 0.3 * n).abs() < 4.0 * sigma,
+        "{} A atoms of {}",
+        n_a,
+        n
+    );
+    assert!(crystal_random_alloy(Shape::Fcc, 0.408, 6.0, 1.5, 11).is_err());
+}
+
+#[test]
+fn cylinder_and_ellipsoid_form_factors_follow_the_guinier_law() {
+    // P(q) ~ 1 - (q R_g)^2 / 3 at low q, with R_g^2 = R^2 / 2 + H^2 / 12 for
+    // a cylinder and (a^2 + b^2 + c^2) / 5 for an ellipsoid.
+    let q = [0.0, 0.005];
+    let cylinder = form_factor_cylinder(&q, 2.0, 6.0).unwrap();
+    let ellipsoid = form_factor_ellipsoid(&q, 1.0, 2.0, 3.0).unwrap();
+    assert!((cylinder[0] - 1.0).abs() < 1e-9 && (ellipsoid[0] - 1.0).abs() < 1e-9);
+    let guinier = |rg_sq: f64| 1.0 - q[1] * q[1] * rg_sq / 3.0;
+    assert!((cylinder[1] - guinier(2.0 + 3.0)).abs() < 1e-6);
+    assert!((ellipsoid[1] - guinier(14.0 / 5.0)).abs() < 1e-6);
+}
+
+#[test]
+fn ellipsoid_with_equal_semi_axes_is_a_sphere() {
+    let q_values = q_grid(0.1, 10.0, 0.3);
+    let sphere = form_factor_sphere(&q_values, 1.5).unwrap();
+    let ellipsoid = form_factor_ellipsoid(&q_values, 1.5, 1.5, 1.5).unwrap();
+    for (s, e) in sphere.iter().zip(&ellipsoid) {
+        assert!((s - e).abs() < 1e-12);
+    }
+    assert!(form_factor_cylinder(&q_values, 1.0, 0.0).is_err());
+}
+
+#[test]
+fn dse_differential_matches_two_full_evaluations() {
+    let cube = crystal_core(Shape::Cube, 0.4, 1.6).unwrap();
+    let displacement = [0.05, -0.02, 0.11];
+    let mut moved = cube.positions().to_vec();
+    for (x, d) in moved[7].iter_mut().zip(displacement) {
+        *x += d;
+    }
+    let before = dse_core(0.5, 30.0, 0.5, cube.positions());
+    let after = dse_core(0.5, 30.0, 0.5, &moved);
+    let delta = dse_differential(0.5, 30.0, 0.5, cube.positions(), 7, displacement).unwrap();
+    for ((d, a), b) in delta.iter().zip(&after).zip(&before) {
+        assert!((d - (a - b)).abs() < 1e-9, "{} vs {}", d, a - b);
+    }
+    assert!(dse_differential(0.5, 30.0, 0.5, cube.positions(), cube.len(), displacement).is_err());
+}
+
+#[test]
+fn twotheta_conversion_round_trips_and_resample_interpolates() {
+    // Cu K-alpha: the fcc Au (111) reflection at q = 26.67 1/nm lies near 38.2 degrees.
+    let wavelength = 0.15406;
+    let twotheta = q_to_twotheta(&[0.0, 26.67, 4.0 * PI / wavelength], wavelength).unwrap();
+    assert_eq!(twotheta[0], 0.0);
+    assert!((twotheta[1] - 38.2).abs() < 0.1);
+    assert!((twotheta[2] - 180.0).abs() < 1e-9);
+    let q = twotheta_to_q(&twotheta, wavelength).unwrap();
+    assert!((q[1] - 26.67).abs() < 1e-12);
+    assert!(q_to_twotheta(&[4.0 * PI / wavelength * 1.001], wavelength).is_err());
+    assert!(twotheta_to_q(&[30.0], 0.0).is_err());
+
+    let q_in = [1.0, 2.0, 4.0];
+    let i_in = [10.0, 20.0, 0.0];
+    let resampled = resample_iq(&q_in, &i_in, &[1.0, 1.5, 3.0, 4.0]).unwrap();
+    assert_eq!(resampled, vec![10.0, 15.0, 10.0, 0.0]);
+    assert!(resample_iq(&q_in, &i_in, &[4.5]).is_err());
+    assert!(resample_iq(&[2.0, 1.0], &[1.0, 1.0], &[1.5]).is_err());
+}
+
+#[test]
+fn fractional_coordinates_round_trip() {
+    let cube = crystal_core(Shape::Cube, 0.4, 1.2).unwrap();
+    let fractional = to_fractional_coordinates(cube.positions(), 1.2, 1.2, 1.2).unwrap();
+    assert!(fractional
+        .positions()
+        .iter()
+        .flatten()
+        .all(|&x| (0.0..=1.0).contains(&x)));
+    let back = from_fractional_coordinates(fractional.positions(), 1.2, 1.2, 1.2).unwrap();
+    for (p, q) in back.positions().iter().zip(cube.positions()) {
+        assert!((0..3).all(|axis| (p[axis] - q[axis]).abs() < 1e-12));
+    }
+    assert!(to_fractional_coordinates(cube.positions(), 1.2, 0.0, 1.2).is_err());
+
+    // Hexagonal cell: the fractional (1/3, 2/3, 1/2) site of hcp.
+    let a = 0.25;
+    let lattice = [
+        [a, 0.0, 0.0],
+        [-a / 2.0, a * 3f64.sqrt() / 2.0, 0.0],
+        [0.0, 0.0, 0.41],
+    ];
+    let site = to_cartesian_from_fractional(&[[1.0 / 3.0, 2.0 / 3.0, 0.5]], &lattice).unwrap();
+    let expected = [0.0, a / 3f64.sqrt(), 0.205];
+    assert!((0..3).all(|axis| (site.positions()[0][axis] - expected[axis]).abs() < 1e-12));
+    let coplanar = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    assert!(to_cartesian_from_fractional(&[[0.0; 3]], &coplanar).is_err());
+}
+
+#[test]
+fn benchmark_dse_times_every_implementation() {
+    let fcc = crystal_core(Shape::Fcc, 0.408, 1.5).unwrap();
+    let timings = benchmark_dse(0.0, 30.0, 1.0, fcc.positions(), 2).unwrap();
+    let names: Vec<_> = timings.iter().map(|(name, _)| *name).collect();
+    assert_eq!(
+        names,
+        [
+            "dse_serial",
+            "dse_optimized",
+            "dse_ultra_optimized",
+            "dse_symmetric",
+            "dse_no_alloc",
+            "dse_simd"
+        ]
+    );
+    assert!(timings.iter().all(|&(_, seconds)| seconds >= 0.0));
+    assert!(benchmark_dse(0.0, 30.0, 1.0, fcc.positions(), 0).is_err());
+}
+
+#[test]
+fn crystal_nanorod_is_a_cylinder_with_hemispherical_caps() {
+    let (lattice_param, diameter, total_length) = (0.25, 4.0, 12.0);
+    let rod = crystal_nanorod(lattice_param, diameter, total_length).unwrap();
+    let cylinder = crystal_cylinder(lattice_param, diameter, total_length).unwrap();
+    // The caps trim the ends of the enclosing cylinder.
+    assert!(rod.len() < cylinder.len());
+    let radius = diameter / 2.0;
+    for p in rod.positions() {
+        let dz = (radius - p[2]).max(p[2] - (total_length - radius)).max(0.0);
+        let r_sq = (p[0] - radius).powi(2) + (p[1] - radius).powi(2) + dz * dz;
+        assert!(r_sq <= radius * radius + 1e-12);
+    }
+    // Close to the volume of the capsule, pi r^2 (L - 2r) + 4/3 pi r^3, per cell.
+    let volume = PI * radius.powi(2) * (total_length - diameter) + 4.0 / 3.0 * PI * radius.powi(3);
+    let expected = volume / lattice_param.powi(3);
+    assert!((rod.len() as f64 - expected).abs() < 0.05 * expected);
+
+    assert!(crystal_nanorod(lattice_param, 4.0, 4.0).is_err());
+    assert!(crystal_nanorod(lattice_param, 0.0, 4.0).is_err());
+}
+
+#[test]
+fn dse_adaptive_concatenates_segments() {
+    let cube = crystal_core(Shape::Cube, 0.4, 2.0).unwrap();
+    let (q, intensities) =
+        dse_adaptive(cube.positions(), &[(1.0, 5.0, 1.0), (5.0, 6.0, 0.5)]).unwrap();
+    assert_eq!(q, vec![1.0, 2.0, 3.0, 4.0, 5.0, 5.0, 5.5, 6.0]);
+    let coarse = dse_core(1.0, 5.0, 1.0, cube.positions());
+    let fine = dse_core(5.0, 6.0, 0.5, cube.positions());
+    let expected: Vec<f64> = coarse.into_iter().chain(fine).collect();
+    for (value, reference) in intensities.iter().zip(&expected) {
+        assert!((value - reference).abs() <= 1e-9 * reference.abs().max(1.0));
+    }
+    assert!(dse_adaptive(cube.positions(), &[(2.0, 1.0, 0.1)]).is_err());
+    assert!(dse_adaptive(cube.positions(), &[(1.0, 2.0, 0.0)]).is_err());
+}
+
+#[test]
+fn auto_adaptive_q_grid_is_finer_around_bragg_peaks() {
+    let lattice_param = 0.4;
+    let q_unit = 2.0 * PI / lattice_param;
+    let q = auto_adaptive_q_grid(5.0, 60.0, 400, lattice_param).unwrap();
+    assert_eq!(q[0], 5.0);
+    assert_eq!(*q.last().unwrap(), 60.0);
+    assert!(q.windows(2).all(|w| w[1] > w[0]));
+    assert!(q.len().abs_diff(400) <= 20);
+
+    let step_near = |q0: f64| {
+        let i = q.partition_point(|&x| x < q0);
+        q[i] - q[i - 1]
+    };
+    // The (100) peak at q_unit against the featureless range below it. Rounding each
+    // segment to whole steps moves the ratio slightly off 10.
+    let fine = step_near(q_unit);
+    let coarse = step_near(10.0);
+    assert!((coarse / fine - 10.0).abs() < 1.0);
+
+    // 7 = 4^0 (8 * 0 + 7) is not a sum of three squares, so sqrt(7) q_unit gets no window.
+    assert!((step_near(7f64.sqrt() * q_unit) / coarse - 1.0).abs() < 0.2);
+
+    let cube = crystal_core(Shape::Cube, lattice_param, 2.0).unwrap();
+    let (q_auto, intensities) =
+        dse_auto_adaptive(cube.positions(), 5.0, 60.0, 400, lattice_param).unwrap();
+    assert_eq!(q_auto, q);
+    assert_eq!(intensities.len(), q.len());
+    assert!(auto_adaptive_q_grid(5.0, 5.0, 400, lattice_param).is_err());
+    assert!(auto_adaptive_q_grid(5.0, 60.0, 1, lattice_param).is_err());
+}
+
+#[test]
+fn classify_surface_atoms_peels_the_outer_layers() {
+    // 5 x 5 x 5 simple cubic atoms: 3^3 lie below the outer layer and 1 below two.
+    let cube = crystal_core(Shape::Cube, 0.4, 2.0).unwrap();
+    assert_eq!(cube.len(), 125);
+    let count = |probe_radius| {
+        classify_surface_atoms(cube.positions(), probe_radius)
+            .unwrap()
+            .into_iter()
+            .filter(|&s| s)
+            .count()
+    };
+    assert_eq!(count(0.0), 125 - 27);
+    assert_eq!(count(0.5), 125 - 1);
+    assert!(classify_surface_atoms(cube.positions(), -0.1).is_err());
+
+    // The fraction of surface atoms falls as N^(-1/3) for compact shapes.
+    let small = crystal_core(Shape::Sphere, 0.4, 4.0).unwrap();
+    let large = crystal_core(Shape::Sphere, 0.4, 8.0).unwrap();
+    let ratio = surface_fraction(small.positions(), 0.2).unwrap()
+        / surface_fraction(large.positions(), 0.2).unwrap();
+    let expected = (large.len() as f64 / small.len() as f64).cbrt();
+    assert!((ratio / expected - 1.0).abs() < 0.25);
+    assert!(surface_fraction(&[], 0.2).is_err());
+}
+
+#[test]
+fn finite_size_error_falls_as_the_lattice_gets_finer() {
+    // Below the first minimum of the sphere form factor, at q R ~ 4.49.
+    let (min_q, max_q, q_step) = (0.1, 1.4, 0.05);
+    let errors =
+        convergence_with_size(Shape::Sphere, &[0.6, 0.4, 0.3], 6.0, min_q, max_q, q_step).unwrap();
+    assert!(errors[0] < 0.05);
+    assert!(errors[2] < errors[1] && errors[1] < errors[0]);
+    assert!(errors[2] < 2e-3);
+
+    let sphere = crystal_core(Shape::Sphere, 0.3, 6.0).unwrap();
+    let per_q = dse_finite_size_error(min_q, max_q, q_step, sphere.positions(), Shape::Sphere, 3.0)
+        .unwrap();
+    assert_eq!(per_q.len(), q_grid(min_q, max_q, q_step).len());
+    assert!(per_q[0] < 1e-2);
+
+    assert!(convergence_with_size(Shape::Fcc, &[0.4], 6.0, min_q, max_q, q_step).is_err());
+    assert!(convergence_with_size(Shape::Sphere, &[0.0], 6.0, min_q, max_q, q_step).is_err());
+    assert!(dse_finite_size_error(min_q, max_q, q_step, &[], Shape::Sphere, 3.0).is_err());
+}
+
+#[test]
+fn scherrer_analysis_recovers_the_width_of_a_peak() {
+    let wavelength = 0.15406;
+    // Gaussian of FWHM 0.3 1/nm on a sloping background.
+    let fwhm = 0.3;
+    let sigma = fwhm / (8.0 * 2f64.ln()).sqrt();
+    let q_values: Vec<f64> = (0..1000).map(|i| 20.0 + 0.01 * i as f64).collect();
+    let intensities: Vec<f64> = q_values
+        .iter()
+        .map(|&q| 5.0 + 0.1 * q + 100.0 * (-(q - 25.0).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let (length, sigma_length) =
+        scherrer_analysis(&q_values, &intensities, 25.0, wavelength, 0.9).unwrap();
+    // To first order L = 2 pi K / FWHM_q.
+    assert!((length / (2.0 * PI * 0.9 / fwhm) - 1.0).abs() < 1e-4);
+    assert!(sigma_length < 1e-2);
+
+    // The (111) peak of a 3 nm FCC cube gives a coherence length near its size.
+    let cube = crystal_core(Shape::Fcc, 0.408, 3.0).unwrap();
+    let q_values = q_grid(22.0, 32.0, 0.05);
+    let intensities = dse_core(22.0, 32.0, 0.05, cube.positions());
+    let q_111 = 2.0 * PI * 3f64.sqrt() / 0.408;
+    let (length, _) = scherrer_analysis(&q_values, &intensities, q_111, wavelength, 0.9).unwrap();
+    assert!((2.4..3.6).contains(&length));
+
+    assert!(scherrer_analysis(&q_values, &intensities, 40.0, wavelength, 0.9).is_err());
+    assert!(scherrer_analysis(&q_values, &intensities, q_111, wavelength, 0.0).is_err());
+}
+
+#[test]
+fn partials_recombine_into_the_species_weighted_totals() {
+    let (alloy, species) = crystal_random_alloy(Shape::Fcc, 0.408, 1.6, 0.4, 7).unwrap();
+    let positions = alloy.positions();
+    let weights = [2.0, -0.5];
+    let (min_q, max_q, q_step) = (0.5, 40.0, 0.5);
+    let total = dse_with_species(min_q, max_q, q_step, positions, &species, &weights).unwrap();
+    let partial = |a: u32, b: u32| partial_dse(min_q, max_q, q_step, positions, &species, a, b);
+    let (i_aa, i_ab, i_ba, i_bb) = (
+        partial(0, 0).unwrap(),
+        partial(0, 1).unwrap(),
+        partial(1, 0).unwrap(),
+        partial(1, 1).unwrap(),
+    );
+    for k in 0..total.len() {
+        assert!((i_ab[k] - i_ba[k]).abs() < 1e-9 * total[0].abs());
+        let recombined = weights[0] * weights[0] * i_aa[k]
+            + 2.0 * weights[0] * weights[1] * i_ab[k]
+            + weights[1] * weights[1] * i_bb[k];
+        assert!((recombined - total[k]).abs() < 1e-9 * total[0].abs());
+    }
+    assert!(partial(0, 2).unwrap().iter().all(|&i| i == 0.0));
+    assert!(partial_dse(min_q, max_q, q_step, positions, &species[1..], 0, 1).is_err());
+    assert!(partial_pair_correlation(positions, &species, 0.0, 1.5, f64::NAN, 0, 1).is_err());
+
+    let n = positions.len() as f64;
+    let c_a = species.iter().filter(|&&s| s == 0).count() as f64 / n;
+    let c = [c_a, 1.0 - c_a];
+    let (r_values, g_r) = radial_distribution_function(positions, 0.0, 1.5, 0.05).unwrap();
+    for (r_min, r_max, dr) in [
+        (0.0, 1.5, f64::NAN),
+        (f64::NAN, 1.5, 0.05),
+        (0.0, f64::NAN, 0.05),
+        (0.0, f64::INFINITY, 0.05),
+        (0.0, 1.5, 1e-300),
+    ] {
+        assert!(radial_distribution_function(positions, r_min, r_max, dr).is_err());
+    }
+    let mut g_sum = vec![0.0; r_values.len()];
+    for a in 0..2u32 {
+        for b in 0..2u32 {
+            let (r_ab, g_ab) =
+                partial_pair_correlation(positions, &species, 0.0, 1.5, 0.05, a, b).unwrap();
+            assert_eq!(r_ab, r_values);
+            for (s, g) in g_sum.iter_mut().zip(g_ab) {
+                *s += c[a as usize] * c[b as usize] * g;
+            }
+        }
+    }
+    for (s, g) in g_sum.iter().zip(&g_r) {
+        assert!((s - g).abs() < 1e-9 * g.max(1.0));
+    }
+    assert!(partial_pair_correlation(positions, &species, 0.0, 1.5, 0.05, 0, 2).is_err());
+}
+
+#[test]
+fn pdf_lorch_transform_finds_the_nearest_neighbour_distance() {
+    let lattice_param = 0.408;
+    let fcc = crystal_core(Shape::Fcc, lattice_param, 2.0).unwrap();
+    let (min_q, max_q, q_step) = (0.1, 80.0, 0.1);
+    let q_values = q_grid(min_q, max_q, q_step);
+    let s_q = structure_factor(min_q, max_q, q_step, fcc.positions()).unwrap();
+
+    let lorch = lorch_modification_function(&[0.0, 30.0, 60.0], 60.0).unwrap();
+    assert!((lorch[0] - 1.0).abs() < 1e-12 && (lorch[1] - 2.0 / PI).abs() < 1e-12);
+    assert!(lorch[2].abs() < 1e-12);
+    assert_eq!(
+        apply_modification_function(&[1.0, 2.0], &[3.0, 4.0], &[0.5, 0.25]).unwrap(),
+        vec![1.5, 1.0]
+    );
+    assert!(apply_modification_function(&[1.0, 2.0], &[3.0], &[0.5, 0.25]).is_err());
+
+    // The transform of I / N over the whole range is pdf_from_iq.
+    let q_last = *q_values.last().unwrap();
+    let (_, g_r) = pdf_lorch_transform(&q_values, &s_q, 0.1, 0.6, 0.002, q_last).unwrap();
+    let intensities: Vec<f64> = s_q.iter().map(|s| s * fcc.len() as f64).collect();
+    let (_, g_from_iq) = pdf_from_iq(&q_values, &intensities, 0.1, 0.6, 0.002, fcc.len()).unwrap();
+    for (a, b) in g_r.iter().zip(&g_from_iq) {
+        assert!((a - b).abs() < 1e-9 * (1.0 + b.abs()));
+    }
+
+    // Up to the second shell, a truncated transform still peaks at the nearest-neighbour
+    // distance a / sqrt(2).
+    for q_max in [q_last, 40.0] {
+        let (r_values, g_r) =
+            pdf_lorch_transform(&q_values, &s_q, 0.1, 0.38, 0.002, q_max).unwrap();
+        let peak = r_values[(0..g_r.len())
+            .max_by(|&a, &b| g_r[a].total_cmp(&g_r[b]))
+            .unwrap()];
+        assert!((peak - lattice_param / 2f64.sqrt()).abs() < 0.01);
+    }
+    assert!(pdf_lorch_transform(&q_values, &s_q, 0.1, 0.6, 0.002, 0.0).is_err());
+    assert!(pdf_lorch_transform(&q_values, &s_q, 0.1, 0.6, 0.002, 0.15).is_err());
+    assert!(pdf_lorch_transform(&q_values, &s_q, 0.1, 0.6, f64::NAN, q_last).is_err());
+}
+
+#[test]
+fn crystal_stacking_faults_follows_the_stacking_sequence() {
+    let lattice_param = 0.408;
+    let layer_height = lattice_param / 3f64.sqrt();
+    // Lateral positions of each layer, rounded to compare sites.
+    let layers = |atoms: &Atoms, n_layers: usize| -> Vec<Vec<[i64; 2]>> {
+        (0..n_layers)
+            .map(|k| {
+                let mut sites: Vec<[i64; 2]> = atoms
+                    .positions()
+                    .iter()
+                    .filter(|p| (p[2] - k as f64 * layer_height).abs() < 1e-9)
+                    .map(|p| [(p[0] * 1e6).round() as i64, (p[1] * 1e6).round() as i64])
+                    .collect();
+                sites.sort_unstable();
+                sites
+            })
+            .collect()
+    };
+
+    // Without faults the stacking is ABCABC and repeats every three layers.
+    let fcc = crystal_stacking_faults(lattice_param, 3.0, 7, 0.0, 1).unwrap();
+    let fcc_layers = layers(&fcc, 7);
+    assert_eq!(fcc.len(), fcc_layers.iter().map(Vec::len).sum::<usize>());
+    assert_eq!(fcc_layers[0], fcc_layers[3]);
+    assert_eq!(fcc_layers[1], fcc_layers[4]);
+    assert_ne!(fcc_layers[0], fcc_layers[1]);
+    assert_ne!(fcc_layers[0], fcc_layers[2]);
+
+    // Close packing gives 12 nearest neighbours at a / sqrt(2) around the axis,
+    // whatever the stacking.
+    let faulted = crystal_stacking_faults(lattice_param, 3.0, 7, 0.5, 2).unwrap();
+    let nearest = lattice_param / 2f64.sqrt();
+    for crystal in [&fcc, &faulted] {
+        let numbers = coordination_numbers(crystal.positions(), nearest * 1.05).unwrap();
+        let centre = crystal
+            .positions()
+            .iter()
+            .position(|p| {
+                (p[2] - 3.0 * layer_height).abs() < 1e-9
+                    && (p[0] - 1.5).hypot(p[1] - 1.5) < nearest / 2.0
+            })
+            .unwrap();
+        assert_eq!(numbers[centre], 12);
+    }
+    assert_eq!(
+        crystal_stacking_faults(lattice_param, 3.0, 7, 0.5, 2).unwrap(),
+        faulted
+    );
+    assert!(crystal_stacking_faults(lattice_param, 3.0, 0, 0.5, 2).is_err());
+    assert!(crystal_stacking_faults(lattice_param, 3.0, 7, 1.5, 2).is_err());
+}
+
+#[test]
+fn extrapolated_porod_invariant_of_a_sphere_matches_two_pi_squared_over_volume() {
+    // For P(0) = 1 the invariant of any particle is 2 pi^2 / V.
+    let radius: f64 = 1.5;
+    let expected = 2.0 * PI * PI / (4.0 / 3.0 * PI * radius.powi(3));
+    let q_values = q_grid(0.4, 15.0, 0.01);
+    let form_factor = form_factor_sphere(&q_values, radius).unwrap();
+
+    let (measured, low_q, high_q) =
+        porod_invariant_contributions(&q_values, &form_factor, true, true).unwrap();
+    let truncated = porod_invariant(&q_values, &form_factor, false, false).unwrap();
+    let extrapolated = porod_invariant(&q_values, &form_factor, true, true).unwrap();
+    assert_eq!(truncated, measured);
+    assert!((extrapolated - (measured + low_q + high_q)).abs() < 1e-12);
+    assert!(low_q > 0.0 && high_q > 0.0);
+    assert!((truncated - expected).abs() / expected > 0.01);
+    assert!((extrapolated - expected).abs() / expected < 0.01);
+}
+
+#[test]
+fn core_shell_particle_splits_the_two_lattices_at_the_core_radius() {
+    let (core_radius, shell_thickness) = (1.0, 0.6);
+    let outer = core_radius + shell_thickness;
+    let (atoms, species) = crystal_core_shell(0.4, 0.5, core_radius, shell_thickness).unwrap();
+    assert_eq!(atoms.len(), species.len());
+    let distance = |p: &[f64; 3]| p.iter().map(|x| (x - outer).powi(2)).sum::<f64>().sqrt();
+    assert!(atoms
+        .positions()
+        .iter()
+        .zip(&species)
+        .any(|(p, &s)| s == 0 && distance(p) < 1e-12));
+    for (p, &s) in atoms.positions().iter().zip(&species) {
+        let r = distance(p);
+        match s {
+            0 => assert!(r <= core_radius + 1e-12),
+            1 => assert!(r > core_radius && r <= outer + 1e-12),
+            _ => panic!("unexpected species {}", s),
+        }
+    }
+    let n_core = species.iter().filter(|&&s| s == 0).count();
+    assert!(n_core > 0 && n_core < atoms.len());
+
+    let (bare, bare_species) = crystal_core_shell(0.4, 0.5, core_radius, 0.0).unwrap();
+    assert_eq!(bare.len(), n_core);
+    assert!(bare_species.iter().all(|&s| s == 0));
+    assert!(crystal_core_shell(0.4, 0.5, core_radius, -0.1).is_err());
+}
+
+#[test]
+fn linspace_grid_has_exactly_n_points_and_matches_dse_core() {
+    let q_values = q_linspace(0.5, 4.5, 9).unwrap();
+    assert_eq!(q_values.len(), 9);
+    assert_eq!(q_values[0], 0.5);
+    assert_eq!(q_values[8], 4.5);
+    assert!(q_linspace(1.0, 0.5, 5).is_err() && q_linspace(0.5, 1.0, 1).is_err());
+
+    let cube = crystal_core(Shape::Cube, 0.4, 1.6).unwrap();
+    let (q, intensities) = dse_linspace(0.5, 4.5, 9, cube.positions()).unwrap();
+    assert_eq!(q, q_values);
+    let reference = dse_core(0.5, 4.5, 0.5, cube.positions());
+    assert_eq!(reference.len(), intensities.len());
+    for (value, expected) in intensities.iter().zip(&reference) {
+        assert!((value - expected).abs() <= 1e-9 * expected.abs());
+    }
+}
+
+#[test]
+fn logspace_and_custom_q_grids_match_dse_core() {
+    let q_values = q_logspace(0.01, 10.0, 4).unwrap();
+    let expected = [0.01, 0.1, 1.0, 10.0];
+    assert_eq!(q_values[0], 0.01);
+    assert_eq!(q_values[3], 10.0);
+    for (q, expected) in q_values.iter().zip(expected) {
+        assert!((q - expected).abs() <= 1e-12 * expected);
+    }
+    assert!(q_logspace(0.0, 1.0, 5).is_err());
+
+    let cube = crystal_core(Shape::Cube, 0.4, 1.6).unwrap();
+    let (q, intensities) = dse_logspace_q(0.01, 10.0, 4, cube.positions()).unwrap();
+    assert_eq!(q, q_values);
+    assert_eq!(intensities, dse_custom_q(&q, cube.positions()).unwrap());
+
+    // Any order, compared with the uniform grid of dse_core.
+    let reference = dse_core(0.5, 2.0, 0.5, cube.positions());
+    let custom = dse_custom_q(&[2.0, 0.5, 1.5, 1.0], cube.positions()).unwrap();
+    for (value, expected) in custom.iter().zip([3, 0, 2, 1].map(|i| reference[i])) {
+        assert!((value - expected).abs() <= 1e-9 * expected.abs());
+    }
+    assert!(dse_custom_q(&[1.0, f64::NAN], cube.positions()).is_err());
+}
+
+#[test]
+fn truncated_octahedra_have_the_magic_atom_counts() {
+    let lattice_param = 0.408;
+    let spacing = lattice_param / 2.0_f64.sqrt();
+    for (s, expected) in [(1, 38), (2, 201), (3, 586), (4, 1289), (5, 2406)] {
+        let edge_length = s as f64 * spacing;
+        let particle = crystal_truncated_octahedron(lattice_param, edge_length).unwrap();
+        assert_eq!(particle.len(), expected);
+        let centroid = [0, 1, 2].map(|axis| {
+            particle.positions().iter().map(|p| p[axis]).sum::<f64>() / expected as f64
+        });
+        assert!(centroid.iter().all(|c| c.abs() < 1e-12));
+        let via_shape =
+            crystal_core(Shape::TruncatedOctahedron, lattice_param, edge_length).unwrap();
+        assert_eq!(via_shape, particle);
+    }
+    assert!(crystal_truncated_octahedron(lattice_param, 0.1 * spacing).is_err());
+}
+
+#[test]
+fn sphere_normalized_by_its_equal_volume_sphere_stays_near_one() {
+    let sphere = crystal_core(Shape::Sphere, 0.4, 4.0).unwrap();
+    let (q, ratio) = dse_normalized_by_sphere(0.1, 1.5, 0.1, sphere.positions(), None).unwrap();
+    assert_eq!(q, q_grid(0.1, 1.5, 0.1));
+    assert!(ratio.iter().all(|r| (r - 1.0).abs() < 0.02));
+    let (_, explicit) =
+        dse_normalized_by_sphere(0.1, 1.5, 0.1, sphere.positions(), Some(0.4)).unwrap();
+    for (a, b) in ratio.iter().zip(&explicit) {
+        assert!((a - b).abs() < 1e-9);
+    }
+
+    // A cube of the same volume scatters less than the sphere at the same q R.
+    let cube = crystal_core(Shape::Cube, 0.4, 2.8).unwrap();
+    let (_, cube_ratio) = dse_normalized_by_sphere(0.1, 1.5, 0.1, cube.positions(), None).unwrap();
+    assert!(cube_ratio.last().unwrap() < ratio.last().unwrap());
+    assert!(dse_normalized_by_sphere(0.1, 1.5, 0.1, &[[0.0; 3]], None).is_err());
+}
+
+#[test]
+fn grain_aggregate_rotates_and_concatenates_the_grains() {
+    let grain = crystal_core(Shape::Cube, 0.4, 1.2).unwrap();
+    let quarter_turn = rotation_matrix_z(PI / 2.0);
+    let identity = rotation_matrix_z(0.0);
+    let aggregate =
+        crystal_grain_aggregate(&[(grain.clone(), identity), (grain.clone(), quarter_turn)])
+            .unwrap();
+    assert_eq!(aggregate.len(), 2 * grain.len());
+    let [x, y, z] = aggregate.positions()[grain.len() + 1 + 3];
+    let [gx, gy, gz] = grain.positions()[1 + 3];
+    assert!((x + gy).abs() < 1e-12 && (y - gx).abs() < 1e-12 && (z - gz).abs() < 1e-12);
+    let shear = [[1.0, 0.5, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    assert!(crystal_grain_aggregate(&[(grain, shear)]).is_err());
+}
+
+#[test]
+fn polycrystal_grains_stay_inside_the_cube_without_close_pairs() {
+    let (lattice_param, total_size) = (0.4, 4.0);
+    let single = crystal_core(Shape::Sphere, lattice_param, 2.0).unwrap();
+    let sample = crystal_polycrystalline(lattice_param, 2.0, total_size, 4, 7).unwrap();
+    assert!(sample.len() > single.len() && sample.len() < 4 * single.len());
+    assert!(sample
+        .positions()
+        .iter()
+        .flatten()
+        .all(|&x| (-1e-9..=total_size + 1e-9).contains(&x)));
+    let shortest = lattice_param * GRAIN_OVERLAP_DISTANCE;
+    assert_eq!(
+        coordination_numbers(sample.positions(), shortest * 0.999)
+            .unwrap()
+            .iter()
+            .sum::<usize>(),
+        0
+    );
+    assert_eq!(
+        crystal_polycrystalline(lattice_param, 2.0, total_size, 4, 7).unwrap(),
+        sample
+    );
+    assert!(crystal_polycrystalline(lattice_param, 2.0, 1.0, 4, 7).is_err());
+}
+
+#[test]
+fn shell_decomposition_rows_add_up_to_the_full_intensity() {
+    let fcc = crystal_core(Shape::Fcc, 0.4, 1.2).unwrap();
+    let shells = dse_shell_decomposition(0.5, 30.0, 0.5, fcc.positions(), 0.05).unwrap();
+    let full = dse_core(0.5, 30.0, 0.5, fcc.positions());
+    let n = fcc.len() as f64;
+    for (i, &expected) in full.iter().enumerate() {
+        let total: f64 = shells.iter().map(|row| row[i]).sum();
+        assert!((total - expected).abs() <= 1e-9 * n);
+    }
+    // Shells between the self terms and the nearest neighbours at a / sqrt(2) are empty.
+    let nearest = (0.4 / 2.0_f64.sqrt() / 0.05) as usize;
+    assert!(shells[0].iter().all(|&i| i == n));
+    assert!(shells[1..nearest].iter().flatten().all(|&i| i == 0.0));
+    assert!(shells[nearest].iter().any(|&i| i != 0.0));
+    for shell_dr in [0.0, -0.05, f64::NAN, 1e-300] {
+        assert!(dse_shell_decomposition(0.5, 30.0, 0.5, fcc.positions(), shell_dr).is_err());
+    }
+}
+
+#[test]
+fn poscar_direct_and_cartesian_coordinates_are_converted_to_nm() {
+    let direct = "\
+NaCl with selective dynamics
+2.0
+  2.0 0.0 0.0
+  1.0 2.0 0.0
+  0.0 0.0 3.0
+  Na Cl
+  1 2
+Selective dynamics
+Direct
+  0.0 0.0 0.0 T T T
+  0.5 0.5 0.5 F F F
+  1.0 0.0 0.5 T F T
+";
+    let (atoms, labels) = crystal_from_poscar(direct).unwrap();
+    assert_eq!(labels, ["Na", "Cl", "Cl"]);
+    assert_positions(
+        atoms.positions(),
+        &[[0.0, 0.0, 0.0], [0.3, 0.2, 0.3], [0.4, 0.0, 0.3]],
+    );
+
+    // A negative scale factor is the volume: 8 Å^3 of a unit cube scales it by 2.
+    let cartesian = "\
+Au dimer
+-8.0
+1.0 0.0 0.0
+0.0 1.0 0.0
+0.0 0.0 1.0
+Au
+2
+Cartesian
+0.0 0.0 0.0
+0.5 0.5 0.0
+";
+    let (atoms, labels) = crystal_from_poscar(cartesian).unwrap();
+    assert_eq!(labels, ["Au", "Au"]);
+    assert_positions(atoms.positions(), &[[0.0, 0.0, 0.0], [0.1, 0.1, 0.0]]);
+
+    // VASP 4 files have no symbols line, and the atoms must all be listed.
+    let vasp4 = cartesian.replace("Au\n2\n", "2\n");
+    assert!(crystal_from_poscar(&vasp4).is_err());
+    assert!(crystal_from_poscar(&cartesian.replace("0.5 0.5 0.0\n", "")).is_err());
+    assert!(crystal_from_poscar(&cartesian.replace("Cartesian", "Fractional")).is_err());
+    let overflowing = cartesian.replace("Au\n2\n", "Au Ag\n18446744073709551615 1\n");
+    assert!(matches!(
+        crystal_from_poscar(&overflowing),
+        Err(fast_dse::core::Error::Parse { line: 7, .. })
+    ));
+    // A corrupt count must not be trusted for the allocation.
+    let huge = cartesian.replace("Au\n2\n", "Au\n99999999999999\n");
+    assert!(matches!(
+        crystal_from_poscar(&huge),
+        Err(fast_dse::core::Error::Parse { .. })
+    ));
+}
+
+#[test]
+fn convergence_r_factors_fall_as_the_crystal_grows() {
+    let sizes = [1.0, 1.5, 2.0, 2.5, 3.0];
+    let r_factors = convergence_plot_data(Shape::Sphere, 0.4, &sizes, 1.0, 20.0, 0.2).unwrap();
+    assert_eq!(r_factors.len(), sizes.len() - 1);
+    assert!(r_factors.iter().all(|&r| r > 0.0));
+    assert!(r_factors[3] < r_factors[0]);
+
+    // The first size whose R-factor against the next is below the tolerance.
+    let tolerance = r_factors[1..].iter().cloned().fold(f64::INFINITY, f64::min) * 1.001;
+    let first = r_factors.iter().position(|&r| r < tolerance).unwrap();
+    let converged = convergence_test(Shape::Sphere, 0.4, &sizes, 1.0, 20.0, 0.2, tolerance);
+    assert_eq!(converged.unwrap(), sizes[first]);
+    let strict = r_factors.iter().cloned().fold(f64::INFINITY, f64::min) / 2.0;
+    assert!(convergence_test(Shape::Sphere, 0.4, &sizes, 1.0, 20.0, 0.2, strict).is_err());
+
+    // Sizes 0.1 nm apart give the same crystal, whose R-factor of 0 means nothing.
+    assert!(convergence_plot_data(Shape::Sphere, 0.4, &[1.0, 1.1], 1.0, 20.0, 0.2).is_err());
+    assert!(convergence_plot_data(Shape::Sphere, 0.4, &[2.0, 1.0], 1.0, 20.0, 0.2).is_err());
+}
+
+#[test]
+fn q_grid_is_empty_for_a_step_that_cannot_reach_max_q() {
+    assert_eq!(q_grid(0.5, 1.0, 0.25), [0.5, 0.75, 1.0]);
+    for q_step in [0.0, -0.1, f64::NAN, f64::INFINITY] {
+        assert!(q_grid(0.5, 1.0, q_step).is_empty());
+    }
+    assert!(q_grid(1.0, 0.5, 0.1).is_empty());
+    assert!(q_grid(0.5, f64::INFINITY, 0.1).is_empty());
+}
+
+#[test]
+fn thermal_disorder_averages_to_the_debye_waller_attenuated_intensity() {
+    let ideal = crystal_core(Shape::Cube, 0.4, 2.0).unwrap();
+    let n = ideal.len() as f64;
+    let sigma = 0.05;
+    let n_seeds = 400;
+    // A quarter of 2 pi / a, so that the (100) peak lies on the grid.
+    let q_step = 2.0 * PI / 0.4 / 4.0;
+    let (min_q, max_q) = (q_step, 8.0 * q_step);
+    let mut mean = vec![0.0; q_grid(min_q, max_q, q_step).len()];
+    for seed in 0..n_seeds {
+        let disordered = apply_thermal_disorder(ideal.positions(), sigma, seed).unwrap();
+        for (m, i) in mean
+            .iter_mut()
+            .zip(dse_core(min_q, max_q, q_step, disordered.positions()))
+        {
+            *m += i / n_seeds as f64;
+        }
+    }
+    // I = N + exp(-q^2 sigma^2) (I_0 - N): the self terms are not attenuated.
+    // The mean of 400 seeds is within about 2e-5 N^2 of it, well below the
+    // 7e-4 N^2 by which exp(-0.8 q^2 sigma^2) would miss at the (100) peak.
+    let ideal_intensity = dse_core(min_q, max_q, q_step, ideal.positions());
+    for ((q, m), i_0) in q_grid(min_q, max_q, q_step)
+        .into_iter()
+        .zip(&mean)
+        .zip(&ideal_intensity)
+    {
+        let expected = n + (-(q * sigma).powi(2)).exp() * (i_0 - n);
+        assert!(
+            (m - expected).abs() < 1e-4 * n * n,
+            "q = {}: {} vs {}",
+            q,
+            m,
+            expected
+        );
+    }
+
+    let again = apply_thermal_disorder(ideal.positions(), sigma, 7).unwrap();
+    assert_eq!(
+        again.positions(),
+        apply_thermal_disorder(ideal.positions(), sigma, 7)
+            .unwrap()
+            .positions()
+    );
+    let isotropic = apply_anisotropic_disorder(ideal.positions(), sigma, sigma, sigma, 7).unwrap();
+    assert_eq!(isotropic.positions(), again.positions());
+    assert!(apply_thermal_disorder(ideal.positions(), -0.01, 0).is_err());
+    assert!(apply_anisotropic_disorder(ideal.positions(), 0.01, -0.01, 0.01, 0).is_err());
+}
+
+#[test]
+fn single_atom_curve_normalises_and_scales_analytically() {
+    // One atom scatters I(q) = 1 at every q.
+    let intensities = dse_core(0.1, 20.0, 0.5, &[[0.0, 0.0, 0.0]]);
+    assert!(normalise_intensity(&intensities, 1)
+        .iter()
+        .all(|&i| (i - 1.0).abs() < 1e-12));
+    let (concentration, contrast) = (2.5, 3.0);
+    assert!(scale_to_absolute(&intensities, concentration, contrast)
+        .iter()
+        .all(|&i| (i - concentration * contrast * contrast).abs() < 1e-12));
+    // Two coincident atoms scatter N^2 = 4, which normalises to 1 as well.
+    let pair = dse_core(0.1, 20.0, 0.5, &[[0.0, 0.0, 0.0]; 2]);
+    assert!(normalise_intensity(&pair, 2)
+        .iter()
+        .all(|&i| (i - 1.0).abs() < 1e-12));
+
+    let background = vec![0.25; intensities.len()];
+    let subtracted = subtract_background(&intensities, &background).unwrap();
+    assert!(subtracted.iter().all(|&i| (i - 0.75).abs() < 1e-12));
+    assert!(matches!(
+        subtract_background(&intensities, &background[1..]),
+        Err(fast_dse::core::Error::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn histogram_dse_approaches_the_exact_sum_and_rejects_bad_bins() {
+    let fcc = crystal_core(Shape::Fcc, 0.4, 1.2).unwrap();
+    let exact = dse_core(0.5, 20.0, 0.5, fcc.positions());
+    let binned = dse_histogram(0.5, 20.0, 0.5, fcc.positions(), 1e-4).unwrap();
+    let n = fcc.len() as f64;
+    assert!(binned
+        .iter()
+        .zip(&exact)
+        .all(|(b, e)| (b - e).abs() < 1e-3 * n * n));
+    for dr in [0.0, -0.01, f64::NAN, 1e-300] {
+        assert!(dse_histogram(0.5, 20.0, 0.5, fcc.positions(), dr).is_err());
+    }
+}
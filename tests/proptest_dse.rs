@@ -0,0 +1,65 @@
+use fast_dse::core::{dse_core, dse_linspace, dse_symmetric};
+use proptest::prelude::*;
+
+/// Relative tolerance between intensities that differ only in the order of
+/// their pair sums, in units of the number of atoms `N`, the large-q limit.
+const SUMMATION_TOLERANCE: f64 = 1e-9;
+
+/// Crystals of 1 to 30 atoms with coordinates in `[-2, 2)` nanometers.
+fn crystal(max_atoms: usize) -> impl Strategy<Value = Vec<[f64; 3]>> {
+    prop::collection::vec(prop::array::uniform3(-2.0..2.0f64), 1..=max_atoms)
+}
+
+/// Whether `a` and `b` agree to [`SUMMATION_TOLERANCE`] relative to the
+/// larger of each value and `n_atoms`.
+fn agree(a: &[f64], b: &[f64], n_atoms: usize) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(&x, &y)| (x - y).abs() <= SUMMATION_TOLERANCE * y.abs().max(n_atoms as f64))
+}
+
+proptest! {
+    #[test]
+    fn swapping_two_atoms_leaves_the_intensity_unchanged(
+        positions in crystal(30),
+        i in any::<prop::sample::Index>(),
+        j in any::<prop::sample::Index>(),
+    ) {
+        let mut swapped = positions.clone();
+        swapped.swap(i.index(positions.len()), j.index(positions.len()));
+        prop_assert!(agree(
+            &dse_core(0.1, 20.0, 0.5, &swapped),
+            &dse_core(0.1, 20.0, 0.5, &positions),
+            positions.len(),
+        ));
+    }
+
+    #[test]
+    fn scaling_the_coordinates_by_s_rescales_q_by_one_over_s(
+        positions in crystal(30),
+        scale in 0.25..4.0f64,
+    ) {
+        // I_scaled(q / s) = I(q), since the DSE depends on q r only.
+        let scaled: Vec<[f64; 3]> = positions.iter().map(|p| p.map(|x| x * scale)).collect();
+        let (_, original) = dse_linspace(0.1, 20.0, 40, &positions).unwrap();
+        let (_, rescaled) = dse_linspace(0.1 / scale, 20.0 / scale, 40, &scaled).unwrap();
+        prop_assert!(agree(&rescaled, &original, positions.len()));
+    }
+
+    #[test]
+    fn dse_symmetric_matches_dse_core(positions in crystal(30)) {
+        prop_assert!(agree(
+            &dse_symmetric(0.1, 20.0, 0.5, &positions),
+            &dse_core(0.1, 20.0, 0.5, &positions),
+            positions.len(),
+        ));
+    }
+
+    #[test]
+    fn intensity_is_never_negative(positions in crystal(30)) {
+        // I(q) is the orientational average of |sum_j exp(i q . r_j)|^2.
+        let floor = -SUMMATION_TOLERANCE * positions.len() as f64;
+        prop_assert!(dse_core(0.1, 20.0, 0.5, &positions).iter().all(|&i| i >= floor));
+    }
+}